@@ -0,0 +1,518 @@
+use chrono::Datelike;
+use clap::ArgMatches;
+use log::trace;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+use crate::{
+    binance_account_info::{get_account_info, AccountInfo},
+    binance_history::{
+        get_deposit_history, get_withdraw_history, DEPOSIT_STATUS_SUCCESS,
+        WITHDRAW_STATUS_COMPLETED,
+    },
+    binance_trade::{convert, order_log_file},
+    configuration::Configuration,
+    date_time_utc::DateTimeUtc,
+};
+
+use dec_utils::{dec_to_separated_string, dec_to_usd_string};
+use time_ms_conversions::{time_ms_to_utc, utc_now_to_time_ms};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SnapshotBalance {
+    pub asset: String,
+    pub quantity: Decimal,
+    pub price_in_usd: Decimal,
+    pub value_in_usd: Decimal,
+}
+
+/// One line of the snapshot history file
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SnapshotRec {
+    pub time: i64,
+    pub total_value_usd: Decimal,
+    pub balances: Vec<SnapshotBalance>,
+}
+
+impl SnapshotRec {
+    /// Create from an AccountInfo whose values have been updated
+    pub fn from_account_info(ai: &AccountInfo, time: i64) -> SnapshotRec {
+        let balances: Vec<SnapshotBalance> = ai
+            .balances_map
+            .values()
+            .filter(|b| b.free + b.locked != dec!(0))
+            .map(|b| SnapshotBalance {
+                asset: b.asset.clone(),
+                quantity: b.free + b.locked,
+                price_in_usd: b.price_in_usd,
+                value_in_usd: b.value_in_usd,
+            })
+            .collect();
+        let total_value_usd = balances.iter().map(|b| b.value_in_usd).sum();
+
+        SnapshotRec {
+            time,
+            total_value_usd,
+            balances,
+        }
+    }
+
+    pub fn value_of(&self, asset: &str) -> Decimal {
+        self.balances
+            .iter()
+            .find(|b| b.asset == asset)
+            .map_or(dec!(0), |b| b.value_in_usd)
+    }
+}
+
+impl fmt::Display for SnapshotRec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} total value: {} assets: {}",
+            time_ms_to_utc(self.time),
+            dec_to_usd_string(self.total_value_usd),
+            self.balances.len()
+        )
+    }
+}
+
+pub fn append_snapshot(path: &Path, rec: &SnapshotRec) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = order_log_file(path)?;
+    serde_json::to_writer(&mut writer, rec)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Read the snapshots in path sorted by time
+pub fn read_snapshots(path: &Path) -> Result<Vec<SnapshotRec>, Box<dyn std::error::Error>> {
+    let in_file = if let Ok(in_f) = File::open(path) {
+        in_f
+    } else {
+        let path_string = path.to_string_lossy();
+        return Err(format!("Unable to open {path_string:?}, use the snapshot subcommand").into());
+    };
+
+    let mut snapshots: Vec<SnapshotRec> = Vec::new();
+    for (i, result) in BufReader::new(in_file).lines().enumerate() {
+        let line = match result {
+            Ok(l) => l,
+            Err(e) => return Err(format!("line: {} Err: {}", i + 1, e).into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<SnapshotRec>(&line) {
+            Ok(rec) => snapshots.push(rec),
+            Err(e) => return Err(format!("line: {} Err: {}", i + 1, e).into()),
+        }
+    }
+    snapshots.sort_by_key(|s| s.time);
+
+    Ok(snapshots)
+}
+
+/// A deposit, positive, or withdrawal, negative, of an asset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CashFlow {
+    pub time: i64,
+    pub asset: String,
+    pub quantity: Decimal,
+    pub value_usd: Decimal,
+}
+
+/// Get the completed deposits and withdrawals between the times valued
+/// in USD at the time of each. Withdraw fees are not included so they
+/// reduce the returns.
+pub async fn get_cash_flows(
+    config: &Configuration,
+    start_time_ms: i64,
+    end_time_ms: i64,
+) -> Result<Vec<CashFlow>, Box<dyn std::error::Error>> {
+    let start = Some(time_ms_to_utc(start_time_ms));
+    let end = Some(time_ms_to_utc(end_time_ms));
+
+    let mut flows: Vec<CashFlow> = Vec::new();
+    for dr in get_deposit_history(config, None, None, start, end).await? {
+        if dr.status != DEPOSIT_STATUS_SUCCESS {
+            continue;
+        }
+        let value_usd = convert(config, dr.insert_time, &dr.asset, dr.amount, "USD").await?;
+        flows.push(CashFlow {
+            time: dr.insert_time,
+            asset: dr.asset,
+            quantity: dr.amount,
+            value_usd,
+        });
+    }
+    for wr in get_withdraw_history(config, None, None, start, end).await? {
+        if wr.status != WITHDRAW_STATUS_COMPLETED {
+            continue;
+        }
+        let value_usd = convert(config, wr.apply_time, &wr.asset, wr.amount, "USD").await?;
+        flows.push(CashFlow {
+            time: wr.apply_time,
+            asset: wr.asset,
+            quantity: -wr.amount,
+            value_usd: -value_usd,
+        });
+    }
+    flows.sort_by_key(|f| f.time);
+    trace!("get_cash_flows: {} flows", flows.len());
+
+    Ok(flows)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowReturn {
+    pub name: String,
+    pub start: SnapshotRec,
+    pub end: SnapshotRec,
+    pub net_flows_usd: Decimal,
+    pub gain_usd: Decimal,
+    // Modified Dietz return, None if there was nothing invested
+    pub return_percent: Option<Decimal>,
+}
+
+fn flows_between(flows: &[CashFlow], start_time: i64, end_time: i64) -> Vec<&CashFlow> {
+    flows
+        .iter()
+        .filter(|f| f.time > start_time && f.time <= end_time)
+        .collect()
+}
+
+/// The return from the last snapshot at or before start_time_ms, or the
+/// first after it, to the last snapshot. Flows are weighted by the
+/// fraction of the window they were invested for.
+pub fn window_return(
+    name: &str,
+    snapshots: &[SnapshotRec],
+    start_time_ms: i64,
+    flows: &[CashFlow],
+) -> Option<WindowReturn> {
+    let end = snapshots.last()?;
+    let start = snapshots
+        .iter()
+        .rev()
+        .find(|s| s.time <= start_time_ms)
+        .or_else(|| snapshots.iter().find(|s| s.time >= start_time_ms))?;
+    if start.time >= end.time {
+        return None;
+    }
+
+    let duration = Decimal::from(end.time - start.time);
+    let mut net_flows_usd = dec!(0);
+    let mut weighted_flows_usd = dec!(0);
+    for f in flows_between(flows, start.time, end.time) {
+        net_flows_usd += f.value_usd;
+        weighted_flows_usd += f.value_usd * Decimal::from(end.time - f.time) / duration;
+    }
+    let gain_usd = end.total_value_usd - start.total_value_usd - net_flows_usd;
+    let invested = start.total_value_usd + weighted_flows_usd;
+    let return_percent = if invested > dec!(0) {
+        Some(gain_usd / invested * dec!(100))
+    } else {
+        None
+    };
+
+    Some(WindowReturn {
+        name: name.to_string(),
+        start: start.clone(),
+        end: end.clone(),
+        net_flows_usd,
+        gain_usd,
+        return_percent,
+    })
+}
+
+/// Change in value of each asset between the snapshots less its
+/// deposits and withdrawals, largest first. Trades move value from
+/// the asset sold to the asset bought.
+pub fn asset_contributions(
+    start: &SnapshotRec,
+    end: &SnapshotRec,
+    flows: &[CashFlow],
+) -> Vec<(String, Decimal)> {
+    let mut contributions: BTreeMap<String, Decimal> = BTreeMap::new();
+    for b in start.balances.iter().chain(end.balances.iter()) {
+        contributions.insert(
+            b.asset.clone(),
+            end.value_of(&b.asset) - start.value_of(&b.asset),
+        );
+    }
+    for f in flows_between(flows, start.time, end.time) {
+        *contributions.entry(f.asset.clone()).or_insert(dec!(0)) -= f.value_usd;
+    }
+
+    let mut contributions: Vec<(String, Decimal)> = contributions.into_iter().collect();
+    contributions.sort_by_key(|c| Reverse(c.1));
+    contributions
+}
+
+/// Start times of the day, week, month, YTD and all windows ending now
+fn report_windows(now_ms: i64, first_ms: i64) -> Vec<(&'static str, i64)> {
+    let year = time_ms_to_utc(now_ms).year();
+    let ytd = DateTimeUtc::from_utc_ymd_hmsn(year, 1, 1, 0, 0, 0, 0);
+
+    vec![
+        ("day", now_ms - DAY_MS),
+        ("week", now_ms - 7 * DAY_MS),
+        ("month", now_ms - 30 * DAY_MS),
+        ("YTD", ytd.get_dt().timestamp_millis()),
+        ("all", first_ms),
+    ]
+}
+
+pub async fn snapshot_cmd(config: &Configuration) -> Result<(), Box<dyn std::error::Error>> {
+    trace!("snapshot_cmd:+");
+
+    let time_ms = utc_now_to_time_ms();
+    let mut ai = get_account_info(config, time_ms).await?;
+    ai.update_values_in_usd(config, config.verbose, time_ms)
+        .await;
+
+    let rec = SnapshotRec::from_account_info(&ai, time_ms);
+    append_snapshot(&config.snapshot_path, &rec)?;
+    println!("{rec} appended to {:?}", config.snapshot_path);
+
+    trace!("snapshot_cmd:-");
+    Ok(())
+}
+
+pub async fn performance_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    trace!("performance_cmd:+");
+
+    let limit: usize = sc_matches
+        .value_of("LIMIT")
+        .unwrap_or("10")
+        .parse()
+        .map_err(|e| format!("LIMIT must be a number: {e}"))?;
+
+    let snapshots = read_snapshots(&config.snapshot_path)?;
+    let (first, last) = match (snapshots.first(), snapshots.last()) {
+        (Some(f), Some(l)) if f.time < l.time => (f, l),
+        _ => {
+            return Err("At least two snapshots are needed, use the snapshot subcommand".into());
+        }
+    };
+    let flows = get_cash_flows(config, first.time, last.time).await?;
+
+    println!("Value history:");
+    let mut prev: Option<&SnapshotRec> = None;
+    for s in snapshots.iter().skip(snapshots.len().saturating_sub(limit)) {
+        let flows_usd: Decimal = match prev {
+            Some(p) => flows_between(&flows, p.time, s.time)
+                .iter()
+                .map(|f| f.value_usd)
+                .sum(),
+            None => dec!(0),
+        };
+        println!(
+            "{:30} {:>16} {:>16}",
+            time_ms_to_utc(s.time).to_string(),
+            dec_to_usd_string(s.total_value_usd),
+            if flows_usd != dec!(0) {
+                format!("flows {}", dec_to_usd_string(flows_usd))
+            } else {
+                "".to_string()
+            }
+        );
+        prev = Some(s);
+    }
+
+    println!("\nReturns net of deposits and withdrawals:");
+    println!(
+        "{:6} {:30} {:>16} {:>16} {:>16} {:>16} {:>9}",
+        "Window", "From", "Start value", "End value", "Net flows", "Gain", "Return"
+    );
+    for (name, start_ms) in report_windows(last.time, first.time) {
+        if let Some(wr) = window_return(name, &snapshots, start_ms, &flows) {
+            println!(
+                "{:6} {:30} {:>16} {:>16} {:>16} {:>16} {:>9}",
+                wr.name,
+                time_ms_to_utc(wr.start.time).to_string(),
+                dec_to_usd_string(wr.start.total_value_usd),
+                dec_to_usd_string(wr.end.total_value_usd),
+                dec_to_usd_string(wr.net_flows_usd),
+                dec_to_usd_string(wr.gain_usd),
+                match wr.return_percent {
+                    Some(p) => format!("{}%", dec_to_separated_string(p, 2)),
+                    None => "-".to_string(),
+                }
+            );
+        }
+    }
+
+    println!(
+        "\nContribution by asset since {}:",
+        time_ms_to_utc(first.time)
+    );
+    for (asset, contribution) in asset_contributions(first, last, &flows) {
+        println!("{:10} {:>16}", asset, dec_to_usd_string(contribution));
+    }
+
+    trace!("performance_cmd:-");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    fn snapshot(time: i64, balances: &[(&str, Decimal, Decimal)]) -> SnapshotRec {
+        let balances: Vec<SnapshotBalance> = balances
+            .iter()
+            .map(|(asset, quantity, price)| SnapshotBalance {
+                asset: asset.to_string(),
+                quantity: *quantity,
+                price_in_usd: *price,
+                value_in_usd: quantity * price,
+            })
+            .collect();
+        SnapshotRec {
+            time,
+            total_value_usd: balances.iter().map(|b| b.value_in_usd).sum(),
+            balances,
+        }
+    }
+
+    fn flow(time: i64, asset: &str, quantity: Decimal, value_usd: Decimal) -> CashFlow {
+        CashFlow {
+            time,
+            asset: asset.to_string(),
+            quantity,
+            value_usd,
+        }
+    }
+
+    #[test]
+    fn test_append_and_read_snapshots() {
+        let dir = TempDir::new("snapshot");
+        let path = dir.path().join("snapshots.jsonl");
+
+        let s1 = snapshot(2000, &[("BTC", dec!(1), dec!(30000))]);
+        let s2 = snapshot(
+            1000,
+            &[("BTC", dec!(1), dec!(29000)), ("USD", dec!(100), dec!(1))],
+        );
+        append_snapshot(&path, &s1).unwrap();
+        append_snapshot(&path, &s2).unwrap();
+
+        let snapshots = read_snapshots(&path).unwrap();
+        assert_eq!(snapshots, vec![s2, s1]);
+        assert_eq!(snapshots[0].total_value_usd, dec!(29100));
+        assert_eq!(snapshots[0].value_of("USD"), dec!(100));
+        assert_eq!(snapshots[0].value_of("ETH"), dec!(0));
+    }
+
+    #[test]
+    fn test_window_return_no_flows() {
+        let snapshots = vec![
+            snapshot(0, &[("BTC", dec!(1), dec!(1000))]),
+            snapshot(DAY_MS, &[("BTC", dec!(1), dec!(1100))]),
+            snapshot(2 * DAY_MS, &[("BTC", dec!(1), dec!(1210))]),
+        ];
+
+        let wr = window_return("day", &snapshots, DAY_MS, &[]).unwrap();
+        assert_eq!(wr.start.time, DAY_MS);
+        assert_eq!(wr.gain_usd, dec!(110));
+        assert_eq!(wr.return_percent, Some(dec!(10)));
+
+        // Before the first snapshot starts at the first
+        let wr = window_return("all", &snapshots, -DAY_MS, &[]).unwrap();
+        assert_eq!(wr.start.time, 0);
+        assert_eq!(wr.gain_usd, dec!(210));
+        assert_eq!(wr.return_percent, Some(dec!(21)));
+
+        // Nothing after the last snapshot
+        assert!(window_return("none", &snapshots, 3 * DAY_MS, &[]).is_none());
+        assert!(window_return("none", &[], 0, &[]).is_none());
+    }
+
+    #[test]
+    fn test_window_return_with_flows() {
+        let snapshots = vec![
+            snapshot(0, &[("USD", dec!(1000), dec!(1))]),
+            snapshot(
+                2 * DAY_MS,
+                &[("USD", dec!(1000), dec!(1)), ("ETH", dec!(1), dec!(1200))],
+            ),
+        ];
+        // ETH worth 1000 deposited half way through, now worth 1200
+        let flows = vec![flow(DAY_MS, "ETH", dec!(1), dec!(1000))];
+
+        let wr = window_return("all", &snapshots, 0, &flows).unwrap();
+        assert_eq!(wr.net_flows_usd, dec!(1000));
+        assert_eq!(wr.gain_usd, dec!(200));
+        assert_eq!(wr.return_percent, Some(dec!(200) / dec!(1500) * dec!(100)));
+
+        // A withdrawal of everything is not a loss
+        let snapshots = vec![
+            snapshot(0, &[("USD", dec!(1000), dec!(1))]),
+            snapshot(DAY_MS, &[]),
+        ];
+        let flows = vec![flow(DAY_MS, "USD", dec!(-1000), dec!(-1000))];
+        let wr = window_return("all", &snapshots, 0, &flows).unwrap();
+        assert_eq!(wr.gain_usd, dec!(0));
+        assert_eq!(wr.return_percent, Some(dec!(0)));
+    }
+
+    #[test]
+    fn test_asset_contributions() {
+        let start = snapshot(
+            0,
+            &[("BTC", dec!(1), dec!(30000)), ("ETH", dec!(2), dec!(2000))],
+        );
+        let end = snapshot(
+            DAY_MS,
+            &[
+                ("BTC", dec!(1), dec!(29000)),
+                ("ETH", dec!(3), dec!(2100)),
+                ("USD", dec!(50), dec!(1)),
+            ],
+        );
+        let flows = vec![
+            flow(DAY_MS / 2, "ETH", dec!(1), dec!(2050)),
+            // Before the start so ignored
+            flow(-1, "USD", dec!(10), dec!(10)),
+        ];
+
+        let contributions = asset_contributions(&start, &end, &flows);
+        assert_eq!(
+            contributions,
+            vec![
+                ("ETH".to_string(), dec!(250)),
+                ("USD".to_string(), dec!(50)),
+                ("BTC".to_string(), dec!(-1000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_windows() {
+        // 2022-03-02T00:00:00Z
+        let now = 1646179200000;
+        let windows = report_windows(now, 0);
+        assert_eq!(windows[0], ("day", now - DAY_MS));
+        assert_eq!(windows[1], ("week", now - 7 * DAY_MS));
+        assert_eq!(windows[2], ("month", now - 30 * DAY_MS));
+        // 2022-01-01T00:00:00Z
+        assert_eq!(windows[3], ("YTD", 1640995200000));
+        assert_eq!(windows[4], ("all", 0));
+    }
+}
@@ -0,0 +1,757 @@
+//! Price and account value alerts.
+//!
+//! Alerts are written as short phrases, for example:
+//!   "BTCUSD crosses 30000"
+//!   "BTCUSD above 30000" or "BTCUSD below 20000"
+//!   "ETHUSD moves 5% in 1h", "+5%" only fires up and "-5%" only down
+//!   "account value below 10000"
+//! and are checked on an interval or as prices arrive from the
+//! WebSocket stream. When an alert fires each of the notifiers is called.
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display},
+    time::Duration,
+};
+
+use clap::ArgMatches;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::Serialize;
+use time_ms_conversions::{time_ms_to_utc, utc_now_to_time_ms};
+use tokio::sync::mpsc::unbounded_channel;
+
+use dec_utils::dec_to_usd_string;
+
+use crate::{
+    binance_account_info::get_account_info,
+    binance_exchange_info::{get_exchange_info, ExchangeInfo},
+    binance_klines::get_kline,
+    binance_ticker::{get_ticker_prices, prices_map},
+    binance_websocket::{stream_name, MarketStreamClient, StreamEvent, StreamKind},
+    configuration::Configuration,
+    notifications::{notifiers_from_strings, notify_all, Notification},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveDirection {
+    Up,
+    Down,
+    Either,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlertCondition {
+    /// The price moves from one side of price to the other
+    Crosses {
+        symbol: String,
+        price: Decimal,
+    },
+    Above {
+        symbol: String,
+        price: Decimal,
+    },
+    Below {
+        symbol: String,
+        price: Decimal,
+    },
+
+    /// The price changes by percent within window_ms
+    Moves {
+        symbol: String,
+        percent: Decimal,
+        direction: MoveDirection,
+        window_ms: i64,
+    },
+    AccountValueBelow(Decimal),
+    AccountValueAbove(Decimal),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alert {
+    pub text: String,
+    pub condition: AlertCondition,
+}
+
+/// Parse a decimal that may have a leading $ and thousands separators
+fn parse_amount(s: &str) -> Result<Decimal, Box<dyn std::error::Error>> {
+    let cleaned: String = s.chars().filter(|c| *c != '$' && *c != ',').collect();
+    match Decimal::from_str(&cleaned) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(format!("{s} is not a number: {e}").into()),
+    }
+}
+
+/// Parse a duration such as 30m, 1h or 2d to milli-seconds
+pub fn parse_duration_ms(s: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    let (num, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let n: i64 = match num.parse() {
+        Ok(n) => n,
+        Err(_) => {
+            return Err(format!("{s} is not a duration, expecting e.g. 30m, 1h or 1d").into())
+        }
+    };
+    let unit_ms = match unit {
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => return Err(format!("{s} has an unknown unit, expecting s, m, h or d").into()),
+    };
+
+    Ok(n * unit_ms)
+}
+
+impl Alert {
+    pub fn from_string(s: &str) -> Result<Alert, Box<dyn std::error::Error>> {
+        let words: Vec<String> = s.split_whitespace().map(|w| w.to_lowercase()).collect();
+        let words: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+        let condition = match words[..] {
+            ["account", "value", "below", amount] => {
+                AlertCondition::AccountValueBelow(parse_amount(amount)?)
+            }
+            ["account", "value", "above", amount] => {
+                AlertCondition::AccountValueAbove(parse_amount(amount)?)
+            }
+            [symbol, "crosses", price] => AlertCondition::Crosses {
+                symbol: symbol.to_uppercase(),
+                price: parse_amount(price)?,
+            },
+            [symbol, "above", price] => AlertCondition::Above {
+                symbol: symbol.to_uppercase(),
+                price: parse_amount(price)?,
+            },
+            [symbol, "below", price] => AlertCondition::Below {
+                symbol: symbol.to_uppercase(),
+                price: parse_amount(price)?,
+            },
+            [symbol, "moves", percent, "in", window] => {
+                let percent = percent.trim_end_matches('%');
+                let (direction, percent) = if let Some(p) = percent.strip_prefix('+') {
+                    (MoveDirection::Up, p)
+                } else if let Some(p) = percent.strip_prefix('-') {
+                    (MoveDirection::Down, p)
+                } else if let Some(p) = percent.strip_prefix('±') {
+                    (MoveDirection::Either, p)
+                } else {
+                    (MoveDirection::Either, percent)
+                };
+                AlertCondition::Moves {
+                    symbol: symbol.to_uppercase(),
+                    percent: parse_amount(percent)?,
+                    direction,
+                    window_ms: parse_duration_ms(window)?,
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown alert, {s}, expecting: \"SYMBOL crosses|above|below PRICE\", \
+                     \"SYMBOL moves [+-]PERCENT% in DURATION\" or \"account value below|above USD\""
+                )
+                .into())
+            }
+        };
+
+        Ok(Alert {
+            text: s.to_string(),
+            condition,
+        })
+    }
+
+    pub fn symbol(&self) -> Option<&str> {
+        match &self.condition {
+            AlertCondition::Crosses { symbol, .. }
+            | AlertCondition::Above { symbol, .. }
+            | AlertCondition::Below { symbol, .. }
+            | AlertCondition::Moves { symbol, .. } => Some(symbol.as_str()),
+            _ => None,
+        }
+    }
+
+    /// An asset name, such as ETH, is changed to its symbol in quote_asset
+    pub fn resolve_symbol(
+        &mut self,
+        ei: &ExchangeInfo,
+        quote_asset: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let symbol = match &mut self.condition {
+            AlertCondition::Crosses { symbol, .. }
+            | AlertCondition::Above { symbol, .. }
+            | AlertCondition::Below { symbol, .. }
+            | AlertCondition::Moves { symbol, .. } => symbol,
+            _ => return Ok(()),
+        };
+        if ei.get_symbol(symbol).is_none() {
+            let sym_name = format!("{symbol}{quote_asset}");
+            if ei.get_symbol(&sym_name).is_none() {
+                return Err(
+                    format!("{symbol} is not a symbol or an asset with {quote_asset}").into(),
+                );
+            }
+            *symbol = sym_name;
+        }
+
+        Ok(())
+    }
+}
+
+/// A fired alert, serialized as the body of webhook notifications
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertEvent {
+    pub alert: String,
+    pub time: i64,
+    pub message: String,
+    pub value: Decimal,
+}
+
+impl Notification for AlertEvent {
+    fn env_vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("ALERT", self.alert.clone()),
+            ("ALERT_MESSAGE", self.message.clone()),
+            ("ALERT_VALUE", self.value.to_string()),
+            ("ALERT_TIME", self.time.to_string()),
+        ]
+    }
+}
+
+impl Display for AlertEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ALERT {}: {}",
+            time_ms_to_utc(self.time),
+            self.alert,
+            self.message
+        )
+    }
+}
+
+struct AlertState {
+    alert: Alert,
+
+    last_price: Option<Decimal>,
+
+    /// True while an above or below condition holds, it fires again
+    /// only after becoming false
+    active: bool,
+
+    /// Prices within the window of a moves alert, oldest first
+    history: VecDeque<(i64, Decimal)>,
+}
+
+/// Evaluates alerts as prices and account values are observed
+pub struct AlertEngine {
+    states: Vec<AlertState>,
+}
+
+impl AlertEngine {
+    pub fn new(alerts: Vec<Alert>) -> AlertEngine {
+        AlertEngine {
+            states: alerts
+                .into_iter()
+                .map(|alert| AlertState {
+                    alert,
+                    last_price: None,
+                    active: false,
+                    history: VecDeque::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// The symbols whose prices are needed
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self
+            .states
+            .iter()
+            .filter_map(|s| s.alert.symbol().map(|s| s.to_string()))
+            .collect();
+        symbols.sort();
+        symbols.dedup();
+        symbols
+    }
+
+    pub fn needs_account_value(&self) -> bool {
+        self.states.iter().any(|s| {
+            matches!(
+                s.alert.condition,
+                AlertCondition::AccountValueBelow(_) | AlertCondition::AccountValueAbove(_)
+            )
+        })
+    }
+
+    /// The moves alerts, as (symbol, window_ms), whose starting price can be seeded
+    pub fn move_windows(&self) -> Vec<(String, i64)> {
+        self.states
+            .iter()
+            .filter_map(|s| match &s.alert.condition {
+                AlertCondition::Moves {
+                    symbol, window_ms, ..
+                } => Some((symbol.clone(), *window_ms)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Add an earlier price of symbol to the history of its moves alerts
+    pub fn seed(&mut self, symbol: &str, time_ms: i64, price: Decimal) {
+        for state in &mut self.states {
+            if let AlertCondition::Moves { symbol: s, .. } = &state.alert.condition {
+                if s == symbol {
+                    state.history.push_back((time_ms, price));
+                }
+            }
+        }
+    }
+
+    /// Observe the price of symbol and return the alerts that fired
+    pub fn observe_price(&mut self, symbol: &str, time_ms: i64, price: Decimal) -> Vec<AlertEvent> {
+        let mut events = vec![];
+        for state in &mut self.states {
+            if state.alert.symbol() != Some(symbol) {
+                continue;
+            }
+            let message = match &state.alert.condition {
+                AlertCondition::Crosses { price: level, .. } => {
+                    let crossed = match state.last_price {
+                        Some(last) if last < *level && price >= *level => Some("above"),
+                        Some(last) if last > *level && price <= *level => Some("below"),
+                        _ => None,
+                    };
+                    crossed.map(|dir| format!("{symbol} crossed {dir} {level} at {price}"))
+                }
+                AlertCondition::Above { price: level, .. } => {
+                    edge(&mut state.active, price > *level)
+                        .then(|| format!("{symbol} is above {level} at {price}"))
+                }
+                AlertCondition::Below { price: level, .. } => {
+                    edge(&mut state.active, price < *level)
+                        .then(|| format!("{symbol} is below {level} at {price}"))
+                }
+                AlertCondition::Moves {
+                    percent,
+                    direction,
+                    window_ms,
+                    ..
+                } => {
+                    let history = &mut state.history;
+                    history.push_back((time_ms, price));
+
+                    // Keep the latest price at or before the start of the window
+                    let start = time_ms - window_ms;
+                    while history.len() > 1 && history[1].0 <= start {
+                        history.pop_front();
+                    }
+
+                    let (ref_time, ref_price) = history[0];
+                    let change = if ref_price > dec!(0) {
+                        (price - ref_price) / ref_price * dec!(100)
+                    } else {
+                        dec!(0)
+                    };
+                    let fired = match direction {
+                        MoveDirection::Up => change >= *percent,
+                        MoveDirection::Down => -change >= *percent,
+                        MoveDirection::Either => change.abs() >= *percent,
+                    };
+                    if fired {
+                        // Start a new window from this price
+                        history.clear();
+                        history.push_back((time_ms, price));
+                        let sign = if change > dec!(0) { "+" } else { "" };
+                        Some(format!(
+                            "{symbol} moved {sign}{}% from {ref_price} at {} to {price}",
+                            change.round_dp(2),
+                            time_ms_to_utc(ref_time).format("%Y-%m-%d %H:%M")
+                        ))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+            state.last_price = Some(price);
+
+            if let Some(message) = message {
+                events.push(AlertEvent {
+                    alert: state.alert.text.clone(),
+                    time: time_ms,
+                    message,
+                    value: price,
+                });
+            }
+        }
+
+        events
+    }
+
+    /// Observe the account value in USD and return the alerts that fired
+    pub fn observe_account_value(&mut self, time_ms: i64, value: Decimal) -> Vec<AlertEvent> {
+        let mut events = vec![];
+        for state in &mut self.states {
+            let message = match &state.alert.condition {
+                AlertCondition::AccountValueBelow(level) => edge(&mut state.active, value < *level)
+                    .then(|| {
+                        format!(
+                            "account value {} is below {}",
+                            dec_to_usd_string(value),
+                            dec_to_usd_string(*level)
+                        )
+                    }),
+                AlertCondition::AccountValueAbove(level) => edge(&mut state.active, value > *level)
+                    .then(|| {
+                        format!(
+                            "account value {} is above {}",
+                            dec_to_usd_string(value),
+                            dec_to_usd_string(*level)
+                        )
+                    }),
+                _ => None,
+            };
+
+            if let Some(message) = message {
+                events.push(AlertEvent {
+                    alert: state.alert.text.clone(),
+                    time: time_ms,
+                    message,
+                    value,
+                });
+            }
+        }
+
+        events
+    }
+}
+
+/// Update active with condition, returns true if it just became true
+fn edge(active: &mut bool, condition: bool) -> bool {
+    let fired = condition && !*active;
+    *active = condition;
+    fired
+}
+
+/// The value in USD of the account
+async fn account_value(config: &Configuration) -> Result<Decimal, Box<dyn std::error::Error>> {
+    let time_ms = utc_now_to_time_ms();
+    let mut ai = get_account_info(config, time_ms).await?;
+    Ok(ai.update_values_in_usd(config, false, time_ms).await)
+}
+
+/// Check the prices and account value once
+async fn check_alerts(
+    config: &Configuration,
+    engine: &mut AlertEngine,
+) -> Result<Vec<AlertEvent>, Box<dyn std::error::Error>> {
+    let time_ms = utc_now_to_time_ms();
+    let mut events = vec![];
+
+    let symbols = engine.symbols();
+    if !symbols.is_empty() {
+        let prices = prices_map(&get_ticker_prices(config, None).await?);
+        for symbol in &symbols {
+            match prices.get(symbol) {
+                Some(price) => events.extend(engine.observe_price(symbol, time_ms, *price)),
+                None => println!("No price for {symbol}"),
+            }
+        }
+    }
+
+    if engine.needs_account_value() {
+        let value = account_value(config).await?;
+        events.extend(engine.observe_account_value(time_ms, value));
+    }
+
+    Ok(events)
+}
+
+pub async fn alerts_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let alert_strs: Vec<String> = match sc_matches.values_of("ALERTS") {
+        Some(values) => values.map(|s| s.to_string()).collect(),
+        None => config.alerts.clone(),
+    };
+    if alert_strs.is_empty() {
+        return Err("No alerts, add them to the config file alerts or the command line".into());
+    }
+    let mut alerts = alert_strs
+        .iter()
+        .map(|s| Alert::from_string(s))
+        .collect::<Result<Vec<Alert>, _>>()?;
+
+    let notifier_strs: Vec<String> = match sc_matches.values_of("NOTIFY") {
+        Some(values) => values.map(|s| s.to_string()).collect(),
+        None if !config.alert_notifiers.is_empty() => config.alert_notifiers.clone(),
+        None => vec!["stdout".to_string()],
+    };
+    let notifiers = notifiers_from_strings(&notifier_strs)?;
+
+    let interval_secs = match sc_matches.value_of("INTERVAL") {
+        Some(s) => s.parse::<u64>()?,
+        None => config.alert_interval_secs,
+    };
+
+    if alerts.iter().any(|a| a.symbol().is_some()) {
+        let ei = get_exchange_info(config).await?;
+        for alert in &mut alerts {
+            alert.resolve_symbol(&ei, &config.default_quote_asset)?;
+        }
+    }
+    for alert in &alerts {
+        println!("Alert: {}", alert.text);
+    }
+
+    let mut engine = AlertEngine::new(alerts);
+
+    // Seed moves alerts with the price at the start of their window
+    let now = utc_now_to_time_ms();
+    for (symbol, window_ms) in engine.move_windows() {
+        let kr = get_kline(config, &symbol, now - window_ms).await?;
+        engine.seed(&symbol, kr.open_time, kr.open);
+    }
+
+    if sc_matches.is_present("ONCE") {
+        let events = check_alerts(config, &mut engine).await?;
+        notify_all(config, &notifiers, &events).await;
+        return Ok(());
+    }
+
+    let interval = Duration::from_secs(interval_secs);
+    if !sc_matches.is_present("STREAM") {
+        loop {
+            match check_alerts(config, &mut engine).await {
+                Ok(events) => notify_all(config, &notifiers, &events).await,
+                Err(e) => println!("Checking alerts failed: {e}"),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    // Prices come from the stream's handler, which is sync, so the
+    // notifications and account values are handled alongside it
+    let (events_tx, mut events_rx) = unbounded_channel::<Vec<AlertEvent>>();
+    let (account_value_tx, mut account_value_rx) = unbounded_channel::<(i64, Decimal)>();
+    let account_value_alerts = engine.needs_account_value();
+    let notify = async {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick(), if account_value_alerts => {
+                    match account_value(config).await {
+                        Ok(value) => {
+                            let _ = account_value_tx.send((utc_now_to_time_ms(), value));
+                        }
+                        Err(e) => println!("Getting the account value failed: {e}"),
+                    }
+                }
+                events = events_rx.recv() => match events {
+                    Some(events) => notify_all(config, &notifiers, &events).await,
+                    None => break,
+                },
+            }
+        }
+    };
+
+    let streams: Vec<String> = engine
+        .symbols()
+        .iter()
+        .map(|s| stream_name(s, &StreamKind::MiniTicker))
+        .collect();
+    let mut client = MarketStreamClient::new(&config.make_ws_url(""), &streams);
+    let stream = client.run(|_stream, event| {
+        // Account values are observed as the next price arrives
+        let mut events = vec![];
+        while let Ok((time_ms, value)) = account_value_rx.try_recv() {
+            events.extend(engine.observe_account_value(time_ms, value));
+        }
+        if let StreamEvent::MiniTicker(e) = event {
+            events.extend(engine.observe_price(&e.symbol, e.event_time, e.close));
+        }
+        events.is_empty() || events_tx.send(events).is_ok()
+    });
+
+    tokio::select! {
+        result = stream => result,
+        _ = notify => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{notifications::Notifier, test_utils::TempDir};
+
+    const MIN: i64 = 60 * 1000;
+
+    #[test]
+    fn test_alert_from_string() {
+        let alert = Alert::from_string("BTCUSD crosses 30,000").unwrap();
+        assert_eq!(
+            alert.condition,
+            AlertCondition::Crosses {
+                symbol: "BTCUSD".to_string(),
+                price: dec!(30000)
+            }
+        );
+
+        let alert = Alert::from_string("eth moves ±5% in 1h").unwrap();
+        assert_eq!(
+            alert.condition,
+            AlertCondition::Moves {
+                symbol: "ETH".to_string(),
+                percent: dec!(5),
+                direction: MoveDirection::Either,
+                window_ms: 60 * MIN,
+            }
+        );
+        let alert = Alert::from_string("ETHUSD moves -2.5% in 30m").unwrap();
+        assert!(matches!(
+            alert.condition,
+            AlertCondition::Moves { direction: MoveDirection::Down, percent, window_ms, .. }
+                if percent == dec!(2.5) && window_ms == 30 * MIN
+        ));
+
+        let alert = Alert::from_string("Account value below $10,000").unwrap();
+        assert_eq!(
+            alert.condition,
+            AlertCondition::AccountValueBelow(dec!(10000))
+        );
+        assert_eq!(alert.symbol(), None);
+
+        assert!(Alert::from_string("BTCUSD crosses").is_err());
+        assert!(Alert::from_string("BTCUSD crosses abc").is_err());
+        assert!(Alert::from_string("ETH moves 5% in 1y").is_err());
+    }
+
+    #[test]
+    fn test_crosses_and_above() {
+        let mut engine = AlertEngine::new(vec![
+            Alert::from_string("BTCUSD crosses 30000").unwrap(),
+            Alert::from_string("BTCUSD above 30000").unwrap(),
+        ]);
+        assert_eq!(engine.symbols(), vec!["BTCUSD".to_string()]);
+
+        // The first price establishes the side for crosses
+        let events = engine.observe_price("BTCUSD", 1, dec!(29000));
+        assert!(events.is_empty());
+        let events = engine.observe_price("BTCUSD", 2, dec!(30100));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].message, "BTCUSD crossed above 30000 at 30100");
+
+        // Above doesn't fire again until it is below
+        assert!(engine.observe_price("BTCUSD", 3, dec!(30200)).is_empty());
+        let events = engine.observe_price("BTCUSD", 4, dec!(29900));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].message, "BTCUSD crossed below 30000 at 29900");
+        assert_eq!(engine.observe_price("BTCUSD", 5, dec!(30001)).len(), 2);
+
+        // Other symbols are ignored
+        assert!(engine.observe_price("ETHUSD", 6, dec!(1)).is_empty());
+    }
+
+    #[test]
+    fn test_moves() {
+        let mut engine =
+            AlertEngine::new(vec![Alert::from_string("ETHUSD moves 5% in 1h").unwrap()]);
+        assert_eq!(
+            engine.move_windows(),
+            vec![("ETHUSD".to_string(), 60 * MIN)]
+        );
+        engine.seed("ETHUSD", 0, dec!(1000));
+
+        assert!(engine
+            .observe_price("ETHUSD", 30 * MIN, dec!(1040))
+            .is_empty());
+        let events = engine.observe_price("ETHUSD", 40 * MIN, dec!(949));
+        assert_eq!(events.len(), 1);
+        assert!(events[0].message.starts_with("ETHUSD moved -5.1"));
+
+        // The window restarts at the price it fired at
+        assert!(engine
+            .observe_price("ETHUSD", 50 * MIN, dec!(990))
+            .is_empty());
+
+        // Prices older than the window are dropped
+        assert!(engine
+            .observe_price("ETHUSD", 115 * MIN, dec!(1000))
+            .is_empty());
+        assert!(engine
+            .observe_price("ETHUSD", 170 * MIN, dec!(1035))
+            .is_empty());
+
+        // 1045 is 5.6% above 990 but 990 is out of the window so 1000 is the reference
+        assert!(engine
+            .observe_price("ETHUSD", 176 * MIN, dec!(1045))
+            .is_empty());
+        assert_eq!(
+            engine.observe_price("ETHUSD", 177 * MIN, dec!(1050)).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_account_value() {
+        let mut engine = AlertEngine::new(vec![
+            Alert::from_string("account value below 10000").unwrap()
+        ]);
+        assert!(engine.needs_account_value());
+        assert!(engine.symbols().is_empty());
+
+        assert!(engine.observe_account_value(1, dec!(10500)).is_empty());
+        let events = engine.observe_account_value(2, dec!(9500));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].value, dec!(9500));
+        assert!(engine.observe_account_value(3, dec!(9000)).is_empty());
+        assert!(engine.observe_account_value(4, dec!(11000)).is_empty());
+        assert_eq!(engine.observe_account_value(5, dec!(9999)).len(), 1);
+    }
+
+    fn test_event() -> AlertEvent {
+        AlertEvent {
+            alert: "BTCUSD crosses 30000".to_string(),
+            time: 1672531200000,
+            message: "BTCUSD crossed above 30000 at 30100".to_string(),
+            value: dec!(30100),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_and_command_notifiers() {
+        let dir = TempDir::new("alerts");
+        let file_path = dir.path().join("alerts.log");
+        let cmd_path = dir.path().join("cmd.out");
+
+        let notifiers = vec![
+            Notifier::File(file_path.clone()),
+            Notifier::Command(format!(
+                "echo \"$ALERT_VALUE $ALERT\" > {}",
+                cmd_path.display()
+            )),
+        ];
+        notify_all(
+            &Configuration::default(),
+            &notifiers,
+            &[test_event(), test_event()],
+        )
+        .await;
+
+        let lines = std::fs::read_to_string(&file_path).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+        assert!(lines.contains("ALERT BTCUSD crosses 30000: BTCUSD crossed above 30000 at 30100"));
+        let out = std::fs::read_to_string(&cmd_path).unwrap();
+        assert_eq!(out, "30100 BTCUSD crosses 30000\n");
+    }
+
+    #[test]
+    fn test_alert_event_notification() {
+        let event = test_event();
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["alert"], "BTCUSD crosses 30000");
+        assert_eq!(json["time"].as_i64(), Some(1672531200000));
+        assert_eq!(json["message"], "BTCUSD crossed above 30000 at 30100");
+        assert!(event
+            .env_vars()
+            .contains(&("ALERT_VALUE", "30100".to_string())));
+    }
+}
@@ -1,8 +1,13 @@
 // Based on https://stackoverflow.com/a/55134333/4812090
 use clap::{Arg, ArgMatches, Command};
 use std::error::Error;
+use time_ms_conversions::time_ms_to_utc;
 
-use crate::common::{APP_NAME, APP_VERSION};
+use crate::{
+    binance_history::DateTimeRange,
+    binance_order_response::TRADE_RESPONSE_KINDS,
+    common::{time_str_to_time_ms, APP_NAME, APP_VERSION},
+};
 
 pub fn time_offset_days_to_time_ms_offset(
     sc_matches: &ArgMatches,
@@ -24,6 +29,21 @@ pub fn time_offset_days_to_time_ms_offset(
     Ok(time_ms_offset)
 }
 
+/// Get the START and END options, a date alone is the beginning of
+/// the day for START and the end of the day for END.
+pub fn start_end_date_times(sc_matches: &ArgMatches) -> Result<DateTimeRange, Box<dyn Error>> {
+    let start = match sc_matches.value_of("START") {
+        Some(s) => Some(time_ms_to_utc(time_str_to_time_ms(s, false)?)),
+        None => None,
+    };
+    let end = match sc_matches.value_of("END") {
+        Some(s) => Some(time_ms_to_utc(time_str_to_time_ms(s, true)?)),
+        None => None,
+    };
+
+    Ok((start, end))
+}
+
 pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
     // The config option is the only option that has a default_value,
     // all others get their defaults from the Configuration.
@@ -136,7 +156,44 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
         .global(true)
         .long("withdraw-addr")
         .value_name("ADDR")
-        .help("Default destination address for the withdraw command")
+        .help("Default destination address or withdraw_addresses label for the withdraw command")
+        .takes_value(true);
+    let max_slippage_percent_arg = Arg::new("max-slippage-percent")
+        .global(true)
+        .long("max-slippage-percent")
+        .value_name("PERCENT")
+        .help("Maximum percent a market order's estimated fill price may be worse than the average price")
+        .takes_value(true);
+    let slippage_action_arg = Arg::new("slippage-action")
+        .global(true)
+        .long("slippage-action")
+        .value_name("ACTION")
+        .help(
+            "When max-slippage-percent is exceeded either block the order or split it into chunks",
+        )
+        .takes_value(true);
+    let paper_trading_arg = Arg::new("paper-trading")
+        .global(true)
+        .long("paper-trading")
+        .help("Enable paper trading, orders are simulated using a local account");
+    let no_paper_trading_arg = Arg::new("no-paper-trading")
+        .global(true)
+        .long("no-paper-trading")
+        .help("Disable paper trading");
+
+    // Date range of the history subcommands
+    let start_arg = Arg::new("START")
+        .short('s')
+        .long("start")
+        .value_name("TIME")
+        .help("Start of the history, YYYY-MM-DD or YYYY-MM-DDTHR:MIN:SEC{TZ}, long ranges are split into 90 day requests")
+        .takes_value(true);
+    let end_arg = Arg::new("END")
+        .short('e')
+        .long("end")
+        .value_name("TIME")
+        .help("End of the history, YYYY-MM-DD is the end of that day, the default is now")
+        .requires("START")
         .takes_value(true);
 
     let matches = Command::new(APP_NAME.as_str())
@@ -159,18 +216,60 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
         .arg(scheme_arg)
         .arg(domain_arg)
         .arg(withdraw_addr_arg.clone())
+        .arg(max_slippage_percent_arg)
+        .arg(slippage_action_arg)
+        .arg(paper_trading_arg)
+        .arg(no_paper_trading_arg)
         .subcommand(
             Command::new("ai")
                 .display_order(1)
-                .about("Display the account info")
-                // Turns out this isn't the time you'd like for information
-                // but related to the receive window :()
-                //.arg(
-                //    Arg::new("TIME")
-                //        .help("Time in form of YYYY-MM-DD H:M:S")
-                //        .required(false)
-                //        .index(1),
-                //),
+                .about("Display the account info, or the holdings at a past TIME")
+                .arg(
+                    Arg::new("TIME")
+                        .help("Reconstruct the holdings at TIME from the history and value them with klines. A date alone, YYYY-MM-DD, is the end of that day UTC, otherwise YYYY-MM-DDTHR:MIN:SEC{TZ} as in skrs --start_time")
+                        .required(false)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("TT_FILES")
+                        .short('t')
+                        .long("tt-files")
+                        .value_name("FILE")
+                        .help("TokenTax files, such as from ttffbudf, replayed instead of the account history")
+                        .requires("TIME")
+                        .multiple_values(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("SYMBOLS")
+                        .short('s')
+                        .long("symbols")
+                        .value_name("SYMBOL")
+                        .help("Symbols whose trades are replayed, the default is the symbols between held, deposited and withdrawn assets")
+                        .requires("TIME")
+                        .conflicts_with("TT_FILES")
+                        .multiple_values(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            Command::new("snapshot")
+                .display_order(1)
+                .about("Append the balances, prices and total value of the account to the snapshot_path history"),
+        )
+        .subcommand(
+            Command::new("performance")
+                .display_order(1)
+                .about("Display the account value history and returns net of deposits and withdrawals from the snapshots")
+                .arg(
+                    Arg::new("LIMIT")
+                        .short('l')
+                        .long("limit")
+                        .value_name("LIMIT")
+                        .default_value("10")
+                        .help("Number of the most recent snapshots displayed")
+                        .takes_value(true),
+                ),
         )
         .subcommand(
             Command::new("auto-buy")
@@ -182,6 +281,11 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
                 .display_order(2)
                 .about("Automatically sell assets as defined in the configuration keep section"),
         )
+        .subcommand(
+            Command::new("eval-sell-rules")
+                .display_order(2)
+                .about("Evaluate the configuration sell_rules section and sell assets whose rules fire"),
+        )
         .subcommand(
             Command::new("buy-market-value")
                 .display_order(5)
@@ -253,7 +357,7 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
         .subcommand(
             Command::new("withdraw")
                 .display_order(5)
-                .about("Withdraw an asset, either quantity, dollars or precent.\nExamples:\n  withdraw ETH '$1000' --withdraw-addr 1543abcd --keep-min \\$200\n  withdraw ETH 100% --withdraw-addr 1543abcd --keep-min '$200'\n  withdraw ETH 100 --withdraw-addr 1543abcd\nNOTE 1: withdraw-addr maybe placed in config.toml as withdraw_addr = \"1543abcd\"\nNOTE 2: Dollar values must be written\n in single quotes '$123' or with a backslash \\$1234")
+                .about("Withdraw an asset, either quantity, dollars or precent.\nExamples:\n  withdraw ETH '$1000' --withdraw-addr ledger --keep-min \\$200\n  withdraw ETH 100% --withdraw-addr ledger --keep-min '$200'\n  withdraw ETH 100\n  withdraw ETH 0.5 --network ETH --wait\nNOTE 1: Only addresses in withdraw_addresses of config.toml are allowed,\n withdraw-addr is the label or address of an entry, it's optional if\n the asset has one entry. Use --allow-unlisted-addr for other addresses\nNOTE 2: The network's fee and the net received are shown before confirming\nNOTE 3: The daily limits, cooling-off and kill switch of config.toml apply\nNOTE 4: Dollar values must be written\n in single quotes '$123' or with a backslash \\$1234")
                 .arg(
                     Arg::new("SYMBOL")
                         .help("Name of asset")
@@ -290,6 +394,202 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
                         .value_name("DEST_LABEL")
                         .help("A label identifying a destination address")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::new("allow-unlisted-addr")
+                        .global(false)
+                        .long("allow-unlisted-addr")
+                        .help("Allow a withdraw-addr that isn't in withdraw_addresses, it's still validated")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new("network")
+                        .global(false)
+                        .long("network")
+                        .value_name("NETWORK")
+                        .help("Network of the withdrawal, e.g. ETH or BSC, the default is the withdraw_addresses\nentry's network or the asset's default network")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("wait")
+                        .global(false)
+                        .long("wait")
+                        .help("Wait until the withdrawal is completed, rejected or failed and display its tx id")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            Command::new("dust")
+                .display_order(5)
+                .about("Convert dust, assets whose free balance is too small to sell, to BNB")
+                .arg(
+                    Arg::new("ASSETS")
+                        .help("Names of assets to convert, all dust if absent")
+                        .required(false)
+                        .multiple_values(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            Command::new("bt")
+                .display_order(5)
+                .about("Backtest auto-buy or auto-sell over kline csv files, named SYMBOL-*.csv, using paper_initial_balances")
+                .arg(
+                    Arg::new("IN_FILES")
+                        .global(false)
+                        .required(true)
+                        .long("files")
+                        .short('f')
+                        .multiple_values(true)
+                        .help("List of kline csv files")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("STRATEGY")
+                        .global(false)
+                        .required(true)
+                        .long("strategy")
+                        .short('s')
+                        .value_name("STRATEGY")
+                        .possible_values(["auto-buy", "auto-sell"])
+                        .help("Strategy to backtest")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("EVERY")
+                        .global(false)
+                        .required(false)
+                        .long("every")
+                        .value_name("KLINES")
+                        .default_value("1")
+                        .help("Run the strategy every KLINES klines")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("OUT_FILE")
+                        .global(false)
+                        .required(false)
+                        .long("out-file")
+                        .short('o')
+                        .help("Equity curve csv output file")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            Command::new("depth")
+                .display_order(10)
+                .about("Display a symbols order book spread, depth near the mid price and volume weighted price for a quantity")
+                .arg(
+                    Arg::new("SYMBOL")
+                        .help("Name of symbol")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("LIMIT")
+                        .short('l')
+                        .long("limit")
+                        .value_name("LIMIT")
+                        .possible_values(["5", "10", "20", "50", "100", "500", "1000", "5000"])
+                        .default_value("100")
+                        .help("Number of order book levels to get")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("PERCENT")
+                        .short('p')
+                        .long("percent")
+                        .value_name("PERCENT")
+                        .default_value("1")
+                        .help("Cumulative depth of levels within PERCENT of the mid price")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("QUANTITY")
+                        .short('q')
+                        .long("qty")
+                        .value_name("QUANTITY")
+                        .help("Display the volume weighted price to buy and sell QUANTITY")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            Command::new("alerts")
+                .display_order(10)
+                .about("Check price and account value alerts until interrupted, notifying when they fire")
+                .arg(
+                    Arg::new("ALERTS")
+                        .help("Alerts such as \"BTCUSD crosses 30000\" or \"ETH moves 5% in 1h\", if absent the config file alerts")
+                        .required(false)
+                        .multiple_values(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("NOTIFY")
+                        .short('n')
+                        .long("notify")
+                        .value_name("NOTIFIER")
+                        .help("Notifier, stdout, command:CMD, file:PATH or webhook:URL, may be repeated")
+                        .multiple_occurrences(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("INTERVAL")
+                        .short('i')
+                        .long("interval")
+                        .value_name("SECS")
+                        .help("Seconds between checks, the default is alert_interval_secs")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("STREAM")
+                        .short('s')
+                        .long("stream")
+                        .help("Check prices as they arrive from the WebSocket stream")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new("ONCE")
+                        .long("once")
+                        .help("Check the alerts once and exit")
+                        .conflicts_with("STREAM")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            Command::new("uds")
+                .display_order(10)
+                .about("Stream order updates and balances, appending fills to the order log, until interrupted"),
+        )
+        .subcommand(
+            Command::new("watch")
+                .display_order(10)
+                .about("Stream live prices of symbols, or of the assets in the account, until interrupted")
+                .arg(
+                    Arg::new("SYMBOLS")
+                        .help("Names of symbols, if absent the account assets in the default quote asset")
+                        .required(false)
+                        .multiple_values(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("STREAM")
+                        .short('s')
+                        .long("stream")
+                        .value_name("STREAM")
+                        .possible_values(["trade", "kline", "book", "mini"])
+                        .default_value("mini")
+                        .help("Stream to watch, trades, klines, best bid and ask or the 24hr mini ticker")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("INTERVAL")
+                        .short('i')
+                        .long("interval")
+                        .value_name("INTERVAL")
+                        .default_value("1m")
+                        .help("Kline interval of the kline stream, one of: 1m 3m 5m 15m 30m 1h 2h 4h 6h 8h 12h 1d 3d 1w 1M")
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -370,6 +670,168 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
                         .value_name("INTERVAL")
                         .help("Kline interval, one of: 1m 3m 5m 15m 30m 1h 2h 4h 6h 8h 12h 1d 3d 1w 1M")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::new("CHART")
+                        .short('c')
+                        .long("chart")
+                        .help("Display a candlestick chart with a volume histogram scaled to the terminal width, if LIMIT is absent the chart is filled")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new("MA")
+                        .long("ma")
+                        .value_name("PERIOD")
+                        .requires("CHART")
+                        .help("Overlay a simple moving average of PERIOD klines on the chart")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            Command::new("ind")
+                .display_order(10)
+                .about("Display or export indicators of a symbols kline records or a kline csv file")
+                .arg(
+                    Arg::new("SYMBOL")
+                        .help("Name of asset, not needed when --file is used")
+                        .required_unless_present("IN_FILE")
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("INDICATORS")
+                        .short('n')
+                        .long("indicators")
+                        .value_name("INDICATOR")
+                        .multiple_values(true)
+                        .required(true)
+                        .help("Indicators with optional parameters separated by ':', one of: sma:20 ema:20 rsi:14 macd:12:26:9 bb:20:2 atr:14 vwap")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("START-TIME")
+                        .short('s')
+                        .long("start_time")
+                        .value_name("START-TIME")
+                        .help("Define the starting time, see skrs for the format")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("LIMIT")
+                        .short('l')
+                        .long("limit")
+                        .value_name("LIMIT")
+                        .help("Number of kline records to get, value between 1 and 1000")
+                        .default_value("100")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("INTERVAL")
+                        .short('i')
+                        .long("interval")
+                        .value_name("INTERVAL")
+                        .help("Kline interval, one of: 1m 3m 5m 15m 30m 1h 2h 4h 6h 8h 12h 1d 3d 1w 1M")
+                        .default_value("1h")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("IN_FILE")
+                        .long("file")
+                        .short('f')
+                        .value_name("FILE")
+                        .help("Kline csv file to use instead of getting klines from the exchange")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("OUT_FILE")
+                        .long("out-file")
+                        .short('o')
+                        .help("Indicators csv output file")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            Command::new("trades")
+                .display_order(10)
+                .about("Display a symbols recent trades")
+                .arg(
+                    Arg::new("SYMBOL")
+                        .help("Name of symbol")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("LIMIT")
+                        .short('l')
+                        .long("limit")
+                        .value_name("LIMIT")
+                        .default_value("20")
+                        .help("Number of trades to get, value between 1 and 1000")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("AGG")
+                        .short('a')
+                        .long("agg")
+                        .help("Display aggregate trades")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            Command::new("t24")
+                .display_order(10)
+                .about("Display 24hr ticker statistics of a symbol or all symbols")
+                .arg(
+                    Arg::new("SYMBOL")
+                        .help("Name of symbol, all symbols if absent")
+                        .required(false)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("SORT")
+                        .long("sort")
+                        .value_name("SORT")
+                        .possible_values(["volume", "change"])
+                        .default_value("volume")
+                        .help("Sort by quote volume or price change percent, largest first")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("QUOTE_ASSET")
+                        .short('q')
+                        .long("quote")
+                        .value_name("ASSET")
+                        .help("Only symbols with this quote asset")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("LIMIT")
+                        .short('l')
+                        .long("limit")
+                        .value_name("LIMIT")
+                        .help("Number of symbols to display")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            Command::new("tb")
+                .display_order(10)
+                .about("Display the best bid and ask of a symbol or all symbols")
+                .arg(
+                    Arg::new("SYMBOL")
+                        .help("Name of symbol, all symbols if absent")
+                        .required(false)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            Command::new("tp")
+                .display_order(10)
+                .about("Display the latest price of a symbol or all symbols")
+                .arg(
+                    Arg::new("SYMBOL")
+                        .help("Name of symbol, all symbols if absent")
+                        .required(false)
+                        .index(1),
                 ),
         )
         .subcommand(
@@ -383,6 +845,25 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
                         .index(1),
                 ),
         )
+        .subcommand(
+            Command::new("sync")
+                .display_order(7)
+                .about("Save the trades and orders of traded symbols to sync_dir, fetching only those since the last sync")
+                .arg(
+                    Arg::new("SYMBOLS")
+                        .help("Names of symbols, if absent the symbols already synced and those between held, deposited and withdrawn assets")
+                        .required(false)
+                        .multiple_values(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("ALL")
+                        .long("all")
+                        .help("Check every symbol in the exchange info, slow but finds symbols no longer held")
+                        .conflicts_with("SYMBOLS")
+                        .takes_value(false),
+                ),
+        )
         .subcommand(
             Command::new("dh")
                 .display_order(7)
@@ -392,6 +873,15 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
                         .help("Name of asset or all assets if absent")
                         .required(false)
                         .index(1),
+                )
+                .arg(start_arg.clone())
+                .arg(end_arg.clone())
+                .arg(
+                    Arg::new("STATUS")
+                        .long("status")
+                        .value_name("STATUS")
+                        .help("Only deposits with STATUS, 0 pending, 6 credited but cannot withdraw, 1 success")
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -403,6 +893,15 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
                         .help("Name of asset or all assets if absent")
                         .required(false)
                         .index(1),
+                )
+                .arg(start_arg.clone())
+                .arg(end_arg.clone())
+                .arg(
+                    Arg::new("STATUS")
+                        .long("status")
+                        .value_name("STATUS")
+                        .help("Only withdrawals with STATUS, 0 email sent, 1 cancelled, 2 awaiting approval, 3 rejected, 4 processing, 5 failure, 6 completed")
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -414,6 +913,15 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
                         .help("Name of fiat currency or USD if absent")
                         .required(false)
                         .index(1),
+                )
+                .arg(start_arg.clone())
+                .arg(end_arg.clone())
+                .arg(
+                    Arg::new("STATUS")
+                        .long("status")
+                        .value_name("STATUS")
+                        .help("Only deposits whose order status is STATUS, e.g. Successful or Processing")
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -425,17 +933,51 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
                         .help("Name of fiat currency or USD if absent")
                         .required(false)
                         .index(1),
+                )
+                .arg(start_arg.clone())
+                .arg(end_arg.clone())
+                .arg(
+                    Arg::new("STATUS")
+                        .long("status")
+                        .value_name("STATUS")
+                        .help("Only withdrawals whose order status is STATUS, e.g. Successful or Processing")
+                        .takes_value(true),
                 ),
         )
         .subcommand(
             Command::new("mt")
                 .display_order(8)
-                .about("Display my trades for a symbol")
+                .about("Display a summary of my trades with realized and unrealized P&L for symbols")
                 .arg(
-                    Arg::new("SYMBOL")
-                        .help("Name of asset")
+                    Arg::new("SYMBOLS")
+                        .help("Names of symbols")
                         .required(true)
+                        .multiple_values(true)
                         .index(1),
+                )
+                .arg(
+                    Arg::new("LOT_METHOD")
+                        .long("lot-method")
+                        .value_name("METHOD")
+                        .help("Which buys a sell closes for the realized P&L")
+                        .possible_values(["fifo", "lifo", "average"])
+                        .default_value("fifo")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("FORMAT")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Output format")
+                        .possible_values(["table", "csv", "json"])
+                        .default_value("table")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("SYNCED")
+                        .long("synced")
+                        .help("Use the trades in the sync store, see sync, instead of requesting them")
+                        .takes_value(false),
                 ),
         )
         .subcommand(
@@ -469,12 +1011,80 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
         .subcommand(
             Command::new("ol")
                 .display_order(9)
-                .about("Display order log"),
+                .about("Query the order log, display the selected entries or export their fills as csv or json")
+                .arg(start_arg.clone().help("Only entries at or after TIME, YYYY-MM-DD or YYYY-MM-DDTHR:MIN:SEC{TZ}"))
+                .arg(end_arg.clone().help("Only entries at or before TIME, YYYY-MM-DD is the end of that day"))
+                .arg(
+                    Arg::new("SYMBOLS")
+                        .long("symbols")
+                        .value_name("SYMBOL")
+                        .help("Only entries of these symbols, or assets for withdrawals")
+                        .multiple_values(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("SIDE")
+                        .long("side")
+                        .value_name("SIDE")
+                        .help("Only orders of this side")
+                        .possible_values(["buy", "sell"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("KINDS")
+                        .long("kinds")
+                        .value_name("KIND")
+                        .help("Only entries of these response kinds")
+                        .possible_values(TRADE_RESPONSE_KINDS)
+                        .multiple_values(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("MODE")
+                        .long("mode")
+                        .value_name("MODE")
+                        .help("Only test or live entries")
+                        .possible_values(["all", "live", "test"])
+                        .default_value("all")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("FAILURES")
+                        .long("failures")
+                        .help("Only failures")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new("FORMAT")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("table displays the entries, csv and json export their fills")
+                        .possible_values(["table", "csv", "json"])
+                        .default_value("table")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("OUT_FILE")
+                        .long("out-file")
+                        .short('o')
+                        .value_name("FILE")
+                        .help("File the csv or json fills are written to, the default is stdout")
+                        .takes_value(true),
+                ),
             )
         .subcommand(
             Command::new("pol")
                 .display_order(9)
-                .about("process order log"),
+                .about("process order log")
+                .arg(
+                    Arg::new("OUT_FILE")
+                        .global(false)
+                        .required(false)
+                        .long("out-file")
+                        .short('o')
+                        .help("Token Tax output file for dust conversions in the order log")
+                        .takes_value(true),
+                ),
         )
         .subcommand(
             Command::new("ubudf")
@@ -575,6 +1185,85 @@ pub fn arg_matches() -> Result<ArgMatches, Box<dyn Error>> {
                 )
                 .arg(&time_offset_days_arg)
         )
+        .subcommand(
+            Command::new("ttffapi")
+                .display_order(9)
+                .about("Token Tax file from the api trade and transfer history")
+                .arg(
+                    Arg::new("OUT_FILE")
+                        .global(false)
+                        .required(true)
+                        .long("out-file")
+                        .short('o')
+                        .help("The output file")
+                        .takes_value(true),
+                )
+                .arg(start_arg.clone().required(true))
+                .arg(end_arg.clone())
+                .arg(
+                    Arg::new("SYMBOLS")
+                        .long("symbols")
+                        .value_name("SYMBOL")
+                        .help("Symbols whose trades are exported, the default is the symbols between held, deposited and withdrawn assets")
+                        .multiple_values(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("SYNCED")
+                        .long("synced")
+                        .help("Use the trades in the sync store, see sync, instead of requesting them")
+                        .takes_value(false),
+                )
+                .arg(&time_offset_days_arg)
+        )
+        .subcommand(
+            Command::new("reconcile")
+                .display_order(9)
+                .about("Reconcile binance.us distribution or binance.com trade history files with the api history")
+                .arg(
+                    Arg::new("IN_FILES")
+                        .global(false)
+                        .required(true)
+                        .long("files")
+                        .short('f')
+                        .multiple_values(true)
+                        .help("List of input files")
+                        .takes_value(true),
+                )
+                .arg(start_arg)
+                .arg(end_arg)
+                .arg(
+                    Arg::new("SYMBOLS")
+                        .long("symbols")
+                        .value_name("SYMBOL")
+                        .help("Symbols whose trades are compared, the default is the symbols between held, deposited and withdrawn assets")
+                        .multiple_values(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("SYNCED")
+                        .long("synced")
+                        .help("Use the trades in the sync store, see sync, instead of requesting them")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new("TIME_TOLERANCE")
+                        .long("time-tolerance")
+                        .value_name("SECONDS")
+                        .help("Movements without matching ids match if their times are within SECONDS")
+                        .default_value("60")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("AMOUNT_TOLERANCE")
+                        .long("amount-tolerance")
+                        .value_name("QUANTITY")
+                        .help("Quantities within QUANTITY are equal")
+                        .default_value("0.00000001")
+                        .takes_value(true),
+                )
+                .arg(&time_offset_days_arg)
+        )
         .subcommand(
             Command::new("pbcthf")
                 .display_order(9)
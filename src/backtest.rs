@@ -0,0 +1,526 @@
+//! Backtest the auto-buy and auto-sell logic over local kline CSV files.
+//!
+//! The kline files are either headerless, as downloaded from
+//! data.binance.vision, or have a header of the KlineRec field names.
+//! The symbol is the file name up to the first '-', for example
+//! `BTCUSD-1h-2022-01.csv` is `BTCUSD`. The simulated account starts
+//! with `paper_initial_balances` and orders are filled at the kline
+//! close using the same exchange filters the exchange would apply.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use clap::ArgMatches;
+use dec_utils::dec_to_usd_string;
+use log::trace;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use time_ms_conversions::time_ms_to_utc;
+
+use crate::{
+    binance_avg_price::AvgPrice,
+    binance_exchange_info::{get_exchange_info, Symbol},
+    binance_klines::KlineRec,
+    binance_paper_trading::PaperState,
+    binance_verify_order::{adj_quantity_verify_lot_size, verify_min_notional},
+    common::{create_buf_writer, verify_input_files_exist, InternalErrorRec, Side},
+    configuration::Configuration,
+    ier_new,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BacktestStrategy {
+    /// Buy using the configuration `buy` section, i.e. DCA
+    AutoBuy,
+
+    /// Sell using the configuration `keep` section
+    AutoSell,
+}
+
+impl BacktestStrategy {
+    pub fn from_string(s: &str) -> Result<BacktestStrategy, Box<dyn std::error::Error>> {
+        match s {
+            "auto-buy" => Ok(BacktestStrategy::AutoBuy),
+            "auto-sell" => Ok(BacktestStrategy::AutoSell),
+            _ => Err(format!("Unknown strategy, {s}, expecting: auto-buy or auto-sell").into()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BacktestResult {
+    /// Time and equity in the quote asset after each kline
+    pub equity_curve: Vec<(i64, Decimal)>,
+
+    /// Total commissions in the quote asset
+    pub fees: Decimal,
+
+    pub trades: usize,
+
+    /// Orders the exchange filters or balances would reject
+    pub rejected: usize,
+
+    pub max_drawdown_percent: Decimal,
+}
+
+/// Read klines from a csv with or without a header
+pub fn read_kline_csv<R: Read>(reader: R) -> Result<Vec<KlineRec>, Box<dyn std::error::Error>> {
+    let mut kline_reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(reader);
+
+    let mut klines = Vec::<KlineRec>::new();
+    for (rec_idx, result) in kline_reader.records().enumerate() {
+        let record = result?;
+
+        // Skip a header, the first field of a KlineRec is a number
+        if rec_idx == 0 && record.get(0).is_none_or(|f| f.parse::<i64>().is_err()) {
+            continue;
+        }
+        let kr: KlineRec = match record.deserialize(None) {
+            Ok(kr) => kr,
+            Err(e) => return Err(format!("record: {} Err: {}", rec_idx + 1, e).into()),
+        };
+        klines.push(kr);
+    }
+
+    Ok(klines)
+}
+
+/// The symbol is the file name up to the first '-'
+pub fn symbol_from_kline_file_name(path: &str) -> String {
+    let file_name = Path::new(path)
+        .file_stem()
+        .map_or("".to_string(), |s| s.to_string_lossy().to_string());
+    match file_name.split_once('-') {
+        Some((sym, _)) => sym.to_string(),
+        None => file_name,
+    }
+}
+
+/// The largest percentage decline from a peak
+pub fn max_drawdown_percent(equity_curve: &[(i64, Decimal)]) -> Decimal {
+    let mut peak = dec!(0);
+    let mut max_dd = dec!(0);
+    for (_, equity) in equity_curve {
+        if *equity > peak {
+            peak = *equity;
+        } else if peak > dec!(0) {
+            let dd = ((peak - equity) / peak) * dec!(100);
+            max_dd = max_dd.max(dd);
+        }
+    }
+
+    max_dd
+}
+
+struct Market<'a> {
+    symbol: &'a Symbol,
+    price: Option<Decimal>,
+}
+
+/// Verify qty passes the exchange filters and fill it
+fn backtest_order(
+    state: &mut PaperState,
+    result: &mut BacktestResult,
+    market: &Market,
+    side: Side,
+    qty: Decimal,
+    fee_percent: Decimal,
+) {
+    let price = if let Some(p) = market.price {
+        p
+    } else {
+        return;
+    };
+    let symbol = market.symbol;
+
+    let qty = adj_quantity_verify_lot_size(symbol, qty);
+    let avg_price = AvgPrice { mins: 5, price };
+    if qty <= dec!(0) || verify_min_notional(&avg_price, symbol, qty).is_err() {
+        trace!("backtest_order: {} rejected qty: {}", symbol.symbol, qty);
+        result.rejected += 1;
+        return;
+    }
+
+    match state.fill_market_order(
+        &symbol.base_asset,
+        &symbol.quote_asset,
+        side,
+        qty,
+        price,
+        fee_percent,
+    ) {
+        Ok(full) => {
+            for f in &full.fills {
+                result.fees += if f.commission_asset == symbol.quote_asset {
+                    f.commission
+                } else {
+                    f.commission * f.price
+                };
+            }
+            result.trades += 1;
+        }
+        Err(e) => {
+            trace!("backtest_order: {} rejected: {}", symbol.symbol, e);
+            result.rejected += 1;
+        }
+    }
+}
+
+fn free_balance(state: &PaperState, asset: &str) -> Decimal {
+    state
+        .balances
+        .iter()
+        .find(|b| b.asset == asset)
+        .map_or(dec!(0), |b| b.free)
+}
+
+fn run_strategy(
+    config: &Configuration,
+    strategy: BacktestStrategy,
+    state: &mut PaperState,
+    result: &mut BacktestResult,
+    markets: &[Market],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let quote_asset = config.default_quote_asset.as_str();
+    match strategy {
+        BacktestStrategy::AutoBuy => {
+            let buy_recs = if let Some(brs) = &config.buy {
+                brs
+            } else {
+                return Err(ier_new!(8, "Missing `buy` field in configuration").into());
+            };
+
+            // Like auto-buy the values are based on the free quote asset before buying
+            let quote_free = free_balance(state, quote_asset);
+            for market in markets {
+                if let Some(br) = buy_recs.get(&market.symbol.base_asset) {
+                    if let Some(price) = market.price {
+                        let buy_value = (br.percent / dec!(100)) * quote_free;
+                        let qty = buy_value / price;
+                        let fee_percent = config.paper_fee_percent;
+                        backtest_order(state, result, market, Side::BUY, qty, fee_percent);
+                    }
+                }
+            }
+        }
+        BacktestStrategy::AutoSell => {
+            let keep_recs = if let Some(krs) = &config.keep {
+                krs
+            } else {
+                return Err(ier_new!(8, "Missing `keep` field in configuration").into());
+            };
+
+            for market in markets {
+                let owned_qty = free_balance(state, &market.symbol.base_asset);
+                let keep_qty = match keep_recs.get(&market.symbol.base_asset) {
+                    Some(keeping) => keeping.min.min(owned_qty),
+                    None => dec!(0),
+                };
+                let sell_qty = owned_qty - keep_qty;
+                if sell_qty > dec!(0) {
+                    let fee_percent = config.paper_fee_percent;
+                    backtest_order(state, result, market, Side::SELL, sell_qty, fee_percent);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn equity(state: &PaperState, quote_asset: &str, markets: &[Market]) -> Decimal {
+    let mut total = dec!(0);
+    for b in &state.balances {
+        if b.asset == quote_asset {
+            total += b.free;
+        } else if let Some(m) = markets.iter().find(|m| m.symbol.base_asset == b.asset) {
+            total += b.free * m.price.unwrap_or(dec!(0));
+        }
+    }
+
+    total
+}
+
+/// Run strategy every `every` klines over klines_vec, where klines_vec[i]
+/// are the klines of symbols[i].
+pub fn run_backtest(
+    config: &Configuration,
+    strategy: BacktestStrategy,
+    every: usize,
+    symbols: &[&Symbol],
+    klines_vec: &[Vec<KlineRec>],
+) -> Result<BacktestResult, Box<dyn std::error::Error>> {
+    assert_eq!(symbols.len(), klines_vec.len());
+    if config.paper_initial_balances.is_empty() {
+        return Err("paper_initial_balances is empty, the backtest has nothing to trade".into());
+    }
+    for symbol in symbols {
+        if symbol.quote_asset != config.default_quote_asset {
+            return Err(format!(
+                "{} quote asset must be the default_quote_asset: {}",
+                symbol.symbol, config.default_quote_asset
+            )
+            .into());
+        }
+    }
+
+    // Merge the klines by open_time
+    let mut timeline = BTreeMap::<i64, Vec<(usize, Decimal)>>::new();
+    for (idx, klines) in klines_vec.iter().enumerate() {
+        for kr in klines {
+            timeline
+                .entry(kr.open_time)
+                .or_default()
+                .push((idx, kr.close));
+        }
+    }
+
+    let mut markets: Vec<Market> = symbols
+        .iter()
+        .map(|s| Market {
+            symbol: s,
+            price: None,
+        })
+        .collect();
+    let mut state = PaperState::new(config);
+    let mut result = BacktestResult::default();
+    for (step, (time, closes)) in timeline.iter().enumerate() {
+        for (idx, close) in closes {
+            markets[*idx].price = Some(*close);
+        }
+        if step % every.max(1) == 0 {
+            run_strategy(config, strategy, &mut state, &mut result, &markets)?;
+        }
+        let eq = equity(&state, &config.default_quote_asset, &markets);
+        result.equity_curve.push((*time, eq));
+    }
+    result.max_drawdown_percent = max_drawdown_percent(&result.equity_curve);
+
+    Ok(result)
+}
+
+pub async fn backtest_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let in_file_paths: Vec<&str> = sc_matches
+        .values_of("IN_FILES")
+        .expect("files option is missing")
+        .collect();
+    verify_input_files_exist(&in_file_paths)?;
+
+    let strategy = BacktestStrategy::from_string(
+        sc_matches
+            .value_of("STRATEGY")
+            .expect("strategy option is missing"),
+    )?;
+    let every = match sc_matches.value_of("EVERY").unwrap_or("1").parse::<usize>() {
+        Ok(e) if e > 0 => e,
+        _ => return Err("every must be a number > 0".into()),
+    };
+
+    let ei = get_exchange_info(config).await?;
+    let mut symbols = Vec::<&Symbol>::new();
+    let mut klines_vec = Vec::<Vec<KlineRec>>::new();
+    for f in in_file_paths {
+        let sym_name = symbol_from_kline_file_name(f);
+        let symbol = match ei.get_symbol(&sym_name) {
+            Some(s) => s,
+            None => return Err(format!("{sym_name} from {f} is not a valid symbol").into()),
+        };
+        let reader = BufReader::new(File::open(f)?);
+        let klines = read_kline_csv(reader)?;
+        println!("{f}: {} klines of {sym_name}", klines.len());
+
+        symbols.push(symbol);
+        klines_vec.push(klines);
+    }
+
+    let result = run_backtest(config, strategy, every, &symbols, &klines_vec)?;
+    let (first, last) = match (result.equity_curve.first(), result.equity_curve.last()) {
+        (Some(f), Some(l)) => (*f, *l),
+        _ => {
+            println!("No klines");
+            return Ok(());
+        }
+    };
+
+    if let Some(out_file) = sc_matches.value_of("OUT_FILE") {
+        let mut writer = csv::Writer::from_writer(create_buf_writer(out_file)?);
+        writer.write_record(["time", "equity"])?;
+        for (time, eq) in &result.equity_curve {
+            writer.write_record(&[time_ms_to_utc(*time).to_string(), eq.to_string()])?;
+        }
+        writer.flush()?;
+        println!("Equity curve written to {out_file}");
+    }
+
+    let return_percent = if first.1 > dec!(0) {
+        ((last.1 - first.1) / first.1) * dec!(100)
+    } else {
+        dec!(0)
+    };
+    println!();
+    println!(
+        "Backtest of {strategy:?} every {every} klines from {} to {}",
+        time_ms_to_utc(first.0),
+        time_ms_to_utc(last.0)
+    );
+    println!("  Start equity: {}", dec_to_usd_string(first.1.round_dp(2)));
+    println!(
+        "    End equity: {} {:.2}%",
+        dec_to_usd_string(last.1.round_dp(2)),
+        return_percent
+    );
+    println!("        Trades: {}", result.trades);
+    println!("      Rejected: {}", result.rejected);
+    println!(
+        "          Fees: {}",
+        dec_to_usd_string(result.fees.round_dp(2))
+    );
+    println!("  Max drawdown: {:.2}%", result.max_drawdown_percent);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::configuration::{BuyRec, PaperBalanceRec};
+    use std::collections::HashMap;
+
+    const KLINES_CSV_NO_HEADER: &str = "\
+1640995200000,100.0,110.0,90.0,100.0,10.0,1640998799999,1000.0,5,5.0,500.0,0
+1640998800000,100.0,110.0,90.0,50.0,10.0,1641002399999,1000.0,5,5.0,500.0,0
+1641002400000,50.0,110.0,40.0,100.0,10.0,1641005999999,1000.0,5,5.0,500.0,0
+";
+
+    const KLINES_CSV_HEADER: &str = "\
+open_time,open,high,low,close,volume,close_time,quote_asset_volume,number_of_trades,taker_buy_base_asset_volume,taker_buy_quote_asset_volume,ignore
+1640995200000,100.0,110.0,90.0,100.0,10.0,1640998799999,1000.0,5,5.0,500.0,0
+";
+
+    const SYMBOL_DATA: &str = r#"{
+        "symbol": "BTCUSD",
+        "baseAsset": "BTC",
+        "quoteAsset": "USD",
+        "baseAssetPrecision": 8,
+        "baseCommissionPrecision": 8,
+        "icebergAllowed": true,
+        "isMarginTradingAllowed": false,
+        "isSpotTradingAllowed": true,
+        "ocoAllowed": true,
+        "quoteAssetPrecision": 4,
+        "quoteCommissionPrecision": 2,
+        "quoteOrderQtyMarketAllowed": true,
+        "quotePrecision": 4,
+        "status": "TRADING",
+        "permissions": [
+            "SPOT"
+        ],
+        "orderTypes": [
+            "MARKET"
+        ],
+        "filters": [
+            {
+                "filterType": "LOT_SIZE",
+                "maxQty": "9000.00000000",
+                "minQty": "0.00000100",
+                "stepSize": "0.00000100"
+            },
+            {
+                "filterType": "MIN_NOTIONAL",
+                "applyToMarket": true,
+                "avgPriceMins": 5,
+                "minNotional": "10.0"
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_read_kline_csv() {
+        let klines = read_kline_csv(KLINES_CSV_NO_HEADER.as_bytes()).unwrap();
+        assert_eq!(klines.len(), 3);
+        assert_eq!(klines[0].open_time, 1640995200000);
+        assert_eq!(klines[1].close, dec!(50));
+        assert_eq!(klines[2].number_of_trades, 5);
+
+        let klines = read_kline_csv(KLINES_CSV_HEADER.as_bytes()).unwrap();
+        assert_eq!(klines.len(), 1);
+        assert_eq!(klines[0].close_time, 1640998799999);
+    }
+
+    #[test]
+    fn test_symbol_from_kline_file_name() {
+        assert_eq!(
+            symbol_from_kline_file_name("data/BTCUSD-1h-2022-01.csv"),
+            "BTCUSD"
+        );
+        assert_eq!(symbol_from_kline_file_name("ETHUSD.csv"), "ETHUSD");
+    }
+
+    #[test]
+    fn test_max_drawdown_percent() {
+        let curve = vec![
+            (1, dec!(100)),
+            (2, dec!(120)),
+            (3, dec!(90)),
+            (4, dec!(130)),
+            (5, dec!(117)),
+        ];
+        assert_eq!(max_drawdown_percent(&curve), dec!(25));
+        assert_eq!(max_drawdown_percent(&[]), dec!(0));
+    }
+
+    #[test]
+    fn test_run_backtest_auto_buy() {
+        let symbol: Symbol = serde_json::from_str(SYMBOL_DATA).unwrap();
+        let klines = read_kline_csv(KLINES_CSV_NO_HEADER.as_bytes()).unwrap();
+
+        let mut buy = HashMap::new();
+        buy.insert(
+            "BTC".to_string(),
+            BuyRec {
+                name: "BTC".to_string(),
+                percent: dec!(50),
+                quote_asset: "".to_string(),
+            },
+        );
+        let config = Configuration {
+            buy: Some(buy),
+            paper_fee_percent: dec!(0),
+            paper_initial_balances: vec![PaperBalanceRec {
+                asset: "USD".to_string(),
+                free: dec!(100),
+            }],
+            ..Default::default()
+        };
+
+        let result =
+            run_backtest(&config, BacktestStrategy::AutoBuy, 1, &[&symbol], &[klines]).unwrap();
+
+        // Buy $50 at 100, $25 at 50 and the last $12.50 at 100
+        assert_eq!(result.trades, 3);
+        assert_eq!(result.rejected, 0);
+        assert_eq!(result.fees, dec!(0));
+        assert_eq!(result.equity_curve.len(), 3);
+        assert_eq!(result.equity_curve[0].1, dec!(100));
+        assert_eq!(result.equity_curve[1].1, dec!(75));
+        assert_eq!(result.equity_curve[2].1, dec!(125));
+        assert_eq!(result.max_drawdown_percent, dec!(25));
+
+        // A minimum notional of $10 rejects the third buy of $4.69 when buying 75%
+        let mut config = config;
+        config.buy.as_mut().unwrap().get_mut("BTC").unwrap().percent = dec!(75);
+        let klines = read_kline_csv(KLINES_CSV_NO_HEADER.as_bytes()).unwrap();
+        let result =
+            run_backtest(&config, BacktestStrategy::AutoBuy, 1, &[&symbol], &[klines]).unwrap();
+        assert_eq!(result.trades, 2);
+        assert_eq!(result.rejected, 1);
+    }
+}
@@ -10,14 +10,19 @@ use std::{
 
 use crate::{
     binance_klines::get_kline_of_primary_asset_for_value_asset,
+    binance_paper_trading::paper_account_info,
     binance_signature::{append_signature, binance_signature, query_vec_u8},
+    binance_ticker::{get_ticker_prices, price_of_asset_in_value_asset, prices_map},
     common::{get_req_get_response, VALUE_ASSETS},
     de_string_or_number::de_string_or_number_to_i64,
     Configuration,
 };
 
 use dec_utils::{dec_to_separated_string, dec_to_usd_string};
-use time_ms_conversions::time_ms_to_utc;
+use time_ms_conversions::{time_ms_to_utc, utc_now_to_time_ms};
+
+/// Values at times within this of now use the current prices
+const CURRENT_PRICES_MAX_AGE_MS: i64 = 5 * 60 * 1000;
 
 // from: https://github.com/serde-rs/serde/issues/936#ref-issue-557235055
 // TODO: Maybe a process macro can be created that generates de_vec_xxx_to_hashmap?
@@ -92,11 +97,32 @@ impl AccountInfo {
         verbose: bool,
         time_ms: i64,
     ) -> Decimal {
+        // Current values use one request for the prices of all symbols,
+        // otherwise or if that fails a kline is requested for each asset.
+        let prices = if utc_now_to_time_ms() - time_ms < CURRENT_PRICES_MAX_AGE_MS {
+            match get_ticker_prices(config, None).await {
+                Ok(tps) => Some(prices_map(&tps)),
+                Err(e) => {
+                    trace!("update_values_in_usd: get_ticker_prices failed: {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         let mut total_value = dec!(0);
-        for mut balance in self.balances_map.values_mut() {
+        for balance in self.balances_map.values_mut() {
             // Print all assets with a free or locked balance
             if balance.free != dec!(0) || balance.locked != dec!(0) {
-                let price_in_usd = if balance.asset != "USD" {
+                let price = prices.as_ref().and_then(|pm| {
+                    price_of_asset_in_value_asset(pm, &balance.asset, &VALUE_ASSETS)
+                });
+                let price_in_usd = if balance.asset == "USD" {
+                    dec!(1)
+                } else if let Some(price) = price {
+                    price
+                } else {
                     let r = get_kline_of_primary_asset_for_value_asset(
                         config,
                         time_ms,
@@ -116,8 +142,6 @@ impl AccountInfo {
                         }
                         None => dec!(0),
                     }
-                } else {
-                    dec!(1)
                 };
                 balance.price_in_usd = price_in_usd;
                 balance.value_in_usd = price_in_usd * (balance.free + balance.locked);
@@ -185,12 +209,16 @@ impl AccountInfo {
     }
 }
 
-pub async fn get_account_info<'e>(
+pub async fn get_account_info(
     config: &Configuration,
     time_ms: i64,
 ) -> Result<AccountInfo, Box<dyn std::error::Error>> {
     trace!("get_account_info: +");
 
+    if config.paper_trading {
+        return paper_account_info(config);
+    }
+
     let api_key = config.keys.get_ak_or_err()?;
     let secret_key = &config.keys.get_sk_vec_u8_or_err()?;
 
@@ -210,7 +238,7 @@ pub async fn get_account_info<'e>(
     let query_string = String::from_utf8(query)?;
     trace!("query_string={}", &query_string);
 
-    let url = config.make_url("api", &format!("/api/v3/account?{}", &query_string));
+    let url = config.make_url("api", &format!("/api/v3/account?{}", query_string));
     trace!("get_account_info: url={}", url);
 
     let response = get_req_get_response(api_key, &url).await?;
@@ -11,6 +11,7 @@ use crate::{
     common::{are_you_sure_stdout_stdin, InternalErrorRec, Side},
     configuration::Configuration,
     ier_new,
+    notifications::{notify_trade_summary, TradeSummary},
 };
 
 use time_ms_conversions::{time_ms_to_utc, utc_now_to_time_ms};
@@ -123,8 +124,9 @@ pub async fn auto_buy(
     if !process_recs.is_empty() {
         if test || !config.confirmation_required || are_you_sure_stdout_stdin() {
             // Do the auto-buy
+            let mut summary = TradeSummary::new("auto-buy", test);
             for pr in process_recs {
-                match market_order(
+                let result = market_order(
                     config,
                     ei,
                     &pr.symbol_name,
@@ -132,8 +134,12 @@ pub async fn auto_buy(
                     Side::BUY,
                     config.test,
                 )
-                .await
-                {
+                .await;
+                match &result {
+                    Ok(tr) => summary.add(tr),
+                    Err(e) => summary.add_error(&pr.symbol_name, e.as_ref()),
+                }
+                match result {
                     Ok(tr) => match tr {
                         TradeResponse::SuccessTest(_) => {
                             println!(
@@ -174,6 +180,7 @@ pub async fn auto_buy(
                     Err(e) => println!("{:8} {:10}, {}", "SKIPPING", pr.symbol_name, e),
                 }
             }
+            notify_trade_summary(config, &summary).await;
         }
     } else {
         println!("\n ** NOTHING to buy **");
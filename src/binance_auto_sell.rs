@@ -15,6 +15,7 @@ use crate::{
     common::{are_you_sure_stdout_stdin, InternalErrorRec, Side},
     configuration::Configuration,
     ier_new,
+    notifications::{notify_trade_summary, TradeSummary},
 };
 
 use dec_utils::dec_to_usd_string;
@@ -170,6 +171,7 @@ pub async fn auto_sell(
             if test {
                 println!();
             }
+            let mut summary = TradeSummary::new("auto-sell", test);
             for kr in &vec_process_rec {
                 if kr.sell_qty > dec!(0) {
                     print!(
@@ -179,9 +181,14 @@ pub async fn auto_sell(
                     let _ = stdout().flush();
                     let order_type =
                         TradeOrderType::Market(MarketQuantityType::Quantity(kr.sell_qty));
-                    match market_order(config, ei, &kr.symbol_name, &order_type, Side::SELL, test)
-                        .await
-                    {
+                    let result =
+                        market_order(config, ei, &kr.symbol_name, &order_type, Side::SELL, test)
+                            .await;
+                    match &result {
+                        Ok(tr) => summary.add(tr),
+                        Err(e) => summary.add_error(&kr.symbol_name, e.as_ref()),
+                    }
+                    match result {
                         Ok(tr) => match tr {
                             TradeResponse::SuccessTest(_) => {
                                 println!(
@@ -228,6 +235,7 @@ pub async fn auto_sell(
                     }
                 }
             }
+            notify_trade_summary(config, &summary).await;
         } else {
             println!("\n ** Aborted **");
         }
@@ -13,7 +13,7 @@ pub struct AvgPrice {
     pub price: Decimal,
 }
 
-pub async fn get_avg_price<'e>(
+pub async fn get_avg_price(
     config: &Configuration,
     symbol: &str,
 ) -> Result<AvgPrice, Box<dyn std::error::Error>> {
@@ -0,0 +1,279 @@
+//! The per-coin network configuration of the capital api,
+//! withdraw fees, minimums and increments of each network.
+//!
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use rust_decimal::prelude::*;
+use time_ms_conversions::utc_now_to_time_ms;
+
+use crate::{
+    binance_signature::{append_signature, binance_signature, query_vec_u8},
+    common::{get_req_get_response, InternalErrorRec, ResponseErrorRec},
+    configuration::Configuration,
+    ier_new,
+};
+
+// Example network of a coin's networkList
+//{
+//    "network": "ETH",
+//    "coin": "ETH",
+//    "name": "Ethereum (ERC20)",
+//    "isDefault": true,
+//    "withdrawEnable": true,
+//    "withdrawDesc": "",
+//    "withdrawFee": "0.0016",
+//    "withdrawMin": "0.0098",
+//    "withdrawMax": "9999999",
+//    "withdrawIntegerMultiple": "0.00000001",
+//    ...
+//}
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkRec {
+    pub network: String,
+    pub coin: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub is_default: bool,
+    pub withdraw_enable: bool,
+    #[serde(default)]
+    pub withdraw_desc: String,
+    pub withdraw_fee: Decimal,
+    pub withdraw_min: Decimal,
+    pub withdraw_max: Decimal,
+    pub withdraw_integer_multiple: Decimal,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoinConfigRec {
+    pub coin: String,
+    pub network_list: Vec<NetworkRec>,
+}
+
+/// Get the configuration of all coins
+pub async fn get_all_coins_config(
+    config: &Configuration,
+) -> Result<Vec<CoinConfigRec>, Box<dyn std::error::Error>> {
+    let api_key = config.keys.get_ak_or_err()?;
+    let secret_key = &config.keys.get_sk_vec_u8_or_err()?;
+
+    let mut params: Vec<(&str, &str)> = vec![("recvWindow", "5000")];
+
+    let ts_string: String = format!("{}", utc_now_to_time_ms());
+    params.push(("timestamp", ts_string.as_str()));
+
+    let mut query = query_vec_u8(&params);
+
+    // Calculate the signature using sig_key and the data is qs and query as body
+    let signature = binance_signature(secret_key, &query, &[]);
+
+    // Append the signature to query
+    append_signature(&mut query, signature);
+
+    // Convert to a string
+    let query_string = String::from_utf8(query)?;
+    trace!("get_all_coins_config: query_string={}", &query_string);
+
+    let mut url = config.make_url("api", "/sapi/v1/capital/config/getall?");
+    url.push_str(&query_string);
+    trace!("get_all_coins_config: url={}", url);
+
+    let response = get_req_get_response(api_key, &url).await?;
+    let response_headers = response.headers().clone();
+    let response_status = response.status();
+    let response_body = response.text().await?;
+
+    if response_status == 200 {
+        let coins: Vec<CoinConfigRec> = serde_json::from_str(&response_body)?;
+        trace!("get_all_coins_config: coins.len()={}", coins.len());
+
+        Ok(coins)
+    } else {
+        let rer = ResponseErrorRec::new(
+            false,
+            response_status.as_u16(),
+            &query_string,
+            response_headers,
+            &response_body,
+        );
+        trace!("get_all_coins_config: ResponseErrRec={:#?}", &rer);
+
+        Err(ier_new!(8, &rer.to_string()).into())
+    }
+}
+
+/// Get the configuration of coin
+pub async fn get_coin_config(
+    config: &Configuration,
+    coin: &str,
+) -> Result<CoinConfigRec, Box<dyn std::error::Error>> {
+    match get_all_coins_config(config)
+        .await?
+        .into_iter()
+        .find(|c| c.coin == coin)
+    {
+        Some(c) => Ok(c),
+        None => Err(format!("No capital configuration for {coin}").into()),
+    }
+}
+
+/// The network of coin_config named network or, if None, the default
+/// network. It's an error if withdrawals are disabled on the network.
+pub fn select_network<'a>(
+    coin_config: &'a CoinConfigRec,
+    network: Option<&str>,
+) -> Result<&'a NetworkRec, Box<dyn std::error::Error>> {
+    let found = match network {
+        Some(n) => coin_config
+            .network_list
+            .iter()
+            .find(|nr| nr.network.eq_ignore_ascii_case(n)),
+        None => coin_config.network_list.iter().find(|nr| nr.is_default),
+    };
+    let nr = match found {
+        Some(nr) => nr,
+        None => {
+            let networks: Vec<&str> = coin_config
+                .network_list
+                .iter()
+                .map(|nr| nr.network.as_str())
+                .collect();
+            return Err(format!(
+                "{} has no {} network, choose one of: {}",
+                coin_config.coin,
+                network.unwrap_or("default"),
+                networks.join(", ")
+            )
+            .into());
+        }
+    };
+
+    if !nr.withdraw_enable {
+        return Err(format!(
+            "Withdrawals of {} on {} are disabled{}",
+            nr.coin,
+            nr.network,
+            if nr.withdraw_desc.is_empty() {
+                "".to_string()
+            } else {
+                format!(", {}", nr.withdraw_desc)
+            }
+        )
+        .into());
+    }
+
+    Ok(nr)
+}
+
+/// Round quantity down to the network's withdraw increment and verify
+/// it's within the network's withdraw minimum and maximum.
+pub fn adj_withdraw_quantity(
+    nr: &NetworkRec,
+    quantity: Decimal,
+) -> Result<Decimal, Box<dyn std::error::Error>> {
+    let multiple = nr.withdraw_integer_multiple;
+    let adj_quantity = if multiple > Decimal::ZERO {
+        (quantity / multiple).floor() * multiple
+    } else {
+        quantity
+    };
+    trace!(
+        "adj_withdraw_quantity: quantity={} adj_quantity={} multiple={}",
+        quantity,
+        adj_quantity,
+        multiple
+    );
+
+    if adj_quantity < nr.withdraw_min {
+        return Err(format!(
+            "Withdraw quantity {} {} is less than the {} minimum of {}",
+            adj_quantity, nr.coin, nr.network, nr.withdraw_min
+        )
+        .into());
+    }
+    if nr.withdraw_max > Decimal::ZERO && adj_quantity > nr.withdraw_max {
+        return Err(format!(
+            "Withdraw quantity {} {} is more than the {} maximum of {}",
+            adj_quantity, nr.coin, nr.network, nr.withdraw_max
+        )
+        .into());
+    }
+
+    Ok(adj_quantity.normalize())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const COIN_CONFIG: &str = r#"{
+        "coin": "ETH",
+        "name": "Ethereum",
+        "networkList": [
+            {
+                "network": "BSC",
+                "coin": "ETH",
+                "name": "BNB Smart Chain (BEP20)",
+                "isDefault": false,
+                "withdrawEnable": false,
+                "withdrawDesc": "Wallet maintenance",
+                "withdrawFee": "0.00011",
+                "withdrawMin": "0.00022",
+                "withdrawMax": "9999999",
+                "withdrawIntegerMultiple": "0.00000001"
+            },
+            {
+                "network": "ETH",
+                "coin": "ETH",
+                "name": "Ethereum (ERC20)",
+                "isDefault": true,
+                "withdrawEnable": true,
+                "withdrawDesc": "",
+                "withdrawFee": "0.0016",
+                "withdrawMin": "0.0098",
+                "withdrawMax": "100",
+                "withdrawIntegerMultiple": "0.0001"
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_select_network() {
+        let cc: CoinConfigRec = serde_json::from_str(COIN_CONFIG).unwrap();
+        assert_eq!(cc.network_list.len(), 2);
+
+        let nr = select_network(&cc, None).unwrap();
+        assert_eq!(nr.network, "ETH");
+        assert_eq!(nr.withdraw_fee, dec!(0.0016));
+
+        let nr = select_network(&cc, Some("eth")).unwrap();
+        assert_eq!(nr.name, "Ethereum (ERC20)");
+
+        // Disabled and unknown networks
+        let e = select_network(&cc, Some("BSC")).unwrap_err();
+        assert!(e.to_string().contains("Wallet maintenance"));
+        assert!(select_network(&cc, Some("SOL")).is_err());
+    }
+
+    #[test]
+    fn test_adj_withdraw_quantity() {
+        let cc: CoinConfigRec = serde_json::from_str(COIN_CONFIG).unwrap();
+        let nr = select_network(&cc, Some("ETH")).unwrap();
+
+        assert_eq!(
+            adj_withdraw_quantity(nr, dec!(1.23456)).unwrap(),
+            dec!(1.2345)
+        );
+        assert_eq!(
+            adj_withdraw_quantity(nr, dec!(0.0098)).unwrap(),
+            dec!(0.0098)
+        );
+        assert!(adj_withdraw_quantity(nr, dec!(0.00989)).is_ok());
+        assert!(adj_withdraw_quantity(nr, dec!(0.0097)).is_err());
+        assert!(adj_withdraw_quantity(nr, dec!(100.1)).is_err());
+    }
+}
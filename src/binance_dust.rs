@@ -0,0 +1,374 @@
+//! Convert "dust", balances too small to be sold, to BNB
+//!
+use std::io::Write;
+
+use clap::ArgMatches;
+use log::trace;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use time_ms_conversions::utc_now_to_time_ms;
+use tokentaxrec::{TokenTaxRec, TokenTaxRecType};
+
+use dec_utils::dec_to_usd_string;
+
+use crate::{
+    binance_account_info::{get_account_info, AccountInfo},
+    binance_avg_price::get_avg_price,
+    binance_exchange_info::{get_exchange_info, ExchangeInfo},
+    binance_order_response::{DustResponseRec, DustTransferResultRec, TradeResponse},
+    binance_signature::{append_signature, binance_signature, query_vec_u8},
+    binance_trade::{log_order_response, order_log_file},
+    binance_verify_order::{adj_quantity_verify_lot_size, verify_min_notional},
+    common::{are_you_sure_stdout_stdin, post_req_get_response, ResponseErrorRec},
+    configuration::Configuration,
+    token_tax_comment_vers::create_tt_cmt_ver5_string,
+};
+
+/// An asset whose free balance can't be sold
+#[derive(Debug, Clone)]
+pub struct DustRec {
+    pub asset: String,
+    pub free: Decimal,
+    pub value_in_usd: Decimal,
+    pub reason: String,
+}
+
+/// Return the assets in ai whose free balance is below the lot size
+/// or min notional of the asset's default_quote_asset symbol. If
+/// assets is not empty only those assets are considered.
+pub async fn get_dust(
+    config: &Configuration,
+    ei: &ExchangeInfo,
+    ai: &AccountInfo,
+    assets: &[&str],
+) -> Result<Vec<DustRec>, Box<dyn std::error::Error>> {
+    let mut dust_vec = Vec::new();
+    for balance in ai.balances_map.values() {
+        if balance.free <= dec!(0) || balance.asset == "BNB" || balance.asset == "USD" {
+            continue;
+        }
+        if !assets.is_empty() && !assets.contains(&balance.asset.as_str()) {
+            continue;
+        }
+
+        let symbol_name = balance.asset.clone() + &config.default_quote_asset;
+        let reason = if let Some(symbol) = ei.get_symbol(&symbol_name) {
+            let qty = adj_quantity_verify_lot_size(symbol, balance.free);
+            if qty <= dec!(0) {
+                "below lot size".to_string()
+            } else {
+                let avg_price = get_avg_price(config, &symbol_name).await?;
+                if verify_min_notional(&avg_price, symbol, qty).is_err() {
+                    "below min notional".to_string()
+                } else {
+                    trace!("get_dust: {} is not dust", balance.asset);
+                    continue;
+                }
+            }
+        } else {
+            format!("no {symbol_name} market")
+        };
+
+        let value_in_usd = balance.price_in_usd * balance.free;
+        dust_vec.push(DustRec {
+            asset: balance.asset.clone(),
+            free: balance.free,
+            value_in_usd,
+            reason,
+        });
+    }
+
+    Ok(dust_vec)
+}
+
+/// The toAsset of a conversion, binance.us requires it while binance.com
+/// always converts to BNB and doesn't take it.
+fn dust_to_asset(
+    config: &Configuration,
+) -> Result<Option<&'static str>, Box<dyn std::error::Error>> {
+    match config.domain.as_str() {
+        "binance.com" => Ok(None),
+        "binance.us" => Ok(Some("BNB")),
+        domain => Err(format!("Dust conversion is not supported on {domain}").into()),
+    }
+}
+
+/// The asset parameters of converting dust_vec to BNB, binance.com takes
+/// an asset per dust asset and binance.us a fromAsset plus the toAsset.
+fn dust_asset_params<'a>(
+    config: &Configuration,
+    dust_vec: &'a [DustRec],
+) -> Result<Vec<(&'static str, &'a str)>, Box<dyn std::error::Error>> {
+    let params = match dust_to_asset(config)? {
+        None => dust_vec
+            .iter()
+            .map(|d| ("asset", d.asset.as_str()))
+            .collect(),
+        Some(to_asset) => {
+            let mut params: Vec<(&str, &str)> = dust_vec
+                .iter()
+                .map(|d| ("fromAsset", d.asset.as_str()))
+                .collect();
+            params.push(("toAsset", to_asset));
+            params
+        }
+    };
+
+    Ok(params)
+}
+
+async fn dust_post_and_response(
+    config: &Configuration,
+    mut log_writer: &mut dyn Write,
+    dust_vec: &[DustRec],
+) -> Result<TradeResponse, Box<dyn std::error::Error>> {
+    let api_key = config.keys.get_ak_or_err()?;
+    let secret_key = &config.keys.get_sk_vec_u8_or_err()?;
+    let value_usd: Decimal = dust_vec.iter().map(|d| d.value_in_usd).sum();
+
+    let mut param_tuples = dust_asset_params(config, dust_vec)?;
+    param_tuples.push(("recvWindow", "5000"));
+
+    let ts_string: String = format!("{}", utc_now_to_time_ms());
+    param_tuples.push(("timestamp", ts_string.as_str()));
+
+    let mut query = query_vec_u8(&param_tuples);
+
+    // Calculate the signature using sig_key and the data is qs and query as body
+    let signature = binance_signature(secret_key, &query, &[]);
+
+    // Append the signature to query
+    append_signature(&mut query, signature);
+
+    // Convert to a string
+    let query_string = String::from_utf8(query)?;
+    trace!("dust_post_and_response: query_string={}", &query_string);
+
+    let url = config.make_url("api", "/sapi/v1/asset/dust?");
+    trace!("dust_post_and_response: url={}", url);
+
+    let tr = if !config.test {
+        let response = post_req_get_response(api_key, &url, &query_string).await?;
+        trace!("dust_post_and_response: response={:#?}", response);
+        let response_headers = response.headers().clone();
+        let response_status = response.status();
+        let response_body = response.text().await?;
+        trace!(
+            "dust_post_and_response: response_status={} response_body={}",
+            response_status,
+            response_body
+        );
+
+        if response_status == 200 {
+            let mut response: DustResponseRec = serde_json::from_str(&response_body)?;
+            response.test = config.test;
+            response.query = query_string;
+            response.response_body = response_body;
+            response.value_usd = value_usd;
+
+            TradeResponse::SuccessDust(response)
+        } else {
+            let rer = ResponseErrorRec::new(
+                false,
+                response_status.as_u16(),
+                &query_string,
+                response_headers,
+                &format!(r#"response_body: {response_body}"#),
+            );
+            trace!("dust_post_and_response: ResponseErrRec={:#?}", &rer);
+
+            TradeResponse::FailureResponse(rer)
+        }
+    } else {
+        // The amount of BNB isn't known until the conversion is done
+        let operate_time = utc_now_to_time_ms();
+        let drr = DustResponseRec {
+            test: config.test,
+            query: query_string,
+            value_usd,
+            transfer_result: dust_vec
+                .iter()
+                .map(|d| DustTransferResultRec {
+                    amount: d.free,
+                    from_asset: d.asset.clone(),
+                    operate_time,
+                    service_charge_amount: dec!(0),
+                    tran_id: 0,
+                    transfered_amount: dec!(0),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        trace!("dust_post_and_response: test DustResponseRec={:#?}", &drr);
+
+        TradeResponse::SuccessTestDust(drr)
+    };
+
+    log_order_response(&mut log_writer, &tr)?;
+
+    Ok(tr)
+}
+
+/// Convert a dust conversion to TokenTaxRecs the same way the
+/// binance.com "Small assets exchange BNB" records are, each
+/// asset converted is a Spend and the BNB received is Income.
+pub fn ttr_vec_from_dust_response(exchange: &str, drr: &DustResponseRec) -> Vec<TokenTaxRec> {
+    let mut result_a = Vec::<TokenTaxRec>::new();
+
+    for dtr in &drr.transfer_result {
+        let mut ttr = TokenTaxRec::new();
+        ttr.exchange = exchange.to_owned();
+        ttr.comment = create_tt_cmt_ver5_string(dtr, "Small assets exchange BNB");
+        ttr.time = dtr.operate_time;
+        ttr.type_txs = TokenTaxRecType::Spend;
+        ttr.sell_amount = Some(dtr.amount);
+        ttr.sell_currency = dtr.from_asset.clone();
+        result_a.push(ttr);
+
+        let mut ttr = TokenTaxRec::new();
+        ttr.exchange = exchange.to_owned();
+        ttr.comment = create_tt_cmt_ver5_string(dtr, "Small assets exchange BNB");
+        ttr.time = dtr.operate_time;
+        ttr.type_txs = TokenTaxRecType::Income;
+        ttr.buy_amount = Some(dtr.transfered_amount);
+        ttr.buy_currency = "BNB".to_owned();
+        result_a.push(ttr);
+    }
+
+    result_a
+}
+
+pub async fn dust_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    trace!("dust_cmd:+ test: {}", config.test);
+    if config.paper_trading {
+        return Err("Dust conversion is not available when paper trading".into());
+    }
+    dust_to_asset(config)?;
+
+    let assets: Vec<&str> = match sc_matches.values_of("ASSETS") {
+        Some(values) => values.collect(),
+        None => vec![],
+    };
+
+    let order_log_path = if let Some(olp) = &config.order_log_path {
+        olp
+    } else {
+        return Err("No order log path, set it in the config file or use --order_log_path".into());
+    };
+    let mut log_writer = order_log_file(order_log_path)?;
+
+    let ei = get_exchange_info(config).await?;
+    let time_ms = utc_now_to_time_ms();
+    let mut ai = get_account_info(config, time_ms).await?;
+    ai.update_values_in_usd(config, config.verbose, time_ms)
+        .await;
+
+    let dust_vec = get_dust(config, &ei, &ai, &assets).await?;
+    if dust_vec.is_empty() {
+        println!("\n ** NO dust to convert **");
+        return Ok(());
+    }
+
+    let mut total_value_in_usd = dec!(0);
+    for d in &dust_vec {
+        println!(
+            "{:8} {:14.8} of {:10} worth {:10} {}",
+            "Dust",
+            d.free,
+            d.asset,
+            dec_to_usd_string(d.value_in_usd.round_dp(2)),
+            d.reason,
+        );
+        total_value_in_usd += d.value_in_usd;
+    }
+    println!(
+        "\nConverting {} assets to BNB worth about {}",
+        dust_vec.len(),
+        dec_to_usd_string(total_value_in_usd.round_dp(2))
+    );
+
+    if config.test || !config.confirmation_required || are_you_sure_stdout_stdin() {
+        let tr = dust_post_and_response(config, &mut log_writer, &dust_vec).await?;
+        println!("{tr}");
+    } else {
+        println!("\n ** Aborted **");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dust_asset_params() {
+        let dust_vec: Vec<DustRec> = ["ETH", "ADA"]
+            .iter()
+            .map(|asset| DustRec {
+                asset: asset.to_string(),
+                free: dec!(0.001),
+                value_in_usd: dec!(1),
+                reason: "below lot size".to_string(),
+            })
+            .collect();
+        let query = |domain: &str| -> Result<String, Box<dyn std::error::Error>> {
+            let config = Configuration {
+                domain: domain.to_string(),
+                ..Default::default()
+            };
+            let params = dust_asset_params(&config, &dust_vec)?;
+            Ok(String::from_utf8(query_vec_u8(&params))?)
+        };
+
+        assert_eq!(query("binance.com").unwrap(), "asset=ETH&asset=ADA");
+        assert_eq!(
+            query("binance.us").unwrap(),
+            "fromAsset=ETH&fromAsset=ADA&toAsset=BNB"
+        );
+        assert!(query("example.com").is_err());
+    }
+
+    #[test]
+    fn test_ttr_vec_from_dust_response() {
+        let drr = DustResponseRec {
+            total_service_charge: dec!(0.005),
+            total_transfered: dec!(0.25),
+            transfer_result: vec![DustTransferResultRec {
+                amount: dec!(0.03),
+                from_asset: "ETH".to_owned(),
+                operate_time: 1563368549307,
+                service_charge_amount: dec!(0.005),
+                tran_id: 2970932918,
+                transfered_amount: dec!(0.25),
+            }],
+            ..Default::default()
+        };
+
+        let ttr_vec = ttr_vec_from_dust_response("binance.us", &drr);
+        assert_eq!(ttr_vec.len(), 2);
+
+        let spend = &ttr_vec[0];
+        assert_eq!(spend.type_txs, TokenTaxRecType::Spend);
+        assert_eq!(spend.sell_amount, Some(dec!(0.03)));
+        assert_eq!(spend.sell_currency, "ETH");
+        assert_eq!(spend.buy_amount, None);
+        assert_eq!(spend.fee_amount, None);
+        assert_eq!(spend.exchange, "binance.us");
+        assert_eq!(spend.time, 1563368549307);
+        assert_eq!(
+            spend.comment,
+            "v5,2970932918,1563368549307,ETH,Small assets exchange BNB"
+        );
+
+        let income = &ttr_vec[1];
+        assert_eq!(income.type_txs, TokenTaxRecType::Income);
+        assert_eq!(income.buy_amount, Some(dec!(0.25)));
+        assert_eq!(income.buy_currency, "BNB");
+        assert_eq!(income.sell_amount, None);
+        assert_eq!(income.time, 1563368549307);
+    }
+}
@@ -573,7 +573,7 @@ impl ExchangeInfo {
     }
 }
 
-pub async fn get_exchange_info<'e>(
+pub async fn get_exchange_info(
     config: &Configuration,
 ) -> Result<ExchangeInfo, Box<dyn std::error::Error>> {
     trace!("get_exchange_info: +");
@@ -655,7 +655,7 @@ mod test {
         assert!(!btcusd.order_types.contains(&OrderType::TAKE_PROFIT));
 
         // Verify we get None when a symbol isn't found
-        assert!(ei.symbols_map.get("NOT-A-SYMBOL").is_none());
+        assert!(!ei.symbols_map.contains_key("NOT-A-SYMBOL"));
 
         let ei_mno = ei.get_max_num_orders();
         assert!(ei_mno.is_some());
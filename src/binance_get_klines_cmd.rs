@@ -1,8 +1,16 @@
+use std::{fs::File, io::BufReader};
+
+use clap::ArgMatches;
 use log::trace;
+use rust_decimal::prelude::*;
 
 use crate::{
+    backtest::read_kline_csv,
     binance_klines::{get_klines, KlineInterval, KlineRec},
+    common::create_buf_writer,
     configuration::Configuration,
+    indicators::{closes, sma, Indicator},
+    kline_chart::{render_kline_chart, ChartSize},
 };
 
 use time_ms_conversions::{
@@ -23,6 +31,29 @@ pub struct GetKlinesCmdRec {
 
     // Kline interval
     pub interval: Option<String>,
+
+    // Display a candlestick chart instead of the records
+    pub chart: bool,
+
+    // Moving average period to overlay on the chart
+    pub ma_period: Option<usize>,
+}
+
+/// Convert a start date time string with or without a TZ to time ms
+fn start_date_time_to_time_ms(dt_str: &str) -> Option<i64> {
+    match dt_str_to_utc_time_ms(dt_str, LocalTz) {
+        Ok(ndt) => {
+            println!("get_klines_cmd: ndt={ndt}");
+            Some(ndt)
+        }
+        Err(_) => match dt_str_to_utc_time_ms(dt_str, HasTz) {
+            Ok(dt) => {
+                println!("get_klines_cmd: dt={dt}");
+                Some(dt)
+            }
+            Err(_) => None,
+        },
+    }
 }
 
 pub async fn get_klines_cmd(
@@ -36,24 +67,19 @@ pub async fn get_klines_cmd(
     trace!("get_klines_cmd: rec: {:#?}", rec);
 
     let start_time_ms = if let Some(dt_str) = &rec.start_date_time {
-        match dt_str_to_utc_time_ms(dt_str, LocalTz) {
-            Ok(ndt) => {
-                println!("get_klines_cmd: ndt={ndt}");
-                Some(ndt)
-            }
-            Err(_) => match dt_str_to_utc_time_ms(dt_str, HasTz) {
-                Ok(dt) => {
-                    println!("get_klines_cmd: dt={dt}");
-                    Some(dt)
-                }
-                Err(_) => None,
-            },
-        }
+        start_date_time_to_time_ms(dt_str)
     } else {
         None
     };
 
-    let limit: u16 = if let Some(v) = rec.limit { v } else { 1 };
+    let chart_size = ChartSize::default();
+    let limit: u16 = if let Some(v) = rec.limit {
+        v
+    } else if rec.chart {
+        chart_size.max_klines().min(1000) as u16
+    } else {
+        1
+    };
 
     let interval = if let Some(s) = &rec.interval {
         KlineInterval::from_string(s)?
@@ -71,6 +97,15 @@ pub async fn get_klines_cmd(
     )
     .await?;
 
+    if rec.chart {
+        let ma = rec.ma_period.map(|p| sma(&closes(&krs), p));
+        println!("{} {}", rec.sym_name, interval.to_string());
+        for line in render_kline_chart(&krs, ma.as_deref(), &chart_size) {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+
     for kr in &krs {
         println!(
             "Open time: {} Close time: {} diff minutes: {}",
@@ -83,3 +118,86 @@ pub async fn get_klines_cmd(
 
     Ok(())
 }
+
+/// Display or export indicators of klines from the exchange or a kline csv file
+pub async fn indicators_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let indicators = sc_matches
+        .values_of("INDICATORS")
+        .expect("indicators option is missing")
+        .map(Indicator::from_string)
+        .collect::<Result<Vec<Indicator>, _>>()?;
+
+    let krs: Vec<KlineRec> = if let Some(in_file) = sc_matches.value_of("IN_FILE") {
+        read_kline_csv(BufReader::new(File::open(in_file)?))?
+    } else {
+        let sym_name = match sc_matches.value_of("SYMBOL") {
+            Some(s) => s,
+            None => return Err("A SYMBOL or --file is required".into()),
+        };
+        let start_time_ms = match sc_matches.value_of("START-TIME") {
+            Some(dt_str) => start_date_time_to_time_ms(dt_str),
+            None => None,
+        };
+        let limit = u16::from_str(sc_matches.value_of("LIMIT").expect("LIMIT is missing"))?;
+        let interval = KlineInterval::from_string(
+            sc_matches
+                .value_of("INTERVAL")
+                .expect("INTERVAL is missing"),
+        )?;
+        trace!(
+            "indicators_cmd: {} interval: {} start_time_ms: {:?} limit: {}",
+            sym_name,
+            interval.to_string(),
+            start_time_ms,
+            limit
+        );
+
+        get_klines(config, sym_name, interval, start_time_ms, None, Some(limit)).await?
+    };
+
+    let mut header = vec!["open_time".to_string(), "close".to_string()];
+    let mut columns = Vec::<Vec<Option<Decimal>>>::new();
+    for indicator in &indicators {
+        header.extend(indicator.column_names());
+        columns.extend(indicator.compute(&krs));
+    }
+
+    if let Some(out_file) = sc_matches.value_of("OUT_FILE") {
+        let mut writer = csv::Writer::from_writer(create_buf_writer(out_file)?);
+        writer.write_record(&header)?;
+        for (idx, kr) in krs.iter().enumerate() {
+            let mut record = vec![kr.open_time.to_string(), kr.close.to_string()];
+            record.extend(
+                columns
+                    .iter()
+                    .map(|c| c[idx].map_or("".to_string(), |v| v.to_string())),
+            );
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        println!("{} kline indicators written to {out_file}", krs.len());
+    } else {
+        let time_width = 20;
+        print!("{:<time_width$}", header[0]);
+        for h in &header[1..] {
+            print!(" {h:>14}");
+        }
+        println!();
+        for (idx, kr) in krs.iter().enumerate() {
+            let time = time_ms_to_utc(kr.open_time).format("%Y-%m-%d %H:%M:%S");
+            print!("{time:<time_width$} {:>14}", kr.close.normalize());
+            for c in &columns {
+                match c[idx] {
+                    Some(v) => print!(" {:>14}", v.round_dp(4).normalize()),
+                    None => print!(" {:>14}", "-"),
+                }
+            }
+            println!();
+        }
+    }
+
+    Ok(())
+}
@@ -1,9 +1,10 @@
 use chrono::{DateTime, Utc};
 use log::trace;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 use rust_decimal::prelude::*;
-use time_ms_conversions::{utc_now_to_time_ms, utc_to_time_ms};
+use time_ms_conversions::{time_ms_to_utc, utc_now_to_time_ms, utc_to_time_ms};
 
 use crate::{
     binance_signature::{append_signature, binance_signature, query_vec_u8},
@@ -13,6 +14,33 @@ use crate::{
     ier_new,
 };
 
+/// Status of a deposit that has been credited
+pub const DEPOSIT_STATUS_SUCCESS: i32 = 1;
+
+/// Status of a withdrawal that has been sent
+pub const WITHDRAW_STATUS_COMPLETED: i32 = 6;
+
+/// Status of a withdrawal that was cancelled
+pub const WITHDRAW_STATUS_CANCELLED: i32 = 1;
+
+/// Status of a withdrawal that was rejected
+pub const WITHDRAW_STATUS_REJECTED: i32 = 3;
+
+/// Status of a withdrawal that failed
+pub const WITHDRAW_STATUS_FAILURE: i32 = 5;
+
+/// Seconds between polls of the withdraw history when waiting
+pub const WITHDRAW_POLL_SECS: u64 = 30;
+
+/// Maximum time to wait for a withdrawal to complete
+pub const WITHDRAW_WAIT_TIMEOUT_MS: i64 = 4 * 60 * 60 * 1000;
+
+/// Consecutive failures to get the withdraw history before giving up waiting
+pub const WITHDRAW_WAIT_MAX_ERRORS: u32 = 10;
+
+/// Maximum time between the startTime and endTime of a history request
+pub const HISTORY_WINDOW_MS: i64 = 90 * 24 * 60 * 60 * 1000;
+
 // Example response_body
 //{
 //    "insertTime":1620402126963,
@@ -83,6 +111,36 @@ pub struct WithdrawRec {
     pub withdraw_order_id: Option<String>,
 }
 
+// Example response_body of the capital withdraw history
+//{
+//    "id":"b6ae22b3aa844210a7041aee7589627c",
+//    "amount":"8.91000000",
+//    "transactionFee":"0.004",
+//    "coin":"USDT",
+//    "status":6,
+//    "address":"0x94df8b352de7f46f64b01d3666bf6e936e44ce60",
+//    "txId":"0xb5ef8c13b968a406cc62a93a8bd80f9e9a906ef1b3fcf20a2e48573c17659268",
+//    "applyTime":"2019-10-12 11:12:02",
+//    "network":"ETH",
+//    "transferType":0
+//}
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapitalWithdrawRec {
+    pub id: String,
+    pub amount: Decimal,
+    pub transaction_fee: Decimal,
+    pub coin: String,
+    #[serde(deserialize_with = "de_string_or_number_to_i32")]
+    pub status: i32,
+    #[serde(default)]
+    pub network: String,
+
+    // None or empty until the withdrawal is sent
+    #[serde(default)]
+    pub tx_id: Option<String>,
+}
+
 // {
 //     "orderId":"6c2ff984890145fdac2b7160299062f0",
 //     "paymentAccount": "4a992541-c12d-4cca-bbd6-df637f801526",
@@ -117,13 +175,17 @@ pub struct WithdrawRec {
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AssetLogRec {
-    order_id: String,
-    payment_account: Option<String>,
-    payment_method: String,
-    order_status: String,
-    amount: Decimal,
-    transaction_fee: Decimal,
-    platform_fee: Decimal,
+    pub order_id: String,
+    pub payment_account: Option<String>,
+    pub payment_method: String,
+    pub order_status: String,
+    pub amount: Decimal,
+    pub transaction_fee: Decimal,
+    pub platform_fee: Decimal,
+
+    // Not in the documented response
+    #[serde(default)]
+    pub create_time: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -207,6 +269,49 @@ async fn history_get_req_and_response(
     result
 }
 
+/// Split start_time_ms..=end_time_ms into consecutive windows of at most window_ms
+pub fn history_windows(start_time_ms: i64, end_time_ms: i64, window_ms: i64) -> Vec<(i64, i64)> {
+    let mut windows = Vec::new();
+    let mut start = start_time_ms;
+    while start <= end_time_ms {
+        let end = (start + window_ms - 1).min(end_time_ms);
+        windows.push((start, end));
+        start = end + 1;
+    }
+
+    windows
+}
+
+/// A start and end date time, either may be absent
+pub type DateTimeRange = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// The windows of the range, an absent end is now. If there is no
+/// start the range is passed as is and the server picks the window.
+fn date_time_windows(
+    start_date_time: Option<DateTime<Utc>>,
+    end_date_time: Option<DateTime<Utc>>,
+) -> Vec<DateTimeRange> {
+    match start_date_time {
+        Some(sdt) => {
+            let end_time_ms = match end_date_time {
+                Some(edt) => utc_to_time_ms(&edt),
+                None => utc_now_to_time_ms(),
+            };
+            history_windows(utc_to_time_ms(&sdt), end_time_ms, HISTORY_WINDOW_MS)
+                .into_iter()
+                .map(|(s, e)| (Some(time_ms_to_utc(s)), Some(time_ms_to_utc(e))))
+                .collect()
+        }
+        None => vec![(None, end_date_time)],
+    }
+}
+
+/// Remove all but the first of the recs with the same key
+fn dedupe<T>(recs: Vec<T>, key: impl Fn(&T) -> String) -> Vec<T> {
+    let mut seen: HashSet<String> = HashSet::new();
+    recs.into_iter().filter(|r| seen.insert(key(r))).collect()
+}
+
 pub async fn get_history(
     config: &Configuration,
     full_path: &str,
@@ -249,24 +354,113 @@ pub async fn get_deposit_history(
     start_date_time: Option<DateTime<Utc>>,
     end_date_time: Option<DateTime<Utc>>,
 ) -> Result<Vec<DepositRec>, Box<dyn std::error::Error>> {
-    let histories = get_history(
-        config,
-        "/wapi/v3/depositHistory.html",
-        asset,
-        status,
-        start_date_time,
-        end_date_time,
-    )
-    .await?;
+    let mut deposits: Vec<DepositRec> = Vec::new();
+    for (sdt, edt) in date_time_windows(start_date_time, end_date_time) {
+        let histories = get_history(
+            config,
+            "/wapi/v3/depositHistory.html",
+            asset,
+            status,
+            sdt,
+            edt,
+        )
+        .await?;
+
+        if let Some(mut deposit_list) = histories.deposit_list {
+            deposits.append(&mut deposit_list);
+        } else {
+            return Err(ier_new!(
+                7,
+                "Should not happen; expected depositList, but there was None"
+            )
+            .into());
+        }
+    }
 
-    if let Some(deposit_list) = histories.deposit_list {
-        Ok(deposit_list)
-    } else {
-        Err(ier_new!(
-            7,
-            "Should not happen; expected depositList, but there was None"
+    let mut deposits = dedupe(deposits, |dr| {
+        format!(
+            "{}-{}-{}-{}",
+            dr.insert_time,
+            dr.asset,
+            dr.amount,
+            dr.tx_id.as_deref().unwrap_or("")
         )
-        .into())
+    });
+    deposits.sort_by_key(|dr| dr.insert_time);
+
+    Ok(deposits)
+}
+
+/// The name of a withdrawal's status
+pub fn withdraw_status_name(status: i32) -> &'static str {
+    match status {
+        0 => "email sent",
+        WITHDRAW_STATUS_CANCELLED => "cancelled",
+        2 => "awaiting approval",
+        WITHDRAW_STATUS_REJECTED => "rejected",
+        4 => "processing",
+        WITHDRAW_STATUS_FAILURE => "failure",
+        WITHDRAW_STATUS_COMPLETED => "completed",
+        _ => "unknown",
+    }
+}
+
+/// Get the capital withdraw history of coin, the most recent first
+pub async fn get_capital_withdraw_history(
+    config: &Configuration,
+    coin: &str,
+) -> Result<Vec<CapitalWithdrawRec>, Box<dyn std::error::Error>> {
+    let api_key = config.keys.get_ak_or_err()?;
+    let secret_key = &config.keys.get_sk_vec_u8_or_err()?;
+
+    let mut params: Vec<(&str, &str)> = vec![("coin", coin), ("recvWindow", "5000")];
+
+    let ts_string: String = format!("{}", utc_now_to_time_ms());
+    params.push(("timestamp", ts_string.as_str()));
+
+    let mut query = query_vec_u8(&params);
+
+    // Calculate the signature using sig_key and the data is qs and query as body
+    let signature = binance_signature(secret_key, &query, &[]);
+
+    // Append the signature to query
+    append_signature(&mut query, signature);
+
+    // Convert to a string
+    let query_string = String::from_utf8(query)?;
+    trace!(
+        "get_capital_withdraw_history: query_string={}",
+        &query_string
+    );
+
+    let mut url = config.make_url("api", "/sapi/v1/capital/withdraw/history?");
+    url.push_str(&query_string);
+    trace!("get_capital_withdraw_history: url={}", url);
+
+    let response = get_req_get_response(api_key, &url).await?;
+    let response_headers = response.headers().clone();
+    let response_status = response.status();
+    let response_body = response.text().await?;
+
+    if response_status == 200 {
+        trace!(
+            "get_capital_withdraw_history: response_body={}",
+            response_body
+        );
+        let withdrawals: Vec<CapitalWithdrawRec> = serde_json::from_str(&response_body)?;
+
+        Ok(withdrawals)
+    } else {
+        let rer = ResponseErrorRec::new(
+            false,
+            response_status.as_u16(),
+            &query_string,
+            response_headers,
+            &response_body,
+        );
+        trace!("get_capital_withdraw_history: ResponseErrRec={:#?}", &rer);
+
+        Err(ier_new!(8, &rer.to_string()).into())
     }
 }
 
@@ -277,25 +471,33 @@ pub async fn get_withdraw_history(
     start_date_time: Option<DateTime<Utc>>,
     end_date_time: Option<DateTime<Utc>>,
 ) -> Result<Vec<WithdrawRec>, Box<dyn std::error::Error>> {
-    let histories = get_history(
-        config,
-        "/wapi/v3/withdrawHistory.html",
-        asset,
-        status,
-        start_date_time,
-        end_date_time,
-    )
-    .await?;
-
-    if let Some(withdraw_list) = histories.withdraw_list {
-        Ok(withdraw_list)
-    } else {
-        Err(ier_new!(
-            7,
-            "Should not happen; expected withdrawList, but there was None"
+    let mut withdrawals: Vec<WithdrawRec> = Vec::new();
+    for (sdt, edt) in date_time_windows(start_date_time, end_date_time) {
+        let histories = get_history(
+            config,
+            "/wapi/v3/withdrawHistory.html",
+            asset,
+            status,
+            sdt,
+            edt,
         )
-        .into())
+        .await?;
+
+        if let Some(mut withdraw_list) = histories.withdraw_list {
+            withdrawals.append(&mut withdraw_list);
+        } else {
+            return Err(ier_new!(
+                7,
+                "Should not happen; expected withdrawList, but there was None"
+            )
+            .into());
+        }
     }
+
+    let mut withdrawals = dedupe(withdrawals, |wr| wr.id.clone());
+    withdrawals.sort_by_key(|wr| wr.apply_time);
+
+    Ok(withdrawals)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -334,29 +536,36 @@ pub async fn get_fiat_currency_history(
         params.push(("paymentMethod", pm));
     }
 
-    let stms_string: String;
-    if let Some(sdt) = start_date_time {
-        stms_string = utc_to_time_ms(&sdt).to_string();
-        params.push(("startTime", &stms_string));
+    let mut asset_logs: Vec<AssetLogRec> = Vec::new();
+    for (sdt, edt) in date_time_windows(start_date_time, end_date_time) {
+        let mut window_params = params.clone();
+
+        let stms_string: String;
+        if let Some(sdt) = sdt {
+            stms_string = utc_to_time_ms(&sdt).to_string();
+            window_params.push(("startTime", &stms_string));
+        }
+
+        let etms_string: String;
+        if let Some(edt) = edt {
+            etms_string = utc_to_time_ms(&edt).to_string();
+            window_params.push(("endTime", &etms_string));
+        }
+
+        let histories = history_get_req_and_response(config, full_path, window_params).await?;
+
+        if let Some(mut alrs) = histories.asset_log_record_list {
+            asset_logs.append(&mut alrs);
+        } else {
+            return Err(ier_new!(
+                7,
+                "Should not happen; expected assetLogRecordList, but there was None"
+            )
+            .into());
+        }
     }
 
-    let etms_string: String;
-    if let Some(edt) = end_date_time {
-        etms_string = utc_to_time_ms(&edt).to_string();
-        params.push(("endTime", &etms_string));
-    }
-
-    let histories = history_get_req_and_response(config, full_path, params).await?;
-
-    if let Some(alrs) = histories.asset_log_record_list {
-        Ok(alrs)
-    } else {
-        Err(ier_new!(
-            7,
-            "Should not happen; expected assetLogRecordList, but there was None"
-        )
-        .into())
-    }
+    Ok(dedupe(asset_logs, |alr| alr.order_id.clone()))
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -411,7 +620,7 @@ pub async fn get_fiat_currency_withdraw_history(
 
 #[cfg(test)]
 mod test {
-    //use super::*;
+    use super::*;
     //use rust_decimal_macros::dec;
 
     //const HISTORY_REC: &str = r#"{
@@ -430,6 +639,63 @@ mod test {
     //    "isBestMatch": true
     //}"#;
 
+    #[test]
+    fn test_history_windows() {
+        assert_eq!(history_windows(0, 9, 10), vec![(0, 9)]);
+        assert_eq!(history_windows(0, 10, 10), vec![(0, 9), (10, 10)]);
+        assert_eq!(
+            history_windows(5, 30, 10),
+            vec![(5, 14), (15, 24), (25, 30)]
+        );
+        assert_eq!(history_windows(5, 5, 10), vec![(5, 5)]);
+        assert!(history_windows(6, 5, 10).is_empty());
+
+        // A year is 5 windows
+        let year_ms = 365 * 24 * 60 * 60 * 1000;
+        let windows = history_windows(0, year_ms - 1, HISTORY_WINDOW_MS);
+        assert_eq!(windows.len(), 5);
+        assert_eq!(windows[1], (HISTORY_WINDOW_MS, 2 * HISTORY_WINDOW_MS - 1));
+        assert_eq!(windows[4], (4 * HISTORY_WINDOW_MS, year_ms - 1));
+    }
+
+    #[test]
+    fn test_date_time_windows() {
+        assert_eq!(date_time_windows(None, None), vec![(None, None)]);
+
+        let end = time_ms_to_utc(HISTORY_WINDOW_MS + 1000);
+        assert_eq!(date_time_windows(None, Some(end)), vec![(None, Some(end))]);
+
+        let windows = date_time_windows(Some(time_ms_to_utc(1000)), Some(end));
+        assert_eq!(
+            windows,
+            vec![
+                (
+                    Some(time_ms_to_utc(1000)),
+                    Some(time_ms_to_utc(HISTORY_WINDOW_MS + 999))
+                ),
+                (Some(time_ms_to_utc(HISTORY_WINDOW_MS + 1000)), Some(end)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let recs: Vec<WithdrawRec> = [("a", 3), ("b", 1), ("a", 2)]
+            .iter()
+            .map(|(id, t)| {
+                serde_json::from_str(&format!(
+                    r#"{{"amount":"1","transactionFee":"0","address":"x","id":"{id}","asset":"ETH","applyTime":{t},"status":6,"network":"ETH"}}"#
+                ))
+                .unwrap()
+            })
+            .collect();
+        let recs = dedupe(recs, |wr| wr.id.clone());
+        assert_eq!(recs.len(), 2);
+        assert_eq!(recs[0].id, "a");
+        assert_eq!(recs[0].apply_time, 3);
+        assert_eq!(recs[1].id, "b");
+    }
+
     #[test]
     fn test_history_rec() {
         //let tr: DepositRec = match serde_json::from_str(HISTORY_REC) {
@@ -449,4 +715,31 @@ mod test {
         //assert_eq!(true, tr.is_maker);
         //assert_eq!(true, tr.is_best_match);
     }
+
+    #[test]
+    fn test_capital_withdraw_rec() {
+        let wr: CapitalWithdrawRec = serde_json::from_str(
+            r#"{
+                "id":"b6ae22b3aa844210a7041aee7589627c",
+                "amount":"8.91000000",
+                "transactionFee":"0.004",
+                "coin":"USDT",
+                "status":6,
+                "address":"0x94df8b352de7f46f64b01d3666bf6e936e44ce60",
+                "txId":"0xb5ef8c13b968a406cc62a93a8bd80f9e9a906ef1b3fcf20a2e48573c17659268",
+                "applyTime":"2019-10-12 11:12:02",
+                "network":"ETH",
+                "transferType":0
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(wr.id, "b6ae22b3aa844210a7041aee7589627c");
+        assert_eq!(wr.amount, Decimal::from_str("8.91").unwrap());
+        assert_eq!(wr.coin, "USDT");
+        assert_eq!(withdraw_status_name(wr.status), "completed");
+        assert_eq!(
+            wr.tx_id.as_deref(),
+            Some("0xb5ef8c13b968a406cc62a93a8bd80f9e9a906ef1b3fcf20a2e48573c17659268")
+        );
+    }
 }
@@ -59,7 +59,7 @@ impl Default for KlineRec {
 }
 
 #[allow(unused)]
-#[derive(Eq, PartialEq, IntoStaticStr)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, IntoStaticStr)]
 pub enum KlineInterval {
     Mins1,
     Mins3,
@@ -132,7 +132,7 @@ impl KlineInterval {
         Ok(interval)
     }
 
-    pub fn to_string(&self) -> &str {
+    pub fn to_string(self) -> &'static str {
         let interval_string: &str = match self {
             KlineInterval::Mins1 => "1m",
             KlineInterval::Mins3 => "3m",
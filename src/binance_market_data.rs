@@ -0,0 +1,314 @@
+//! Order book depth and recent trades market data
+use clap::ArgMatches;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use time_ms_conversions::time_ms_to_utc;
+
+use crate::{
+    binance_order_book::{get_order_book, OrderBook, OrderBookLevel},
+    common::{get_api_response_body, Side},
+    configuration::Configuration,
+    de_string_or_number::{de_string_or_number_to_i64, de_string_or_number_to_u64},
+};
+
+/// A trade from /api/v3/trades
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentTrade {
+    #[serde(deserialize_with = "de_string_or_number_to_u64")]
+    pub id: u64,
+    pub price: Decimal,
+    pub qty: Decimal,
+    pub quote_qty: Decimal,
+    #[serde(deserialize_with = "de_string_or_number_to_i64")]
+    pub time: i64,
+    pub is_buyer_maker: bool,
+    pub is_best_match: bool,
+}
+
+/// Trades at the same time and price from the same order, from /api/v3/aggTrades
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AggTrade {
+    #[serde(rename = "a")]
+    #[serde(deserialize_with = "de_string_or_number_to_u64")]
+    pub agg_trade_id: u64,
+
+    #[serde(rename = "p")]
+    pub price: Decimal,
+
+    #[serde(rename = "q")]
+    pub qty: Decimal,
+
+    #[serde(rename = "f")]
+    #[serde(deserialize_with = "de_string_or_number_to_u64")]
+    pub first_trade_id: u64,
+
+    #[serde(rename = "l")]
+    #[serde(deserialize_with = "de_string_or_number_to_u64")]
+    pub last_trade_id: u64,
+
+    #[serde(rename = "T")]
+    #[serde(deserialize_with = "de_string_or_number_to_i64")]
+    pub time: i64,
+
+    #[serde(rename = "m")]
+    pub is_buyer_maker: bool,
+
+    #[serde(rename = "M")]
+    pub is_best_match: bool,
+}
+
+/// Cumulative quantity and quote value of one side of the book
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Depth {
+    pub qty: Decimal,
+    pub quote_qty: Decimal,
+    pub levels: usize,
+}
+
+pub fn best_bid(ob: &OrderBook) -> Option<&OrderBookLevel> {
+    ob.bids.first()
+}
+
+pub fn best_ask(ob: &OrderBook) -> Option<&OrderBookLevel> {
+    ob.asks.first()
+}
+
+/// The price half way between the best bid and best ask
+pub fn mid_price(ob: &OrderBook) -> Option<Decimal> {
+    match (best_bid(ob), best_ask(ob)) {
+        (Some(bid), Some(ask)) => Some((bid.price + ask.price) / dec!(2)),
+        _ => None,
+    }
+}
+
+/// The spread and the spread as a percent of the mid price
+pub fn spread(ob: &OrderBook) -> Option<(Decimal, Decimal)> {
+    match (best_bid(ob), best_ask(ob), mid_price(ob)) {
+        (Some(bid), Some(ask), Some(mid)) if mid > dec!(0) => {
+            let spread = ask.price - bid.price;
+            Some((spread, (spread / mid) * dec!(100)))
+        }
+        _ => None,
+    }
+}
+
+/// The depth of the levels a market order on side fills from whose
+/// price is within percent of the mid price.
+pub fn depth_within_percent(ob: &OrderBook, side: &Side, percent: Decimal) -> Depth {
+    let mut depth = Depth::default();
+    let mid = if let Some(mid) = mid_price(ob) {
+        mid
+    } else {
+        return depth;
+    };
+    let offset = mid * (percent / dec!(100));
+
+    for level in ob.levels_for(side) {
+        let within = match side {
+            Side::BUY => level.price <= mid + offset,
+            Side::SELL => level.price >= mid - offset,
+        };
+        if !within {
+            break;
+        }
+        depth.qty += level.qty;
+        depth.quote_qty += level.qty * level.price;
+        depth.levels += 1;
+    }
+
+    depth
+}
+
+/// The most recent trades of symbol, limit defaults to 500 and max is 1000
+pub async fn get_recent_trades(
+    config: &Configuration,
+    symbol: &str,
+    limit: Option<u16>,
+) -> Result<Vec<RecentTrade>, Box<dyn std::error::Error>> {
+    let mut path = format!("/api/v3/trades?symbol={symbol}");
+    if let Some(l) = limit {
+        path.push_str(&format!("&limit={l}"));
+    }
+    Ok(serde_json::from_str(
+        &get_api_response_body(config, &path).await?,
+    )?)
+}
+
+/// The most recent aggregate trades of symbol, limit defaults to 500 and max is 1000
+pub async fn get_agg_trades(
+    config: &Configuration,
+    symbol: &str,
+    limit: Option<u16>,
+) -> Result<Vec<AggTrade>, Box<dyn std::error::Error>> {
+    let mut path = format!("/api/v3/aggTrades?symbol={symbol}");
+    if let Some(l) = limit {
+        path.push_str(&format!("&limit={l}"));
+    }
+    Ok(serde_json::from_str(
+        &get_api_response_body(config, &path).await?,
+    )?)
+}
+
+pub async fn depth_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let symbol = sc_matches.value_of("SYMBOL").expect("SYMBOL is missing");
+    let limit = u16::from_str(sc_matches.value_of("LIMIT").expect("LIMIT is missing"))?;
+    let percent = Decimal::from_str(sc_matches.value_of("PERCENT").expect("PERCENT is missing"))?;
+    let quantity = match sc_matches.value_of("QUANTITY") {
+        Some(q) => Some(Decimal::from_str(q)?),
+        None => None,
+    };
+
+    let ob = get_order_book(config, symbol, Some(limit)).await?;
+    let (bid, ask, mid) = match (best_bid(&ob), best_ask(&ob), mid_price(&ob)) {
+        (Some(bid), Some(ask), Some(mid)) => (bid, ask, mid),
+        _ => {
+            println!("{symbol}: order book is empty");
+            return Ok(());
+        }
+    };
+
+    println!(
+        "{symbol} order book, {} bids {} asks",
+        ob.bids.len(),
+        ob.asks.len()
+    );
+    println!("  Best bid: {} qty: {}", bid.price, bid.qty);
+    println!("  Best ask: {} qty: {}", ask.price, ask.qty);
+    println!("       Mid: {}", mid.round_dp(8).normalize());
+    if let Some((spread, spread_percent)) = spread(&ob) {
+        println!("    Spread: {} {:.4}%", spread.normalize(), spread_percent);
+    }
+
+    println!("Depth within {percent}% of mid:");
+    for (name, side) in [("Bids", Side::SELL), ("Asks", Side::BUY)] {
+        let depth = depth_within_percent(&ob, &side, percent);
+        println!(
+            "  {name}: qty: {} quote qty: {} levels: {}",
+            depth.qty.normalize(),
+            depth.quote_qty.round_dp(2),
+            depth.levels
+        );
+    }
+
+    if let Some(qty) = quantity {
+        println!("Volume weighted price for {qty}:");
+        for (name, side) in [("Sell", Side::SELL), ("Buy", Side::BUY)] {
+            let est = ob.estimate_fill(&side, qty);
+            let incomplete = if est.is_complete() {
+                "".to_string()
+            } else {
+                format!(" ** only {} in the book **", est.filled_qty)
+            };
+            println!(
+                "  {name:>4}: avg price: {} worst price: {} levels: {}{incomplete}",
+                est.avg_price.round_dp(8).normalize(),
+                est.worst_price,
+                est.levels
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn trades_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let symbol = sc_matches.value_of("SYMBOL").expect("SYMBOL is missing");
+    let limit = u16::from_str(sc_matches.value_of("LIMIT").expect("LIMIT is missing"))?;
+
+    let side = |is_buyer_maker: bool| if is_buyer_maker { "SELL" } else { "BUY" };
+    if sc_matches.is_present("AGG") {
+        let trades = get_agg_trades(config, symbol, Some(limit)).await?;
+        for t in &trades {
+            println!(
+                "{} {:>12} {:4} {:>16} @ {:>16} trades: {}..={}",
+                time_ms_to_utc(t.time),
+                t.agg_trade_id,
+                side(t.is_buyer_maker),
+                t.qty.normalize(),
+                t.price.normalize(),
+                t.first_trade_id,
+                t.last_trade_id,
+            );
+        }
+    } else {
+        let trades = get_recent_trades(config, symbol, Some(limit)).await?;
+        for t in &trades {
+            println!(
+                "{} {:>12} {:4} {:>16} @ {:>16} quote qty: {}",
+                time_ms_to_utc(t.time),
+                t.id,
+                side(t.is_buyer_maker),
+                t.qty.normalize(),
+                t.price.normalize(),
+                t.quote_qty.normalize(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::ORDER_BOOK;
+
+    #[test]
+    fn test_spread_and_depth() {
+        let ob: OrderBook = serde_json::from_str(ORDER_BOOK).unwrap();
+        assert_eq!(mid_price(&ob), Some(dec!(10.25)));
+        let (spread, spread_percent) = spread(&ob).unwrap();
+        assert_eq!(spread, dec!(0.5));
+        assert_eq!(spread_percent.round_dp(4), dec!(4.878));
+
+        // 10% of 10.25 is 1.025, bids >= 9.225 and asks <= 11.275
+        let bids = depth_within_percent(&ob, &Side::SELL, dec!(10));
+        assert_eq!(bids.qty, dec!(3));
+        assert_eq!(bids.quote_qty, dec!(29));
+        assert_eq!(bids.levels, 2);
+        let asks = depth_within_percent(&ob, &Side::BUY, dec!(10));
+        assert_eq!(asks.qty, dec!(2));
+        assert_eq!(asks.quote_qty, dec!(21.5));
+        assert_eq!(asks.levels, 2);
+
+        let empty = OrderBook {
+            last_update_id: 0,
+            bids: vec![],
+            asks: vec![],
+        };
+        assert_eq!(mid_price(&empty), None);
+        assert_eq!(
+            depth_within_percent(&empty, &Side::BUY, dec!(10)),
+            Depth::default()
+        );
+    }
+
+    #[test]
+    fn test_trades_deserialize() {
+        let trades: Vec<RecentTrade> = serde_json::from_str(
+            r#"[{"id":28457,"price":"4.00000100","qty":"12.00000000","quoteQty":"48.000012","time":1499865549590,"isBuyerMaker":true,"isBestMatch":true}]"#,
+        )
+        .unwrap();
+        assert_eq!(trades[0].id, 28457);
+        assert_eq!(trades[0].quote_qty, dec!(48.000012));
+        assert_eq!(trades[0].time, 1499865549590);
+
+        let agg: Vec<AggTrade> = serde_json::from_str(
+            r#"[{"a":26129,"p":"0.01633102","q":"4.70443515","f":27781,"l":27781,"T":1498793709153,"m":true,"M":true}]"#,
+        )
+        .unwrap();
+        assert_eq!(agg[0].agg_trade_id, 26129);
+        assert_eq!(agg[0].price, dec!(0.01633102));
+        assert_eq!(agg[0].first_trade_id, 27781);
+        assert_eq!(agg[0].time, 1498793709153);
+        assert!(agg[0].is_buyer_maker);
+    }
+}
@@ -1,4 +1,5 @@
 //use std::fs;
+use std::{fmt, io::Write, time::Duration};
 
 use log::trace;
 use rust_decimal::prelude::*;
@@ -8,7 +9,8 @@ use time_ms_conversions::utc_now_to_time_ms;
 use crate::{
     binance_account_info::get_account_info,
     binance_avg_price::{get_avg_price, AvgPrice},
-    binance_exchange_info::{get_exchange_info, ExchangeInfo},
+    binance_exchange_info::{get_exchange_info, ExchangeInfo, Symbol},
+    binance_order_book::{get_order_book, slippage_bound_price, slippage_percent},
     binance_order_response::TradeResponse,
     binance_orders::get_open_orders,
     binance_trade::{
@@ -19,11 +21,145 @@ use crate::{
         verify_quanity_is_less_than_or_eq_free,
     },
     common::{InternalErrorRec, Side},
-    configuration::Configuration,
+    configuration::{Configuration, SlippageAction},
     ier_new,
+    notifications::{notify_trade_summary, TradeSummary},
 };
 use binance_trade::log_order_response;
 
+/// Place a market order as a sequence of smaller orders so that
+/// each has an estimated average fill price within max_slippage_percent
+/// of the avg_price. The order book is fetched before each chunk so
+/// it has a chance to be replenished between chunks.
+#[allow(clippy::too_many_arguments)]
+async fn market_order_in_chunks(
+    config: &Configuration,
+    log_writer: &mut dyn Write,
+    ei: &ExchangeInfo,
+    symbol: &Symbol,
+    side: Side,
+    quantity: Decimal,
+    avg_price: &AvgPrice,
+    max_slippage_percent: Decimal,
+    test: bool,
+) -> Result<TradeResponse, Box<dyn std::error::Error>> {
+    let bound_price = slippage_bound_price(&side, avg_price.price, max_slippage_percent);
+    trace!(
+        "market_order_in_chunks: {} side: {} quantity: {} bound_price: {}",
+        symbol.symbol,
+        side,
+        quantity,
+        bound_price
+    );
+
+    let mut remaining = quantity;
+    let mut last_tr: Option<TradeResponse> = None;
+    let mut chunks = ChunksSummary::default();
+    for chunk in 1..=config.slippage_max_chunks {
+        if chunk > 1 && config.slippage_chunk_delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(config.slippage_chunk_delay_ms)).await;
+        }
+
+        let order_book =
+            get_order_book(config, &symbol.symbol, Some(config.slippage_book_limit)).await?;
+        let max_qty = order_book.max_qty_within_avg_price(&side, bound_price);
+        let chunk_qty = adj_quantity_verify_lot_size(symbol, remaining.min(max_qty));
+        if chunk_qty <= dec!(0) || verify_min_notional(avg_price, symbol, chunk_qty).is_err() {
+            trace!(
+                "market_order_in_chunks: chunk: {} chunk_qty: {} too small, max_qty: {}",
+                chunk,
+                chunk_qty,
+                max_qty
+            );
+            break;
+        }
+
+        let order_type = TradeOrderType::Market(MarketQuantityType::Quantity(chunk_qty));
+        let tr = binance_new_order_or_test(
+            config,
+            log_writer,
+            ei,
+            &symbol.symbol,
+            side.clone(),
+            order_type,
+            test,
+        )
+        .await?;
+        println!("Chunk {chunk}: {tr}");
+
+        // Stop at the first failed chunk, it's already logged
+        if !tr.is_success() {
+            println!("{}: {chunks}", symbol.symbol);
+            return Ok(tr);
+        }
+
+        chunks.add(chunk_qty, &tr);
+        remaining -= chunk_qty;
+        last_tr = Some(tr);
+        if remaining <= dec!(0) {
+            break;
+        }
+    }
+    println!("{}: {chunks}", symbol.symbol);
+
+    // Report a failure if a tradable quantity is left over
+    let unfilled = adj_quantity_verify_lot_size(symbol, remaining);
+    match last_tr {
+        Some(tr) if unfilled <= dec!(0) => Ok(tr),
+        _ => {
+            let tr = TradeResponse::FailureInternal(ier_new!(
+                6,
+                &format!(
+                    "For {} side: {side} only {} of {quantity} was placed, the remaining {remaining} exceeds max_slippage_percent: {max_slippage_percent}% {chunks} test: {test}",
+                    symbol.symbol,
+                    quantity - remaining,
+                )
+            ));
+            log_order_response(log_writer, &tr)?;
+            Ok(tr)
+        }
+    }
+}
+
+/// Totals of the chunks placed by market_order_in_chunks
+#[derive(Debug, Default)]
+struct ChunksSummary {
+    count: u32,
+    placed_qty: Decimal,
+    executed_qty: Decimal,
+    quote_qty: Decimal,
+}
+
+impl ChunksSummary {
+    /// Add a chunk of placed_qty, the executed and quote quantities
+    /// are only known for FULL and RESULT responses
+    fn add(&mut self, placed_qty: Decimal, tr: &TradeResponse) {
+        self.count += 1;
+        self.placed_qty += placed_qty;
+        match tr {
+            TradeResponse::SuccessFull(ftr) => {
+                self.executed_qty += ftr.executed_qty;
+                self.quote_qty += ftr.cummulative_quote_qty;
+            }
+            TradeResponse::SuccessResult(rtr) => {
+                self.executed_qty += rtr.executed_qty;
+                self.quote_qty += rtr.cummulative_quote_qty;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for ChunksSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "chunks: {} placed: {} executed: {} quote value: {}",
+            self.count, self.placed_qty, self.executed_qty, self.quote_qty
+        )
+    }
+}
+
 pub async fn market_order(
     config: &Configuration,
     ei: &ExchangeInfo,
@@ -40,7 +176,10 @@ pub async fn market_order(
         side,
         test
     );
-    let order_log_path = if let Some(olp) = &config.order_log_path {
+    // Paper orders are kept out of the real order log
+    let order_log_path = if config.paper_trading {
+        &config.paper_order_log_path
+    } else if let Some(olp) = &config.order_log_path {
         olp
     } else {
         return Err("No order log path, set it in the config file or use --order_log_path".into());
@@ -122,6 +261,51 @@ pub async fn market_order(
         }
     }
 
+    // Verify the order book can fill the quantity without too much slippage
+    if let Some(max_slippage_percent) = config.max_slippage_percent {
+        let order_book =
+            get_order_book(config, &symbol.symbol, Some(config.slippage_book_limit)).await?;
+        let est = order_book.estimate_fill(&side, quantity);
+        let slippage = slippage_percent(&side, avg_price.price, est.avg_price);
+        trace!(
+            "market_order: {} avg_price: {} slippage: {}% {}",
+            symbol_name,
+            avg_price.price,
+            slippage,
+            est
+        );
+
+        if !est.is_complete() || slippage > max_slippage_percent {
+            match config.slippage_action {
+                SlippageAction::Block => {
+                    let tr = TradeResponse::FailureInternal(ier_new!(
+                        5,
+                        &format!(
+                            "For {symbol_name} side: {side} estimated slippage: {:.2}% > max_slippage_percent: {max_slippage_percent}% {est} test: {test}",
+                            slippage.round_dp(2),
+                        )
+                    ));
+                    log_order_response(&mut log_writer, &tr)?;
+                    return Ok(tr);
+                }
+                SlippageAction::Split => {
+                    return market_order_in_chunks(
+                        config,
+                        &mut log_writer,
+                        ei,
+                        symbol,
+                        side,
+                        quantity,
+                        &avg_price,
+                        max_slippage_percent,
+                        test,
+                    )
+                    .await;
+                }
+            }
+        }
+    }
+
     let tr = binance_new_order_or_test(
         config,
         &mut log_writer,
@@ -137,15 +321,6 @@ pub async fn market_order(
     Ok(tr)
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct MarketCmdRec {
-    /// Symbol name
-    pub sym_name: String,
-
-    /// Number of shares
-    pub quantity: Decimal,
-}
-
 pub async fn buy_market_order_cmd(
     config: &Configuration,
     sym_name: &str,
@@ -159,10 +334,20 @@ pub async fn buy_market_order_cmd(
     );
 
     let ei = &get_exchange_info(config).await?;
-    let tr = market_order(config, ei, sym_name, &order_type, Side::BUY, config.test).await?;
-    println!("{tr}");
-
-    Ok(())
+    let mut summary = TradeSummary::new("buy-market", config.test);
+    match market_order(config, ei, sym_name, &order_type, Side::BUY, config.test).await {
+        Ok(tr) => {
+            println!("{tr}");
+            summary.add(&tr);
+            notify_trade_summary(config, &summary).await;
+            Ok(())
+        }
+        Err(e) => {
+            summary.add_error(sym_name, e.as_ref());
+            notify_trade_summary(config, &summary).await;
+            Err(e)
+        }
+    }
 }
 
 pub async fn sell_market_order_cmd(
@@ -178,8 +363,18 @@ pub async fn sell_market_order_cmd(
     );
 
     let ei = &get_exchange_info(config).await?;
-    let tr = market_order(config, ei, sym_name, &order_type, Side::SELL, config.test).await?;
-    println!("{tr}");
-
-    Ok(())
+    let mut summary = TradeSummary::new("sell-market", config.test);
+    match market_order(config, ei, sym_name, &order_type, Side::SELL, config.test).await {
+        Ok(tr) => {
+            println!("{tr}");
+            summary.add(&tr);
+            notify_trade_summary(config, &summary).await;
+            Ok(())
+        }
+        Err(e) => {
+            summary.add_error(sym_name, e.as_ref());
+            notify_trade_summary(config, &summary).await;
+            Err(e)
+        }
+    }
 }
@@ -14,7 +14,7 @@ use crate::{
     ier_new,
 };
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TradeRec {
     pub symbol: String,
@@ -0,0 +1,281 @@
+use std::fmt::{self, Display};
+
+use log::trace;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{get_api_response_body, Side},
+    configuration::Configuration,
+    de_string_or_number::de_string_or_number_to_u64,
+};
+
+/// A single price level of the order book, binance sends
+/// these as an array of two strings: ["price", "qty"]
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+pub struct OrderBookLevel {
+    pub price: Decimal,
+    pub qty: Decimal,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderBook {
+    #[serde(deserialize_with = "de_string_or_number_to_u64")]
+    pub last_update_id: u64,
+    pub bids: Vec<OrderBookLevel>,
+    pub asks: Vec<OrderBookLevel>,
+}
+
+/// The estimated result of a market order that walks the order book.
+#[derive(Debug, Clone, Default)]
+pub struct FillEstimate {
+    /// The quantity requested
+    pub quantity: Decimal,
+
+    /// The quantity the book can fill, less than quantity if the book is too thin
+    pub filled_qty: Decimal,
+
+    /// The quote asset value of filled_qty
+    pub quote_qty: Decimal,
+
+    /// Volume weighted average fill price
+    pub avg_price: Decimal,
+
+    /// Price of the last level touched
+    pub worst_price: Decimal,
+
+    /// Number of levels touched
+    pub levels: usize,
+}
+
+impl FillEstimate {
+    pub fn is_complete(&self) -> bool {
+        self.filled_qty >= self.quantity
+    }
+}
+
+impl Display for FillEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "qty: {} filled_qty: {} quote_qty: {} avg_price: {} worst_price: {} levels: {}",
+            self.quantity,
+            self.filled_qty,
+            self.quote_qty,
+            self.avg_price,
+            self.worst_price,
+            self.levels,
+        )
+    }
+}
+
+impl OrderBook {
+    /// The side of the book a market order on `side` is filled from.
+    pub fn levels_for(&self, side: &Side) -> &[OrderBookLevel] {
+        match side {
+            Side::BUY => &self.asks,
+            Side::SELL => &self.bids,
+        }
+    }
+
+    /// Walk the book estimating the fill of a market order for quantity.
+    pub fn estimate_fill(&self, side: &Side, quantity: Decimal) -> FillEstimate {
+        let mut est = FillEstimate {
+            quantity,
+            ..Default::default()
+        };
+
+        for level in self.levels_for(side) {
+            let remaining = quantity - est.filled_qty;
+            if remaining <= dec!(0) {
+                break;
+            }
+            let qty = remaining.min(level.qty);
+            est.filled_qty += qty;
+            est.quote_qty += qty * level.price;
+            est.worst_price = level.price;
+            est.levels += 1;
+        }
+
+        if est.filled_qty > dec!(0) {
+            est.avg_price = est.quote_qty / est.filled_qty;
+        }
+        trace!("estimate_fill: side: {} {}", side, est);
+
+        est
+    }
+
+    /// The largest quantity whose volume weighted average fill price
+    /// is no worse than bound_price, i.e. >= bound_price when selling
+    /// and <= bound_price when buying.
+    pub fn max_qty_within_avg_price(&self, side: &Side, bound_price: Decimal) -> Decimal {
+        let mut cum_qty = dec!(0);
+        let mut cum_quote = dec!(0);
+
+        for level in self.levels_for(side) {
+            let within = match side {
+                Side::BUY => level.price <= bound_price,
+                Side::SELL => level.price >= bound_price,
+            };
+            if within {
+                cum_qty += level.qty;
+                cum_quote += level.qty * level.price;
+                continue;
+            }
+
+            // Only part of this level can be taken, solve for x where:
+            //    (cum_quote + x * price) / (cum_qty + x) == bound_price
+            let x = (cum_quote - bound_price * cum_qty) / (bound_price - level.price);
+            if x > dec!(0) {
+                cum_qty += x.min(level.qty);
+            }
+            break;
+        }
+        trace!(
+            "max_qty_within_avg_price: side: {} bound_price: {} qty: {}",
+            side,
+            bound_price,
+            cum_qty
+        );
+
+        cum_qty
+    }
+}
+
+/// The percentage avg_price is worse than reference_price,
+/// negative values mean the fill is better than the reference.
+pub fn slippage_percent(side: &Side, reference_price: Decimal, avg_price: Decimal) -> Decimal {
+    if reference_price <= dec!(0) {
+        return dec!(0);
+    }
+    let diff = match side {
+        Side::BUY => avg_price - reference_price,
+        Side::SELL => reference_price - avg_price,
+    };
+
+    (diff / reference_price) * dec!(100)
+}
+
+/// The worst acceptable average fill price for a market order
+pub fn slippage_bound_price(
+    side: &Side,
+    reference_price: Decimal,
+    max_slippage_percent: Decimal,
+) -> Decimal {
+    let pct = max_slippage_percent / dec!(100);
+    match side {
+        Side::BUY => reference_price * (dec!(1) + pct),
+        Side::SELL => reference_price * (dec!(1) - pct),
+    }
+}
+
+/// Get the order book for symbol, limit defaults to 100
+/// and valid values are 5, 10, 20, 50, 100, 500, 1000, 5000.
+pub async fn get_order_book(
+    config: &Configuration,
+    symbol: &str,
+    limit: Option<u16>,
+) -> Result<OrderBook, Box<dyn std::error::Error>> {
+    let mut path = format!("/api/v3/depth?symbol={symbol}");
+    if let Some(l) = limit {
+        path.push_str(&format!("&limit={l}"));
+    }
+    trace!("get_order_book: path={}", path);
+
+    let order_book: OrderBook = serde_json::from_str(&get_api_response_body(config, &path).await?)?;
+    trace!(
+        "get_order_book: last_update_id: {} bids: {} asks: {}",
+        order_book.last_update_id,
+        order_book.bids.len(),
+        order_book.asks.len()
+    );
+
+    Ok(order_book)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::ORDER_BOOK;
+
+    #[test]
+    fn test_order_book() {
+        let ob: OrderBook = serde_json::from_str(ORDER_BOOK).unwrap();
+        assert_eq!(ob.last_update_id, 1027024);
+        assert_eq!(ob.bids.len(), 3);
+        assert_eq!(ob.asks.len(), 3);
+        assert_eq!(ob.bids[0].price, dec!(10));
+        assert_eq!(ob.bids[0].qty, dec!(1));
+        assert_eq!(ob.asks[2].price, dec!(15));
+        assert_eq!(ob.asks[2].qty, dec!(10));
+    }
+
+    #[test]
+    fn test_estimate_fill() {
+        let ob: OrderBook = serde_json::from_str(ORDER_BOOK).unwrap();
+
+        // Sell 3 takes all of the first two bid levels
+        let est = ob.estimate_fill(&Side::SELL, dec!(3));
+        assert!(est.is_complete());
+        assert_eq!(est.filled_qty, dec!(3));
+        assert_eq!(est.quote_qty, dec!(29));
+        assert_eq!(est.worst_price, dec!(9.5));
+        assert_eq!(est.levels, 2);
+
+        // Buy 1.5 partially takes the second ask level
+        let est = ob.estimate_fill(&Side::BUY, dec!(1.5));
+        assert!(est.is_complete());
+        assert_eq!(est.quote_qty, dec!(16));
+        assert_eq!(est.worst_price, dec!(11));
+
+        // Selling more than the book holds is incomplete
+        let est = ob.estimate_fill(&Side::SELL, dec!(100));
+        assert!(!est.is_complete());
+        assert_eq!(est.filled_qty, dec!(8));
+        assert_eq!(est.levels, 3);
+    }
+
+    #[test]
+    fn test_slippage_percent() {
+        assert_eq!(slippage_percent(&Side::SELL, dec!(10), dec!(9)), dec!(10));
+        assert_eq!(slippage_percent(&Side::BUY, dec!(10), dec!(11)), dec!(10));
+        assert_eq!(slippage_percent(&Side::BUY, dec!(10), dec!(9)), dec!(-10));
+        assert_eq!(
+            slippage_bound_price(&Side::SELL, dec!(10), dec!(5)),
+            dec!(9.5)
+        );
+        assert_eq!(
+            slippage_bound_price(&Side::BUY, dec!(10), dec!(5)),
+            dec!(10.5)
+        );
+    }
+
+    #[test]
+    fn test_max_qty_within_avg_price() {
+        let ob: OrderBook = serde_json::from_str(ORDER_BOOK).unwrap();
+
+        // Only the best bid averages 10, any of the next level lowers it
+        assert_eq!(ob.max_qty_within_avg_price(&Side::SELL, dec!(10)), dec!(1));
+
+        // Average of 9.25: the first two levels are >= 9.25 and
+        // (29 + 8x) / (3 + x) == 9.25 => x == 1
+        assert_eq!(
+            ob.max_qty_within_avg_price(&Side::SELL, dec!(9.25)),
+            dec!(4)
+        );
+
+        // Average of 9.0: (29 + 8x) / (3 + x) == 9 => x == 2
+        assert_eq!(ob.max_qty_within_avg_price(&Side::SELL, dec!(9)), dec!(5));
+
+        // Average of 10.75: (10.5 + 11x) / (1 + x) == 10.75 => x == 1
+        assert_eq!(
+            ob.max_qty_within_avg_price(&Side::BUY, dec!(10.75)),
+            dec!(2)
+        );
+
+        // Nothing is better than the best ask
+        assert_eq!(ob.max_qty_within_avg_price(&Side::BUY, dec!(10)), dec!(0));
+    }
+}
@@ -1,4 +1,4 @@
-use std::{error::Error, fmt, fs::File, io::BufRead, io::BufReader, path::PathBuf};
+use std::{cell::RefCell, error::Error, fmt, fs::File, io::BufRead, io::BufReader, path::PathBuf};
 
 use clap::ArgMatches;
 use log::trace;
@@ -9,16 +9,19 @@ use rust_decimal::prelude::*;
 use rust_decimal_macros::dec;
 use semver::Version;
 use time_ms_conversions::time_ms_to_utc;
+use tokentaxrec::TokenTaxRec;
 
 use crate::{
+    binance_dust::ttr_vec_from_dust_response,
     binance_withdraw_cmd::WithdrawParams,
-    common::{InternalErrorRec, ResponseErrorRec, Side},
+    common::{create_buf_writer, InternalErrorRec, ResponseErrorRec, Side},
     configuration::Configuration,
     de_string_or_number::{de_string_or_number_to_i64, de_string_or_number_to_u64},
 };
 
 use crate::common::OrderType;
 
+#[allow(unused)]
 #[derive(Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HeaderRec {
@@ -255,6 +258,13 @@ pub struct WithdrawResponseRec {
     #[serde(default)]
     pub id: String,
 
+    // Time the withdrawal was requested, 0 for entries logged before it was added
+    #[serde(default)]
+    pub time: i64,
+
+    // The capital withdraw response is only the id so success
+    // is determined after parsing
+    #[serde(default)]
     pub success: bool,
 }
 
@@ -311,6 +321,74 @@ impl Default for WithdrawResponseRec {
             msg: "".to_string(),
             success: false,
             id: "".to_string(),
+            time: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DustTransferResultRec {
+    pub amount: Decimal,
+    pub from_asset: String,
+    #[serde(deserialize_with = "de_string_or_number_to_i64")]
+    pub operate_time: i64,
+    pub service_charge_amount: Decimal,
+    #[serde(deserialize_with = "de_string_or_number_to_u64")]
+    pub tran_id: u64,
+    pub transfered_amount: Decimal,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DustResponseRec {
+    #[serde(default)]
+    pub test: bool,
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub response_body: String,
+    #[serde(default)]
+    pub value_usd: Decimal,
+    pub total_service_charge: Decimal,
+    pub total_transfered: Decimal,
+    pub transfer_result: Vec<DustTransferResultRec>,
+}
+
+impl fmt::Display for DustResponseRec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        trace!("Display::drr: {:#?}", self);
+        let assets: Vec<&str> = self
+            .transfer_result
+            .iter()
+            .map(|r| r.from_asset.as_str())
+            .collect();
+        write!(
+            f,
+            "{} {} valued at {} to {:.8} BNB service charge {:.8} BNB",
+            if self.test {
+                "Successful test converting dust of"
+            } else {
+                "Converted dust of"
+            },
+            assets.join(","),
+            dec_to_usd_string(self.value_usd),
+            self.total_transfered,
+            self.total_service_charge,
+        )
+    }
+}
+
+impl Default for DustResponseRec {
+    fn default() -> DustResponseRec {
+        DustResponseRec {
+            test: false,
+            query: "".to_string(),
+            response_body: "".to_string(),
+            value_usd: dec!(0),
+            total_service_charge: dec!(0),
+            total_transfered: dec!(0),
+            transfer_result: vec![],
         }
     }
 }
@@ -371,6 +449,96 @@ impl Default for UnknownTradeResponseRec {
     }
 }
 
+/// An executionReport from the user data stream, the serialized field
+/// names are the long names and the stream's short names are aliases.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionReportRec {
+    #[serde(alias = "E")]
+    pub event_time: i64,
+    #[serde(alias = "s")]
+    pub symbol: String,
+    #[serde(alias = "c")]
+    pub client_order_id: String,
+    #[serde(alias = "S")]
+    pub side: Side,
+    #[serde(alias = "o")]
+    #[serde(rename = "type")]
+    pub order_type: OrderType,
+    #[serde(alias = "q")]
+    pub orig_qty: Decimal,
+    #[serde(alias = "p")]
+    pub price: Decimal,
+    #[serde(alias = "x")]
+    pub execution_type: String, // NEW, CANCELED, REPLACED, REJECTED, TRADE, EXPIRED
+    #[serde(alias = "X")]
+    pub status: String,
+    #[serde(alias = "r")]
+    pub reject_reason: String,
+    #[serde(alias = "i")]
+    pub order_id: u64,
+    #[serde(alias = "l")]
+    pub last_executed_qty: Decimal,
+    #[serde(alias = "z")]
+    pub executed_qty: Decimal,
+    #[serde(alias = "L")]
+    pub last_executed_price: Decimal,
+    #[serde(alias = "n")]
+    pub commission: Decimal,
+    #[serde(alias = "N")]
+    pub commission_asset: Option<String>,
+    #[serde(alias = "T")]
+    pub transact_time: i64,
+    #[serde(alias = "t")]
+    pub trade_id: i64,
+    #[serde(alias = "m")]
+    pub is_maker: bool,
+    #[serde(alias = "Z")]
+    pub cummulative_quote_qty: Decimal,
+    #[serde(alias = "Y")]
+    pub last_quote_qty: Decimal,
+    #[serde(default)]
+    pub value_usd: Decimal,
+    #[serde(default)]
+    pub commission_usd: Decimal,
+}
+
+impl ExecutionReportRec {
+    /// True if the report is a fill of some or all of the order
+    pub fn is_fill(&self) -> bool {
+        self.execution_type == "TRADE"
+    }
+}
+
+impl fmt::Display for ExecutionReportRec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        trace!("Display::err: {:#?}", self);
+        if self.is_fill() {
+            write!(
+                f,
+                "{:8} {:14.6} at {:.4}/per of {:10} {} order_id: {}",
+                self.side,
+                self.last_executed_qty,
+                self.last_executed_price.round_dp(4),
+                self.symbol,
+                self.status,
+                self.order_id,
+            )
+        } else {
+            write!(
+                f,
+                "{:8} {:14.6} of {:10} {} {} order_id: {}",
+                self.side,
+                self.orig_qty,
+                self.symbol,
+                self.execution_type,
+                self.status,
+                self.order_id,
+            )
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub enum TradeResponse {
     SuccessAck(AckTradeResponseRec),
@@ -380,13 +548,51 @@ pub enum TradeResponse {
     SuccessWithdraw(WithdrawResponseRec),
     FailureWithdraw(WithdrawResponseRec),
     SuccessTestWithdraw(WithdrawResponseRec),
+    SuccessDust(DustResponseRec),
+    SuccessTestDust(DustResponseRec),
     SuccessUnknown(UnknownTradeResponseRec),
+    SuccessExecutionReport(ExecutionReportRec),
     FailureResponse(ResponseErrorRec),
     FailureInternal(InternalErrorRec),
 }
 
 impl Error for TradeResponse {}
 
+/// Define TRADE_RESPONSE_KINDS and TradeResponse::kind from one list
+/// of the variants, the match of kind doesn't compile if one is missing
+macro_rules! trade_response_kinds {
+    ( $( $variant:ident ),* $(,)? ) => {
+        /// The names of the TradeResponse variants
+        pub const TRADE_RESPONSE_KINDS: [&str; [$(stringify!($variant)),*].len()] =
+            [$(stringify!($variant)),*];
+
+        impl TradeResponse {
+            /// The name of the variant, one of TRADE_RESPONSE_KINDS
+            pub fn kind(&self) -> &'static str {
+                match self {
+                    $(TradeResponse::$variant(_) => stringify!($variant),)*
+                }
+            }
+        }
+    };
+}
+
+trade_response_kinds!(
+    SuccessAck,
+    SuccessResult,
+    SuccessFull,
+    SuccessTest,
+    SuccessWithdraw,
+    FailureWithdraw,
+    SuccessTestWithdraw,
+    SuccessDust,
+    SuccessTestDust,
+    SuccessUnknown,
+    SuccessExecutionReport,
+    FailureResponse,
+    FailureInternal,
+);
+
 impl fmt::Display for TradeResponse {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -397,18 +603,43 @@ impl fmt::Display for TradeResponse {
             TradeResponse::SuccessWithdraw(tr) => write!(f, "{tr}"),
             TradeResponse::FailureWithdraw(tr) => write!(f, "{tr}"),
             TradeResponse::SuccessTestWithdraw(tr) => write!(f, "{tr}"),
+            TradeResponse::SuccessDust(tr) => write!(f, "{tr}"),
+            TradeResponse::SuccessTestDust(tr) => write!(f, "{tr}"),
             TradeResponse::SuccessUnknown(tr) => write!(f, "{tr}"),
+            TradeResponse::SuccessExecutionReport(tr) => write!(f, "{tr}"),
             TradeResponse::FailureResponse(ber) => write!(f, "{ber}"),
             TradeResponse::FailureInternal(ier) => write!(f, "{ier}"),
         }
     }
 }
 
+impl TradeResponse {
+    pub fn is_success(&self) -> bool {
+        !matches!(
+            self,
+            TradeResponse::FailureWithdraw(_)
+                | TradeResponse::FailureResponse(_)
+                | TradeResponse::FailureInternal(_)
+        )
+    }
+
+    /// The value in USD of a completed trade, withdraw or dust conversion, otherwise 0
+    pub fn value_usd(&self) -> Decimal {
+        match self {
+            TradeResponse::SuccessFull(tr) => tr.value_usd,
+            TradeResponse::SuccessWithdraw(tr) => tr.params.quantity_usd,
+            TradeResponse::SuccessDust(tr) => tr.value_usd,
+            TradeResponse::SuccessExecutionReport(tr) => tr.value_usd,
+            _ => dec!(0),
+        }
+    }
+}
+
 pub async fn iterate_order_log(
     order_log_path: Option<PathBuf>,
     process_line: impl Fn(&str, usize) -> Result<(), Box<dyn std::error::Error>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    println!("terate_order_log: order_log_path={order_log_path:?}");
+    trace!("iterate_order_log: order_log_path={order_log_path:?}");
 
     match &order_log_path {
         Some(path) => {
@@ -437,62 +668,60 @@ pub async fn iterate_order_log(
     Ok(())
 }
 
-pub fn display_order_log_line(
-    line: &str,
-    line_number: usize,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let tr: TradeResponse = match serde_json::from_str(line) {
-        Ok(tr) => tr,
-        Err(e) => {
-            return Err(format!("line: {} Err: {}", line_number + 1, e).into());
-        }
-    };
-    println!("{tr:#?}");
-
-    Ok(())
-}
-
-pub async fn display_order_log(config: &Configuration) -> Result<(), Box<dyn std::error::Error>> {
-    println!("display_order_log: config={config:?}");
-
-    iterate_order_log(config.order_log_path.clone(), display_order_log_line).await?;
-
-    Ok(())
-}
-
 pub async fn process_order_log(
     config: &Configuration,
-    _sc_matches: &ArgMatches,
+    sc_matches: &ArgMatches,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    //println!("process_order_log: config={config:?} sc_matches={_sc_matches:?}");
-
-    pub fn process_order_log_line(
-        line: &str,
-        line_number: usize,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let tr: TradeResponse = match serde_json::from_str(line) {
-            Ok(tr) => tr,
-            Err(e) => {
-                return Err(format!("line: {} Err: {}", line_number + 1, e).into());
-            }
-        };
+    //println!("process_order_log: config={config:?} sc_matches={sc_matches:?}");
+
+    // Dust conversions are collected as TokenTaxRecs
+    let ttr_vec = RefCell::new(Vec::<TokenTaxRec>::new());
+
+    let process_order_log_line =
+        |line: &str, line_number: usize| -> Result<(), Box<dyn std::error::Error>> {
+            let tr: TradeResponse = match serde_json::from_str(line) {
+                Ok(tr) => tr,
+                Err(e) => {
+                    return Err(format!("line: {} Err: {}", line_number + 1, e).into());
+                }
+            };
 
-        match tr {
-            TradeResponse::SuccessFull(tr) => {
-                println!("{line_number} SuccessFull: {tr:#?}");
-            }
-            TradeResponse::SuccessWithdraw(tr) => {
-                println!("{line_number} SuccessWithdraw: {tr:#?}");
+            match tr {
+                TradeResponse::SuccessFull(tr) => {
+                    println!("{line_number} SuccessFull: {tr:#?}");
+                }
+                TradeResponse::SuccessWithdraw(tr) => {
+                    println!("{line_number} SuccessWithdraw: {tr:#?}");
+                }
+                TradeResponse::SuccessExecutionReport(tr) => {
+                    println!("{line_number} SuccessExecutionReport: {tr:#?}");
+                }
+                TradeResponse::SuccessDust(tr) => {
+                    println!("{line_number} SuccessDust: {tr:#?}");
+                    ttr_vec
+                        .borrow_mut()
+                        .append(&mut ttr_vec_from_dust_response(&config.domain, &tr));
+                }
+                _ => (),
             }
-            _ => (),
-        }
 
-        Ok(())
-    }
+            Ok(())
+        };
 
     // For the moment just display
     iterate_order_log(config.order_log_path.clone(), process_order_log_line).await?;
 
+    if let Some(out_file) = sc_matches.value_of("OUT_FILE") {
+        let mut token_tax_writer = csv::Writer::from_writer(create_buf_writer(out_file)?);
+        let ttr_vec = ttr_vec.into_inner();
+        let len = ttr_vec.len();
+        for ttr in ttr_vec {
+            token_tax_writer.serialize(ttr)?;
+        }
+        token_tax_writer.flush()?;
+        println!("Writing token tax records to {out_file}: Done; records written: {len}");
+    }
+
     Ok(())
 }
 
@@ -684,6 +913,64 @@ mod test {
         assert_eq!(order_response.error_internal, "some error message");
     }
 
+    const SUCCESS_DUST: &str = r#"{
+        "totalServiceCharge":"0.02102542",
+        "totalTransfered":"1.05127099",
+        "transferResult":[
+            {
+                "amount":"0.03000000",
+                "fromAsset":"ETH",
+                "operateTime":1563368549307,
+                "serviceChargeAmount":"0.00500000",
+                "tranId":2970932918,
+                "transferedAmount":"0.25000000"
+            },
+            {
+                "amount":"0.09000000",
+                "fromAsset":"LTC",
+                "operateTime":1563368549404,
+                "serviceChargeAmount":"0.01548000",
+                "tranId":2970932918,
+                "transferedAmount":"0.77400000"
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_order_response_success_dust() {
+        let order_response: DustResponseRec = match serde_json::from_str(SUCCESS_DUST) {
+            Ok(response) => response,
+            Err(e) => panic!("Error processing response: e={e}"),
+        };
+        assert!(!order_response.test);
+        assert_eq!(order_response.total_service_charge, dec!(0.02102542));
+        assert_eq!(order_response.total_transfered, dec!(1.05127099));
+        assert_eq!(order_response.value_usd, dec!(0));
+        assert_eq!(order_response.transfer_result.len(), 2);
+        let r = &order_response.transfer_result[1];
+        assert_eq!(r.amount, dec!(0.09));
+        assert_eq!(r.from_asset, "LTC");
+        assert_eq!(r.operate_time, 1563368549404);
+        assert_eq!(r.service_charge_amount, dec!(0.01548));
+        assert_eq!(r.tran_id, 2970932918);
+        assert_eq!(r.transfered_amount, dec!(0.774));
+
+        // Round trip through the order log as a TradeResponse
+        let tr = TradeResponse::SuccessDust(order_response);
+        let line = serde_json::to_string(&tr).unwrap();
+        let tr: TradeResponse = serde_json::from_str(&line).unwrap();
+        assert!(matches!(tr, TradeResponse::SuccessDust(drr) if drr.transfer_result.len() == 2));
+    }
+
+    #[test]
+    fn test_order_response_withdraw() {
+        let wrr: WithdrawResponseRec =
+            serde_json::from_str(r#"{"id":"7213fea8e94b4a5593d507237e5a555b"}"#).unwrap();
+        assert_eq!(wrr.id, "7213fea8e94b4a5593d507237e5a555b");
+        assert!(!wrr.success);
+        assert!(wrr.msg.is_empty());
+    }
+
     #[test]
     fn test_order_response_semver() {
         let ver = Version::parse("1.2.3-alpha1+1234").unwrap();
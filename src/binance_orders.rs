@@ -181,6 +181,11 @@ pub async fn get_open_orders(
     config: &Configuration,
     symbol: &str,
 ) -> Result<Orders, Box<dyn std::error::Error>> {
+    // Paper trading orders are always filled immediately
+    if config.paper_trading {
+        return Ok(Orders { orders: vec![] });
+    }
+
     let mut params: Vec<(&str, &str)> = Vec::new();
 
     if !symbol.is_empty() {
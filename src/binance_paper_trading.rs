@@ -0,0 +1,381 @@
+//! Paper trading, a simulated exchange backend.
+//!
+//! When `paper_trading` is enabled orders are filled against a local
+//! simulated account whose state is persisted in `paper_state_path`
+//! and are logged to `paper_order_log_path`. A test order is filled
+//! against the account but the result isn't saved. Market data, such
+//! as prices, still comes from the exchange.
+//! Withdrawals and dust conversions are refused.
+use std::{collections::BTreeMap, fs, io::Write};
+
+use log::trace;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use time_ms_conversions::utc_now_to_time_ms;
+
+use crate::{
+    binance_account_info::{AccountInfo, Balance},
+    binance_avg_price::get_avg_price,
+    binance_exchange_info::ExchangeInfo,
+    binance_klines::{get_klines, KlineInterval},
+    binance_order_response::{Fill, FullTradeResponseRec, TradeResponse},
+    binance_trade::{
+        convert, convert_commission, log_order_response, MarketQuantityType, TradeOrderType,
+    },
+    binance_verify_order::adj_quantity_verify_lot_size,
+    common::{InternalErrorRec, OrderType, Side},
+    configuration::{Configuration, PaperFillPrice},
+    ier_new,
+};
+
+/// The persisted state of the paper trading account
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperState {
+    pub balances: Vec<Balance>,
+    pub next_order_id: u64,
+    pub next_trade_id: u64,
+    pub update_time: i64,
+}
+
+impl PaperState {
+    pub fn new(config: &Configuration) -> PaperState {
+        PaperState {
+            balances: config
+                .paper_initial_balances
+                .iter()
+                .map(|pbr| Balance {
+                    asset: pbr.asset.clone(),
+                    free: pbr.free,
+                    locked: dec!(0),
+                    price_in_usd: dec!(0),
+                    value_in_usd: dec!(0),
+                })
+                .collect(),
+            next_order_id: 1,
+            next_trade_id: 1,
+            update_time: utc_now_to_time_ms(),
+        }
+    }
+
+    /// Load the state from paper_state_path or create a new one if it doesn't exist
+    pub fn load(config: &Configuration) -> Result<PaperState, Box<dyn std::error::Error>> {
+        let path = &config.paper_state_path;
+        if path.exists() {
+            let data = fs::read_to_string(path)?;
+            match serde_json::from_str(&data) {
+                Ok(ps) => Ok(ps),
+                Err(e) => Err(format!("Error processing {}: {e}", path.to_string_lossy()).into()),
+            }
+        } else {
+            trace!("PaperState::load: new state, {:?} not found", path);
+            Ok(PaperState::new(config))
+        }
+    }
+
+    pub fn save(&self, config: &Configuration) -> Result<(), Box<dyn std::error::Error>> {
+        let path = &config.paper_state_path;
+        if let Some(parent_dirs) = path.parent() {
+            // Be sure the parent directories exist
+            fs::create_dir_all(parent_dirs)?;
+        }
+        let mut writer = fs::File::create(path)?;
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    pub fn account_info(&self, config: &Configuration) -> AccountInfo {
+        // Commissions are in basis points
+        let commission = config.paper_fee_percent * dec!(100);
+        let balances_map: BTreeMap<String, Balance> = self
+            .balances
+            .iter()
+            .map(|b| (b.asset.clone(), b.clone()))
+            .collect();
+
+        AccountInfo {
+            account_type: "SPOT".to_string(),
+            can_deposit: false,
+            can_trade: true,
+            can_withdraw: false,
+            buyer_commission: dec!(0),
+            maker_commission: commission,
+            seller_commission: dec!(0),
+            taker_commission: commission,
+            update_time: self.update_time,
+            permissions: vec!["SPOT".to_string()],
+            balances_map,
+        }
+    }
+
+    fn balance_mut(&mut self, asset: &str) -> &mut Balance {
+        let idx = match self.balances.iter().position(|b| b.asset == asset) {
+            Some(idx) => idx,
+            None => {
+                self.balances.push(Balance {
+                    asset: asset.to_string(),
+                    free: dec!(0),
+                    locked: dec!(0),
+                    price_in_usd: dec!(0),
+                    value_in_usd: dec!(0),
+                });
+                self.balances.len() - 1
+            }
+        };
+
+        &mut self.balances[idx]
+    }
+
+    /// Fill a market order for qty of base_asset at price, the commission
+    /// is paid in the asset received.
+    pub fn fill_market_order(
+        &mut self,
+        base_asset: &str,
+        quote_asset: &str,
+        side: Side,
+        qty: Decimal,
+        price: Decimal,
+        fee_percent: Decimal,
+    ) -> Result<FullTradeResponseRec, String> {
+        let fee_rate = fee_percent / dec!(100);
+        let quote_qty = qty * price;
+
+        let (commission, commission_asset) = match side {
+            Side::BUY => {
+                let quote = self.balance_mut(quote_asset);
+                if quote.free < quote_qty {
+                    return Err(format!(
+                        "Insufficient {quote_asset}, need {quote_qty} have {}",
+                        quote.free
+                    ));
+                }
+                quote.free -= quote_qty;
+
+                let commission = qty * fee_rate;
+                self.balance_mut(base_asset).free += qty - commission;
+                (commission, base_asset)
+            }
+            Side::SELL => {
+                let base = self.balance_mut(base_asset);
+                if base.free < qty {
+                    return Err(format!(
+                        "Insufficient {base_asset}, need {qty} have {}",
+                        base.free
+                    ));
+                }
+                base.free -= qty;
+
+                let commission = quote_qty * fee_rate;
+                self.balance_mut(quote_asset).free += quote_qty - commission;
+                (commission, quote_asset)
+            }
+        };
+
+        let order_id = self.next_order_id;
+        let trade_id = self.next_trade_id;
+        self.next_order_id += 1;
+        self.next_trade_id += 1;
+        self.update_time = utc_now_to_time_ms();
+
+        Ok(FullTradeResponseRec {
+            query: "paper-trading".to_string(),
+            symbol: base_asset.to_string() + quote_asset,
+            order_id,
+            client_order_id: format!("paper-{order_id}"),
+            transact_time: self.update_time,
+            orig_qty: qty,
+            executed_qty: qty,
+            cummulative_quote_qty: quote_qty,
+            status: "FILLED".to_string(),
+            time_in_force: "GTC".to_string(),
+            order_type: OrderType::MARKET,
+            side,
+            fills: vec![Fill {
+                commission_asset: commission_asset.to_string(),
+                commission,
+                price,
+                qty,
+                trade_id,
+            }],
+            ..Default::default()
+        })
+    }
+}
+
+/// The simulated AccountInfo
+pub fn paper_account_info(
+    config: &Configuration,
+) -> Result<AccountInfo, Box<dyn std::error::Error>> {
+    Ok(PaperState::load(config)?.account_info(config))
+}
+
+async fn paper_fill_price(
+    config: &Configuration,
+    symbol: &str,
+) -> Result<Decimal, Box<dyn std::error::Error>> {
+    let price = match config.paper_fill_price {
+        PaperFillPrice::AvgPrice => get_avg_price(config, symbol).await?.price,
+        PaperFillPrice::Kline => {
+            let krs = get_klines(config, symbol, KlineInterval::Mins1, None, None, Some(1)).await?;
+            match krs.last() {
+                Some(kr) => kr.close,
+                None => return Err(format!("No KlineRec available for {symbol}").into()),
+            }
+        }
+    };
+
+    Ok(price)
+}
+
+/// Fill an order using the paper trading account, this has
+/// the same interface as binance_new_order_or_test.
+pub async fn paper_new_order(
+    config: &Configuration,
+    mut log_writer: &mut dyn Write,
+    ei: &ExchangeInfo,
+    symbol: &str,
+    side: Side,
+    order_type: TradeOrderType,
+    test: bool,
+) -> Result<TradeResponse, Box<dyn std::error::Error>> {
+    let ei_symbol = match ei.get_symbol(symbol) {
+        Some(s) => s,
+        None => {
+            return Err(format!("{symbol} was not found in exchange_info").into());
+        }
+    };
+
+    let price = paper_fill_price(config, symbol).await?;
+    let qty = match order_type {
+        TradeOrderType::Market(MarketQuantityType::Quantity(qty)) => qty,
+        TradeOrderType::Market(MarketQuantityType::QuoteOrderQty(value)) => {
+            adj_quantity_verify_lot_size(ei_symbol, value / price)
+        }
+    };
+    trace!(
+        "paper_new_order: {} side: {} qty: {} price: {}",
+        symbol,
+        side,
+        qty,
+        price
+    );
+
+    let mut state = PaperState::load(config)?;
+    let tr = match state.fill_market_order(
+        &ei_symbol.base_asset,
+        &ei_symbol.quote_asset,
+        side,
+        qty,
+        price,
+        config.paper_fee_percent,
+    ) {
+        Ok(mut full) => {
+            full.test = test;
+            full.value_usd = convert(
+                config,
+                full.transact_time,
+                &ei_symbol.quote_asset,
+                full.cummulative_quote_qty,
+                "USD",
+            )
+            .await?;
+            full.commission_usd = convert_commission(config, &full, "USD").await?;
+
+            // A test order only validates, the filled state is discarded
+            if !test {
+                state.save(config)?;
+            }
+
+            TradeResponse::SuccessFull(full)
+        }
+        Err(msg) => TradeResponse::FailureInternal(ier_new!(10, &msg)),
+    };
+    trace!("paper_new_order: tr={:#?}", tr);
+
+    log_order_response(&mut log_writer, &tr)?;
+
+    Ok(tr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::configuration::PaperBalanceRec;
+
+    fn paper_config() -> Configuration {
+        Configuration {
+            paper_initial_balances: vec![PaperBalanceRec {
+                asset: "USD".to_string(),
+                free: dec!(1000),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_paper_state_fill_market_order() {
+        let config = paper_config();
+        let mut ps = PaperState::new(&config);
+        assert_eq!(ps.balances.len(), 1);
+
+        // Buy 2 at 100 paying a 1% commission in the base asset
+        let full = ps
+            .fill_market_order("BNB", "USD", Side::BUY, dec!(2), dec!(100), dec!(1))
+            .unwrap();
+        assert_eq!(full.symbol, "BNBUSD");
+        assert_eq!(full.order_id, 1);
+        assert_eq!(full.side, Side::BUY);
+        assert_eq!(full.status, "FILLED");
+        assert_eq!(full.executed_qty, dec!(2));
+        assert_eq!(full.cummulative_quote_qty, dec!(200));
+        assert_eq!(full.fills.len(), 1);
+        assert_eq!(full.fills[0].commission, dec!(0.02));
+        assert_eq!(full.fills[0].commission_asset, "BNB");
+        assert_eq!(full.fills[0].trade_id, 1);
+
+        let ai = ps.account_info(&config);
+        assert_eq!(ai.balances_map["USD"].free, dec!(800));
+        assert_eq!(ai.balances_map["BNB"].free, dec!(1.98));
+        assert_eq!(ai.taker_commission, dec!(10));
+
+        // Sell 1 at 150 paying a 1% commission in the quote asset
+        let full = ps
+            .fill_market_order("BNB", "USD", Side::SELL, dec!(1), dec!(150), dec!(1))
+            .unwrap();
+        assert_eq!(full.order_id, 2);
+        assert_eq!(full.fills[0].commission, dec!(1.5));
+        assert_eq!(full.fills[0].commission_asset, "USD");
+        let ai = ps.account_info(&config);
+        assert_eq!(ai.balances_map["USD"].free, dec!(948.5));
+        assert_eq!(ai.balances_map["BNB"].free, dec!(0.98));
+
+        // Insufficient balances fail and leave the state unchanged
+        assert!(ps
+            .fill_market_order("BNB", "USD", Side::SELL, dec!(1), dec!(150), dec!(1))
+            .is_err());
+        assert!(ps
+            .fill_market_order("BNB", "USD", Side::BUY, dec!(10), dec!(150), dec!(1))
+            .is_err());
+        assert_eq!(ps.next_order_id, 3);
+        let ai = ps.account_info(&config);
+        assert_eq!(ai.balances_map["USD"].free, dec!(948.5));
+        assert_eq!(ai.balances_map["BNB"].free, dec!(0.98));
+    }
+
+    #[test]
+    fn test_paper_state_serde() {
+        let config = paper_config();
+        let mut ps = PaperState::new(&config);
+        ps.fill_market_order("BNB", "USD", Side::BUY, dec!(1), dec!(100), dec!(0))
+            .unwrap();
+
+        let json = serde_json::to_string(&ps).unwrap();
+        let ps2: PaperState = serde_json::from_str(&json).unwrap();
+        assert_eq!(ps2.balances, ps.balances);
+        assert_eq!(ps2.next_order_id, 2);
+        assert_eq!(ps2.next_trade_id, 2);
+    }
+}
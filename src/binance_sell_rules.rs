@@ -0,0 +1,471 @@
+//! Take profit, trailing stop and maximum value sell rules.
+//!
+//! The rules are defined in the configuration `sell_rules` section
+//! and evaluated against the current price and the cost basis
+//! calculated from the `myTrades` history of the asset's symbol.
+use log::trace;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+
+use dec_utils::dec_to_usd_string;
+use time_ms_conversions::utc_now_to_time_ms;
+
+use crate::{
+    binance_account_info::get_account_info,
+    binance_avg_price::get_avg_price,
+    binance_exchange_info::{get_exchange_info, ExchangeInfo},
+    binance_klines::{get_klines, KlineInterval, KlineRec},
+    binance_market_order_cmd::market_order,
+    binance_my_trades::{get_my_trades, TradeRec},
+    binance_trade::{MarketQuantityType, TradeOrderType},
+    common::{are_you_sure_stdout_stdin, InternalErrorRec, Side},
+    configuration::{Configuration, SellRuleRec},
+    ier_new,
+};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// Quantity and cost, in the quote asset, of the currently held
+/// position using the average cost method.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CostBasis {
+    pub qty: Decimal,
+    pub cost: Decimal,
+    pub last_buy_time: i64,
+}
+
+impl CostBasis {
+    pub fn avg_price(&self) -> Option<Decimal> {
+        if self.qty > dec!(0) {
+            Some(self.cost / self.qty)
+        } else {
+            None
+        }
+    }
+
+    /// Add a buy of qty costing cost at time
+    pub fn buy(&mut self, qty: Decimal, cost: Decimal, time: i64) {
+        self.qty += qty;
+        self.cost += cost;
+        self.last_buy_time = time;
+    }
+
+    /// Remove a sell of qty at the average price, returns the quantity
+    /// removed, which is less than qty if more is sold than held, and its cost.
+    pub fn sell(&mut self, qty: Decimal) -> (Decimal, Decimal) {
+        match self.avg_price() {
+            Some(avg_price) => {
+                let qty = qty.min(self.qty);
+                let cost = avg_price * qty;
+                self.cost -= cost;
+                self.qty -= qty;
+                (qty, cost)
+            }
+            None => (dec!(0), dec!(0)),
+        }
+    }
+}
+
+/// Calculate the cost basis from trades sorted by time.
+pub fn cost_basis_from_trades(
+    base_asset: &str,
+    quote_asset: &str,
+    trades: &[TradeRec],
+) -> CostBasis {
+    let mut cb = CostBasis::default();
+
+    for tr in trades {
+        if tr.is_buyer {
+            let mut qty = tr.qty;
+            let mut cost = tr.quote_qty;
+            if tr.commission_asset == base_asset {
+                qty -= tr.commission;
+            } else if tr.commission_asset == quote_asset {
+                cost += tr.commission;
+            }
+            cb.buy(qty, cost, tr.time);
+        } else {
+            cb.sell(tr.qty);
+        }
+    }
+    trace!("cost_basis_from_trades: {}: {:?}", base_asset, cb);
+
+    cb
+}
+
+/// Get all of the trades for symbol
+pub async fn get_all_my_trades(
+    config: &Configuration,
+    symbol: &str,
+) -> Result<Vec<TradeRec>, Box<dyn std::error::Error>> {
+    const LIMIT: i64 = 1000;
+
+    let mut trades = Vec::<TradeRec>::new();
+    let mut from_id = 0u64;
+    loop {
+        let mut t = get_my_trades(config, symbol, Some(from_id), None, None, Some(LIMIT)).await?;
+        let len = t.trades.len();
+        if let Some(last) = t.trades.last() {
+            from_id = last.id as u64 + 1;
+        }
+        trades.append(&mut t.trades);
+        if len < LIMIT as usize {
+            break;
+        }
+    }
+
+    Ok(trades)
+}
+
+/// The highest of price and the highs of klines opening at or after
+/// time_ms, a kline opening before time_ms may have its high before it.
+fn high_since(price: Decimal, time_ms: i64, klines: &[KlineRec]) -> Decimal {
+    klines
+        .iter()
+        .filter(|kr| kr.open_time >= time_ms)
+        .fold(price, |high, kr| high.max(kr.high))
+}
+
+/// Get the highest price of symbol since time_ms. The rest of the day
+/// of time_ms uses 5 minute klines and the following days daily klines.
+async fn get_high_since(
+    config: &Configuration,
+    symbol_name: &str,
+    price: Decimal,
+    time_ms: i64,
+) -> Result<Decimal, Box<dyn std::error::Error>> {
+    let next_day_ms = (time_ms / DAY_MS + 1) * DAY_MS;
+    let mut klines = get_klines(
+        config,
+        symbol_name,
+        KlineInterval::Mins5,
+        Some(time_ms),
+        Some(next_day_ms - 1),
+        Some(1000),
+    )
+    .await?;
+    klines.append(
+        &mut get_klines(
+            config,
+            symbol_name,
+            KlineInterval::Days1,
+            Some(next_day_ms),
+            None,
+            Some(1000),
+        )
+        .await?,
+    );
+
+    Ok(high_since(price, time_ms, &klines))
+}
+
+/// The result of evaluating a SellRuleRec
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SellRuleResult {
+    pub sell_qty: Decimal,
+    pub fired: Vec<String>,
+}
+
+/// Evaluate rule, price is in the quote asset and high is the
+/// highest price since the last buy or zero if unknown.
+pub fn evaluate_sell_rule(
+    rule: &SellRuleRec,
+    free: Decimal,
+    price: Decimal,
+    price_in_usd: Decimal,
+    cost_basis: &CostBasis,
+    high: Decimal,
+) -> SellRuleResult {
+    let mut result = SellRuleResult::default();
+    let percent_qty = free * (rule.sell_percent / dec!(100));
+
+    if let (Some(tp), Some(avg_price)) = (rule.take_profit_percent, cost_basis.avg_price()) {
+        let target = avg_price * (dec!(1) + tp / dec!(100));
+        if price >= target {
+            result.fired.push(format!("take profit {tp}%"));
+            result.sell_qty = result.sell_qty.max(percent_qty);
+        }
+    }
+
+    if let Some(ts) = rule.trailing_stop_percent {
+        if high > dec!(0) {
+            let stop = high * (dec!(1) - ts / dec!(100));
+            if price <= stop {
+                result.fired.push(format!("trailing stop {ts}%"));
+                result.sell_qty = result.sell_qty.max(percent_qty);
+            }
+        }
+    }
+
+    if let Some(max_value) = rule.max_value_usd {
+        let value = free * price_in_usd;
+        if value > max_value && price_in_usd > dec!(0) {
+            result
+                .fired
+                .push(format!("max value {}", dec_to_usd_string(max_value)));
+            result.sell_qty = result.sell_qty.max((value - max_value) / price_in_usd);
+        }
+    }
+    result.sell_qty = result.sell_qty.min(free);
+
+    result
+}
+
+pub async fn eval_sell_rules(
+    config: &Configuration,
+    ei: &ExchangeInfo,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let test = config.test;
+    trace!("eval_sell_rules:+ test: {}", test);
+
+    let rules = if let Some(srs) = &config.sell_rules {
+        srs
+    } else {
+        return Err(ier_new!(8, "Missing `sell_rules` field in configuration").into());
+    };
+
+    let time_ms = utc_now_to_time_ms();
+    let mut ai = get_account_info(config, time_ms).await?;
+    ai.update_values_in_usd(config, config.verbose, time_ms)
+        .await;
+
+    struct ProcessRec {
+        precision: usize,
+        symbol_name: String,
+        price_in_usd: Decimal,
+        sell_qty: Decimal,
+    }
+
+    let mut vec_process_rec = Vec::new();
+    let mut rule_names: Vec<&String> = rules.keys().collect();
+    rule_names.sort();
+    for name in rule_names {
+        let rule = &rules[name];
+        let balance = match ai.balances_map.get(name) {
+            Some(b) if b.free > dec!(0) => b,
+            _ => {
+                println!("{:8} {:10} none owned", "NONE", name);
+                continue;
+            }
+        };
+
+        let quote_asset = if rule.quote_asset.is_empty() {
+            &config.default_quote_asset
+        } else {
+            &rule.quote_asset
+        };
+        let symbol_name = name.clone() + quote_asset;
+        let symbol = if let Some(s) = ei.get_symbol(&symbol_name) {
+            s
+        } else {
+            println!("{:8} {:10} no such symbol", "SKIPPING", symbol_name);
+            continue;
+        };
+
+        let price = get_avg_price(config, &symbol_name).await?.price;
+        let trades = get_all_my_trades(config, &symbol_name).await?;
+        let cost_basis = cost_basis_from_trades(name, quote_asset, &trades);
+
+        let high = if rule.trailing_stop_percent.is_some() && cost_basis.last_buy_time > 0 {
+            get_high_since(config, &symbol_name, price, cost_basis.last_buy_time).await?
+        } else {
+            dec!(0)
+        };
+
+        let result = evaluate_sell_rule(
+            rule,
+            balance.free,
+            price,
+            balance.price_in_usd,
+            &cost_basis,
+            high,
+        );
+
+        let precision = symbol.quote_precision as usize;
+        println!(
+            "{0:8} {2:14.1$} of {3:10} at {4:.4} cost basis {5} high {6} fired: {7}",
+            if result.sell_qty > dec!(0) {
+                "SELLING"
+            } else {
+                "Keeping"
+            },
+            precision,
+            result.sell_qty,
+            symbol_name,
+            price,
+            if let Some(avg_price) = cost_basis.avg_price() {
+                format!("{:.4}", avg_price)
+            } else {
+                "unknown".to_string()
+            },
+            if high > dec!(0) {
+                format!("{:.4}", high)
+            } else {
+                "unknown".to_string()
+            },
+            if result.fired.is_empty() {
+                "NONE".to_string()
+            } else {
+                result.fired.join(", ")
+            },
+        );
+
+        if result.sell_qty > dec!(0) {
+            vec_process_rec.push(ProcessRec {
+                precision,
+                symbol_name,
+                price_in_usd: balance.price_in_usd,
+                sell_qty: result.sell_qty,
+            });
+        }
+    }
+
+    if vec_process_rec.is_empty() {
+        println!("\n ** NOTHING to sell **");
+        return Ok(());
+    }
+
+    let total_sell_in_usd: Decimal = vec_process_rec
+        .iter()
+        .map(|pr| pr.sell_qty * pr.price_in_usd)
+        .sum();
+    println!(
+        "\nSELLING {} assets for {:10}",
+        vec_process_rec.len(),
+        dec_to_usd_string(total_sell_in_usd.round_dp(2)),
+    );
+    if test || !config.confirmation_required || are_you_sure_stdout_stdin() {
+        for pr in &vec_process_rec {
+            let order_type = TradeOrderType::Market(MarketQuantityType::Quantity(pr.sell_qty));
+            match market_order(config, ei, &pr.symbol_name, &order_type, Side::SELL, test).await {
+                Ok(tr) => println!(
+                    "{0:8} {2:14.1$} of {3:10} {4}",
+                    "SOLD", pr.precision, pr.sell_qty, pr.symbol_name, tr
+                ),
+                Err(e) => println!("SKIPPING {}, {}", pr.symbol_name, e),
+            }
+        }
+    } else {
+        println!("\n ** Aborted **");
+    }
+
+    Ok(())
+}
+
+pub async fn eval_sell_rules_cmd(config: &Configuration) -> Result<(), Box<dyn std::error::Error>> {
+    trace!("eval_sell_rules_cmd: {:#?}", config);
+
+    let ei = get_exchange_info(config).await?;
+    eval_sell_rules(config, &ei).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::trade_rec;
+
+    fn sell_rule() -> SellRuleRec {
+        SellRuleRec {
+            name: "ETH".to_string(),
+            sell_percent: dec!(50),
+            take_profit_percent: None,
+            trailing_stop_percent: None,
+            max_value_usd: None,
+            quote_asset: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_cost_basis_from_trades() {
+        let trades = vec![
+            trade_rec(true, dec!(1), dec!(100), 1),
+            trade_rec(true, dec!(1), dec!(300), 2),
+            trade_rec(false, dec!(1), dec!(250), 3),
+        ];
+        let cb = cost_basis_from_trades("ETH", "USD", &trades);
+        assert_eq!(cb.qty, dec!(1));
+        assert_eq!(cb.cost, dec!(200));
+        assert_eq!(cb.avg_price(), Some(dec!(200)));
+        assert_eq!(cb.last_buy_time, 2);
+
+        // Commission in the base asset reduces the quantity
+        let mut tr = trade_rec(true, dec!(2), dec!(100), 1);
+        tr.commission = dec!(0.5);
+        tr.commission_asset = "ETH".to_string();
+        let cb = cost_basis_from_trades("ETH", "USD", &[tr]);
+        assert_eq!(cb.qty, dec!(1.5));
+        assert_eq!(cb.cost, dec!(100));
+
+        // Selling everything leaves no cost basis
+        let trades = vec![
+            trade_rec(true, dec!(1), dec!(100), 1),
+            trade_rec(false, dec!(2), dec!(300), 2),
+        ];
+        let cb = cost_basis_from_trades("ETH", "USD", &trades);
+        assert_eq!(cb.qty, dec!(0));
+        assert_eq!(cb.avg_price(), None);
+    }
+
+    #[test]
+    fn test_evaluate_sell_rule() {
+        let cb = CostBasis {
+            qty: dec!(10),
+            cost: dec!(1000),
+            last_buy_time: 1,
+        };
+
+        // Take profit at 50% above the cost basis of 100
+        let mut rule = sell_rule();
+        rule.take_profit_percent = Some(dec!(50));
+        let r = evaluate_sell_rule(&rule, dec!(10), dec!(149), dec!(149), &cb, dec!(0));
+        assert_eq!(r.sell_qty, dec!(0));
+        assert!(r.fired.is_empty());
+        let r = evaluate_sell_rule(&rule, dec!(10), dec!(150), dec!(150), &cb, dec!(0));
+        assert_eq!(r.sell_qty, dec!(5));
+        assert_eq!(r.fired.len(), 1);
+
+        // Trailing stop 10% below a high of 200
+        let mut rule = sell_rule();
+        rule.trailing_stop_percent = Some(dec!(10));
+        let r = evaluate_sell_rule(&rule, dec!(10), dec!(181), dec!(181), &cb, dec!(200));
+        assert_eq!(r.sell_qty, dec!(0));
+        let r = evaluate_sell_rule(&rule, dec!(10), dec!(180), dec!(180), &cb, dec!(200));
+        assert_eq!(r.sell_qty, dec!(5));
+        let r = evaluate_sell_rule(&rule, dec!(10), dec!(1), dec!(1), &cb, dec!(0));
+        assert_eq!(r.sell_qty, dec!(0));
+
+        // Only the value above max_value_usd is sold
+        let mut rule = sell_rule();
+        rule.max_value_usd = Some(dec!(1000));
+        let r = evaluate_sell_rule(&rule, dec!(10), dec!(125), dec!(125), &cb, dec!(0));
+        assert_eq!(r.sell_qty, dec!(2));
+
+        // The largest quantity of the rules that fire is sold
+        rule.take_profit_percent = Some(dec!(10));
+        let r = evaluate_sell_rule(&rule, dec!(10), dec!(125), dec!(125), &cb, dec!(0));
+        assert_eq!(r.sell_qty, dec!(5));
+        assert_eq!(r.fired.len(), 2);
+    }
+
+    #[test]
+    fn test_high_since() {
+        let kline = |open_time: i64, high: Decimal| KlineRec {
+            open_time,
+            high,
+            ..Default::default()
+        };
+
+        // The buy at 12:02 is after the day's high of 300 at 12:00
+        let buy_time = DAY_MS + 12 * 60 * 60 * 1000 + 2 * 60 * 1000;
+        let klines = vec![
+            kline(buy_time - 2 * 60 * 1000, dec!(300)),
+            kline(buy_time + 3 * 60 * 1000, dec!(210)),
+            kline(2 * DAY_MS, dec!(220)),
+        ];
+        assert_eq!(high_since(dec!(200), buy_time, &klines), dec!(220));
+        assert_eq!(high_since(dec!(230), buy_time, &klines), dec!(230));
+        assert_eq!(high_since(dec!(200), buy_time, &klines[..1]), dec!(200));
+    }
+}
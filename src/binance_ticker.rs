@@ -0,0 +1,374 @@
+//! 24hr ticker, price ticker and book ticker market data
+use std::{cmp::Reverse, collections::HashMap};
+
+use clap::ArgMatches;
+use log::trace;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    binance_exchange_info::{get_exchange_info, ExchangeInfo},
+    common::get_api_response_body,
+    configuration::Configuration,
+};
+
+use dec_utils::dec_to_separated_string;
+
+/// 24 hour rolling window price change statistics
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ticker24hr {
+    pub symbol: String,
+    pub price_change: Decimal,
+    pub price_change_percent: Decimal,
+    pub weighted_avg_price: Decimal,
+    #[serde(default)]
+    pub prev_close_price: Decimal,
+    pub last_price: Decimal,
+    #[serde(default)]
+    pub last_qty: Decimal,
+    #[serde(default)]
+    pub bid_price: Decimal,
+    #[serde(default)]
+    pub bid_qty: Decimal,
+    #[serde(default)]
+    pub ask_price: Decimal,
+    #[serde(default)]
+    pub ask_qty: Decimal,
+    pub open_price: Decimal,
+    pub high_price: Decimal,
+    pub low_price: Decimal,
+    pub volume: Decimal,
+    pub quote_volume: Decimal,
+    pub open_time: i64,
+    pub close_time: i64,
+    pub first_id: i64,
+    pub last_id: i64,
+    pub count: u64,
+}
+
+/// Latest price of a symbol
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TickerPrice {
+    pub symbol: String,
+    pub price: Decimal,
+}
+
+/// Best bid and ask on the order book
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookTicker {
+    pub symbol: String,
+    pub bid_price: Decimal,
+    pub bid_qty: Decimal,
+    pub ask_price: Decimal,
+    pub ask_qty: Decimal,
+}
+
+/// The ticker endpoints return an object when a symbol
+/// is specified and an array for all symbols.
+fn one_or_vec_from_str<T: DeserializeOwned>(body: &str) -> Result<Vec<T>, serde_json::Error> {
+    let value: serde_json::Value = serde_json::from_str(body)?;
+    if value.is_array() {
+        serde_json::from_value(value)
+    } else {
+        Ok(vec![serde_json::from_value(value)?])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickerSortBy {
+    /// Quote volume, largest first
+    Volume,
+
+    /// Price change percent, largest first
+    Change,
+}
+
+impl TickerSortBy {
+    pub fn from_string(s: &str) -> Result<TickerSortBy, Box<dyn std::error::Error>> {
+        match s {
+            "volume" => Ok(TickerSortBy::Volume),
+            "change" => Ok(TickerSortBy::Change),
+            _ => Err(format!("Unknown sort, {s}, expecting: volume or change").into()),
+        }
+    }
+}
+
+async fn get_ticker<T: DeserializeOwned>(
+    config: &Configuration,
+    path: &str,
+    symbol: Option<&str>,
+) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    let path = match symbol {
+        Some(s) => format!("{path}?symbol={s}"),
+        None => path.to_string(),
+    };
+    trace!("get_ticker: path={}", path);
+
+    let response_body = get_api_response_body(config, &path).await?;
+    Ok(one_or_vec_from_str(&response_body)?)
+}
+
+/// 24hr statistics for symbol or all symbols if None
+pub async fn get_ticker_24hr(
+    config: &Configuration,
+    symbol: Option<&str>,
+) -> Result<Vec<Ticker24hr>, Box<dyn std::error::Error>> {
+    get_ticker(config, "/api/v3/ticker/24hr", symbol).await
+}
+
+/// Latest price for symbol or all symbols if None
+pub async fn get_ticker_prices(
+    config: &Configuration,
+    symbol: Option<&str>,
+) -> Result<Vec<TickerPrice>, Box<dyn std::error::Error>> {
+    get_ticker(config, "/api/v3/ticker/price", symbol).await
+}
+
+/// Best bid and ask for symbol or all symbols if None
+pub async fn get_book_tickers(
+    config: &Configuration,
+    symbol: Option<&str>,
+) -> Result<Vec<BookTicker>, Box<dyn std::error::Error>> {
+    get_ticker(config, "/api/v3/ticker/bookTicker", symbol).await
+}
+
+/// Map of symbol name to price
+pub fn prices_map(prices: &[TickerPrice]) -> HashMap<String, Decimal> {
+    prices
+        .iter()
+        .map(|tp| (tp.symbol.clone(), tp.price))
+        .collect()
+}
+
+/// The price of asset in the first of value_assets it has a market for,
+/// a value asset is worth 1. This is the prices equivalent of
+/// get_kline_of_primary_asset_for_value_asset.
+pub fn price_of_asset_in_value_asset(
+    prices: &HashMap<String, Decimal>,
+    asset: &str,
+    value_assets: &[String],
+) -> Option<Decimal> {
+    for value_asset in value_assets {
+        if asset == value_asset {
+            return Some(dec!(1));
+        } else if let Some(price) = prices.get(&(asset.to_owned() + value_asset)) {
+            return Some(*price);
+        }
+    }
+
+    None
+}
+
+/// Keep the tickers whose symbol has quote_asset as its quote asset
+pub fn filter_tickers_by_quote_asset(
+    ei: &ExchangeInfo,
+    tickers: Vec<Ticker24hr>,
+    quote_asset: &str,
+) -> Vec<Ticker24hr> {
+    tickers
+        .into_iter()
+        .filter(|t| {
+            ei.get_symbol(&t.symbol)
+                .is_some_and(|s| s.quote_asset == quote_asset)
+        })
+        .collect()
+}
+
+pub fn sort_tickers(tickers: &mut [Ticker24hr], sort_by: TickerSortBy) {
+    match sort_by {
+        TickerSortBy::Volume => tickers.sort_by_key(|t| Reverse(t.quote_volume)),
+        TickerSortBy::Change => tickers.sort_by_key(|t| Reverse(t.price_change_percent)),
+    }
+}
+
+pub async fn ticker_24hr_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let symbol = sc_matches.value_of("SYMBOL");
+    let sort_by = TickerSortBy::from_string(sc_matches.value_of("SORT").unwrap_or("volume"))?;
+    let quote_asset = sc_matches.value_of("QUOTE_ASSET");
+    let limit = match sc_matches.value_of("LIMIT") {
+        Some(l) => Some(l.parse::<usize>()?),
+        None => None,
+    };
+
+    let mut tickers = get_ticker_24hr(config, symbol).await?;
+    if let Some(qa) = quote_asset {
+        let ei = get_exchange_info(config).await?;
+        tickers = filter_tickers_by_quote_asset(&ei, tickers, qa);
+    }
+    sort_tickers(&mut tickers, sort_by);
+
+    println!(
+        "{:<12} {:>16} {:>9} {:>16} {:>16} {:>20} {:>8}",
+        "Symbol", "Last", "Change%", "High", "Low", "Quote volume", "Trades"
+    );
+    for t in tickers.iter().take(limit.unwrap_or(usize::MAX)) {
+        println!(
+            "{:<12} {:>16} {:>8.2}% {:>16} {:>16} {:>20} {:>8}",
+            t.symbol,
+            t.last_price.normalize(),
+            t.price_change_percent,
+            t.high_price.normalize(),
+            t.low_price.normalize(),
+            dec_to_separated_string(t.quote_volume.round_dp(0), 0),
+            t.count
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn ticker_price_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let prices = get_ticker_prices(config, sc_matches.value_of("SYMBOL")).await?;
+    for tp in &prices {
+        println!("{:<12} {:>16}", tp.symbol, tp.price.normalize());
+    }
+
+    Ok(())
+}
+
+pub async fn book_ticker_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let book_tickers = get_book_tickers(config, sc_matches.value_of("SYMBOL")).await?;
+    println!(
+        "{:<12} {:>16} {:>16} {:>16} {:>16} {:>8}",
+        "Symbol", "Bid qty", "Bid", "Ask", "Ask qty", "Spread%"
+    );
+    for bt in &book_tickers {
+        let spread_percent = if bt.bid_price > dec!(0) {
+            ((bt.ask_price - bt.bid_price) / bt.bid_price) * dec!(100)
+        } else {
+            dec!(0)
+        };
+        println!(
+            "{:<12} {:>16} {:>16} {:>16} {:>16} {:>7.3}%",
+            bt.symbol,
+            bt.bid_qty.normalize(),
+            bt.bid_price.normalize(),
+            bt.ask_price.normalize(),
+            bt.ask_qty.normalize(),
+            spread_percent
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TICKER_24HR_ONE: &str = r#"{
+        "symbol": "BNBBTC",
+        "priceChange": "-94.99999800",
+        "priceChangePercent": "-95.960",
+        "weightedAvgPrice": "0.29628482",
+        "prevClosePrice": "0.10002000",
+        "lastPrice": "4.00000200",
+        "lastQty": "200.00000000",
+        "bidPrice": "4.00000000",
+        "bidQty": "100.00000000",
+        "askPrice": "4.00000200",
+        "askQty": "100.00000000",
+        "openPrice": "99.00000000",
+        "highPrice": "100.00000000",
+        "lowPrice": "0.10000000",
+        "volume": "8913.30000000",
+        "quoteVolume": "15.30000000",
+        "openTime": 1499783499040,
+        "closeTime": 1499869899040,
+        "firstId": 28385,
+        "lastId": 28460,
+        "count": 76
+    }"#;
+
+    const TICKER_PRICES: &str = r#"[
+        { "symbol": "BTCUSD", "price": "20000.00" },
+        { "symbol": "ETHUSDT", "price": "1500.50" },
+        { "symbol": "ETHBTC", "price": "0.075" }
+    ]"#;
+
+    #[test]
+    fn test_ticker_24hr_one_or_vec_from_str() {
+        let tickers: Vec<Ticker24hr> = one_or_vec_from_str(TICKER_24HR_ONE).unwrap();
+        assert_eq!(tickers.len(), 1);
+        let t = &tickers[0];
+        assert_eq!(t.symbol, "BNBBTC");
+        assert_eq!(t.price_change_percent, dec!(-95.96));
+        assert_eq!(t.quote_volume, dec!(15.3));
+        assert_eq!(t.count, 76);
+
+        let all = format!("[{TICKER_24HR_ONE},{TICKER_24HR_ONE}]");
+        let tickers: Vec<Ticker24hr> = one_or_vec_from_str(&all).unwrap();
+        assert_eq!(tickers.len(), 2);
+    }
+
+    #[test]
+    fn test_book_ticker() {
+        let bt: BookTicker = serde_json::from_str(
+            r#"{"symbol":"LTCBTC","bidPrice":"4.00000000","bidQty":"431.00000000","askPrice":"4.00000200","askQty":"9.00000000"}"#,
+        )
+        .unwrap();
+        assert_eq!(bt.bid_qty, dec!(431));
+        assert_eq!(bt.ask_price, dec!(4.000002));
+    }
+
+    #[test]
+    fn test_price_of_asset_in_value_asset() {
+        let prices: Vec<TickerPrice> = one_or_vec_from_str(TICKER_PRICES).unwrap();
+        let pm = prices_map(&prices);
+        let value_assets = vec!["USD".to_owned(), "USDT".to_owned()];
+
+        assert_eq!(
+            price_of_asset_in_value_asset(&pm, "BTC", &value_assets),
+            Some(dec!(20000))
+        );
+        assert_eq!(
+            price_of_asset_in_value_asset(&pm, "ETH", &value_assets),
+            Some(dec!(1500.5))
+        );
+        assert_eq!(
+            price_of_asset_in_value_asset(&pm, "USDT", &value_assets),
+            Some(dec!(1))
+        );
+        assert_eq!(
+            price_of_asset_in_value_asset(&pm, "XYZ", &value_assets),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sort_tickers() {
+        let t = |symbol: &str, change: Decimal, volume: Decimal| Ticker24hr {
+            symbol: symbol.to_owned(),
+            price_change_percent: change,
+            quote_volume: volume,
+            ..Default::default()
+        };
+        let tickers = vec![
+            t("A", dec!(1), dec!(300)),
+            t("B", dec!(5), dec!(100)),
+            t("C", dec!(-2), dec!(200)),
+        ];
+        let mut sorted = tickers.clone();
+        sort_tickers(&mut sorted, TickerSortBy::Volume);
+        let names: Vec<&str> = sorted.iter().map(|t| t.symbol.as_str()).collect();
+        assert_eq!(names, vec!["A", "C", "B"]);
+
+        let mut sorted = tickers;
+        sort_tickers(&mut sorted, TickerSortBy::Change);
+        let names: Vec<&str> = sorted.iter().map(|t| t.symbol.as_str()).collect();
+        assert_eq!(names, vec!["B", "A", "C"]);
+    }
+}
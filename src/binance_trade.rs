@@ -19,6 +19,7 @@ use crate::{
         AckTradeResponseRec, FullTradeResponseRec, ResultTradeResponseRec, TestTradeResponseRec,
         TradeResponse, UnknownTradeResponseRec,
     },
+    binance_paper_trading::paper_new_order,
     binance_signature::{append_signature, binance_signature, query_vec_u8},
     common::{post_req_get_response, ResponseErrorRec, Side, VALUE_ASSETS},
     configuration::Configuration,
@@ -74,7 +75,6 @@ pub fn order_log_file(order_log_path: &Path) -> Result<File, Box<dyn std::error:
 
     Ok(OpenOptions::new()
         .create(true)
-        .write(true)
         .append(true)
         .open(order_log_path)?)
 }
@@ -226,7 +226,7 @@ pub async fn convert(
     Ok(other_quantity)
 }
 
-async fn convert_commission(
+pub async fn convert_commission(
     config: &Configuration,
     order_response: &FullTradeResponseRec,
     fee_asset: &str,
@@ -261,6 +261,10 @@ pub async fn binance_new_order_or_test(
         }
     };
 
+    if config.paper_trading {
+        return paper_new_order(config, log_writer, ei, symbol, side, order_type, test).await;
+    }
+
     let api_key = config.keys.get_ak_or_err()?;
     let secret_key = &config.keys.get_sk_vec_u8_or_err()?;
 
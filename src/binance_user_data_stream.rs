@@ -0,0 +1,578 @@
+//! User data stream of order execution reports and account updates.
+//!
+//! A listenKey is created and kept alive every 30 minutes, the stream
+//! at /ws/<listenKey> is consumed and reconnected with a backoff. Fills
+//! are appended to the order log and balance updates are applied to an
+//! in-memory AccountInfo, which is passed to the handler with each event.
+use std::{
+    fmt::{self, Display},
+    io::Write,
+    time::Duration,
+};
+
+use log::{trace, warn};
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use time_ms_conversions::{time_ms_to_utc, utc_now_to_time_ms};
+use tokio::time::{interval_at, Instant, Interval};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+use crate::{
+    binance_account_info::{get_account_info, AccountInfo, Balance},
+    binance_exchange_info::{get_exchange_info, ExchangeInfo},
+    binance_order_response::{ExecutionReportRec, TradeResponse},
+    binance_trade::{convert, log_order_response, order_log_file},
+    binance_websocket::{run_connections, ConnectionHandler, Flow, ReconnectPolicy, WsStream},
+    common::{delete_req_get_response, post_req_get_response, put_req_get_response},
+    configuration::Configuration,
+};
+
+/// The listenKey expires after 60 minutes unless kept alive
+pub const LISTEN_KEY_KEEPALIVE_MS: u64 = 30 * 60 * 1000;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenKeyRec {
+    pub listen_key: String,
+}
+
+/// Create a listenKey, an existing active key is returned if there is one
+pub async fn create_listen_key(
+    config: &Configuration,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let url = config.make_url("api", "/api/v3/userDataStream");
+    trace!("create_listen_key: url={url}");
+
+    let response = post_req_get_response(config.keys.get_ak_or_err()?, &url, "").await?;
+    let response_status = response.status();
+    let response_body = response.text().await?;
+    if response_status == 200 {
+        let lkr: ListenKeyRec = serde_json::from_str(&response_body)?;
+        Ok(lkr.listen_key)
+    } else {
+        Err(
+            format!("create_listen_key: response status={response_status} body={response_body}")
+                .into(),
+        )
+    }
+}
+
+/// Extend the validity of listen_key for another 60 minutes
+pub async fn keepalive_listen_key(
+    config: &Configuration,
+    listen_key: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let url = config.make_url(
+        "api",
+        &format!("/api/v3/userDataStream?listenKey={listen_key}"),
+    );
+    trace!("keepalive_listen_key: url={url}");
+
+    let response = put_req_get_response(config.keys.get_ak_or_err()?, &url, "").await?;
+    let response_status = response.status();
+    if response_status == 200 {
+        Ok(())
+    } else {
+        let response_body = response.text().await?;
+        Err(
+            format!("keepalive_listen_key: response status={response_status} body={response_body}")
+                .into(),
+        )
+    }
+}
+
+/// Close the user data stream of listen_key
+pub async fn close_listen_key(
+    config: &Configuration,
+    listen_key: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let url = config.make_url(
+        "api",
+        &format!("/api/v3/userDataStream?listenKey={listen_key}"),
+    );
+    trace!("close_listen_key: url={url}");
+
+    let response = delete_req_get_response(config.keys.get_ak_or_err()?, &url).await?;
+    let response_status = response.status();
+    if response_status == 200 {
+        Ok(())
+    } else {
+        let response_body = response.text().await?;
+        Err(
+            format!("close_listen_key: response status={response_status} body={response_body}")
+                .into(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PositionBalance {
+    #[serde(rename = "a")]
+    pub asset: String,
+
+    #[serde(rename = "f")]
+    pub free: Decimal,
+
+    #[serde(rename = "l")]
+    pub locked: Decimal,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AccountPositionEvent {
+    #[serde(rename = "E")]
+    pub event_time: i64,
+
+    #[serde(rename = "u")]
+    pub last_update_time: i64,
+
+    /// The balances that changed
+    #[serde(rename = "B")]
+    pub balances: Vec<PositionBalance>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BalanceUpdateEvent {
+    #[serde(rename = "E")]
+    pub event_time: i64,
+
+    #[serde(rename = "a")]
+    pub asset: String,
+
+    #[serde(rename = "d")]
+    pub delta: Decimal,
+
+    #[serde(rename = "T")]
+    pub clear_time: i64,
+}
+
+#[derive(Debug, Clone)]
+pub enum UserDataEvent {
+    ExecutionReport(Box<ExecutionReportRec>),
+    AccountPosition(AccountPositionEvent),
+    BalanceUpdate(BalanceUpdateEvent),
+    ListenKeyExpired(i64),
+    Other(serde_json::Value),
+}
+
+impl Display for UserDataEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UserDataEvent::ExecutionReport(e) => {
+                write!(f, "{} {e}", time_ms_to_utc(e.event_time))
+            }
+            UserDataEvent::AccountPosition(e) => {
+                write!(f, "{} balances", time_ms_to_utc(e.event_time))?;
+                for b in &e.balances {
+                    write!(
+                        f,
+                        " {}: {} free {} locked",
+                        b.asset,
+                        b.free.normalize(),
+                        b.locked.normalize()
+                    )?;
+                }
+                Ok(())
+            }
+            UserDataEvent::BalanceUpdate(e) => write!(
+                f,
+                "{} balance update {}: {}",
+                time_ms_to_utc(e.event_time),
+                e.asset,
+                e.delta.normalize()
+            ),
+            UserDataEvent::ListenKeyExpired(event_time) => {
+                write!(f, "{} listenKey expired", time_ms_to_utc(*event_time))
+            }
+            UserDataEvent::Other(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+/// Parse a message from the user data stream, returns None for messages
+/// that aren't events.
+pub fn parse_user_data_event(
+    text: &str,
+) -> Result<Option<UserDataEvent>, Box<dyn std::error::Error>> {
+    let value: serde_json::Value = serde_json::from_str(text)?;
+    let event_type = match value.get("e").and_then(|e| e.as_str()) {
+        Some(e) => e.to_string(),
+        None => {
+            trace!("parse_user_data_event: not an event: {text}");
+            return Ok(None);
+        }
+    };
+
+    let event = match event_type.as_str() {
+        "executionReport" => {
+            UserDataEvent::ExecutionReport(Box::new(serde_json::from_value(value)?))
+        }
+        "outboundAccountPosition" => UserDataEvent::AccountPosition(serde_json::from_value(value)?),
+        "balanceUpdate" => UserDataEvent::BalanceUpdate(serde_json::from_value(value)?),
+        "listenKeyExpired" => {
+            UserDataEvent::ListenKeyExpired(value.get("E").and_then(|e| e.as_i64()).unwrap_or(0))
+        }
+        _ => UserDataEvent::Other(value),
+    };
+
+    Ok(Some(event))
+}
+
+/// Update the balances of ai that changed in event
+pub fn apply_account_position(ai: &mut AccountInfo, event: &AccountPositionEvent) {
+    for pb in &event.balances {
+        let balance = ai
+            .balances_map
+            .entry(pb.asset.clone())
+            .or_insert_with(|| Balance {
+                asset: pb.asset.clone(),
+                free: dec!(0),
+                locked: dec!(0),
+                price_in_usd: dec!(0),
+                value_in_usd: dec!(0),
+            });
+        balance.free = pb.free;
+        balance.locked = pb.locked;
+        balance.value_in_usd = balance.price_in_usd * (balance.free + balance.locked);
+    }
+    ai.update_time = event.last_update_time;
+}
+
+pub struct UserDataStream {
+    /// The account, current as of the last event received
+    pub account_info: AccountInfo,
+    ei: ExchangeInfo,
+    listen_key: Option<String>,
+
+    /// The account info is requested again after reconnecting
+    refresh_account_info: bool,
+
+    pub reconnect: ReconnectPolicy,
+}
+
+impl UserDataStream {
+    pub async fn new(config: &Configuration) -> Result<UserDataStream, Box<dyn std::error::Error>> {
+        if config.paper_trading {
+            return Err("The user data stream is not available when paper trading".into());
+        }
+
+        Ok(UserDataStream {
+            account_info: get_account_info(config, utc_now_to_time_ms()).await?,
+            ei: get_exchange_info(config).await?,
+            listen_key: None,
+            refresh_account_info: false,
+            reconnect: ReconnectPolicy::default(),
+        })
+    }
+
+    /// Receive events, appending fills to log_writer and updating
+    /// account_info, calling handler for each one until it returns false
+    pub async fn run<F>(
+        &mut self,
+        config: &Configuration,
+        log_writer: &mut dyn Write,
+        mut handler: F,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(&UserDataEvent, &AccountInfo) -> bool,
+    {
+        let policy = self.reconnect.clone();
+        let period = Duration::from_millis(LISTEN_KEY_KEEPALIVE_MS);
+        run_connections(
+            &policy,
+            &mut UserDataConnection {
+                uds: self,
+                config,
+                log_writer,
+                handler: &mut handler,
+                keepalive: interval_at(Instant::now() + period, period),
+            },
+        )
+        .await?;
+
+        if let Some(listen_key) = self.listen_key.take() {
+            if let Err(e) = close_listen_key(config, &listen_key).await {
+                trace!("UserDataStream::run: {e}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Update account_info and log fills, the USD values of fills are added to event
+    async fn apply_event(
+        &mut self,
+        config: &Configuration,
+        log_writer: &mut dyn Write,
+        event: &mut UserDataEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match event {
+            UserDataEvent::AccountPosition(e) => apply_account_position(&mut self.account_info, e),
+            UserDataEvent::ExecutionReport(er) if er.is_fill() => {
+                if let Some(symbol) = self.ei.get_symbol(&er.symbol) {
+                    er.value_usd = convert(
+                        config,
+                        er.transact_time,
+                        &symbol.quote_asset,
+                        er.last_quote_qty,
+                        "USD",
+                    )
+                    .await
+                    .unwrap_or_else(|e| {
+                        trace!("apply_event: value_usd of {}: {e}", er.symbol);
+                        dec!(0)
+                    });
+                }
+                if let Some(commission_asset) = &er.commission_asset {
+                    er.commission_usd = convert(
+                        config,
+                        er.transact_time,
+                        commission_asset,
+                        er.commission,
+                        "USD",
+                    )
+                    .await
+                    .unwrap_or_else(|e| {
+                        trace!("apply_event: commission_usd of {}: {e}", er.symbol);
+                        dec!(0)
+                    });
+                }
+                log_order_response(
+                    log_writer,
+                    &TradeResponse::SuccessExecutionReport(er.as_ref().clone()),
+                )?;
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// The connections of a running UserDataStream
+struct UserDataConnection<'a, F> {
+    uds: &'a mut UserDataStream,
+    config: &'a Configuration,
+    log_writer: &'a mut dyn Write,
+    handler: &'a mut F,
+    keepalive: Interval,
+}
+
+impl<F> ConnectionHandler for UserDataConnection<'_, F>
+where
+    F: FnMut(&UserDataEvent, &AccountInfo) -> bool,
+{
+    type Request = ();
+
+    async fn connect(&mut self) -> Result<WsStream, Box<dyn std::error::Error>> {
+        let listen_key = match &self.uds.listen_key {
+            Some(lk) => lk.clone(),
+            None => {
+                let lk = create_listen_key(self.config).await?;
+                self.uds.listen_key = Some(lk.clone());
+                lk
+            }
+        };
+
+        let url = self.config.make_ws_url(&format!("/ws/{listen_key}"));
+        trace!("UserDataConnection::connect: connecting");
+        let (ws, _) = connect_async(&url).await?;
+
+        // Events may have been missed while disconnected
+        if self.uds.refresh_account_info {
+            self.uds.account_info = get_account_info(self.config, utc_now_to_time_ms()).await?;
+            self.uds.refresh_account_info = false;
+        }
+        self.keepalive.reset();
+
+        Ok(ws)
+    }
+
+    async fn next_request(&mut self) {
+        self.keepalive.tick().await;
+    }
+
+    async fn on_request(&mut self, _: ()) -> Result<Option<Message>, Box<dyn std::error::Error>> {
+        if let Some(listen_key) = &self.uds.listen_key {
+            keepalive_listen_key(self.config, listen_key).await?;
+        }
+
+        Ok(None)
+    }
+
+    async fn on_text(&mut self, text: &str) -> Result<Flow, Box<dyn std::error::Error>> {
+        // A bad frame is skipped, only transport errors reconnect
+        let mut event = match parse_user_data_event(text) {
+            Ok(Some(event)) => event,
+            Ok(None) => return Ok(Flow::Ignored),
+            Err(e) => {
+                warn!("UserDataConnection::on_text: skipping frame: {text} Err: {e}");
+                return Ok(Flow::Ignored);
+            }
+        };
+
+        self.uds
+            .apply_event(self.config, self.log_writer, &mut event)
+            .await?;
+        if !(self.handler)(&event, &self.uds.account_info) {
+            return Ok(Flow::Stop);
+        }
+        if matches!(event, UserDataEvent::ListenKeyExpired(_)) {
+            self.uds.listen_key = None;
+            return Ok(Flow::Reconnect);
+        }
+
+        Ok(Flow::Continue)
+    }
+
+    fn on_disconnect(&mut self, failed: bool) {
+        // The listenKey may have expired, create a new one
+        if failed {
+            self.uds.listen_key = None;
+        }
+        self.uds.refresh_account_info = true;
+    }
+}
+
+pub async fn user_data_stream_cmd(
+    config: &Configuration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let order_log_path = if let Some(olp) = &config.order_log_path {
+        olp
+    } else {
+        return Err("No order log path, set it in the config file or use --order_log_path".into());
+    };
+    let mut log_writer = order_log_file(order_log_path)?;
+
+    let mut uds = UserDataStream::new(config).await?;
+    println!(
+        "Streaming user data, fills are appended to {}",
+        order_log_path.to_string_lossy()
+    );
+
+    uds.run(config, &mut log_writer, |event, _ai| {
+        println!("{event}");
+        true
+    })
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::Side;
+
+    const EXECUTION_REPORT: &str = r#"{
+        "e":"executionReport","E":1672531200100,"s":"BTCUSD","c":"mUvoqJxFIILMdfAW5iGSOW",
+        "S":"BUY","o":"MARKET","f":"GTC","q":"0.01000000","p":"0.00000000","P":"0.00000000",
+        "F":"0.00000000","g":-1,"C":"","x":"TRADE","X":"FILLED","r":"NONE","i":4293153,
+        "l":"0.01000000","z":"0.01000000","L":"20000.00000000","n":"0.00001000","N":"BTC",
+        "T":1672531200099,"t":12345,"I":8641984,"w":false,"m":false,"M":true,
+        "O":1672531200099,"Z":"200.00000000","Y":"200.00000000","Q":"0.00000000"
+    }"#;
+
+    const ACCOUNT_POSITION: &str = r#"{
+        "e":"outboundAccountPosition","E":1672531200101,"u":1672531200099,
+        "B":[
+            {"a":"BTC","f":"0.01999000","l":"0.00000000"},
+            {"a":"USD","f":"800.00000000","l":"0.00000000"}
+        ]
+    }"#;
+
+    const ACCOUNT_INFO_DATA: &str = r#"{
+        "makerCommission":10,
+        "takerCommission":10,
+        "buyerCommission":0,
+        "sellerCommission":0,
+        "canTrade":true,
+        "canWithdraw":true,
+        "canDeposit":true,
+        "updateTime":1616461066366,
+        "accountType":"SPOT",
+        "permissions":["SPOT"],
+        "balances":[
+            {"asset":"BTC","free":"0.01000000","locked":"0.00000000"},
+            {"asset":"ETH","free":"1.00000000","locked":"0.00000000"}
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse_execution_report() {
+        let event = parse_user_data_event(EXECUTION_REPORT).unwrap().unwrap();
+        let er = match event {
+            UserDataEvent::ExecutionReport(er) => er,
+            _ => panic!("Expected an ExecutionReport: {event:?}"),
+        };
+        assert!(er.is_fill());
+        assert_eq!(er.symbol, "BTCUSD");
+        assert_eq!(er.side, Side::BUY);
+        assert_eq!(er.order_id, 4293153);
+        assert_eq!(er.trade_id, 12345);
+        assert_eq!(er.last_executed_qty, dec!(0.01));
+        assert_eq!(er.last_executed_price, dec!(20000));
+        assert_eq!(er.last_quote_qty, dec!(200));
+        assert_eq!(er.commission_asset.as_deref(), Some("BTC"));
+        assert_eq!(er.transact_time, 1672531200099);
+    }
+
+    #[test]
+    fn test_execution_report_order_log_round_trip() {
+        let er = match parse_user_data_event(EXECUTION_REPORT).unwrap().unwrap() {
+            UserDataEvent::ExecutionReport(er) => er,
+            _ => panic!("Expected an ExecutionReport"),
+        };
+
+        // The order log has the long field names
+        let line = serde_json::to_string(&TradeResponse::SuccessExecutionReport(*er)).unwrap();
+        assert!(line.contains(r#""lastExecutedQty":"#));
+        let tr: TradeResponse = serde_json::from_str(&line).unwrap();
+        assert!(matches!(
+            tr,
+            TradeResponse::SuccessExecutionReport(er) if er.order_id == 4293153 && er.commission == dec!(0.00001)
+        ));
+    }
+
+    #[test]
+    fn test_parse_other_events() {
+        let event = parse_user_data_event(
+            r#"{"e":"balanceUpdate","E":1573200697110,"a":"BTC","d":"100.00000000","T":1573200697068}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(matches!(event, UserDataEvent::BalanceUpdate(e) if e.delta == dec!(100)));
+
+        let event = parse_user_data_event(r#"{"e":"listenKeyExpired","E":1576653824250}"#)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            event,
+            UserDataEvent::ListenKeyExpired(1576653824250)
+        ));
+
+        let event = parse_user_data_event(r#"{"e":"listStatus","E":1564035303637}"#)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, UserDataEvent::Other(_)));
+
+        assert!(parse_user_data_event(r#"{"result":null,"id":1}"#)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_apply_account_position() {
+        let mut ai: AccountInfo = serde_json::from_str(ACCOUNT_INFO_DATA).unwrap();
+        let event = match parse_user_data_event(ACCOUNT_POSITION).unwrap().unwrap() {
+            UserDataEvent::AccountPosition(e) => e,
+            _ => panic!("Expected an AccountPosition"),
+        };
+        apply_account_position(&mut ai, &event);
+
+        assert_eq!(ai.update_time, 1672531200099);
+        assert_eq!(ai.balances_map["BTC"].free, dec!(0.01999));
+        assert_eq!(ai.balances_map["USD"].free, dec!(800));
+
+        // Balances not in the event are unchanged
+        assert_eq!(ai.balances_map["ETH"].free, dec!(1));
+    }
+}
@@ -256,11 +256,8 @@ mod test {
 
         // Test max_qty
         fn set_lot_size_max_qty(symbol: &mut Symbol, max_qty: Decimal) {
-            match symbol.get_mut_lot_size() {
-                Some(sr) => {
-                    sr.max_qty = max_qty;
-                }
-                None => {}
+            if let Some(sr) = symbol.get_mut_lot_size() {
+                sr.max_qty = max_qty;
             }
         }
 
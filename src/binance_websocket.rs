@@ -0,0 +1,789 @@
+//! WebSocket client for the public market streams.
+//!
+//! The client connects to the combined stream endpoint, subscribes to
+//! its streams and reconnects with a backoff when the connection is
+//! closed, errors or is idle. Pings are answered with pongs and streams
+//! can be subscribed or unsubscribed while running via StreamCommand's.
+//! The reconnect loop, run_connections, is shared with other streams
+//! through the ConnectionHandler trait.
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display},
+    time::Duration,
+};
+
+use clap::ArgMatches;
+use futures_util::{SinkExt, StreamExt};
+use log::{trace, warn};
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time_ms_conversions::{time_ms_to_utc, utc_now_to_time_ms};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::TcpStream,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::timeout,
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use crate::{
+    binance_account_info::get_account_info, binance_exchange_info::get_exchange_info,
+    binance_klines::KlineInterval, configuration::Configuration,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    Trade,
+    Kline(KlineInterval),
+    BookTicker,
+    MiniTicker,
+}
+
+impl StreamKind {
+    pub fn from_string(
+        s: &str,
+        interval: KlineInterval,
+    ) -> Result<StreamKind, Box<dyn std::error::Error>> {
+        match s {
+            "trade" => Ok(StreamKind::Trade),
+            "kline" => Ok(StreamKind::Kline(interval)),
+            "book" => Ok(StreamKind::BookTicker),
+            "mini" => Ok(StreamKind::MiniTicker),
+            _ => Err(format!("Unknown stream, {s}, expecting: trade kline book mini").into()),
+        }
+    }
+}
+
+/// The stream name of symbol, for example btcusd@trade
+pub fn stream_name(symbol: &str, kind: &StreamKind) -> String {
+    let symbol = symbol.to_lowercase();
+    match kind {
+        StreamKind::Trade => format!("{symbol}@trade"),
+        StreamKind::Kline(interval) => format!("{symbol}@kline_{}", interval.to_string()),
+        StreamKind::BookTicker => format!("{symbol}@bookTicker"),
+        StreamKind::MiniTicker => format!("{symbol}@miniTicker"),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TradeEvent {
+    #[serde(rename = "E")]
+    pub event_time: i64,
+
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "t")]
+    pub trade_id: u64,
+
+    #[serde(rename = "p")]
+    pub price: Decimal,
+
+    #[serde(rename = "q")]
+    pub qty: Decimal,
+
+    #[serde(rename = "T")]
+    pub trade_time: i64,
+
+    #[serde(rename = "m")]
+    pub is_buyer_maker: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WsKline {
+    #[serde(rename = "t")]
+    pub open_time: i64,
+
+    #[serde(rename = "T")]
+    pub close_time: i64,
+
+    #[serde(rename = "i")]
+    pub interval: String,
+
+    #[serde(rename = "o")]
+    pub open: Decimal,
+
+    #[serde(rename = "c")]
+    pub close: Decimal,
+
+    #[serde(rename = "h")]
+    pub high: Decimal,
+
+    #[serde(rename = "l")]
+    pub low: Decimal,
+
+    #[serde(rename = "v")]
+    pub volume: Decimal,
+
+    #[serde(rename = "n")]
+    pub number_of_trades: u64,
+
+    /// True when the kline is closed
+    #[serde(rename = "x")]
+    pub is_closed: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KlineEvent {
+    #[serde(rename = "E")]
+    pub event_time: i64,
+
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "k")]
+    pub kline: WsKline,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BookTickerEvent {
+    #[serde(rename = "u")]
+    pub update_id: u64,
+
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "b")]
+    pub bid_price: Decimal,
+
+    #[serde(rename = "B")]
+    pub bid_qty: Decimal,
+
+    #[serde(rename = "a")]
+    pub ask_price: Decimal,
+
+    #[serde(rename = "A")]
+    pub ask_qty: Decimal,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MiniTickerEvent {
+    #[serde(rename = "E")]
+    pub event_time: i64,
+
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "c")]
+    pub close: Decimal,
+
+    #[serde(rename = "o")]
+    pub open: Decimal,
+
+    #[serde(rename = "h")]
+    pub high: Decimal,
+
+    #[serde(rename = "l")]
+    pub low: Decimal,
+
+    #[serde(rename = "v")]
+    pub volume: Decimal,
+
+    #[serde(rename = "q")]
+    pub quote_volume: Decimal,
+}
+
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    Trade(TradeEvent),
+    Kline(KlineEvent),
+    BookTicker(BookTickerEvent),
+    MiniTicker(MiniTickerEvent),
+    Other(serde_json::Value),
+}
+
+impl Display for StreamEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamEvent::Trade(e) => write!(
+                f,
+                "{} {:<10} trade {} @ {}",
+                time_ms_to_utc(e.trade_time),
+                e.symbol,
+                e.qty.normalize(),
+                e.price.normalize()
+            ),
+            StreamEvent::Kline(e) => write!(
+                f,
+                "{} {:<10} kline {} o: {} h: {} l: {} c: {} v: {}{}",
+                time_ms_to_utc(e.event_time),
+                e.symbol,
+                e.kline.interval,
+                e.kline.open.normalize(),
+                e.kline.high.normalize(),
+                e.kline.low.normalize(),
+                e.kline.close.normalize(),
+                e.kline.volume.normalize(),
+                if e.kline.is_closed { " closed" } else { "" }
+            ),
+            StreamEvent::BookTicker(e) => write!(
+                f,
+                "{} {:<10} bid {} @ {} ask {} @ {}",
+                time_ms_to_utc(utc_now_to_time_ms()),
+                e.symbol,
+                e.bid_qty.normalize(),
+                e.bid_price.normalize(),
+                e.ask_qty.normalize(),
+                e.ask_price.normalize()
+            ),
+            StreamEvent::MiniTicker(e) => write!(
+                f,
+                "{} {:<10} {} open: {} high: {} low: {} quote volume: {}",
+                time_ms_to_utc(e.event_time),
+                e.symbol,
+                e.close.normalize(),
+                e.open.normalize(),
+                e.high.normalize(),
+                e.low.normalize(),
+                e.quote_volume.round_dp(2)
+            ),
+            StreamEvent::Other(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+/// Parse a message from the combined stream endpoint,
+///   {"stream":"<streamName>","data":<rawPayload>}
+/// returns None for other messages such as subscription results.
+pub fn parse_stream_message(
+    text: &str,
+) -> Result<Option<(String, StreamEvent)>, Box<dyn std::error::Error>> {
+    let mut value: serde_json::Value = serde_json::from_str(text)?;
+    let stream = match value.get("stream").and_then(|s| s.as_str()) {
+        Some(s) => s.to_string(),
+        None => {
+            trace!("parse_stream_message: not a stream message: {text}");
+            return Ok(None);
+        }
+    };
+    let data = value["data"].take();
+
+    let event_type = data.get("e").and_then(|e| e.as_str()).unwrap_or("");
+    let event = match event_type {
+        "trade" => StreamEvent::Trade(serde_json::from_value(data)?),
+        "kline" => StreamEvent::Kline(serde_json::from_value(data)?),
+        "24hrMiniTicker" => StreamEvent::MiniTicker(serde_json::from_value(data)?),
+        // Book ticker payloads have no event type
+        _ if stream.ends_with("@bookTicker") => {
+            StreamEvent::BookTicker(serde_json::from_value(data)?)
+        }
+        _ => StreamEvent::Other(data),
+    };
+
+    Ok(Some((stream, event)))
+}
+
+#[derive(Debug, Clone)]
+pub enum StreamCommand {
+    Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
+}
+
+/// A websocket connection to the exchange
+pub type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// What to do after a message is handled
+pub enum Flow {
+    /// Not an event, such as a subscription result
+    Ignored,
+
+    /// An event, keep receiving
+    Continue,
+
+    /// An event, close and reconnect
+    Reconnect,
+
+    /// An event, close and stop
+    Stop,
+}
+
+/// How a connection ended
+enum ConnectionEnd {
+    /// The handler asked to stop
+    Stop,
+
+    /// Closed by the server or idle, reconnect
+    Closed,
+}
+
+/// The reconnect backoff and idle timeout of a client
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Delay before the first reconnect, doubled on each failure
+    pub delay_ms: u64,
+    pub max_delay_ms: u64,
+
+    /// Consecutive failed connections before giving up, None is forever
+    pub max_reconnects: Option<u32>,
+
+    /// Reconnect if nothing is received for this long, the server pings every 3 minutes
+    pub idle_timeout_ms: u64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            delay_ms: 1000,
+            max_delay_ms: 60 * 1000,
+            max_reconnects: None,
+            idle_timeout_ms: 10 * 60 * 1000,
+        }
+    }
+}
+
+/// The stream specific parts of a client run by run_connections
+pub trait ConnectionHandler {
+    /// Work other than received messages, such as a command or keepalive
+    type Request;
+
+    /// Connect and do any setup, such as subscribing to streams
+    async fn connect(&mut self) -> Result<WsStream, Box<dyn std::error::Error>>;
+
+    /// Wait for the next request, this must be cancel safe
+    async fn next_request(&mut self) -> Self::Request;
+
+    /// Handle a request, returning a message to send if there is one
+    async fn on_request(
+        &mut self,
+        request: Self::Request,
+    ) -> Result<Option<Message>, Box<dyn std::error::Error>>;
+
+    /// Handle a text message
+    async fn on_text(&mut self, text: &str) -> Result<Flow, Box<dyn std::error::Error>>;
+
+    /// Called when a connection ends, failed is true if it errored
+    fn on_disconnect(&mut self, _failed: bool) {}
+}
+
+/// Run connections of handler until it stops, reconnecting with a
+/// backoff when a connection closes, errors or is idle. Gives up after
+/// policy.max_reconnects consecutive connections without an event.
+pub async fn run_connections<H: ConnectionHandler>(
+    policy: &ReconnectPolicy,
+    handler: &mut H,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut failures = 0u32;
+    let mut delay_ms = policy.delay_ms;
+    loop {
+        let result = run_connection(policy, handler).await;
+        handler.on_disconnect(result.is_err());
+        match result {
+            Ok((ConnectionEnd::Stop, _)) => return Ok(()),
+            Ok((ConnectionEnd::Closed, received)) => {
+                trace!("run_connections: closed, received: {received}");
+                if received > 0 {
+                    failures = 0;
+                    delay_ms = policy.delay_ms;
+                } else {
+                    failures += 1;
+                }
+            }
+            Err(e) => {
+                trace!("run_connections: error: {e}");
+                failures += 1;
+            }
+        }
+
+        if let Some(max) = policy.max_reconnects {
+            if failures > max {
+                return Err(format!("Giving up after {failures} failed connections").into());
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+        delay_ms = (delay_ms * 2).min(policy.max_delay_ms);
+    }
+}
+
+/// Returns how the connection ended and the number of events received
+async fn run_connection<H: ConnectionHandler>(
+    policy: &ReconnectPolicy,
+    handler: &mut H,
+) -> Result<(ConnectionEnd, usize), Box<dyn std::error::Error>> {
+    let mut ws = handler.connect().await?;
+
+    let idle_timeout = Duration::from_millis(policy.idle_timeout_ms);
+    let mut received = 0usize;
+    loop {
+        tokio::select! {
+            request = handler.next_request() => {
+                if let Some(msg) = handler.on_request(request).await? {
+                    ws.send(msg).await?;
+                }
+            }
+            msg = timeout(idle_timeout, ws.next()) => {
+                match msg {
+                    Err(_) => {
+                        trace!("run_connection: idle for {idle_timeout:?}");
+                        return Ok((ConnectionEnd::Closed, received));
+                    }
+                    Ok(None) | Ok(Some(Ok(Message::Close(_)))) => {
+                        return Ok((ConnectionEnd::Closed, received));
+                    }
+                    Ok(Some(Err(e))) => return Err(e.into()),
+                    // tungstenite queues the pong for a ping, so
+                    // a second one must not be sent
+                    Ok(Some(Ok(Message::Ping(_)))) => {}
+                    Ok(Some(Ok(Message::Text(text)))) => match handler.on_text(&text).await? {
+                        Flow::Ignored => {}
+                        Flow::Continue => received += 1,
+                        Flow::Reconnect => {
+                            let _ = ws.close(None).await;
+                            return Ok((ConnectionEnd::Closed, received + 1));
+                        }
+                        Flow::Stop => {
+                            let _ = ws.close(None).await;
+                            return Ok((ConnectionEnd::Stop, received + 1));
+                        }
+                    },
+                    Ok(Some(Ok(_))) => {}
+                }
+            }
+        }
+    }
+}
+
+pub struct MarketStreamClient {
+    /// Base url, the combined stream endpoint is {url}/stream
+    url: String,
+    streams: BTreeSet<String>,
+    next_id: u64,
+    commands_tx: UnboundedSender<StreamCommand>,
+    commands_rx: Option<UnboundedReceiver<StreamCommand>>,
+    pub reconnect: ReconnectPolicy,
+}
+
+impl MarketStreamClient {
+    pub fn new(url: &str, streams: &[String]) -> MarketStreamClient {
+        let (commands_tx, commands_rx) = unbounded_channel();
+        MarketStreamClient {
+            url: url.to_string(),
+            streams: streams.iter().cloned().collect(),
+            next_id: 1,
+            commands_tx,
+            commands_rx: Some(commands_rx),
+            reconnect: ReconnectPolicy::default(),
+        }
+    }
+
+    /// Send StreamCommand's to a running client
+    pub fn command_sender(&self) -> UnboundedSender<StreamCommand> {
+        self.commands_tx.clone()
+    }
+
+    pub fn streams(&self) -> Vec<String> {
+        self.streams.iter().cloned().collect()
+    }
+
+    fn request(&mut self, method: &str, streams: &[String]) -> Message {
+        let id = self.next_id;
+        self.next_id += 1;
+        Message::Text(json!({ "method": method, "params": streams, "id": id }).to_string())
+    }
+
+    /// Receive events calling handler for each one until it returns false
+    pub async fn run<F>(&mut self, mut handler: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(&str, StreamEvent) -> bool,
+    {
+        let mut commands_rx = match self.commands_rx.take() {
+            Some(rx) => rx,
+            None => return Err("MarketStreamClient::run: already running".into()),
+        };
+
+        let policy = self.reconnect.clone();
+        let result = run_connections(
+            &policy,
+            &mut MarketConnection {
+                client: self,
+                commands_rx: &mut commands_rx,
+                handler: &mut handler,
+            },
+        )
+        .await;
+        self.commands_rx = Some(commands_rx);
+
+        result
+    }
+}
+
+/// The connections of a running MarketStreamClient
+struct MarketConnection<'a, F> {
+    client: &'a mut MarketStreamClient,
+    commands_rx: &'a mut UnboundedReceiver<StreamCommand>,
+    handler: &'a mut F,
+}
+
+impl<F> ConnectionHandler for MarketConnection<'_, F>
+where
+    F: FnMut(&str, StreamEvent) -> bool,
+{
+    type Request = Option<StreamCommand>;
+
+    async fn connect(&mut self) -> Result<WsStream, Box<dyn std::error::Error>> {
+        let url = format!("{}/stream", self.client.url);
+        trace!("MarketConnection::connect: connecting to {url}");
+        let (mut ws, _) = connect_async(&url).await?;
+
+        if !self.client.streams.is_empty() {
+            let streams = self.client.streams();
+            let msg = self.client.request("SUBSCRIBE", &streams);
+            ws.send(msg).await?;
+        }
+
+        Ok(ws)
+    }
+
+    async fn next_request(&mut self) -> Option<StreamCommand> {
+        self.commands_rx.recv().await
+    }
+
+    async fn on_request(
+        &mut self,
+        cmd: Option<StreamCommand>,
+    ) -> Result<Option<Message>, Box<dyn std::error::Error>> {
+        let msg = match cmd {
+            Some(StreamCommand::Subscribe(streams)) => {
+                self.client.streams.extend(streams.iter().cloned());
+                self.client.request("SUBSCRIBE", &streams)
+            }
+            Some(StreamCommand::Unsubscribe(streams)) => {
+                for s in &streams {
+                    self.client.streams.remove(s);
+                }
+                self.client.request("UNSUBSCRIBE", &streams)
+            }
+            // All senders are gone, the client has one so this can't happen
+            None => return Ok(None),
+        };
+
+        Ok(Some(msg))
+    }
+
+    async fn on_text(&mut self, text: &str) -> Result<Flow, Box<dyn std::error::Error>> {
+        // A bad frame is skipped, only transport errors reconnect
+        match parse_stream_message(text) {
+            Ok(Some((stream, event))) => {
+                if (self.handler)(&stream, event) {
+                    Ok(Flow::Continue)
+                } else {
+                    Ok(Flow::Stop)
+                }
+            }
+            Ok(None) => Ok(Flow::Ignored),
+            Err(e) => {
+                warn!("MarketConnection::on_text: skipping frame: {text} Err: {e}");
+                Ok(Flow::Ignored)
+            }
+        }
+    }
+}
+
+/// The symbols of the assets in the account, in the default_quote_asset
+async fn account_symbols(
+    config: &Configuration,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let ei = get_exchange_info(config).await?;
+    let ai = get_account_info(config, utc_now_to_time_ms()).await?;
+
+    Ok(ai
+        .balances_map
+        .values()
+        .filter(|b| b.free + b.locked > Decimal::ZERO && b.asset != config.default_quote_asset)
+        .map(|b| b.asset.clone() + &config.default_quote_asset)
+        .filter(|sym| ei.get_symbol(sym).is_some())
+        .collect())
+}
+
+pub async fn watch_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let symbols: Vec<String> = match sc_matches.values_of("SYMBOLS") {
+        Some(values) => values.map(|s| s.to_uppercase()).collect(),
+        None => account_symbols(config).await?,
+    };
+    if symbols.is_empty() {
+        return Err("No symbols to watch".into());
+    }
+
+    let interval = KlineInterval::from_string(
+        sc_matches
+            .value_of("INTERVAL")
+            .expect("INTERVAL is missing"),
+    )?;
+    let kind = StreamKind::from_string(
+        sc_matches.value_of("STREAM").expect("STREAM is missing"),
+        interval,
+    )?;
+    let streams: Vec<String> = symbols.iter().map(|s| stream_name(s, &kind)).collect();
+    println!("Watching: {}", streams.join(" "));
+
+    println!("Type +SYMBOL or -SYMBOL and Enter to watch or stop watching a symbol");
+
+    let mut client = MarketStreamClient::new(&config.make_ws_url(""), &streams);
+    let commands = client.command_sender();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let line = line.trim();
+            let cmd = if let Some(sym) = line.strip_prefix('+') {
+                StreamCommand::Subscribe(vec![stream_name(sym, &kind)])
+            } else if let Some(sym) = line.strip_prefix('-') {
+                StreamCommand::Unsubscribe(vec![stream_name(sym, &kind)])
+            } else {
+                continue;
+            };
+            if commands.send(cmd).is_err() {
+                break;
+            }
+        }
+    });
+
+    client
+        .run(|_stream, event| {
+            println!("{event}");
+            true
+        })
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    const RECORDED_FRAMES: &str = include_str!("../test_data/ws_market_frames.jsonl");
+
+    #[test]
+    fn test_stream_name() {
+        assert_eq!(stream_name("BTCUSD", &StreamKind::Trade), "btcusd@trade");
+        assert_eq!(
+            stream_name("BTCUSD", &StreamKind::Kline(KlineInterval::Mins1)),
+            "btcusd@kline_1m"
+        );
+        assert_eq!(
+            stream_name("BTCUSD", &StreamKind::BookTicker),
+            "btcusd@bookTicker"
+        );
+        assert_eq!(
+            stream_name("BTCUSD", &StreamKind::MiniTicker),
+            "btcusd@miniTicker"
+        );
+    }
+
+    #[test]
+    fn test_parse_stream_message() {
+        let events: Vec<(String, StreamEvent)> = RECORDED_FRAMES
+            .lines()
+            .filter_map(|l| parse_stream_message(l).unwrap())
+            .collect();
+        assert_eq!(events.len(), 4);
+
+        match &events[0].1 {
+            StreamEvent::Trade(e) => {
+                assert_eq!(e.symbol, "BTCUSD");
+                assert_eq!(e.price, dec!(20000.01));
+                assert_eq!(e.trade_id, 12345);
+            }
+            e => panic!("expected Trade got {e:?}"),
+        }
+        match &events[1].1 {
+            StreamEvent::Kline(e) => {
+                assert_eq!(e.kline.interval, "1m");
+                assert_eq!(e.kline.close, dec!(20010));
+                assert!(!e.kline.is_closed);
+            }
+            e => panic!("expected Kline got {e:?}"),
+        }
+        match &events[2].1 {
+            StreamEvent::BookTicker(e) => {
+                assert_eq!(e.bid_price, dec!(20000));
+                assert_eq!(e.ask_qty, dec!(2));
+            }
+            e => panic!("expected BookTicker got {e:?}"),
+        }
+        match &events[3].1 {
+            StreamEvent::MiniTicker(e) => {
+                assert_eq!(e.symbol, "ETHUSD");
+                assert_eq!(e.close, dec!(1500.25));
+            }
+            e => panic!("expected MiniTicker got {e:?}"),
+        }
+
+        // Subscription results are not events
+        assert!(parse_stream_message(r#"{"result":null,"id":1}"#)
+            .unwrap()
+            .is_none());
+    }
+
+    /// Replay the recorded frames to each connection after answering the
+    /// subscription and checking a ping is answered, then close it.
+    async fn replay_server(listener: TcpListener, connections: usize) -> Vec<String> {
+        let mut requests = Vec::new();
+        for _ in 0..connections {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = accept_async(tcp).await.unwrap();
+
+            if let Some(Ok(Message::Text(sub))) = ws.next().await {
+                let id = serde_json::from_str::<serde_json::Value>(&sub).unwrap()["id"].clone();
+                requests.push(sub);
+                let result = json!({ "result": null, "id": id }).to_string();
+                ws.send(Message::Text(result)).await.unwrap();
+            }
+
+            ws.send(Message::Ping(vec![1, 2, 3])).await.unwrap();
+            match ws.next().await {
+                Some(Ok(Message::Pong(payload))) => assert_eq!(payload, vec![1, 2, 3]),
+                m => panic!("expected Pong got {m:?}"),
+            }
+
+            // The client may stop and close before all of the frames are sent
+            for frame in RECORDED_FRAMES.lines() {
+                if ws.send(Message::Text(frame.to_string())).await.is_err() {
+                    break;
+                }
+            }
+            let _ = ws.close(None).await;
+        }
+
+        requests
+    }
+
+    #[tokio::test]
+    async fn test_market_stream_client_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(replay_server(listener, 2));
+
+        let streams = vec!["btcusd@trade".to_string(), "ethusd@miniTicker".to_string()];
+        let mut client = MarketStreamClient::new(&url, &streams);
+        client.reconnect.delay_ms = 10;
+        client.reconnect.max_reconnects = Some(2);
+
+        // Receive all of the frames of the first connection and
+        // the first frame of the second connection.
+        let mut events = Vec::new();
+        client
+            .run(|stream, event| {
+                events.push((stream.to_string(), event));
+                events.len() < 5
+            })
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[4].0, "btcusd@trade");
+
+        // Both connections subscribed to the streams
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        for (idx, r) in requests.iter().enumerate() {
+            let v: serde_json::Value = serde_json::from_str(r).unwrap();
+            assert_eq!(v["method"], "SUBSCRIBE");
+            assert_eq!(v["params"], json!(["btcusd@trade", "ethusd@miniTicker"]));
+            assert_eq!(v["id"], json!(idx + 1));
+        }
+    }
+}
@@ -10,18 +10,31 @@ use log::trace;
 use rust_decimal::prelude::*;
 use rust_decimal_macros::dec;
 use time_ms_conversions::utc_now_to_time_ms;
+use tokio::time::{sleep, Duration};
 
 use crate::{
     binance_account_info::{get_account_info, AccountInfo},
     binance_avg_price::get_avg_price,
+    binance_capital_config::{adj_withdraw_quantity, get_coin_config, select_network},
     binance_exchange_info::{get_exchange_info, ExchangeInfo},
+    binance_history::{
+        get_capital_withdraw_history, withdraw_status_name, CapitalWithdrawRec, WITHDRAW_POLL_SECS,
+        WITHDRAW_STATUS_CANCELLED, WITHDRAW_STATUS_COMPLETED, WITHDRAW_STATUS_FAILURE,
+        WITHDRAW_STATUS_REJECTED, WITHDRAW_WAIT_MAX_ERRORS, WITHDRAW_WAIT_TIMEOUT_MS,
+    },
     binance_order_response::TradeResponse,
     binance_signature::{append_signature, binance_signature, query_vec_u8},
     binance_trade::order_log_file,
-    binance_verify_order::{adj_quantity_verify_lot_size, verify_quanity_is_less_than_or_eq_free},
-    common::InternalErrorRec,
+    binance_verify_order::verify_quanity_is_less_than_or_eq_free,
+    common::{are_you_sure_stdout_stdin, InternalErrorRec},
     configuration::Configuration,
     ier_new,
+    notifications::{notify_trade_summary, TradeSummary},
+    withdraw_address_book::{validate_address, withdraw_destination},
+    withdraw_limits::{
+        cooling_off_remaining_ms, logged_withdrawals, ms_to_hours, verify_daily_limits,
+        verify_kill_switch_off,
+    },
 };
 use crate::{
     binance_order_response::WithdrawResponseRec,
@@ -155,6 +168,14 @@ pub struct WithdrawParams {
     pub label: Option<String>,
     #[serde(default)]
     pub keep_min_amount: Option<Amount>,
+    #[serde(default)]
+    pub allow_unlisted_addr: bool,
+    #[serde(default)]
+    pub network: Option<String>,
+    #[serde(default)]
+    pub fee: Decimal,
+    #[serde(default)]
+    pub wait: bool,
 }
 
 impl Default for WithdrawParams {
@@ -169,6 +190,10 @@ impl Default for WithdrawParams {
             secondary_address: None,
             label: None,
             keep_min_amount: None,
+            allow_unlisted_addr: false,
+            network: None,
+            fee: dec!(0),
+            wait: false,
         }
     }
 }
@@ -199,6 +224,9 @@ impl WithdrawParams {
 
         let secondary_address = sc_matches.value_of("dest-sec-addr").map(|s| s.to_string());
         let label = sc_matches.value_of("dest-label").map(|s| s.to_string());
+        let allow_unlisted_addr = sc_matches.is_present("allow-unlisted-addr");
+        let network = sc_matches.value_of("network").map(|s| s.to_uppercase());
+        let wait = sc_matches.is_present("wait");
 
         Ok(WithdrawParams {
             sym_name,
@@ -210,6 +238,10 @@ impl WithdrawParams {
             secondary_address,
             label,
             keep_min_amount,
+            allow_unlisted_addr,
+            network,
+            fee: dec!(0),
+            wait,
         })
     }
 }
@@ -220,13 +252,14 @@ async fn withdraw_post_and_response(
     full_path: &str,
     params: &WithdrawParams,
     mut param_tuples: Vec<(&str, &str)>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<TradeResponse, Box<dyn std::error::Error>> {
     let api_key = config.keys.get_ak_or_err()?;
     let secret_key = &config.keys.get_sk_vec_u8_or_err()?;
 
     param_tuples.push(("recvWindow", "5000"));
 
-    let ts_string: String = format!("{}", utc_now_to_time_ms());
+    let time_ms = utc_now_to_time_ms();
+    let ts_string: String = format!("{time_ms}");
     param_tuples.push(("timestamp", ts_string.as_str()));
 
     let mut query = query_vec_u8(&param_tuples);
@@ -258,7 +291,10 @@ async fn withdraw_post_and_response(
 
         // Process the response
         if response_status == 200 {
+            // The response is the id of the withdrawal, {"id":"7213fea8e94b4a5593d507237e5a555b"}
             let mut response: WithdrawResponseRec = serde_json::from_str(&response_body)?;
+            response.success = !response.id.is_empty();
+            response.time = time_ms;
             response.test = config.test;
             response.query = query_string;
             response.params = params.clone();
@@ -294,6 +330,7 @@ async fn withdraw_post_and_response(
             msg: "successful test".into(),
             success: true,
             id: "a-test-id".into(),
+            time: time_ms,
             test: config.test,
             query: query_string,
             params: params.clone(),
@@ -309,17 +346,23 @@ async fn withdraw_post_and_response(
 
         TradeResponse::SuccessTestWithdraw(wrr)
     };
-    println!("{}", &tr);
+    println!("{}", tr);
+
+    log_order_response(&mut log_writer, &tr)?;
 
-    // Log response and return Ok or Err
-    log_order_response(&mut log_writer, &tr)
+    Ok(tr)
 }
 
 pub async fn withdraw(
     config: &Configuration,
     ei: &ExchangeInfo,
     params: &WithdrawParams,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<TradeResponse, Box<dyn std::error::Error>> {
+    if config.paper_trading {
+        return Err("Withdrawals are not available when paper trading".into());
+    }
+    verify_kill_switch_off(config)?;
+
     let ai = get_account_info(config, utc_now_to_time_ms()).await?;
     trace!("withdraw: Got AccountInfo: {:#?}", ai);
 
@@ -345,20 +388,42 @@ pub async fn withdraw(
         .await?;
     trace!("org_quantity: {}", org_quantity);
 
-    let config_withdraw_addr = config.withdraw_addr.clone();
-    let params_address = params.address.clone();
-    let withdraw_addr = match params_address {
-        Some(addr) => addr,
-        None => {
-            if let Some(addr) = config_withdraw_addr {
-                addr
-            } else {
-                return Err(ier_new!(2, "No withdraw address given, expecting --withdraw-addr option or withdraw_addr in configuration toml file.").into());
-            }
+    // The address or label of the params or configuration, if
+    // neither the asset's only withdraw_addresses entry
+    let addr = params
+        .address
+        .clone()
+        .or_else(|| config.withdraw_addr.clone());
+    let dest = withdraw_destination(
+        &config.withdraw_addresses,
+        &params.sym_name,
+        addr.as_deref(),
+        params.allow_unlisted_addr,
+    )?
+    .with_memo_and_label(
+        params.secondary_address.as_deref(),
+        params.label.as_deref(),
+        params.allow_unlisted_addr,
+    )?;
+    trace!("withdraw: dest: {:?}", dest);
+    let withdraw_addr = dest.address.clone();
+
+    // The network is from --network, the address book entry or the coin's default
+    let network = match (&params.network, &dest.network) {
+        (Some(pn), Some(dn)) if !pn.eq_ignore_ascii_case(dn) => {
+            return Err(format!(
+                "--network {pn} conflicts with the {dn} network of withdraw address {}",
+                dest.label.as_deref().unwrap_or(&withdraw_addr)
+            )
+            .into());
         }
+        (Some(n), _) | (None, Some(n)) => Some(n.as_str()),
+        (None, None) => None,
     };
-    // Guranatee that withdraw_addr is not empty
-    assert!(!withdraw_addr.is_empty());
+    let coin_config = get_coin_config(config, &params.sym_name).await?;
+    let network_rec = select_network(&coin_config, network)?;
+    trace!("withdraw: network_rec: {:?}", network_rec);
+    validate_address(&network_rec.network, &withdraw_addr)?;
 
     let keep_quantity = match &params.keep_min_amount {
         Some(amount) => Some(amount.to_quantity(config, &ai, &params.sym_name).await?),
@@ -389,24 +454,102 @@ pub async fn withdraw(
         org_quantity
     };
 
-    let quantity = adj_quantity_verify_lot_size(symbol, org_quantity);
+    let quantity = adj_withdraw_quantity(network_rec, org_quantity)?;
     trace!(
-        "withdraw: quantity={} after adjusting for lot_size",
-        quantity
+        "withdraw: quantity={} after adjusting for the {} network",
+        quantity,
+        network_rec.network
     );
+    let net_quantity = quantity - network_rec.withdraw_fee;
+    if net_quantity <= dec!(0) {
+        return Err(format!(
+            "Withdraw quantity {} {} doesn't cover the {} fee of {}",
+            quantity, params.sym_name, network_rec.network, network_rec.withdraw_fee
+        )
+        .into());
+    }
 
     let mut params_x = params.clone();
     params_x.org_quantity = org_quantity;
     params_x.address = Some(withdraw_addr.clone());
+    params_x.secondary_address = dest.memo;
+    params_x.label = dest.label;
     params_x.quantity = quantity;
-    params_x.quantity_usd = quantity * get_avg_price(config, &full_name).await?.price;
+    params_x.network = Some(network_rec.network.clone());
+    params_x.fee = network_rec.withdraw_fee;
+    let price = get_avg_price(config, &full_name).await?.price;
+    params_x.quantity_usd = quantity * price;
     let params = params_x;
 
     verify_quanity_is_less_than_or_eq_free(&ai, symbol, quantity)?;
+
+    let withdrawals = logged_withdrawals(order_log_path)?;
+    let now_ms = utc_now_to_time_ms();
+    verify_daily_limits(
+        config,
+        &withdrawals,
+        &params.sym_name,
+        params.quantity_usd,
+        now_ms,
+    )?;
+    if let Some(remaining_ms) =
+        cooling_off_remaining_ms(config, &withdrawals, &withdraw_addr, config.test, now_ms)
+    {
+        // Log the refused withdrawal
+        let msg = format!(
+            "Withdrawals to {withdraw_addr} are refused for {} more hours, it's in its cooling-off period",
+            ms_to_hours(remaining_ms)
+        );
+        let tr = TradeResponse::FailureWithdraw(WithdrawResponseRec {
+            test: config.test,
+            params: params.clone(),
+            msg: msg.clone(),
+            time: now_ms,
+            success: false,
+            ..WithdrawResponseRec::default()
+        });
+        log_order_response(&mut log_writer, &tr)?;
+        return Err(msg.into());
+    }
+
+    println!(
+        "Withdrawing {} {} valued at ${:.2} on {} ({})",
+        quantity, params.sym_name, params.quantity_usd, network_rec.network, network_rec.name
+    );
+    println!(
+        "  to: {}{}{}",
+        if let Some(l) = &params.label {
+            format!("{l}:")
+        } else {
+            "".to_string()
+        },
+        withdraw_addr,
+        if let Some(sa) = &params.secondary_address {
+            format!(" memo: {sa}")
+        } else {
+            "".to_string()
+        },
+    );
+    println!(
+        "  fee: {} {} valued at ${:.2}",
+        params.fee,
+        params.sym_name,
+        params.fee * price
+    );
+    println!(
+        "  net received: {} {} valued at ${:.2}",
+        net_quantity,
+        params.sym_name,
+        net_quantity * price
+    );
+    if !(config.test || !config.confirmation_required || are_you_sure_stdout_stdin()) {
+        println!("\n ** Aborted **");
+        return Err("Withdraw aborted".into());
+    }
     let quantity_string = quantity.to_string();
 
     let mut param_tuples = vec![
-        ("asset", params.sym_name.as_str()),
+        ("coin", params.sym_name.as_str()),
         ("address", withdraw_addr.as_str()),
         ("amount", quantity_string.as_str()),
     ];
@@ -420,17 +563,74 @@ pub async fn withdraw(
         label_string = l;
         param_tuples.push(("name", label_string.as_str()))
     }
+    param_tuples.push(("network", network_rec.network.as_str()));
 
     withdraw_post_and_response(
         config,
         &mut log_writer,
-        "/wapi/v3/withdraw.html",
+        "/sapi/v1/capital/withdraw/apply",
         &params,
         param_tuples,
     )
     .await
 }
 
+/// Poll the withdraw history of coin until the withdrawal id is
+/// completed, cancelled, rejected or failed. Failures to get the
+/// history are retried until the wait times out or there are
+/// WITHDRAW_WAIT_MAX_ERRORS of them in a row.
+pub async fn wait_for_withdraw(
+    config: &Configuration,
+    coin: &str,
+    id: &str,
+) -> Result<CapitalWithdrawRec, Box<dyn std::error::Error>> {
+    let start_ms = utc_now_to_time_ms();
+    let mut last_status: Option<i32> = None;
+    let mut errors = 0u32;
+    loop {
+        let last_error = match get_capital_withdraw_history(config, coin).await {
+            Ok(withdrawals) => {
+                errors = 0;
+                if let Some(wr) = withdrawals.into_iter().find(|wr| wr.id == id) {
+                    if last_status != Some(wr.status) {
+                        println!("Withdrawal {id}: {}", withdraw_status_name(wr.status));
+                        last_status = Some(wr.status);
+                    }
+                    match wr.status {
+                        WITHDRAW_STATUS_COMPLETED
+                        | WITHDRAW_STATUS_CANCELLED
+                        | WITHDRAW_STATUS_REJECTED
+                        | WITHDRAW_STATUS_FAILURE => return Ok(wr),
+                        _ => {}
+                    }
+                }
+                None
+            }
+            Err(e) => {
+                errors += 1;
+                if errors >= WITHDRAW_WAIT_MAX_ERRORS {
+                    return Err(format!(
+                        "Gave up waiting for withdrawal {id} after {errors} consecutive errors getting the withdraw history, the last was {e}"
+                    )
+                    .into());
+                }
+                println!("Getting the withdraw history failed, retrying: {e}");
+                Some(e.to_string())
+            }
+        };
+
+        if utc_now_to_time_ms() - start_ms > WITHDRAW_WAIT_TIMEOUT_MS {
+            return Err(format!(
+                "Timed out waiting for withdrawal {id}, the last status was {}{}",
+                last_status.map_or("not found", withdraw_status_name),
+                last_error.map_or("".to_string(), |e| format!(", the last error was {e}"))
+            )
+            .into());
+        }
+        sleep(Duration::from_secs(WITHDRAW_POLL_SECS)).await;
+    }
+}
+
 pub async fn withdraw_cmd(
     config: &Configuration,
     params: &WithdrawParams,
@@ -442,7 +642,42 @@ pub async fn withdraw_cmd(
     );
 
     let ei = &get_exchange_info(config).await?;
-    withdraw(config, ei, params).await?;
-
-    Ok(())
+    let mut summary = TradeSummary::new("withdraw", config.test);
+    match withdraw(config, ei, params).await {
+        Ok(tr) => {
+            summary.add(&tr);
+            notify_trade_summary(config, &summary).await;
+
+            match tr {
+                TradeResponse::SuccessWithdraw(wrr) if params.wait => {
+                    let wr = wait_for_withdraw(config, &params.sym_name, &wrr.id).await?;
+                    if wr.status == WITHDRAW_STATUS_COMPLETED {
+                        println!(
+                            "Withdrawal {} of {} {} on {} completed, fee: {} tx id: {}",
+                            wr.id,
+                            wr.amount,
+                            wr.coin,
+                            wr.network,
+                            wr.transaction_fee,
+                            wr.tx_id.as_deref().unwrap_or("")
+                        );
+                        Ok(())
+                    } else {
+                        Err(format!(
+                            "Withdrawal {} was {}",
+                            wr.id,
+                            withdraw_status_name(wr.status)
+                        )
+                        .into())
+                    }
+                }
+                _ => Ok(()),
+            }
+        }
+        Err(e) => {
+            summary.add_error(&params.sym_name, e.as_ref());
+            notify_trade_summary(config, &summary).await;
+            Err(e)
+        }
+    }
 }
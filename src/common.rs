@@ -18,6 +18,13 @@ use strum_macros::IntoStaticStr;
 
 use serde::{Deserialize, Serialize};
 
+use time_ms_conversions::{
+    dt_str_to_utc_time_ms,
+    TzMassaging::{HasTz, LocalTz},
+};
+
+use crate::binance_order_response::TradeResponse;
+use crate::configuration::Configuration;
 use crate::de_string_or_number::de_string_or_number_to_i64;
 use crate::serde_header_map::{de_header_map, se_header_map};
 
@@ -276,6 +283,42 @@ pub async fn get_req_get_response_ll(
     Ok(response)
 }
 
+/// A Low Level put req and get response
+pub async fn put_req_get_response_ll(
+    url: &str,
+    headers_map: HeaderMap,
+    body: &str,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let mut req_builder = reqwest::Client::builder().build()?.put(url);
+    if !headers_map.is_empty() {
+        req_builder = req_builder.headers(headers_map);
+    }
+    req_builder = req_builder.body(body.to_owned());
+    trace!("req_builder={:#?}", req_builder);
+
+    let response = req_builder.send().await?;
+    trace!("response={:#?}", response);
+
+    Ok(response)
+}
+
+/// A Low Level delete req and get response
+pub async fn delete_req_get_response_ll(
+    url: &str,
+    headers_map: HeaderMap,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let mut req_builder = reqwest::Client::builder().build()?.delete(url);
+    if !headers_map.is_empty() {
+        req_builder = req_builder.headers(headers_map);
+    }
+    trace!("req_builder={:#?}", &req_builder);
+
+    let response = req_builder.send().await?;
+    trace!("response={:#?}", response);
+
+    Ok(response)
+}
+
 /// Binance post_req_get_response
 pub async fn post_req_get_response(
     api_key: &str,
@@ -301,6 +344,65 @@ pub async fn get_req_get_response(
     Ok(response)
 }
 
+/// Get the body of the api path, a status other than 200 is
+/// returned as the error TradeResponse::FailureResponse
+pub async fn get_api_response_body(
+    config: &Configuration,
+    path: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let url = config.make_url("api", path);
+    trace!("get_api_response_body: url={}", url);
+
+    let response = get_req_get_response(config.keys.get_ak_or_err()?, &url).await?;
+    let response_headers = response.headers().clone();
+    let response_status = response.status();
+    let response_body = response.text().await?;
+
+    if response_status == 200 {
+        Ok(response_body)
+    } else {
+        let rer = ResponseErrorRec::new(
+            false,
+            response_status.as_u16(),
+            &url,
+            response_headers,
+            &response_body,
+        );
+        let binance_error_response = TradeResponse::FailureResponse(rer);
+        trace!(
+            "get_api_response_body: error resp_failure={:?}",
+            binance_error_response
+        );
+
+        Err(binance_error_response.into())
+    }
+}
+
+/// Binance put_req_get_response
+pub async fn put_req_get_response(
+    api_key: &str,
+    url: &str,
+    body: &str,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let mut headers = HeaderMap::new();
+    headers.insert("X-MBX-APIKEY", HeaderValue::from_str(api_key)?);
+
+    let response = put_req_get_response_ll(url, headers, body).await?;
+    Ok(response)
+}
+
+/// Binance delete_req_get_response
+pub async fn delete_req_get_response(
+    api_key: &str,
+    url: &str,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let mut headers = HeaderMap::new();
+    headers.insert("X-MBX-APIKEY", HeaderValue::from_str(api_key)?);
+
+    let response = delete_req_get_response_ll(url, headers).await?;
+    Ok(response)
+}
+
 pub fn are_you_sure_stdout_stdin() -> bool {
     print!("Are you sure, type Yes: ");
     if stdout().flush().is_err() {
@@ -377,6 +479,32 @@ pub fn create_buf_reader_from_path(
     Ok(BufReader::new(in_file))
 }
 
+/// Convert a time string to time ms. A date alone, YYYY-MM-DD, is the
+/// beginning or if end_of_day the end of that day UTC. Otherwise it's
+/// YYYY-MM-DDTHR:MIN:SEC{TZ} and the users TZ is used if TZ is absent.
+pub fn time_str_to_time_ms(
+    time: &str,
+    end_of_day: bool,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    if time.len() == 10 {
+        let (hms, ms) = if end_of_day {
+            ("23:59:59", 999)
+        } else {
+            ("00:00:00", 0)
+        };
+        if let Ok(t) = dt_str_to_utc_time_ms(&format!("{time}T{hms}z"), HasTz) {
+            return Ok(t + ms);
+        }
+    }
+    match dt_str_to_utc_time_ms(time, LocalTz) {
+        Ok(t) => Ok(t),
+        Err(_) => match dt_str_to_utc_time_ms(time, HasTz) {
+            Ok(t) => Ok(t),
+            Err(_) => Err(format!("Could not convert {time} to a time").into()),
+        },
+    }
+}
+
 #[cfg(test)]
 mod test {
     use reqwest::header::HeaderName;
@@ -447,6 +575,24 @@ mod test {
         assert_eq!(response.msg, "An unexpected error");
     }
 
+    #[test]
+    fn test_time_str_to_time_ms() {
+        // Beginning and end of 2022-12-31 UTC
+        assert_eq!(
+            time_str_to_time_ms("2022-12-31", false).unwrap(),
+            1672444800000
+        );
+        assert_eq!(
+            time_str_to_time_ms("2022-12-31", true).unwrap(),
+            1672531199999
+        );
+        assert_eq!(
+            time_str_to_time_ms("2022-12-31T00:00:00z", true).unwrap(),
+            1672444800000
+        );
+        assert!(time_str_to_time_ms("not a time", false).is_err());
+    }
+
     //fn test_binance_response_failure_as_Error() {
     //    assert!(get_binance_response_failure().is_err());
     //}
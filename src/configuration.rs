@@ -10,7 +10,7 @@ use serde::{
     Deserialize, Deserializer,
 };
 use std::error::Error;
-use std::{collections::HashMap, fmt, fs::read_to_string, path::PathBuf};
+use std::{collections::HashMap, fmt, fs::read_to_string, path::PathBuf, str::FromStr};
 
 // from: https://github.com/serde-rs/serde/issues/936#ref-issue-557235055
 // TODO: Maybe a process macro can be created that generates de_vec_xxx_to_hashmap?
@@ -121,6 +121,135 @@ pub struct BuyRec {
     pub quote_asset: String,
 }
 
+// from: https://github.com/serde-rs/serde/issues/936#ref-issue-557235055
+// TODO: Maybe a process macro can be created that generates de_vec_xxx_to_hashmap?
+fn de_vec_sell_rule_rec_to_hashmap<'de, D>(
+    deserializer: D,
+) -> Result<Option<HashMap<String, SellRuleRec>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct ItemsVisitor;
+
+    impl<'de> Visitor<'de> for ItemsVisitor {
+        type Value = HashMap<String, SellRuleRec>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a sequence of items")
+        }
+
+        fn visit_seq<V>(self, mut seq: V) -> Result<HashMap<String, SellRuleRec>, V::Error>
+        where
+            V: SeqAccess<'de>,
+        {
+            let mut map: HashMap<String, SellRuleRec> =
+                HashMap::with_capacity(seq.size_hint().unwrap_or(0));
+
+            while let Some(item) = seq.next_element::<SellRuleRec>()? {
+                // println!("item={:#?}", item);
+                map.insert(item.name.clone(), item);
+            }
+
+            Ok(map)
+        }
+    }
+
+    // println!("dev_vec_sell_rule_rec_to_hashmap: in Visitor");
+    let result = deserializer.deserialize_seq(ItemsVisitor);
+
+    match result {
+        Ok(v) => Ok(Some(v)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Rules used by eval-sell-rules to decide when to sell an asset,
+/// any rule that is present may fire.
+#[derive(Debug, Clone, Deserialize, Eq, PartialEq)]
+pub struct SellRuleRec {
+    pub name: String,
+
+    // Percent of the free balance sold when take_profit_percent
+    // or trailing_stop_percent fire
+    #[serde(default = "default_sell_percent")]
+    pub sell_percent: Decimal,
+
+    // Fire when the price is this percent above the cost basis
+    #[serde(default)]
+    pub take_profit_percent: Option<Decimal>,
+
+    // Fire when the price is this percent below the high since the last buy
+    #[serde(default)]
+    pub trailing_stop_percent: Option<Decimal>,
+
+    // Fire when the value is above this, only the excess is sold
+    #[serde(default)]
+    pub max_value_usd: Option<Decimal>,
+
+    #[serde(default)]
+    pub quote_asset: String,
+}
+
+fn default_sell_percent() -> Decimal {
+    Decimal::ONE_HUNDRED
+}
+
+/// What market_order does when the estimated slippage
+/// exceeds max_slippage_percent.
+#[derive(Debug, Clone, Copy, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SlippageAction {
+    /// Don't place the order
+    Block,
+
+    /// Place the order in chunks each within max_slippage_percent
+    Split,
+}
+
+impl SlippageAction {
+    pub fn from_string(s: &str) -> Result<SlippageAction, Box<dyn Error>> {
+        match s {
+            "block" => Ok(SlippageAction::Block),
+            "split" => Ok(SlippageAction::Split),
+            _ => Err(format!("Unknown slippage action, {s}, expecting: block or split").into()),
+        }
+    }
+}
+
+/// The price paper trading market orders are filled at.
+#[derive(Debug, Clone, Copy, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PaperFillPrice {
+    /// The current average price, /api/v3/avgPrice
+    AvgPrice,
+
+    /// The close of the most recent 1m kline
+    Kline,
+}
+
+#[derive(Debug, Clone, Deserialize, Eq, PartialEq)]
+pub struct PaperBalanceRec {
+    pub asset: String,
+    pub free: Decimal,
+}
+
+/// An entry of the withdrawal address book
+#[derive(Debug, Clone, Deserialize, Eq, PartialEq)]
+pub struct WithdrawAddressRec {
+    pub label: String,
+    pub asset: String,
+
+    // Network of the address, if None the network named asset
+    #[serde(default)]
+    pub network: Option<String>,
+
+    pub address: String,
+
+    // Memo or tag, sent as the addressTag
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
 #[derive(Clone, Default, Deserialize, Eq, PartialEq)]
 pub struct Keys {
     #[serde(rename = "SECRET_KEY")]
@@ -222,6 +351,10 @@ pub struct Configuration {
     #[serde(deserialize_with = "de_vec_buy_rec_to_hashmap")]
     pub buy: Option<HashMap<String, BuyRec>>,
 
+    #[serde(default)]
+    #[serde(deserialize_with = "de_vec_sell_rule_rec_to_hashmap")]
+    pub sell_rules: Option<HashMap<String, SellRuleRec>>,
+
     #[serde(default = "default_scheme")]
     pub scheme: String,
 
@@ -230,6 +363,102 @@ pub struct Configuration {
 
     #[serde(default)]
     pub withdraw_addr: Option<String>,
+
+    // The only addresses withdrawals are made to unless overridden
+    #[serde(default)]
+    pub withdraw_addresses: Vec<WithdrawAddressRec>,
+
+    // Maximum USD value of all withdrawals in the last 24 hours, if None unlimited
+    #[serde(default)]
+    pub withdraw_daily_limit_usd: Option<Decimal>,
+
+    // Maximum USD value of an asset's withdrawals in the last 24 hours
+    #[serde(default)]
+    pub withdraw_asset_daily_limits_usd: HashMap<String, Decimal>,
+
+    // Hours after the first successful withdrawal to an address before more are made
+    #[serde(default)]
+    pub withdraw_cooling_off_hours: Option<u64>,
+
+    // All withdrawals are refused while this file exists
+    #[serde(default = "default_withdraw_kill_switch_path")]
+    pub withdraw_kill_switch_path: PathBuf,
+
+    // Base url of the websocket streams, if None it is
+    // wss://stream.{domain}:9443
+    #[serde(default)]
+    pub websocket_url: Option<String>,
+
+    // When true orders are filled by the paper trading backend
+    #[serde(default)]
+    pub paper_trading: bool,
+
+    #[serde(default = "default_paper_state_path")]
+    pub paper_state_path: PathBuf,
+
+    // The order log of paper trades, kept apart from order_log_path
+    #[serde(default = "default_paper_order_log_path")]
+    pub paper_order_log_path: PathBuf,
+
+    #[serde(default = "default_paper_fee_percent")]
+    pub paper_fee_percent: Decimal,
+
+    #[serde(default = "default_paper_fill_price")]
+    pub paper_fill_price: PaperFillPrice,
+
+    // The balances of a new paper trading account
+    #[serde(default)]
+    pub paper_initial_balances: Vec<PaperBalanceRec>,
+
+    // Maximum percent the estimated average fill price of a market
+    // order may be worse than the avgPrice, None disables the check
+    #[serde(default)]
+    pub max_slippage_percent: Option<Decimal>,
+
+    #[serde(default = "default_slippage_action")]
+    pub slippage_action: SlippageAction,
+
+    // Number of order book levels fetched when estimating slippage
+    #[serde(default = "default_slippage_book_limit")]
+    pub slippage_book_limit: u16,
+
+    #[serde(default = "default_slippage_max_chunks")]
+    pub slippage_max_chunks: u32,
+
+    #[serde(default = "default_slippage_chunk_delay_ms")]
+    pub slippage_chunk_delay_ms: u64,
+
+    // Alerts checked by the alerts subcommand, e.g. "BTCUSD crosses 30000"
+    #[serde(default)]
+    pub alerts: Vec<String>,
+
+    // Where alerts are sent, stdout, command:CMD, file:PATH or webhook:URL
+    #[serde(default)]
+    pub alert_notifiers: Vec<String>,
+
+    #[serde(default = "default_alert_interval_secs")]
+    pub alert_interval_secs: u64,
+
+    // Where the results of trades and withdrawals are sent, same format
+    // as alert_notifiers
+    #[serde(default)]
+    pub trade_notifiers: Vec<String>,
+
+    // Times a failed notification is retried
+    #[serde(default = "default_notify_retries")]
+    pub notify_retries: u32,
+
+    // Delay before the first retry, doubled for each retry
+    #[serde(default = "default_notify_retry_delay_ms")]
+    pub notify_retry_delay_ms: u64,
+
+    // Account value history appended to by the snapshot subcommand
+    #[serde(default = "default_snapshot_path")]
+    pub snapshot_path: PathBuf,
+
+    // Directory of the trades, orders and cursors saved by the sync subcommand
+    #[serde(default = "default_sync_dir")]
+    pub sync_dir: PathBuf,
 }
 
 fn default_quote_asset() -> String {
@@ -264,6 +493,62 @@ fn default_confirmation_required() -> bool {
     true
 }
 
+fn default_slippage_action() -> SlippageAction {
+    SlippageAction::Block
+}
+
+fn default_slippage_book_limit() -> u16 {
+    100
+}
+
+fn default_slippage_max_chunks() -> u32 {
+    10
+}
+
+fn default_slippage_chunk_delay_ms() -> u64 {
+    1000
+}
+
+fn default_alert_interval_secs() -> u64 {
+    60
+}
+
+fn default_notify_retries() -> u32 {
+    3
+}
+
+fn default_notify_retry_delay_ms() -> u64 {
+    1000
+}
+
+fn default_snapshot_path() -> PathBuf {
+    PathBuf::from("data/snapshots.jsonl")
+}
+
+fn default_sync_dir() -> PathBuf {
+    PathBuf::from("data/sync")
+}
+
+fn default_withdraw_kill_switch_path() -> PathBuf {
+    PathBuf::from("data/withdrawals_disabled")
+}
+
+fn default_paper_state_path() -> PathBuf {
+    PathBuf::from("data/paper_state.json")
+}
+
+fn default_paper_order_log_path() -> PathBuf {
+    PathBuf::from("data/paper_order_log.txt")
+}
+
+fn default_paper_fee_percent() -> Decimal {
+    Decimal::new(1, 1) // 0.1%
+}
+
+fn default_paper_fill_price() -> PaperFillPrice {
+    PaperFillPrice::AvgPrice
+}
+
 fn default_keys() -> Keys {
     Keys {
         secret_key: Some("secret-key".to_owned()),
@@ -286,7 +571,33 @@ impl Default for Configuration {
             domain: default_domain(),
             keep: None,
             buy: None,
+            sell_rules: None,
             withdraw_addr: None,
+            withdraw_addresses: vec![],
+            withdraw_daily_limit_usd: None,
+            withdraw_asset_daily_limits_usd: HashMap::new(),
+            withdraw_cooling_off_hours: None,
+            withdraw_kill_switch_path: default_withdraw_kill_switch_path(),
+            websocket_url: None,
+            paper_trading: false,
+            paper_state_path: default_paper_state_path(),
+            paper_order_log_path: default_paper_order_log_path(),
+            paper_fee_percent: default_paper_fee_percent(),
+            paper_fill_price: default_paper_fill_price(),
+            paper_initial_balances: vec![],
+            max_slippage_percent: None,
+            slippage_action: default_slippage_action(),
+            slippage_book_limit: default_slippage_book_limit(),
+            slippage_max_chunks: default_slippage_max_chunks(),
+            slippage_chunk_delay_ms: default_slippage_chunk_delay_ms(),
+            alerts: vec![],
+            alert_notifiers: vec![],
+            alert_interval_secs: default_alert_interval_secs(),
+            trade_notifiers: vec![],
+            notify_retries: default_notify_retries(),
+            notify_retry_delay_ms: default_notify_retry_delay_ms(),
+            snapshot_path: default_snapshot_path(),
+            sync_dir: default_sync_dir(),
         }
     }
 }
@@ -319,7 +630,7 @@ impl Configuration {
             Configuration::default()
         };
 
-        config.update_config(matches);
+        config.update_config(matches)?;
         trace!("config after update_config:\n{:#?}", config);
 
         Ok(config)
@@ -335,11 +646,18 @@ impl Configuration {
         format!("{}://{}{}{}", self.scheme, sd, self.domain, full_path)
     }
 
+    pub fn make_ws_url(&self, full_path: &str) -> String {
+        match &self.websocket_url {
+            Some(url) => format!("{url}{full_path}"),
+            None => format!("wss://stream.{}:9443{}", self.domain, full_path),
+        }
+    }
+
     /// This updates configuration only from global options.
     // For instance I looked at cloning them into each subcommand on an
     // as-needed-basis but then this function doesn't find any of them
     // and the configuration is not updated.
-    fn update_config(&mut self, matches: &ArgMatches) {
+    fn update_config(&mut self, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
         if let Some(value) = matches.value_of("api-key") {
             self.keys.api_key = Some(value.to_string());
         }
@@ -403,6 +721,29 @@ impl Configuration {
         if let Some(value) = matches.value_of("withdraw-addr") {
             self.withdraw_addr = Some(value.to_string());
         }
+
+        if matches.is_present("paper-trading") {
+            self.paper_trading = true;
+        }
+
+        if matches.is_present("no-paper-trading") {
+            self.paper_trading = false;
+        }
+
+        if let Some(value) = matches.value_of("max-slippage-percent") {
+            match Decimal::from_str(value) {
+                Ok(v) => self.max_slippage_percent = Some(v),
+                Err(e) => {
+                    return Err(format!("converting {value} to Decimal: e={e}").into());
+                }
+            }
+        }
+
+        if let Some(value) = matches.value_of("slippage-action") {
+            self.slippage_action = SlippageAction::from_string(value)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -427,7 +768,41 @@ mod test {
         assert!(config.confirmation_required);
         assert!(config.keep.is_none());
         assert!(config.buy.is_none());
+        assert!(config.sell_rules.is_none());
         assert!(config.withdraw_addr.is_none());
+        assert!(config.withdraw_addresses.is_empty());
+        assert!(config.withdraw_daily_limit_usd.is_none());
+        assert!(config.withdraw_asset_daily_limits_usd.is_empty());
+        assert!(config.withdraw_cooling_off_hours.is_none());
+        assert_eq!(
+            config.withdraw_kill_switch_path,
+            PathBuf::from("data/withdrawals_disabled")
+        );
+        assert!(!config.paper_trading);
+        assert_eq!(
+            config.paper_state_path,
+            PathBuf::from("data/paper_state.json")
+        );
+        assert_eq!(
+            config.paper_order_log_path,
+            PathBuf::from("data/paper_order_log.txt")
+        );
+        assert_eq!(config.paper_fee_percent, dec!(0.1));
+        assert_eq!(config.paper_fill_price, PaperFillPrice::AvgPrice);
+        assert!(config.paper_initial_balances.is_empty());
+        assert!(config.max_slippage_percent.is_none());
+        assert_eq!(config.slippage_action, SlippageAction::Block);
+        assert_eq!(config.slippage_book_limit, 100);
+        assert_eq!(config.slippage_max_chunks, 10);
+        assert_eq!(config.slippage_chunk_delay_ms, 1000);
+        assert!(config.alerts.is_empty());
+        assert!(config.alert_notifiers.is_empty());
+        assert_eq!(config.alert_interval_secs, 60);
+        assert!(config.trade_notifiers.is_empty());
+        assert_eq!(config.notify_retries, 3);
+        assert_eq!(config.notify_retry_delay_ms, 1000);
+        assert_eq!(config.snapshot_path, PathBuf::from("data/snapshots.jsonl"));
+        assert_eq!(config.sync_dir, PathBuf::from("data/sync"));
     }
 
     #[test]
@@ -608,4 +983,176 @@ mod test {
         assert!(config.keep.is_none());
         assert_eq!(config.withdraw_addr, Some("a withdraw addr".to_owned()));
     }
+
+    const TOML_DATA_WITHDRAW_ADDRESSES: &str = r#"
+        withdraw_addresses = [
+            { label = "ledger", asset = "ETH", network = "ETH", address = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed" },
+            { label = "xlm", asset = "XLM", address = "GABC", memo = "123" },
+        ]
+    "#;
+
+    #[test]
+    fn test_config_withdraw_addresses() {
+        let config: Configuration = toml::from_str(TOML_DATA_WITHDRAW_ADDRESSES).unwrap();
+        assert_eq!(
+            config.withdraw_addresses,
+            vec![
+                WithdrawAddressRec {
+                    label: "ledger".to_string(),
+                    asset: "ETH".to_string(),
+                    network: Some("ETH".to_string()),
+                    address: "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string(),
+                    memo: None,
+                },
+                WithdrawAddressRec {
+                    label: "xlm".to_string(),
+                    asset: "XLM".to_string(),
+                    network: None,
+                    address: "GABC".to_string(),
+                    memo: Some("123".to_string()),
+                },
+            ]
+        );
+    }
+
+    const TOML_DATA_WITHDRAW_LIMITS: &str = r#"
+        withdraw_daily_limit_usd = 10000
+        withdraw_asset_daily_limits_usd = { ETH = 5000, BTC = 2500.50 }
+        withdraw_cooling_off_hours = 48
+        withdraw_kill_switch_path = "/tmp/no-withdrawals"
+    "#;
+
+    #[test]
+    fn test_config_withdraw_limits() {
+        let config: Configuration = toml::from_str(TOML_DATA_WITHDRAW_LIMITS).unwrap();
+        assert_eq!(config.withdraw_daily_limit_usd, Some(dec!(10000)));
+        assert_eq!(config.withdraw_asset_daily_limits_usd.len(), 2);
+        assert_eq!(
+            config.withdraw_asset_daily_limits_usd.get("ETH"),
+            Some(&dec!(5000))
+        );
+        assert_eq!(
+            config.withdraw_asset_daily_limits_usd.get("BTC"),
+            Some(&dec!(2500.50))
+        );
+        assert_eq!(config.withdraw_cooling_off_hours, Some(48));
+        assert_eq!(
+            config.withdraw_kill_switch_path,
+            PathBuf::from("/tmp/no-withdrawals")
+        );
+    }
+
+    #[test]
+    fn test_config_make_ws_url() {
+        let config = Configuration::default();
+        assert_eq!(
+            config.make_ws_url("/stream"),
+            "wss://stream.binance.us:9443/stream"
+        );
+
+        let config: Configuration =
+            toml::from_str(r#"websocket_url = "ws://127.0.0.1:9001""#).unwrap();
+        assert_eq!(config.make_ws_url("/stream"), "ws://127.0.0.1:9001/stream");
+    }
+
+    const TOML_DATA_SLIPPAGE: &str = r#"
+        max_slippage_percent = 1.5
+        slippage_action = "split"
+        slippage_book_limit = 500
+        slippage_max_chunks = 4
+        slippage_chunk_delay_ms = 250
+    "#;
+
+    #[test]
+    fn test_config_slippage() {
+        let config: Configuration = toml::from_str(TOML_DATA_SLIPPAGE).unwrap();
+        assert_eq!(config.max_slippage_percent, Some(dec!(1.5)));
+        assert_eq!(config.slippage_action, SlippageAction::Split);
+        assert_eq!(config.slippage_book_limit, 500);
+        assert_eq!(config.slippage_max_chunks, 4);
+        assert_eq!(config.slippage_chunk_delay_ms, 250);
+
+        assert!(toml::from_str::<Configuration>(r#"slippage_action = "xyz""#).is_err());
+        assert_eq!(
+            SlippageAction::from_string("block").unwrap(),
+            SlippageAction::Block
+        );
+        assert!(SlippageAction::from_string("xyz").is_err());
+    }
+
+    const TOML_DATA_SELL_RULES: &str = r#"
+        sell_rules = [
+            { name = "ETH", take_profit_percent = 50, sell_percent = 25 },
+            { name = "BTC", trailing_stop_percent = 10, quote_asset = "USDT" },
+            { name = "BNB", max_value_usd = 1000 },
+        ]
+    "#;
+
+    #[test]
+    fn test_config_sell_rules() {
+        let config: Configuration = toml::from_str(TOML_DATA_SELL_RULES).unwrap();
+        let srs = &config.sell_rules.unwrap();
+        assert_eq!(srs.len(), 3);
+        assert_eq!(
+            srs.get("ETH").unwrap(),
+            &SellRuleRec {
+                name: "ETH".to_string(),
+                sell_percent: dec!(25),
+                take_profit_percent: Some(dec!(50)),
+                trailing_stop_percent: None,
+                max_value_usd: None,
+                quote_asset: "".to_string(),
+            }
+        );
+        assert_eq!(
+            srs.get("BTC").unwrap(),
+            &SellRuleRec {
+                name: "BTC".to_string(),
+                sell_percent: dec!(100),
+                take_profit_percent: None,
+                trailing_stop_percent: Some(dec!(10)),
+                max_value_usd: None,
+                quote_asset: "USDT".to_string(),
+            }
+        );
+        assert_eq!(srs.get("BNB").unwrap().max_value_usd, Some(dec!(1000)));
+    }
+
+    const TOML_DATA_PAPER: &str = r#"
+        paper_trading = true
+        paper_state_path = "data/paper.json"
+        paper_order_log_path = "data/paper_log.txt"
+        paper_fee_percent = 0.075
+        paper_fill_price = "kline"
+        paper_initial_balances = [
+            { asset = "USD", free = 10000 },
+            { asset = "BNB", free = 1.5 },
+        ]
+    "#;
+
+    #[test]
+    fn test_config_paper() {
+        let config: Configuration = toml::from_str(TOML_DATA_PAPER).unwrap();
+        assert!(config.paper_trading);
+        assert_eq!(config.paper_state_path, PathBuf::from("data/paper.json"));
+        assert_eq!(
+            config.paper_order_log_path,
+            PathBuf::from("data/paper_log.txt")
+        );
+        assert_eq!(config.paper_fee_percent, dec!(0.075));
+        assert_eq!(config.paper_fill_price, PaperFillPrice::Kline);
+        assert_eq!(
+            config.paper_initial_balances,
+            vec![
+                PaperBalanceRec {
+                    asset: "USD".to_string(),
+                    free: dec!(10000)
+                },
+                PaperBalanceRec {
+                    asset: "BNB".to_string(),
+                    free: dec!(1.5)
+                },
+            ]
+        );
+    }
 }
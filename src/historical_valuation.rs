@@ -0,0 +1,219 @@
+use clap::ArgMatches;
+use log::trace;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use std::collections::{BTreeMap, BTreeSet};
+use tokentaxrec::TokenTaxRec;
+
+use crate::{
+    binance_exchange_info::{get_exchange_info, ExchangeInfo},
+    binance_klines::get_kline_of_primary_asset_for_value_asset,
+    common::{create_buf_reader, time_str_to_time_ms, verify_input_files_exist, VALUE_ASSETS},
+    configuration::Configuration,
+    process_binance_api::token_tax_recs_from_api,
+};
+
+use dec_utils::{dec_to_separated_string, dec_to_usd_string};
+use time_ms_conversions::time_ms_to_utc;
+
+/// Binance opened in July 2017, 2017-07-01T00:00:00Z, the account
+/// history is fetched from then so it isn't limited to the default window
+const HISTORY_START_TIME_MS: i64 = 1_498_867_200_000;
+
+/// The quantity of each asset after replaying the records up to and
+/// including time_ms, assets with a zero quantity are removed.
+pub fn holdings_at(recs: &[TokenTaxRec], time_ms: i64) -> BTreeMap<String, Decimal> {
+    let mut holdings: BTreeMap<String, Decimal> = BTreeMap::new();
+    for ttr in recs.iter().filter(|ttr| ttr.time <= time_ms) {
+        if let Some(buy_amount) = ttr.buy_amount {
+            *holdings.entry(ttr.buy_currency.clone()).or_insert(dec!(0)) += buy_amount;
+        }
+        if let Some(sell_amount) = ttr.sell_amount {
+            *holdings.entry(ttr.sell_currency.clone()).or_insert(dec!(0)) -= sell_amount;
+        }
+        if let Some(fee_amount) = ttr.fee_amount {
+            *holdings.entry(ttr.fee_currency.clone()).or_insert(dec!(0)) -= fee_amount;
+        }
+    }
+    holdings.retain(|_, q| *q != dec!(0));
+
+    holdings
+}
+
+pub fn read_token_tax_files(
+    in_tt_file_paths: &[&str],
+) -> Result<Vec<TokenTaxRec>, Box<dyn std::error::Error>> {
+    verify_input_files_exist(in_tt_file_paths)?;
+
+    let mut recs: Vec<TokenTaxRec> = Vec::new();
+    for f in in_tt_file_paths {
+        let reader = create_buf_reader(f)?;
+        let mut rdr = csv::Reader::from_reader(reader);
+        for result in rdr.deserialize() {
+            let ttr: TokenTaxRec = result?;
+            recs.push(ttr);
+        }
+    }
+
+    Ok(recs)
+}
+
+/// Symbols whose base and quote assets are both in assets
+pub fn symbols_of_assets(ei: &ExchangeInfo, assets: &BTreeSet<String>) -> Vec<String> {
+    let mut symbols: Vec<String> = ei
+        .symbols_map
+        .values()
+        .filter(|s| assets.contains(&s.base_asset) && assets.contains(&s.quote_asset))
+        .map(|s| s.symbol.clone())
+        .collect();
+    symbols.sort();
+
+    symbols
+}
+
+/// Display the holdings and their value at TIME reconstructed from the
+/// TokenTax files or if absent the account history.
+pub async fn historical_account_info_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let time = sc_matches.value_of("TIME").expect("TIME is missing");
+    let time_ms = time_str_to_time_ms(time, true)?;
+    trace!("historical_account_info_cmd: time: {time} time_ms: {time_ms}");
+
+    let recs = if let Some(files) = sc_matches.values_of("TT_FILES") {
+        let in_tt_file_paths: Vec<&str> = files.collect();
+        read_token_tax_files(&in_tt_file_paths)?
+    } else {
+        let symbols: Option<Vec<String>> = sc_matches
+            .values_of("SYMBOLS")
+            .map(|v| v.map(|s| s.to_string()).collect());
+        let ei = get_exchange_info(config).await?;
+        println!("Distributions are not in the account history, use --tt-files to include them");
+        token_tax_recs_from_api(
+            config,
+            &ei,
+            symbols,
+            false,
+            Some(time_ms_to_utc(HISTORY_START_TIME_MS)),
+            Some(time_ms_to_utc(time_ms)),
+        )
+        .await?
+    };
+
+    let holdings = holdings_at(&recs, time_ms);
+
+    let col_1 = 6;
+    let col_2 = 16;
+    let col_3 = 12;
+    let col_4 = 20;
+    println!("Holdings at {}", time_ms_to_utc(time_ms));
+    println!(
+        "{:<col_1$} {:>col_2$} {:>col_3$} {:>col_4$}",
+        "Asset", "USD value", "USD/coin", "Total Coins"
+    );
+    let mut total_value = dec!(0);
+    let mut negative: Vec<&str> = Vec::new();
+    for (asset, quantity) in &holdings {
+        let price_in_usd =
+            match get_kline_of_primary_asset_for_value_asset(config, time_ms, asset, &VALUE_ASSETS)
+                .await
+            {
+                Some((_, kr)) => kr.close,
+                None => dec!(0),
+            };
+        let value_in_usd = price_in_usd * quantity;
+        total_value += value_in_usd;
+        if *quantity < dec!(0) {
+            negative.push(asset);
+        }
+        println!(
+            "{:<col_1$} {:>col_2$} {:>col_3$} {:>col_4$}",
+            asset,
+            dec_to_usd_string(value_in_usd),
+            dec_to_usd_string(price_in_usd),
+            dec_to_separated_string(*quantity, 8),
+        );
+    }
+    println!("total: {}", dec_to_usd_string(total_value));
+    if !negative.is_empty() {
+        println!(
+            "WARNING the history is incomplete, negative quantities of {}",
+            negative.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        binance_history::{DepositRec, WithdrawRec},
+        process_binance_api::{ttr_from_deposit, ttr_from_trade, ttr_from_withdraw},
+        test_utils::{eth_usd, trade_rec},
+    };
+    use tokentaxrec::TokenTaxRecType;
+
+    #[test]
+    fn test_holdings_at() {
+        let symbol = eth_usd();
+        let deposit: DepositRec = serde_json::from_str(
+            r#"{"insertTime":1000,"amount":"5000","address":"","asset":"USD","status":1}"#,
+        )
+        .unwrap();
+        let withdraw: WithdrawRec = serde_json::from_str(
+            r#"{"amount":"0.5","transactionFee":"0.01","address":"0x1","id":"a","asset":"ETH","applyTime":4000,"status":6,"network":"ETH"}"#,
+        )
+        .unwrap();
+        let mut recs = vec![
+            ttr_from_deposit("binance.us", &deposit),
+            ttr_from_trade(
+                "binance.us",
+                &symbol,
+                &trade_rec(true, dec!(2), dec!(3000), 2000),
+            ),
+            ttr_from_trade(
+                "binance.us",
+                &symbol,
+                &trade_rec(false, dec!(1), dec!(1600), 3000),
+            ),
+            ttr_from_withdraw("binance.us", &withdraw),
+        ];
+        // Distribution from a TokenTax file
+        recs.push(TokenTaxRec {
+            type_txs: TokenTaxRecType::Income,
+            buy_amount: Some(dec!(0.02)),
+            buy_currency: "BNB".to_string(),
+            sell_amount: None,
+            sell_currency: "".to_string(),
+            fee_amount: None,
+            fee_currency: "".to_string(),
+            exchange: "binance.us".to_string(),
+            group: None,
+            comment: "".to_string(),
+            time: 3500,
+        });
+
+        assert!(holdings_at(&recs, 999).is_empty());
+
+        let h = holdings_at(&recs, 1000);
+        assert_eq!(h.len(), 1);
+        assert_eq!(h["USD"], dec!(5000));
+
+        let h = holdings_at(&recs, 2000);
+        assert_eq!(h["USD"], dec!(2000));
+        assert_eq!(h["ETH"], dec!(2));
+        assert_eq!(h["BNB"], dec!(-0.01));
+
+        let h = holdings_at(&recs, 3500);
+        assert_eq!(h["USD"], dec!(3600));
+        assert_eq!(h["ETH"], dec!(1));
+        // The commissions are paid by the distribution
+        assert!(!h.contains_key("BNB"));
+
+        let h = holdings_at(&recs, 4000);
+        assert_eq!(h["ETH"], dec!(0.49));
+    }
+}
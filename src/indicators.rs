@@ -0,0 +1,450 @@
+//! Technical indicators computed from kline records.
+//!
+//! Each indicator returns a Vec with one entry per kline, entries
+//! are None until there are enough klines to compute a value.
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+
+use crate::binance_klines::KlineRec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indicator {
+    Sma(usize),
+    Ema(usize),
+    Rsi(usize),
+    Macd {
+        fast: usize,
+        slow: usize,
+        signal: usize,
+    },
+    Bollinger {
+        period: usize,
+        std_devs: u32,
+    },
+    Atr(usize),
+    Vwap,
+}
+
+impl Indicator {
+    /// Parse an indicator with optional parameters separated by ':',
+    /// for example: sma:20, ema:12, rsi, macd:12:26:9, bb:20:2, atr:14, vwap
+    pub fn from_string(s: &str) -> Result<Indicator, Box<dyn std::error::Error>> {
+        let mut fields = s.split(':');
+        let name = fields.next().unwrap_or("");
+        let params = fields
+            .map(|f| match f.parse::<usize>() {
+                Ok(v) if v > 0 => Ok(v),
+                _ => Err(format!(
+                    "Invalid parameter, {f}, in {s} expecting a number > 0"
+                )),
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+        let param = |idx: usize, default: usize| *params.get(idx).unwrap_or(&default);
+
+        let indicator = match name {
+            "sma" => Indicator::Sma(param(0, 20)),
+            "ema" => Indicator::Ema(param(0, 20)),
+            "rsi" => Indicator::Rsi(param(0, 14)),
+            "macd" => Indicator::Macd {
+                fast: param(0, 12),
+                slow: param(1, 26),
+                signal: param(2, 9),
+            },
+            "bb" => Indicator::Bollinger {
+                period: param(0, 20),
+                std_devs: param(1, 2) as u32,
+            },
+            "atr" => Indicator::Atr(param(0, 14)),
+            "vwap" => Indicator::Vwap,
+            _ => {
+                return Err(format!(
+                    "Unknown indicator, {s}, expecting one of: sma ema rsi macd bb atr vwap"
+                )
+                .into())
+            }
+        };
+
+        Ok(indicator)
+    }
+
+    /// The names of the columns returned by compute
+    pub fn column_names(&self) -> Vec<String> {
+        match self {
+            Indicator::Sma(p) => vec![format!("sma{p}")],
+            Indicator::Ema(p) => vec![format!("ema{p}")],
+            Indicator::Rsi(p) => vec![format!("rsi{p}")],
+            Indicator::Macd { .. } => vec![
+                "macd".to_string(),
+                "macd_signal".to_string(),
+                "macd_hist".to_string(),
+            ],
+            Indicator::Bollinger { period, .. } => vec![
+                format!("bb{period}_lower"),
+                format!("bb{period}_middle"),
+                format!("bb{period}_upper"),
+            ],
+            Indicator::Atr(p) => vec![format!("atr{p}")],
+            Indicator::Vwap => vec!["vwap".to_string()],
+        }
+    }
+
+    /// Compute the indicator, one Vec per column
+    pub fn compute(&self, klines: &[KlineRec]) -> Vec<Vec<Option<Decimal>>> {
+        match *self {
+            Indicator::Sma(p) => vec![sma(&closes(klines), p)],
+            Indicator::Ema(p) => vec![ema(&closes(klines), p)],
+            Indicator::Rsi(p) => vec![rsi(&closes(klines), p)],
+            Indicator::Macd { fast, slow, signal } => {
+                let m = macd(&closes(klines), fast, slow, signal);
+                vec![
+                    m.iter().map(|r| r.map(|r| r.macd)).collect(),
+                    m.iter().map(|r| r.and_then(|r| r.signal)).collect(),
+                    m.iter()
+                        .map(|r| r.and_then(|r| r.signal.map(|s| r.macd - s)))
+                        .collect(),
+                ]
+            }
+            Indicator::Bollinger { period, std_devs } => {
+                let bb = bollinger_bands(&closes(klines), period, Decimal::from(std_devs));
+                vec![
+                    bb.iter().map(|b| b.map(|b| b.lower)).collect(),
+                    bb.iter().map(|b| b.map(|b| b.middle)).collect(),
+                    bb.iter().map(|b| b.map(|b| b.upper)).collect(),
+                ]
+            }
+            Indicator::Atr(p) => vec![atr(klines, p)],
+            Indicator::Vwap => vec![vwap(klines)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacdRec {
+    pub macd: Decimal,
+
+    /// None until there are enough macd values for the signal ema
+    pub signal: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BollingerRec {
+    pub lower: Decimal,
+    pub middle: Decimal,
+    pub upper: Decimal,
+}
+
+pub fn closes(klines: &[KlineRec]) -> Vec<Decimal> {
+    klines.iter().map(|kr| kr.close).collect()
+}
+
+/// Simple moving average
+pub fn sma(values: &[Decimal], period: usize) -> Vec<Option<Decimal>> {
+    let mut result = vec![None; values.len()];
+    if period == 0 {
+        return result;
+    }
+
+    let mut sum = dec!(0);
+    for (idx, v) in values.iter().enumerate() {
+        sum += v;
+        if idx >= period {
+            sum -= values[idx - period];
+        }
+        if idx + 1 >= period {
+            result[idx] = Some(sum / Decimal::from(period));
+        }
+    }
+
+    result
+}
+
+/// Exponential moving average seeded with the sma of the first period values
+pub fn ema(values: &[Decimal], period: usize) -> Vec<Option<Decimal>> {
+    let mut result = vec![None; values.len()];
+    if period == 0 || values.len() < period {
+        return result;
+    }
+
+    let k = dec!(2) / Decimal::from(period + 1);
+    let mut prev = values[..period].iter().sum::<Decimal>() / Decimal::from(period);
+    result[period - 1] = Some(prev);
+    for (idx, v) in values.iter().enumerate().skip(period) {
+        prev = (v - prev) * k + prev;
+        result[idx] = Some(prev);
+    }
+
+    result
+}
+
+/// Wilder's smoothing, the first value is the average of the first period values
+fn wilder_smoothing(values: &[Decimal], period: usize) -> Vec<Option<Decimal>> {
+    let mut result = vec![None; values.len()];
+    if period == 0 || values.len() < period {
+        return result;
+    }
+
+    let p = Decimal::from(period);
+    let mut prev = values[..period].iter().sum::<Decimal>() / p;
+    result[period - 1] = Some(prev);
+    for (idx, v) in values.iter().enumerate().skip(period) {
+        prev = (prev * (p - dec!(1)) + v) / p;
+        result[idx] = Some(prev);
+    }
+
+    result
+}
+
+/// Relative strength index using Wilder's smoothing
+pub fn rsi(values: &[Decimal], period: usize) -> Vec<Option<Decimal>> {
+    let mut result = vec![None; values.len()];
+    if values.len() < 2 {
+        return result;
+    }
+
+    let mut gains = Vec::with_capacity(values.len() - 1);
+    let mut losses = Vec::with_capacity(values.len() - 1);
+    for w in values.windows(2) {
+        let change = w[1] - w[0];
+        gains.push(change.max(dec!(0)));
+        losses.push((-change).max(dec!(0)));
+    }
+
+    let avg_gains = wilder_smoothing(&gains, period);
+    let avg_losses = wilder_smoothing(&losses, period);
+    for (idx, (g, l)) in avg_gains.iter().zip(avg_losses.iter()).enumerate() {
+        if let (Some(g), Some(l)) = (g, l) {
+            // The changes start at the second value
+            result[idx + 1] = Some(if l.is_zero() {
+                dec!(100)
+            } else {
+                dec!(100) - (dec!(100) / (dec!(1) + (g / l)))
+            });
+        }
+    }
+
+    result
+}
+
+/// Moving average convergence divergence
+pub fn macd(values: &[Decimal], fast: usize, slow: usize, signal: usize) -> Vec<Option<MacdRec>> {
+    let fast_ema = ema(values, fast);
+    let slow_ema = ema(values, slow);
+    let macd_values: Vec<Option<Decimal>> = fast_ema
+        .iter()
+        .zip(slow_ema.iter())
+        .map(|(f, s)| match (f, s) {
+            (Some(f), Some(s)) => Some(f - s),
+            _ => None,
+        })
+        .collect();
+
+    // The signal is the ema of the defined macd values
+    let first = macd_values.iter().position(|m| m.is_some());
+    let defined: Vec<Decimal> = macd_values.iter().flatten().copied().collect();
+    let signal_ema = ema(&defined, signal);
+
+    macd_values
+        .iter()
+        .enumerate()
+        .map(|(idx, m)| {
+            m.map(|macd| MacdRec {
+                macd,
+                signal: first.and_then(|f| signal_ema[idx - f]),
+            })
+        })
+        .collect()
+}
+
+/// Bollinger bands, the sma plus and minus std_devs population standard deviations
+pub fn bollinger_bands(
+    values: &[Decimal],
+    period: usize,
+    std_devs: Decimal,
+) -> Vec<Option<BollingerRec>> {
+    let middles = sma(values, period);
+    middles
+        .iter()
+        .enumerate()
+        .map(|(idx, middle)| {
+            middle.map(|middle| {
+                let window = &values[idx + 1 - period..=idx];
+                let variance = window
+                    .iter()
+                    .map(|v| (v - middle) * (v - middle))
+                    .sum::<Decimal>()
+                    / Decimal::from(period);
+                let std_dev = variance.sqrt().unwrap_or_default();
+                BollingerRec {
+                    lower: middle - std_devs * std_dev,
+                    middle,
+                    upper: middle + std_devs * std_dev,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Average true range using Wilder's smoothing
+pub fn atr(klines: &[KlineRec], period: usize) -> Vec<Option<Decimal>> {
+    let true_ranges: Vec<Decimal> = klines
+        .iter()
+        .enumerate()
+        .map(|(idx, kr)| {
+            let range = kr.high - kr.low;
+            if idx == 0 {
+                range
+            } else {
+                let prev_close = klines[idx - 1].close;
+                range
+                    .max((kr.high - prev_close).abs())
+                    .max((kr.low - prev_close).abs())
+            }
+        })
+        .collect();
+
+    wilder_smoothing(&true_ranges, period)
+}
+
+/// Cumulative volume weighted average of the typical price, (high + low + close) / 3
+pub fn vwap(klines: &[KlineRec]) -> Vec<Option<Decimal>> {
+    let mut pv_sum = dec!(0);
+    let mut volume_sum = dec!(0);
+    klines
+        .iter()
+        .map(|kr| {
+            let typical_price = (kr.high + kr.low + kr.close) / dec!(3);
+            pv_sum += typical_price * kr.volume;
+            volume_sum += kr.volume;
+            if volume_sum.is_zero() {
+                None
+            } else {
+                Some(pv_sum / volume_sum)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kline(high: Decimal, low: Decimal, close: Decimal, volume: Decimal) -> KlineRec {
+        KlineRec {
+            open_time: 0,
+            open: close,
+            high,
+            low,
+            close,
+            volume,
+            close_time: 0,
+            quote_asset_volume: dec!(0),
+            number_of_trades: 0,
+            taker_buy_base_asset_volume: dec!(0),
+            taker_buy_quote_asset_volume: dec!(0),
+            ignore: dec!(0),
+        }
+    }
+
+    fn values(v: &[i64]) -> Vec<Decimal> {
+        v.iter().map(|v| Decimal::from(*v)).collect()
+    }
+
+    #[test]
+    fn test_indicator_from_string() {
+        assert_eq!(Indicator::from_string("sma:5").unwrap(), Indicator::Sma(5));
+        assert_eq!(Indicator::from_string("rsi").unwrap(), Indicator::Rsi(14));
+        assert_eq!(
+            Indicator::from_string("macd").unwrap(),
+            Indicator::Macd {
+                fast: 12,
+                slow: 26,
+                signal: 9
+            }
+        );
+        assert_eq!(
+            Indicator::from_string("bb:10:3").unwrap(),
+            Indicator::Bollinger {
+                period: 10,
+                std_devs: 3
+            }
+        );
+        assert!(Indicator::from_string("sma:0").is_err());
+        assert!(Indicator::from_string("xyz").is_err());
+    }
+
+    #[test]
+    fn test_sma() {
+        let r = sma(&values(&[1, 2, 3, 4, 5]), 3);
+        assert_eq!(
+            r,
+            vec![None, None, Some(dec!(2)), Some(dec!(3)), Some(dec!(4))]
+        );
+        assert_eq!(sma(&values(&[1, 2]), 3), vec![None, None]);
+    }
+
+    #[test]
+    fn test_ema() {
+        // k = 2 / (3 + 1) = 0.5
+        let r = ema(&values(&[1, 2, 3, 4, 5]), 3);
+        assert_eq!(
+            r,
+            vec![None, None, Some(dec!(2)), Some(dec!(3)), Some(dec!(4))]
+        );
+        let r = ema(&values(&[2, 4, 6, 10]), 3);
+        assert_eq!(r[3], Some(dec!(7)));
+    }
+
+    #[test]
+    fn test_rsi() {
+        // Only gains is 100
+        let r = rsi(&values(&[1, 2, 3, 4]), 2);
+        assert_eq!(r, vec![None, None, Some(dec!(100)), Some(dec!(100))]);
+
+        // Equal average gains and losses is 50
+        let r = rsi(&values(&[10, 12, 10]), 2);
+        assert_eq!(r[2], Some(dec!(50)));
+    }
+
+    #[test]
+    fn test_macd() {
+        let v = values(&[1, 2, 3, 4, 5, 6]);
+        let r = macd(&v, 1, 3, 2);
+        assert_eq!(r[0], None);
+        assert_eq!(r[1], None);
+
+        // A linear series has an ema that lags by a constant so the macd is constant
+        let m = r[2].unwrap();
+        assert_eq!(m.macd, dec!(1));
+        assert_eq!(m.signal, None);
+        let m = r[3].unwrap();
+        assert_eq!(m.macd, dec!(1));
+        assert_eq!(m.signal, Some(dec!(1)));
+        assert_eq!(r[5].unwrap().signal, Some(dec!(1)));
+    }
+
+    #[test]
+    fn test_bollinger_bands() {
+        let r = bollinger_bands(&values(&[2, 4, 4, 4, 5, 5, 7, 9]), 8, dec!(2));
+        let bb = r[7].unwrap();
+        assert_eq!(bb.middle, dec!(5));
+        assert_eq!(bb.lower, dec!(1));
+        assert_eq!(bb.upper, dec!(9));
+        assert!(r[6].is_none());
+    }
+
+    #[test]
+    fn test_atr_vwap() {
+        let klines = vec![
+            kline(dec!(12), dec!(8), dec!(10), dec!(1)),
+            kline(dec!(16), dec!(11), dec!(15), dec!(3)),
+        ];
+
+        // true ranges are 4 and max(5, 6, 1) = 6
+        let r = atr(&klines, 2);
+        assert_eq!(r, vec![None, Some(dec!(5))]);
+
+        // typical prices are 10 and 14
+        let r = vwap(&klines);
+        assert_eq!(r, vec![Some(dec!(10)), Some(dec!(13))]);
+    }
+}
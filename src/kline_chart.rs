@@ -0,0 +1,244 @@
+//! Render kline records as a terminal candlestick chart with a volume histogram.
+use rust_decimal::prelude::*;
+use time_ms_conversions::time_ms_to_utc;
+
+use crate::binance_klines::KlineRec;
+
+/// Width of the price axis labels on the left of the chart
+pub const AXIS_WIDTH: usize = 14;
+
+const UP_BODY: char = '█';
+const DOWN_BODY: char = '░';
+const WICK: char = '│';
+const MA_POINT: char = '•';
+const VOLUME_BAR: char = '▆';
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChartSize {
+    /// Total width including the axis labels
+    pub width: usize,
+
+    /// Rows of the candlestick part of the chart
+    pub price_height: usize,
+
+    /// Rows of the volume histogram
+    pub volume_height: usize,
+}
+
+impl Default for ChartSize {
+    fn default() -> Self {
+        ChartSize {
+            width: terminal_width(),
+            price_height: 20,
+            volume_height: 5,
+        }
+    }
+}
+
+impl ChartSize {
+    /// The number of klines that fit, one column per kline
+    pub fn max_klines(&self) -> usize {
+        self.width.saturating_sub(AXIS_WIDTH + 1).max(1)
+    }
+}
+
+/// The terminal width from the COLUMNS environment variable, 80 if not set
+pub fn terminal_width() -> usize {
+    match std::env::var("COLUMNS") {
+        Ok(c) => c.parse::<usize>().unwrap_or(80),
+        Err(_) => 80,
+    }
+}
+
+/// The row, 0 is the bottom, of value in a chart of height rows from low to high
+fn scale_row(value: Decimal, low: Decimal, high: Decimal, height: usize) -> usize {
+    if high <= low || height < 2 {
+        return 0;
+    }
+    let fraction = ((value - low) / (high - low)).to_f64().unwrap_or(0.0);
+    ((fraction * (height - 1) as f64).round() as usize).min(height - 1)
+}
+
+/// Render the last klines that fit in size.width as lines of text, ma is an
+/// optional moving average with one entry per kline to overlay on the chart.
+pub fn render_kline_chart(
+    klines: &[KlineRec],
+    ma: Option<&[Option<Decimal>]>,
+    size: &ChartSize,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    if klines.is_empty() {
+        return lines;
+    }
+
+    let first = klines.len().saturating_sub(size.max_klines());
+    let klines = &klines[first..];
+    let ma: Vec<Option<Decimal>> = match ma {
+        Some(ma) => ma[first..].to_vec(),
+        None => vec![None; klines.len()],
+    };
+
+    let ma_values = ma.iter().flatten();
+    let low = klines
+        .iter()
+        .map(|kr| kr.low)
+        .chain(ma_values.clone().copied())
+        .min();
+    let high = klines
+        .iter()
+        .map(|kr| kr.high)
+        .chain(ma_values.copied())
+        .max();
+    let (low, high) = (low.unwrap_or_default(), high.unwrap_or_default());
+    let max_volume = klines.iter().map(|kr| kr.volume).max().unwrap_or_default();
+
+    let height = size.price_height;
+    for row in (0..height).rev() {
+        let label = if row == height - 1 {
+            format!("{:>w$.4}", high, w = AXIS_WIDTH)
+        } else if row == 0 {
+            format!("{:>w$.4}", low, w = AXIS_WIDTH)
+        } else if row == (height - 1) / 2 {
+            format!("{:>w$.4}", (high + low) / Decimal::TWO, w = AXIS_WIDTH)
+        } else {
+            " ".repeat(AXIS_WIDTH)
+        };
+        let mut line = label + " ";
+        for (kr, ma) in klines.iter().zip(ma.iter()) {
+            let open_row = scale_row(kr.open, low, high, height);
+            let close_row = scale_row(kr.close, low, high, height);
+            let body = open_row.min(close_row)..=open_row.max(close_row);
+            let wick = scale_row(kr.low, low, high, height)..=scale_row(kr.high, low, high, height);
+            let ch = if body.contains(&row) {
+                if kr.close >= kr.open {
+                    UP_BODY
+                } else {
+                    DOWN_BODY
+                }
+            } else if ma.map(|v| scale_row(v, low, high, height)) == Some(row) {
+                MA_POINT
+            } else if wick.contains(&row) {
+                WICK
+            } else {
+                ' '
+            };
+            line.push(ch);
+        }
+        lines.push(line.trim_end().to_string());
+    }
+
+    for row in (0..size.volume_height).rev() {
+        let label = if row == size.volume_height - 1 {
+            format!("{:>w$.2}", max_volume, w = AXIS_WIDTH)
+        } else {
+            " ".repeat(AXIS_WIDTH)
+        };
+        let mut line = label + " ";
+        for kr in klines {
+            let bar = if max_volume > Decimal::ZERO {
+                let fraction = (kr.volume / max_volume).to_f64().unwrap_or(0.0);
+                (fraction * size.volume_height as f64).ceil() as usize
+            } else {
+                0
+            };
+            line.push(if bar > row { VOLUME_BAR } else { ' ' });
+        }
+        lines.push(line.trim_end().to_string());
+    }
+
+    let first_time = time_ms_to_utc(klines[0].open_time).format("%Y-%m-%d %H:%M");
+    let last_time = time_ms_to_utc(klines[klines.len() - 1].open_time).format("%Y-%m-%d %H:%M");
+    lines.push(format!(
+        "{:w$} {first_time} .. {last_time}",
+        "",
+        w = AXIS_WIDTH
+    ));
+
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn kline(
+        open: Decimal,
+        high: Decimal,
+        low: Decimal,
+        close: Decimal,
+        volume: Decimal,
+    ) -> KlineRec {
+        KlineRec {
+            open_time: 0,
+            open,
+            high,
+            low,
+            close,
+            volume,
+            close_time: 0,
+            quote_asset_volume: dec!(0),
+            number_of_trades: 0,
+            taker_buy_base_asset_volume: dec!(0),
+            taker_buy_quote_asset_volume: dec!(0),
+            ignore: dec!(0),
+        }
+    }
+
+    #[test]
+    fn test_scale_row() {
+        assert_eq!(scale_row(dec!(0), dec!(0), dec!(10), 11), 0);
+        assert_eq!(scale_row(dec!(10), dec!(0), dec!(10), 11), 10);
+        assert_eq!(scale_row(dec!(5), dec!(0), dec!(10), 11), 5);
+        assert_eq!(scale_row(dec!(5), dec!(5), dec!(5), 11), 0);
+    }
+
+    #[test]
+    fn test_render_kline_chart() {
+        let klines = vec![
+            kline(dec!(2), dec!(4), dec!(0), dec!(3), dec!(10)),
+            kline(dec!(3), dec!(4), dec!(1), dec!(2), dec!(5)),
+        ];
+        let size = ChartSize {
+            width: AXIS_WIDTH + 1 + 10,
+            price_height: 5,
+            volume_height: 2,
+        };
+
+        let lines = render_kline_chart(&klines, None, &size);
+        assert_eq!(lines.len(), 5 + 2 + 1);
+
+        // Columns after the axis: first kline up, second down
+        let cols: Vec<Vec<char>> = lines
+            .iter()
+            .map(|l| l.chars().skip(AXIS_WIDTH + 1).collect())
+            .collect();
+        assert_eq!(cols[0], vec![WICK, WICK]); // 4
+        assert_eq!(cols[1], vec![UP_BODY, DOWN_BODY]); // 3
+        assert_eq!(cols[2], vec![UP_BODY, DOWN_BODY]); // 2
+        assert_eq!(cols[3], vec![WICK, WICK]); // 1
+        assert_eq!(cols[4], vec![WICK]); // 0
+        assert_eq!(cols[5], vec![VOLUME_BAR]);
+        assert_eq!(cols[6], vec![VOLUME_BAR, VOLUME_BAR]);
+        assert!(lines[0].starts_with("        4.0000"));
+
+        // The moving average is drawn where there is no body
+        let ma = vec![None, Some(dec!(4))];
+        let lines = render_kline_chart(&klines, Some(&ma), &size);
+        assert!(lines[0].ends_with(&format!("{WICK}{MA_POINT}")));
+    }
+
+    #[test]
+    fn test_render_kline_chart_width() {
+        let klines = vec![kline(dec!(1), dec!(1), dec!(1), dec!(1), dec!(1)); 100];
+        let size = ChartSize {
+            width: AXIS_WIDTH + 1 + 10,
+            price_height: 3,
+            volume_height: 1,
+        };
+        let lines = render_kline_chart(&klines, None, &size);
+
+        // Only the last 10 klines fit
+        assert_eq!(lines[3].chars().skip(AXIS_WIDTH + 1).count(), 10);
+    }
+}
@@ -1,62 +1,96 @@
+mod account_snapshot;
+mod alerts;
 mod arg_matches;
+mod backtest;
 mod binance_account_info;
 mod binance_auto_buy;
 mod binance_auto_sell;
 mod binance_avg_price;
+mod binance_capital_config;
+mod binance_dust;
 mod binance_exchange_info;
 mod binance_get_klines_cmd;
 mod binance_history;
 mod binance_klines;
+mod binance_market_data;
 mod binance_market_order_cmd;
 mod binance_my_trades;
+mod binance_order_book;
 mod binance_order_response;
 mod binance_orders;
+mod binance_paper_trading;
+mod binance_sell_rules;
 mod binance_signature;
+mod binance_ticker;
 mod binance_trade;
+mod binance_user_data_stream;
 mod binance_verify_order;
+mod binance_websocket;
 mod binance_withdraw_cmd;
 mod common;
 mod configuration;
 mod date_time_utc;
 mod de_string_or_number;
+mod historical_valuation;
+mod indicators;
+mod kline_chart;
+mod notifications;
+mod order_log_query;
+mod process_binance_api;
 mod process_binance_com;
 mod process_binance_us;
 mod process_taxbit;
 mod process_token_tax;
+mod reconcile;
 mod serde_header_map;
+#[cfg(test)]
+mod test_utils;
 mod token_tax_comment_vers;
+mod trade_summary;
+mod trade_sync;
+mod withdraw_address_book;
+mod withdraw_limits;
 
 use clap::ArgMatches;
 use log::trace;
 use std::{path::Path, str::FromStr};
 
-use arg_matches::arg_matches;
+use arg_matches::{arg_matches, start_end_date_times};
 use binance_auto_buy::auto_buy_cmd;
 use binance_auto_sell::auto_sell_cmd;
 use configuration::Configuration;
 
-use dec_utils::{dec_to_separated_string, dec_to_usd_string};
 use rust_decimal::Decimal;
-use rust_decimal_macros::dec;
-use time_ms_conversions::{time_ms_to_utc, utc_now_to_time_ms};
+use time_ms_conversions::utc_now_to_time_ms;
 
 use crate::{
+    account_snapshot::{performance_cmd, snapshot_cmd},
+    alerts::alerts_cmd,
+    backtest::backtest_cmd,
     binance_account_info::get_account_info,
     binance_avg_price::{get_avg_price, AvgPrice},
+    binance_dust::dust_cmd,
     binance_exchange_info::get_exchange_info,
-    binance_get_klines_cmd::{get_klines_cmd, GetKlinesCmdRec},
+    binance_get_klines_cmd::{get_klines_cmd, indicators_cmd, GetKlinesCmdRec},
     binance_history::{
         get_deposit_history, get_fiat_currency_deposit_history, get_fiat_currency_withdraw_history,
         get_withdraw_history, AssetLogRec, DepositRec, WithdrawRec,
     },
     binance_klines::{get_kline, KlineRec},
+    binance_market_data::{depth_cmd, trades_cmd},
     binance_market_order_cmd::{buy_market_order_cmd, sell_market_order_cmd},
-    binance_my_trades::{get_my_trades, Trades},
-    binance_order_response::{display_order_log, process_order_log},
+    binance_order_response::process_order_log,
     binance_orders::{get_all_orders, get_open_orders, Orders},
+    binance_sell_rules::eval_sell_rules_cmd,
+    binance_ticker::{book_ticker_cmd, ticker_24hr_cmd, ticker_price_cmd},
     binance_trade::{MarketQuantityType, TradeOrderType},
+    binance_user_data_stream::user_data_stream_cmd,
+    binance_websocket::watch_cmd,
     binance_withdraw_cmd::{withdraw_cmd, WithdrawParams},
-    common::{InternalErrorRec, APP_VERSION},
+    common::APP_VERSION,
+    historical_valuation::historical_account_info_cmd,
+    order_log_query::order_log_query_cmd,
+    process_binance_api::tt_file_from_api,
     process_binance_com::{
         consolidate_binance_com_trade_history_files, process_binance_com_trade_history_files,
         tt_file_from_binance_com_trade_history_files,
@@ -69,6 +103,9 @@ use crate::{
     process_token_tax::{
         consolidate_token_tax_files, process_token_tax_files, uniq_currency_token_tax_files,
     },
+    reconcile::reconcile_cmd,
+    trade_summary::trade_summary_cmd,
+    trade_sync::sync_cmd,
 };
 
 fn get_sym_qty_or_val(
@@ -89,6 +126,16 @@ fn get_sym_qty_or_val(
     Ok((sym_name.to_string(), quantity))
 }
 
+fn get_status(sc_matches: &ArgMatches) -> Result<Option<u32>, Box<dyn std::error::Error>> {
+    match sc_matches.value_of("STATUS") {
+        Some(s) => match u32::from_str(s) {
+            Ok(status) => Ok(Some(status)),
+            Err(e) => Err(format!("converting STATUS {s} to a number: e={e}").into()),
+        },
+        None => Ok(None),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
@@ -145,6 +192,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "auto-buy" => {
                 auto_buy_cmd(&config).await?;
             }
+            "eval-sell-rules" => {
+                eval_sell_rules_cmd(&config).await?;
+            }
             "buy-market-value" => {
                 let (sym_name, value) = get_sym_qty_or_val(sc_matches, "VALUE")?;
                 let order_type = TradeOrderType::Market(MarketQuantityType::QuoteOrderQty(value));
@@ -169,10 +219,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let params = WithdrawParams::from_subcommand(sc_matches)?;
                 withdraw_cmd(&config, &params).await?;
             }
+            "dust" => {
+                dust_cmd(&config, sc_matches).await?;
+            }
+            "bt" => {
+                backtest_cmd(&config, sc_matches).await?;
+            }
             "ai" => {
-                let time_ms = utc_now_to_time_ms();
-                let mut ai = get_account_info(&config, utc_now_to_time_ms()).await?;
-                ai.update_and_print(&config, time_ms).await;
+                if sc_matches.is_present("TIME") {
+                    historical_account_info_cmd(&config, sc_matches).await?;
+                } else {
+                    let time_ms = utc_now_to_time_ms();
+                    let mut ai = get_account_info(&config, utc_now_to_time_ms()).await?;
+                    ai.update_and_print(&config, time_ms).await;
+                }
+            }
+            "snapshot" => {
+                snapshot_cmd(&config).await?;
+            }
+            "performance" => {
+                performance_cmd(&config, sc_matches).await?;
             }
             "ei" => {
                 let ei = get_exchange_info(&config).await?;
@@ -209,11 +275,45 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let kr: KlineRec = get_kline(&config, sym_name, start_time_ms).await?;
                 println!("{kr}");
             }
+            "depth" => {
+                depth_cmd(&config, sc_matches).await?;
+            }
+            "trades" => {
+                trades_cmd(&config, sc_matches).await?;
+            }
+            "watch" => {
+                watch_cmd(&config, sc_matches).await?;
+            }
+            "alerts" => {
+                alerts_cmd(&config, sc_matches).await?;
+            }
+            "uds" => {
+                user_data_stream_cmd(&config).await?;
+            }
+            "t24" => {
+                ticker_24hr_cmd(&config, sc_matches).await?;
+            }
+            "tb" => {
+                book_ticker_cmd(&config, sc_matches).await?;
+            }
+            "tp" => {
+                ticker_price_cmd(&config, sc_matches).await?;
+            }
+            "ind" => {
+                indicators_cmd(&config, sc_matches).await?;
+            }
             "skrs" => {
                 let mut rec = GetKlinesCmdRec::default();
                 let sym_name = sc_matches.value_of("SYMBOL").expect("SYMBOL is missing");
                 rec.sym_name = sym_name.to_string();
-                if let Some(limit_str) = sc_matches.value_of("LIMIT") {
+                rec.chart = sc_matches.is_present("CHART");
+                if let Some(ma_str) = sc_matches.value_of("MA") {
+                    rec.ma_period = Some(usize::from_str(ma_str)?);
+                }
+                if rec.chart && sc_matches.occurrences_of("LIMIT") == 0 {
+                    // Fill the terminal width
+                    rec.limit = None;
+                } else if let Some(limit_str) = sc_matches.value_of("LIMIT") {
                     println!("skrs: limit={limit_str}");
                     rec.limit = Some(u16::from_str(limit_str)?);
                 } else {
@@ -266,157 +366,51 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     println!("o: {o:#?}");
                 }
             }
-            "mt" => {
-                // TODO: Add support for getting from_id, start_data_time, end_data_time and limit
-                let sym_name = sc_matches.value_of("SYMBOL").expect("SYMBOL is missing");
-                let ei = get_exchange_info(&config).await?;
-                let symbol = if let Some(s) = ei.get_symbol(sym_name) {
-                    s
-                } else {
-                    return Err(
-                        crate::ier_new!(9, &format!("Symbol {sym_name} isn't valid: ")).into(),
-                    );
-                };
-                let asset = &symbol.base_asset;
-
-                // Add in deposit History for the sym
-                let drs: Vec<DepositRec> =
-                    get_deposit_history(&config, Some(asset), None, None, None).await?;
-                let mut dep_qty = dec!(0);
-                for dr in &drs {
-                    println!(
-                        "Deposit: amount: {} Date: {}",
-                        dr.amount,
-                        time_ms_to_utc(dr.insert_time)
-                    );
-                    trace!("{:#?}", dr);
-                    dep_qty += dr.amount;
-                }
-                println!(
-                    "deposits: {} qty: {}",
-                    drs.len(),
-                    dec_to_separated_string(dep_qty, 4),
-                );
-
-                let wrs: Vec<WithdrawRec> =
-                    get_withdraw_history(&config, Some(asset), None, None, None).await?;
-                let mut wd_qty = dec!(0);
-                for wd in &wrs {
-                    println!(
-                        "Withdrawl: amount: {} txs fee: {} Date: {}",
-                        wd.amount,
-                        wd.transaction_fee,
-                        time_ms_to_utc(wd.apply_time)
-                    );
-                    trace!("{:#?}", wd);
-                    wd_qty += wd.amount; // - wd.transaction_fee;
-                }
-                println!(
-                    "withdrawals: {} qty: {}",
-                    wrs.len(),
-                    dec_to_separated_string(wd_qty, symbol.base_asset_precision),
-                );
-
-                let mt: Trades = get_my_trades(&config, sym_name, None, None, None, None).await?;
-                let mut buy_txs: usize = 0;
-                let mut buy_qty = dec!(0);
-                let mut buy_quote_qty = dec!(0);
-                let mut sell_txs: usize = 0;
-                let mut sell_qty = dec!(0);
-                let mut sell_quote_qty = dec!(0);
-                let mut commission_total_usd = dec!(0); // TODO: Should allow different conversions?
-                for tr in &mt.trades {
-                    print!("Trade: orderId: {}", tr.order_id);
-                    if tr.is_buyer {
-                        buy_txs += 1;
-                        buy_qty += tr.qty;
-                        buy_quote_qty += tr.quote_qty;
-                        print!(" buy ");
-                    } else {
-                        sell_txs += 1;
-                        sell_qty += tr.qty;
-                        sell_quote_qty += tr.quote_qty;
-                        print!(" sell");
-                    }
-                    print!(
-                        " {} {} value {}",
-                        symbol.base_asset,
-                        dec_to_separated_string(tr.qty, symbol.base_asset_precision),
-                        dec_to_usd_string(tr.quote_qty),
-                    );
-
-                    let commission_usd = binance_trade::convert(
-                        &config,
-                        tr.time,
-                        &tr.commission_asset,
-                        tr.commission,
-                        "USD",
-                    )
-                    .await?;
-                    commission_total_usd += commission_usd;
-
-                    // Delay so as not to be a bad binance citizen
-                    std::thread::sleep(std::time::Duration::from_millis(500));
-
-                    print!(
-                        " commission: {} commision usd: {} commission_asset: {}",
-                        tr.commission, commission_usd, tr.commission_asset
-                    );
-                    println!(" Date: {}", time_ms_to_utc(tr.time));
-                    trace!("{:#?}", tr);
-                }
-
-                println!(
-                    "total buy transactions: {} buy qty: {} buy quote_qty: {}",
-                    buy_txs,
-                    dec_to_separated_string(buy_qty, symbol.base_asset_precision),
-                    dec_to_usd_string(buy_quote_qty),
-                );
-                println!(
-                    "total sell transactions: {} sell qty: {} sell quote_qty: {}",
-                    sell_txs,
-                    dec_to_separated_string(sell_qty, symbol.base_asset_precision),
-                    dec_to_usd_string(sell_quote_qty),
-                );
-                println!(
-                    "commission for {} trades, value USD: {} ",
-                    mt.trades.len(),
-                    dec_to_usd_string(commission_total_usd),
-                );
+            "mt" => trade_summary_cmd(&config, sc_matches).await?,
+            "sync" => {
+                sync_cmd(&config, sc_matches).await?;
             }
             "dh" => {
-                // TODO: Add support for getting status, start_data_time, end_data_time
                 let asset = sc_matches.value_of("ASSET");
+                let status = get_status(sc_matches)?;
+                let (start, end) = start_end_date_times(sc_matches)?;
                 let dh: Vec<DepositRec> =
-                    get_deposit_history(&config, asset, None, None, None).await?;
+                    get_deposit_history(&config, asset, status, start, end).await?;
                 println!("{dh:#?}");
             }
             "wh" => {
-                // TODO: Add support for getting status, start_data_time, end_data_time
                 let asset = sc_matches.value_of("ASSET");
+                let status = get_status(sc_matches)?;
+                let (start, end) = start_end_date_times(sc_matches)?;
                 let wh: Vec<WithdrawRec> =
-                    get_withdraw_history(&config, asset, None, None, None).await?;
+                    get_withdraw_history(&config, asset, status, start, end).await?;
                 println!("{wh:#?}");
             }
             "fcdh" => {
-                // TODO: Add support for getting status, start_data_time, end_data_time
                 let asset = sc_matches.value_of("FIAT_CURRENCY");
-                let dhfc: Vec<AssetLogRec> = get_fiat_currency_deposit_history(
-                    &config, asset, None, None, None, None, None, None,
+                let (start, end) = start_end_date_times(sc_matches)?;
+                let mut dhfc: Vec<AssetLogRec> = get_fiat_currency_deposit_history(
+                    &config, asset, None, None, None, None, start, end,
                 )
                 .await?;
+                if let Some(status) = sc_matches.value_of("STATUS") {
+                    dhfc.retain(|alr| alr.order_status.eq_ignore_ascii_case(status));
+                }
                 println!("{dhfc:#?}");
             }
             "fcwh" => {
-                // TODO: Add support for getting status, start_data_time, end_data_time
                 let asset = sc_matches.value_of("FIAT_CURRENCY");
-                let whfc: Vec<AssetLogRec> = get_fiat_currency_withdraw_history(
-                    &config, asset, None, None, None, None, None, None,
+                let (start, end) = start_end_date_times(sc_matches)?;
+                let mut whfc: Vec<AssetLogRec> = get_fiat_currency_withdraw_history(
+                    &config, asset, None, None, None, None, start, end,
                 )
                 .await?;
+                if let Some(status) = sc_matches.value_of("STATUS") {
+                    whfc.retain(|alr| alr.order_status.eq_ignore_ascii_case(status));
+                }
                 println!("{whfc:#?}");
             }
-            "ol" => display_order_log(&config).await?,
+            "ol" => order_log_query_cmd(&config, sc_matches).await?,
             "pol" => process_order_log(&config, sc_matches).await?,
             "ubudf" => {
                 process_binance_us_dist_files(
@@ -438,6 +432,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             "cbudf" => consolidate_binance_us_dist_files(&config, sc_matches).await?,
             "ttffbudf" => tt_file_from_binance_us_dist_files(&config, sc_matches).await?,
+            "ttffapi" => tt_file_from_api(&config, sc_matches).await?,
+            "reconcile" => reconcile_cmd(&config, sc_matches).await?,
             "pbcthf" => {
                 process_binance_com_trade_history_files(&config, sc_matches).await?;
             }
@@ -0,0 +1,437 @@
+//! Notifiers used to tell someone about alerts and the results of trades
+//! and withdrawals, for instance when running unattended from cron.
+//!
+//! A notifier is written as stdout, command:CMD, file:PATH or webhook:URL.
+//! Webhooks are POSTed the notification as JSON and commands are run with
+//! sh -c with the notification in environment variables. Failed
+//! notifications are retried with a doubling delay.
+use std::{
+    fmt::{self, Display},
+    io::Write,
+    path::PathBuf,
+    time::Duration,
+};
+
+use dec_utils::dec_to_usd_string;
+use log::trace;
+use reqwest::header::{HeaderMap, HeaderValue};
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::Serialize;
+use time_ms_conversions::{time_ms_to_utc, utc_now_to_time_ms};
+
+use crate::{
+    binance_order_response::TradeResponse, common::post_req_get_response_ll,
+    configuration::Configuration,
+};
+
+/// Something that can be sent by a Notifier
+pub trait Notification: Serialize + Display {
+    /// The environment variables of command notifiers
+    fn env_vars(&self) -> Vec<(&'static str, String)>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notifier {
+    Stdout,
+
+    /// Run with sh -c, see Notification::env_vars
+    Command(String),
+
+    /// Append the notification to the file
+    File(PathBuf),
+
+    /// POST the notification as JSON to the url
+    Webhook(String),
+}
+
+impl Notifier {
+    /// Parse stdout, command:CMD, file:PATH or webhook:URL
+    pub fn from_string(s: &str) -> Result<Notifier, Box<dyn std::error::Error>> {
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, arg),
+            None => (s, ""),
+        };
+        match (kind, arg) {
+            ("stdout", "") => Ok(Notifier::Stdout),
+            ("command", cmd) if !cmd.is_empty() => Ok(Notifier::Command(cmd.to_string())),
+            ("file", path) if !path.is_empty() => Ok(Notifier::File(PathBuf::from(path))),
+            ("webhook", url) if !url.is_empty() => Ok(Notifier::Webhook(url.to_string())),
+            _ => Err(format!(
+                "Unknown notifier, {s}, expecting: stdout, command:CMD, file:PATH or webhook:URL"
+            )
+            .into()),
+        }
+    }
+
+    pub async fn notify<T: Notification>(
+        &self,
+        notification: &T,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        trace!("notify: {self:?} {notification}");
+        match self {
+            Notifier::Stdout => println!("{notification}"),
+            Notifier::Command(cmd) => {
+                let status = tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(cmd)
+                    .envs(notification.env_vars())
+                    .status()
+                    .await?;
+                if !status.success() {
+                    return Err(format!("command \"{cmd}\" failed: {status}").into());
+                }
+            }
+            Notifier::File(path) => {
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                writeln!(file, "{notification}")?;
+            }
+            Notifier::Webhook(url) => {
+                let mut headers = HeaderMap::new();
+                headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+                let body = serde_json::to_string(notification)?;
+                let response = post_req_get_response_ll(url, headers, &body).await?;
+                if !response.status().is_success() {
+                    return Err(
+                        format!("webhook {url} response status={}", response.status()).into(),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse each notifier
+pub fn notifiers_from_strings(
+    strs: &[String],
+) -> Result<Vec<Notifier>, Box<dyn std::error::Error>> {
+    strs.iter().map(|s| Notifier::from_string(s)).collect()
+}
+
+/// Call each notifier, retrying a failed notifier up to config.notify_retries
+/// times. Failures are reported and don't stop the other notifiers.
+pub async fn notify_all<T: Notification>(
+    config: &Configuration,
+    notifiers: &[Notifier],
+    notifications: &[T],
+) {
+    for notification in notifications {
+        for notifier in notifiers {
+            let mut delay_ms = config.notify_retry_delay_ms;
+            for attempt in 0..=config.notify_retries {
+                match notifier.notify(notification).await {
+                    Ok(()) => break,
+                    Err(e) if attempt < config.notify_retries => {
+                        trace!("notify_all: {notifier:?} attempt: {attempt} failed: {e}");
+                        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                        delay_ms *= 2;
+                    }
+                    Err(e) => println!("Notifier {notifier:?} failed: {e}"),
+                }
+            }
+        }
+    }
+}
+
+/// The results of a command that trades or withdraws
+#[derive(Debug, Clone, Serialize)]
+pub struct TradeSummary {
+    pub command: String,
+    pub time: i64,
+    pub test: bool,
+    pub succeeded: u32,
+    pub failed: u32,
+    pub total_value_usd: Decimal,
+
+    /// A line describing each result
+    pub results: Vec<String>,
+
+    /// Each TradeResponse as it is in the order log
+    pub responses: Vec<serde_json::Value>,
+}
+
+impl TradeSummary {
+    pub fn new(command: &str, test: bool) -> TradeSummary {
+        TradeSummary {
+            command: command.to_string(),
+            time: utc_now_to_time_ms(),
+            test,
+            succeeded: 0,
+            failed: 0,
+            total_value_usd: dec!(0),
+            results: vec![],
+            responses: vec![],
+        }
+    }
+
+    pub fn add(&mut self, tr: &TradeResponse) {
+        if tr.is_success() {
+            self.succeeded += 1;
+            self.total_value_usd += tr.value_usd();
+        } else {
+            self.failed += 1;
+        }
+        self.results.push(tr.to_string());
+        match serde_json::to_value(tr) {
+            Ok(v) => self.responses.push(v),
+            Err(e) => trace!("TradeSummary::add: {e}"),
+        }
+    }
+
+    /// Add a failure that has no TradeResponse
+    pub fn add_error(&mut self, name: &str, e: &dyn std::error::Error) {
+        self.failed += 1;
+        self.results.push(format!("FAILED {name}: {e}"));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.succeeded == 0 && self.failed == 0
+    }
+}
+
+impl Display for TradeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}{}: {} succeeded, {} failed, total {}",
+            time_ms_to_utc(self.time),
+            self.command,
+            if self.test { " (test)" } else { "" },
+            self.succeeded,
+            self.failed,
+            dec_to_usd_string(self.total_value_usd.round_dp(2)),
+        )?;
+        for r in &self.results {
+            write!(f, "\n  {r}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Notification for TradeSummary {
+    fn env_vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("TRADE_COMMAND", self.command.clone()),
+            ("TRADE_MESSAGE", self.to_string()),
+            ("TRADE_SUCCEEDED", self.succeeded.to_string()),
+            ("TRADE_FAILED", self.failed.to_string()),
+            (
+                "TRADE_TOTAL_USD",
+                self.total_value_usd.round_dp(2).to_string(),
+            ),
+            ("TRADE_TEST", self.test.to_string()),
+        ]
+    }
+}
+
+/// Send summary to the config.trade_notifiers, if there are any
+pub async fn notify_trade_summary(config: &Configuration, summary: &TradeSummary) {
+    if config.trade_notifiers.is_empty() || summary.is_empty() {
+        return;
+    }
+    match notifiers_from_strings(&config.trade_notifiers) {
+        Ok(notifiers) => notify_all(config, &notifiers, std::slice::from_ref(summary)).await,
+        Err(e) => println!("trade_notifiers: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        binance_order_response::{FullTradeResponseRec, TestTradeResponseRec},
+        common::{InternalErrorRec, Side},
+        ier_new,
+        test_utils::TempDir,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    fn test_summary() -> TradeSummary {
+        let mut summary = TradeSummary::new("auto-sell", false);
+        summary.add(&TradeResponse::SuccessFull(FullTradeResponseRec {
+            symbol: "BTCUSD".to_string(),
+            side: Side::SELL,
+            executed_qty: dec!(0.01),
+            cummulative_quote_qty: dec!(300),
+            value_usd: dec!(300),
+            ..Default::default()
+        }));
+        summary.add(&TradeResponse::FailureInternal(ier_new!(5, "slippage")));
+        summary.add_error("ETHUSD", &*Box::<dyn std::error::Error>::from("no balance"));
+        summary
+    }
+
+    fn test_config() -> Configuration {
+        Configuration {
+            notify_retries: 2,
+            notify_retry_delay_ms: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_notifier_from_string() {
+        assert_eq!(Notifier::from_string("stdout").unwrap(), Notifier::Stdout);
+        assert_eq!(
+            Notifier::from_string("webhook:http://localhost:8080/trades").unwrap(),
+            Notifier::Webhook("http://localhost:8080/trades".to_string())
+        );
+        assert_eq!(
+            Notifier::from_string("command:notify-send \"$TRADE_MESSAGE\"").unwrap(),
+            Notifier::Command("notify-send \"$TRADE_MESSAGE\"".to_string())
+        );
+        assert!(Notifier::from_string("file:").is_err());
+        assert!(Notifier::from_string("email:a@b.c").is_err());
+        assert!(notifiers_from_strings(&["stdout".to_string(), "sms".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_trade_summary() {
+        let summary = test_summary();
+        assert_eq!(summary.succeeded, 1);
+        assert_eq!(summary.failed, 2);
+        assert_eq!(summary.total_value_usd, dec!(300));
+        assert_eq!(summary.results.len(), 3);
+        assert_eq!(summary.responses.len(), 2);
+        assert!(summary.results[2].starts_with("FAILED ETHUSD: no balance"));
+        let text = summary.to_string();
+        assert!(text.contains("auto-sell: 1 succeeded, 2 failed, total $300"));
+
+        // Tests are successes without a value
+        let mut summary = TradeSummary::new("auto-buy", true);
+        assert!(summary.is_empty());
+        summary.add(&TradeResponse::SuccessTest(TestTradeResponseRec::default()));
+        assert_eq!(summary.succeeded, 1);
+        assert_eq!(summary.total_value_usd, dec!(0));
+        assert!(summary.to_string().contains("auto-buy (test)"));
+    }
+
+    #[tokio::test]
+    async fn test_file_and_command_notifiers() {
+        let dir = TempDir::new("notifications");
+        let file_path = dir.path().join("trades.log");
+        let cmd_path = dir.path().join("cmd.out");
+
+        let notifiers = vec![
+            Notifier::File(file_path.clone()),
+            Notifier::Command(format!(
+                "echo \"$TRADE_COMMAND $TRADE_SUCCEEDED $TRADE_FAILED $TRADE_TOTAL_USD\" >> {}",
+                cmd_path.display()
+            )),
+        ];
+        notify_all(&test_config(), &notifiers, &[test_summary()]).await;
+
+        let text = std::fs::read_to_string(&file_path).unwrap();
+        assert_eq!(text.lines().count(), 4);
+        assert!(text.lines().nth(3).unwrap().contains("FAILED ETHUSD"));
+        let out = std::fs::read_to_string(&cmd_path).unwrap();
+        assert_eq!(out, "auto-sell 1 2 300\n");
+
+        // A failing command is retried
+        let count_path = dir.path().join("count.out");
+        let failing = Notifier::Command(format!("echo x >> {}; exit 3", count_path.display()));
+        assert!(failing.notify(&test_summary()).await.is_err());
+        notify_all(&test_config(), &[failing], &[test_summary()]).await;
+        let count = std::fs::read_to_string(&count_path).unwrap();
+        assert_eq!(count.lines().count(), 1 + 3);
+    }
+
+    /// Receive an HTTP request for each of statuses responding with
+    /// that status, returns the requests
+    async fn webhook_stand_in(listener: TcpListener, statuses: Vec<u16>) -> Vec<String> {
+        let mut requests = vec![];
+        for status in statuses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::<u8>::new();
+            let mut buf = [0u8; 1024];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let content_length = text
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().to_string())
+                        })
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + content_length {
+                        break;
+                    }
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 {status} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            requests.push(String::from_utf8(request).unwrap());
+        }
+
+        requests
+    }
+
+    fn request_json(request: &str) -> serde_json::Value {
+        let body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_webhook_notifier() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/trades", listener.local_addr().unwrap());
+        let server = tokio::spawn(webhook_stand_in(listener, vec![200]));
+
+        let notifier = Notifier::from_string(&format!("webhook:{url}")).unwrap();
+        notifier.notify(&test_summary()).await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("POST /trades HTTP/1.1"));
+        let json = request_json(&requests[0]);
+        assert_eq!(json["command"], "auto-sell");
+        assert_eq!(json["succeeded"].as_u64(), Some(1));
+        assert_eq!(json["failed"].as_u64(), Some(2));
+        assert_eq!(json["results"].as_array().unwrap().len(), 3);
+        assert!(json["responses"][0].get("SuccessFull").is_some());
+
+        // A failure status is an error
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/trades", listener.local_addr().unwrap());
+        let server = tokio::spawn(webhook_stand_in(listener, vec![500]));
+        assert!(Notifier::Webhook(url)
+            .notify(&test_summary())
+            .await
+            .is_err());
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retries() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/trades", listener.local_addr().unwrap());
+        let server = tokio::spawn(webhook_stand_in(listener, vec![503, 500, 200]));
+
+        let config = Configuration {
+            trade_notifiers: vec![format!("webhook:{url}")],
+            ..test_config()
+        };
+        notify_trade_summary(&config, &test_summary()).await;
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(request_json(&requests[2])["command"], "auto-sell");
+    }
+}
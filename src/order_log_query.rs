@@ -0,0 +1,509 @@
+//! Query the order log, select entries by time, symbol, side, kind,
+//! test or live and failures and display them or export their fills.
+//!
+use std::{cell::RefCell, collections::HashSet, io::Write};
+
+use clap::ArgMatches;
+use serde::Serialize;
+
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use time_ms_conversions::{time_ms_to_utc, utc_to_time_ms};
+
+use crate::{
+    arg_matches::start_end_date_times,
+    binance_order_response::{iterate_order_log, TradeResponse},
+    common::{create_buf_writer, Side},
+    configuration::Configuration,
+    trade_summary::SummaryFormat,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogMode {
+    All,
+    Live,
+    Test,
+}
+
+impl LogMode {
+    pub fn from_string(s: &str) -> Result<LogMode, Box<dyn std::error::Error>> {
+        match s {
+            "all" => Ok(LogMode::All),
+            "live" => Ok(LogMode::Live),
+            "test" => Ok(LogMode::Test),
+            _ => Err(format!("Unknown mode, {s}, expecting: all, live or test").into()),
+        }
+    }
+}
+
+/// Selects order log entries, an empty or None field selects all
+#[derive(Debug, Clone)]
+pub struct OrderLogFilter {
+    pub start_ms: Option<i64>,
+    pub end_ms: Option<i64>,
+    pub symbols: Vec<String>,
+    pub side: Option<Side>,
+    pub kinds: Vec<String>,
+    pub mode: LogMode,
+    pub failures_only: bool,
+}
+
+impl Default for OrderLogFilter {
+    fn default() -> OrderLogFilter {
+        OrderLogFilter {
+            start_ms: None,
+            end_ms: None,
+            symbols: vec![],
+            side: None,
+            kinds: vec![],
+            mode: LogMode::All,
+            failures_only: false,
+        }
+    }
+}
+
+/// A fill of an order log entry
+#[derive(Debug, Clone, Serialize)]
+pub struct FillRow {
+    pub line: usize,
+    pub time: String,
+    pub kind: &'static str,
+    pub test: bool,
+    pub symbol: String,
+    pub side: String,
+    pub order_id: u64,
+    pub trade_id: Option<i64>,
+    pub price: Decimal,
+    pub qty: Decimal,
+    pub quote_qty: Decimal,
+    pub commission: Decimal,
+    pub commission_asset: String,
+    pub value_usd: Decimal,
+    pub commission_usd: Decimal,
+}
+
+fn side_string(side: &Side) -> &'static str {
+    match side {
+        Side::BUY => "BUY",
+        Side::SELL => "SELL",
+    }
+}
+
+/// The time of an entry, None if it has none
+fn entry_time(tr: &TradeResponse) -> Option<i64> {
+    let time = match tr {
+        TradeResponse::SuccessAck(r) => r.transact_time,
+        TradeResponse::SuccessResult(r) => r.transact_time,
+        TradeResponse::SuccessFull(r) => r.transact_time,
+        TradeResponse::SuccessWithdraw(r)
+        | TradeResponse::FailureWithdraw(r)
+        | TradeResponse::SuccessTestWithdraw(r) => r.time,
+        TradeResponse::SuccessDust(r) | TradeResponse::SuccessTestDust(r) => r
+            .transfer_result
+            .iter()
+            .map(|t| t.operate_time)
+            .max()
+            .unwrap_or(0),
+        TradeResponse::SuccessExecutionReport(r) => r.transact_time,
+        _ => 0,
+    };
+
+    if time > 0 {
+        Some(time)
+    } else {
+        None
+    }
+}
+
+/// The symbol of an entry, the asset of withdrawals
+fn entry_symbol(tr: &TradeResponse) -> Option<&str> {
+    match tr {
+        TradeResponse::SuccessAck(r) => Some(r.symbol.as_str()),
+        TradeResponse::SuccessResult(r) => Some(r.symbol.as_str()),
+        TradeResponse::SuccessFull(r) => Some(r.symbol.as_str()),
+        TradeResponse::SuccessWithdraw(r)
+        | TradeResponse::FailureWithdraw(r)
+        | TradeResponse::SuccessTestWithdraw(r) => Some(r.params.sym_name.as_str()),
+        TradeResponse::SuccessExecutionReport(r) => Some(r.symbol.as_str()),
+        _ => None,
+    }
+}
+
+fn entry_side(tr: &TradeResponse) -> Option<&str> {
+    match tr {
+        TradeResponse::SuccessResult(r) => Some(r.side.as_str()),
+        TradeResponse::SuccessFull(r) => Some(side_string(&r.side)),
+        TradeResponse::SuccessExecutionReport(r) => Some(side_string(&r.side)),
+        _ => None,
+    }
+}
+
+fn entry_is_test(tr: &TradeResponse) -> bool {
+    match tr {
+        TradeResponse::SuccessAck(r) => r.test,
+        TradeResponse::SuccessResult(r) => r.test,
+        TradeResponse::SuccessFull(r) => r.test,
+        TradeResponse::SuccessTest(_)
+        | TradeResponse::SuccessTestWithdraw(_)
+        | TradeResponse::SuccessTestDust(_) => true,
+        TradeResponse::SuccessWithdraw(r) | TradeResponse::FailureWithdraw(r) => r.test,
+        TradeResponse::SuccessDust(r) => r.test,
+        TradeResponse::SuccessUnknown(r) => r.test,
+        TradeResponse::FailureResponse(r) => r.test,
+        TradeResponse::SuccessExecutionReport(_) | TradeResponse::FailureInternal(_) => false,
+    }
+}
+
+impl OrderLogFilter {
+    pub fn from_subcommand(sc_matches: &ArgMatches) -> Result<Self, Box<dyn std::error::Error>> {
+        let (start, end) = start_end_date_times(sc_matches)?;
+        let symbols: Vec<String> = match sc_matches.values_of("SYMBOLS") {
+            Some(v) => v.map(|s| s.to_uppercase()).collect(),
+            None => vec![],
+        };
+        let side = match sc_matches.value_of("SIDE") {
+            Some("buy") => Some(Side::BUY),
+            Some("sell") => Some(Side::SELL),
+            Some(s) => return Err(format!("Unknown side, {s}, expecting: buy or sell").into()),
+            None => None,
+        };
+        let kinds: Vec<String> = match sc_matches.values_of("KINDS") {
+            Some(v) => v.map(|s| s.to_string()).collect(),
+            None => vec![],
+        };
+
+        Ok(OrderLogFilter {
+            start_ms: start.map(|dt| utc_to_time_ms(&dt)),
+            end_ms: end.map(|dt| utc_to_time_ms(&dt)),
+            symbols,
+            side,
+            kinds,
+            mode: LogMode::from_string(sc_matches.value_of("MODE").unwrap_or("all"))?,
+            failures_only: sc_matches.is_present("FAILURES"),
+        })
+    }
+
+    /// True if tr is selected, entries without a time are
+    /// excluded when there is a time range.
+    pub fn matches(&self, tr: &TradeResponse) -> bool {
+        if self.start_ms.is_some() || self.end_ms.is_some() {
+            match entry_time(tr) {
+                Some(t) => {
+                    if self.start_ms.is_some_and(|s| t < s) || self.end_ms.is_some_and(|e| t > e) {
+                        return false;
+                    }
+                }
+                None => return false,
+            }
+        }
+
+        if !self.symbols.is_empty() {
+            match entry_symbol(tr) {
+                Some(sym) if self.symbols.iter().any(|s| s == sym) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(side) = &self.side {
+            if !entry_side(tr).is_some_and(|s| s.eq_ignore_ascii_case(side_string(side))) {
+                return false;
+            }
+        }
+
+        if !self.kinds.is_empty() && !self.kinds.iter().any(|k| k == tr.kind()) {
+            return false;
+        }
+
+        match self.mode {
+            LogMode::Live if entry_is_test(tr) => return false,
+            LogMode::Test if !entry_is_test(tr) => return false,
+            _ => {}
+        }
+
+        !(self.failures_only && tr.is_success())
+    }
+}
+
+/// The fills of the order log entry tr at line, the USD values
+/// of an order are divided between its fills by quantity.
+pub fn fill_rows(line: usize, tr: &TradeResponse) -> Vec<FillRow> {
+    let time = entry_time(tr).map_or("".to_string(), |t| time_ms_to_utc(t).to_string());
+    match tr {
+        TradeResponse::SuccessFull(r) => r
+            .fills
+            .iter()
+            .map(|f| {
+                let share = if r.executed_qty > dec!(0) {
+                    f.qty / r.executed_qty
+                } else {
+                    dec!(0)
+                };
+                FillRow {
+                    line,
+                    time: time.clone(),
+                    kind: tr.kind(),
+                    test: r.test,
+                    symbol: r.symbol.clone(),
+                    side: side_string(&r.side).to_string(),
+                    order_id: r.order_id,
+                    trade_id: Some(f.trade_id as i64),
+                    price: f.price,
+                    qty: f.qty,
+                    quote_qty: f.price * f.qty,
+                    commission: f.commission,
+                    commission_asset: f.commission_asset.clone(),
+                    value_usd: r.value_usd * share,
+                    commission_usd: r.commission_usd * share,
+                }
+            })
+            .collect(),
+        TradeResponse::SuccessResult(r) if r.executed_qty > dec!(0) => vec![FillRow {
+            line,
+            time,
+            kind: tr.kind(),
+            test: r.test,
+            symbol: r.symbol.clone(),
+            side: r.side.clone(),
+            order_id: r.order_id,
+            trade_id: None,
+            price: r.cummulative_quote_qty / r.executed_qty,
+            qty: r.executed_qty,
+            quote_qty: r.cummulative_quote_qty,
+            commission: dec!(0),
+            commission_asset: "".to_string(),
+            value_usd: r.value_usd,
+            commission_usd: r.commission_usd,
+        }],
+        TradeResponse::SuccessExecutionReport(r) if r.is_fill() => vec![FillRow {
+            line,
+            time,
+            kind: tr.kind(),
+            test: false,
+            symbol: r.symbol.clone(),
+            side: side_string(&r.side).to_string(),
+            order_id: r.order_id,
+            trade_id: Some(r.trade_id),
+            price: r.last_executed_price,
+            qty: r.last_executed_qty,
+            quote_qty: r.last_quote_qty,
+            commission: r.commission,
+            commission_asset: r.commission_asset.clone().unwrap_or_default(),
+            value_usd: r.value_usd,
+            commission_usd: r.commission_usd,
+        }],
+        _ => vec![],
+    }
+}
+
+/// Remove the fills of rows already in an earlier row, a fill of an
+/// order is logged both in its response and in an executionReport of
+/// the user data stream. Fills are identified by test, symbol and trade_id.
+pub fn dedup_fills(rows: &mut Vec<FillRow>) {
+    let mut seen = HashSet::<(bool, String, i64)>::new();
+    rows.retain(|row| match row.trade_id {
+        Some(trade_id) => seen.insert((row.test, row.symbol.clone(), trade_id)),
+        None => true,
+    });
+}
+
+/// Display the selected order log entries or, for csv and json,
+/// export their fills to the out-file or stdout.
+pub async fn order_log_query_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let filter = OrderLogFilter::from_subcommand(sc_matches)?;
+    let format = SummaryFormat::from_string(sc_matches.value_of("FORMAT").unwrap_or("table"))?;
+
+    let entries = RefCell::new(0usize);
+    let rows = RefCell::new(Vec::<FillRow>::new());
+    let process_line = |line: &str, line_number: usize| -> Result<(), Box<dyn std::error::Error>> {
+        let tr: TradeResponse = match serde_json::from_str(line) {
+            Ok(tr) => tr,
+            Err(e) => {
+                return Err(format!("line: {} Err: {}", line_number + 1, e).into());
+            }
+        };
+        if !filter.matches(&tr) {
+            return Ok(());
+        }
+
+        *entries.borrow_mut() += 1;
+        match format {
+            SummaryFormat::Table => println!(
+                "{:6} {:24} {:22} {}",
+                line_number + 1,
+                entry_time(&tr).map_or("".to_string(), |t| time_ms_to_utc(t).to_string()),
+                tr.kind(),
+                tr
+            ),
+            _ => rows
+                .borrow_mut()
+                .append(&mut fill_rows(line_number + 1, &tr)),
+        }
+
+        Ok(())
+    };
+    iterate_order_log(config.order_log_path.clone(), process_line).await?;
+
+    if format == SummaryFormat::Table {
+        println!("Entries: {}", entries.into_inner());
+        return Ok(());
+    }
+
+    let mut rows = rows.into_inner();
+    dedup_fills(&mut rows);
+    let mut writer: Box<dyn Write> = match sc_matches.value_of("OUT_FILE") {
+        Some(out_file) => Box::new(create_buf_writer(out_file)?),
+        None => Box::new(std::io::stdout()),
+    };
+    match format {
+        SummaryFormat::Table => {}
+        SummaryFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(writer);
+            for row in &rows {
+                wtr.serialize(row)?;
+            }
+            wtr.flush()?;
+        }
+        SummaryFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &rows)?;
+            writeln!(writer)?;
+            writer.flush()?;
+        }
+    }
+    if let Some(out_file) = sc_matches.value_of("OUT_FILE") {
+        println!(
+            "Writing fills to {out_file}: Done; entries: {} fills: {}",
+            entries.into_inner(),
+            rows.len()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        binance_order_response::{FullTradeResponseRec, WithdrawResponseRec},
+        common::ResponseErrorRec,
+    };
+
+    fn full(symbol: &str, side: Side, time: i64, test: bool) -> TradeResponse {
+        TradeResponse::SuccessFull(FullTradeResponseRec {
+            test,
+            symbol: symbol.to_owned(),
+            side,
+            transact_time: time,
+            executed_qty: dec!(2),
+            cummulative_quote_qty: dec!(200),
+            value_usd: dec!(200),
+            commission_usd: dec!(0.2),
+            fills: serde_json::from_str(
+                r#"[
+                    {"price":"99","qty":"0.5","commission":"0.05","commissionAsset":"BNB","tradeId":1},
+                    {"price":"100.33333333","qty":"1.5","commission":"0.15","commissionAsset":"BNB","tradeId":2}
+                ]"#,
+            )
+            .unwrap(),
+            ..FullTradeResponseRec::default()
+        })
+    }
+
+    #[test]
+    fn test_order_log_filter() {
+        let buy = full("ETHUSD", Side::BUY, 1000, false);
+        let sell = full("BTCUSD", Side::SELL, 2000, true);
+        let withdraw = TradeResponse::FailureWithdraw(WithdrawResponseRec::default());
+        let failure = TradeResponse::FailureResponse(
+            serde_json::from_str::<ResponseErrorRec>(r#"{"code":-2010,"msg":"Insufficient"}"#)
+                .unwrap(),
+        );
+
+        let filter = OrderLogFilter::default();
+        assert!([&buy, &sell, &withdraw, &failure]
+            .iter()
+            .all(|tr| filter.matches(tr)));
+
+        let filter = OrderLogFilter {
+            start_ms: Some(1500),
+            ..OrderLogFilter::default()
+        };
+        assert!(!filter.matches(&buy));
+        assert!(filter.matches(&sell));
+        assert!(!filter.matches(&withdraw));
+
+        let filter = OrderLogFilter {
+            symbols: vec!["ETHUSD".to_owned()],
+            side: Some(Side::BUY),
+            ..OrderLogFilter::default()
+        };
+        assert!(filter.matches(&buy));
+        assert!(!filter.matches(&sell));
+
+        let filter = OrderLogFilter {
+            mode: LogMode::Test,
+            ..OrderLogFilter::default()
+        };
+        assert!(!filter.matches(&buy));
+        assert!(filter.matches(&sell));
+
+        let filter = OrderLogFilter {
+            failures_only: true,
+            ..OrderLogFilter::default()
+        };
+        assert!(!filter.matches(&buy));
+        assert!(filter.matches(&withdraw));
+        assert!(filter.matches(&failure));
+
+        let filter = OrderLogFilter {
+            kinds: vec!["FailureResponse".to_owned()],
+            ..OrderLogFilter::default()
+        };
+        assert!(!filter.matches(&withdraw));
+        assert!(filter.matches(&failure));
+    }
+
+    #[test]
+    fn test_fill_rows() {
+        let rows = fill_rows(7, &full("ETHUSD", Side::BUY, 1000, false));
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].line, 7);
+        assert_eq!(rows[0].side, "BUY");
+        assert_eq!(rows[0].trade_id, Some(1));
+        assert_eq!(rows[0].quote_qty, dec!(49.5));
+        assert_eq!(rows[0].value_usd, dec!(50));
+        assert_eq!(rows[0].commission_usd, dec!(0.05));
+        assert_eq!(rows[1].value_usd, dec!(150));
+        assert_eq!(rows[1].commission_usd, dec!(0.15));
+        assert_eq!(rows[1].commission_asset, "BNB");
+
+        let withdraw = TradeResponse::SuccessWithdraw(WithdrawResponseRec::default());
+        assert!(fill_rows(1, &withdraw).is_empty());
+    }
+
+    #[test]
+    fn test_dedup_fills() {
+        let mut rows = fill_rows(1, &full("ETHUSD", Side::BUY, 1000, false));
+
+        // The same fills from executionReports, a test order and another symbol
+        let mut er_rows = rows.clone();
+        for row in &mut er_rows {
+            row.line = 2;
+            row.kind = "SuccessExecutionReport";
+        }
+        rows.append(&mut er_rows);
+        rows.append(&mut fill_rows(3, &full("ETHUSD", Side::BUY, 1000, true)));
+        rows.append(&mut fill_rows(4, &full("BTCUSD", Side::BUY, 1000, false)));
+        let mut no_id = rows[0].clone();
+        no_id.trade_id = None;
+        rows.push(no_id.clone());
+        rows.push(no_id);
+
+        dedup_fills(&mut rows);
+        let lines: Vec<usize> = rows.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![1, 1, 3, 3, 4, 4, 1, 1]);
+    }
+}
@@ -0,0 +1,465 @@
+//! This file creates TokenTax records from the account history of the api.
+//!
+//!  * Trades come from myTrades of each symbol, or the sync store,
+//!    commissions are the fee.
+//!  * Deposits and withdrawals come from the deposit and withdraw
+//!    history, only those that completed are used.
+//!  * Fiat deposits and withdrawals come from the fiat currency history,
+//!    the documented response has no time so records without a
+//!    createTime are skipped.
+//!  * Distributions are not in the api history, use ttffbudf for them.
+//!
+use chrono::{DateTime, Utc};
+use clap::ArgMatches;
+use log::trace;
+use rust_decimal_macros::dec;
+use std::{collections::BTreeSet, path::Path};
+use tokentaxrec::{TokenTaxRec, TokenTaxRecType};
+
+use crate::{
+    arg_matches::{start_end_date_times, time_offset_days_to_time_ms_offset},
+    binance_account_info::get_account_info,
+    binance_exchange_info::{get_exchange_info, ExchangeInfo, Symbol},
+    binance_history::{
+        get_deposit_history, get_fiat_currency_deposit_history, get_fiat_currency_withdraw_history,
+        get_withdraw_history, AssetLogRec, DepositRec, WithdrawRec, DEPOSIT_STATUS_SUCCESS,
+        WITHDRAW_STATUS_COMPLETED,
+    },
+    binance_my_trades::TradeRec,
+    binance_sell_rules::get_all_my_trades,
+    common::{create_buf_writer_from_path, VALUE_ASSETS},
+    configuration::Configuration,
+    historical_valuation::symbols_of_assets,
+    token_tax_comment_vers::create_tt_cmt_ver6_string,
+    trade_sync::read_synced_trades,
+};
+
+use time_ms_conversions::{utc_now_to_time_ms, utc_to_time_ms};
+
+/// The fiat currency of the fiat history
+const FIAT_CURRENCY: &str = "USD";
+
+/// Status of a fiat deposit or withdrawal that completed
+pub const FIAT_STATUS_SUCCESSFUL: &str = "Successful";
+
+fn empty_ttr(type_txs: TokenTaxRecType, exchange: &str, time: i64, comment: String) -> TokenTaxRec {
+    TokenTaxRec {
+        type_txs,
+        buy_amount: None,
+        buy_currency: "".to_owned(),
+        sell_amount: None,
+        sell_currency: "".to_owned(),
+        fee_amount: None,
+        fee_currency: "".to_owned(),
+        exchange: exchange.to_owned(),
+        group: None,
+        comment,
+        time,
+    }
+}
+
+pub fn ttr_from_deposit(exchange: &str, dr: &DepositRec) -> TokenTaxRec {
+    let comment =
+        create_tt_cmt_ver6_string("deposit", dr.tx_id.as_deref().unwrap_or(""), &dr.asset);
+    let mut ttr = empty_ttr(TokenTaxRecType::Deposit, exchange, dr.insert_time, comment);
+    ttr.buy_amount = Some(dr.amount);
+    ttr.buy_currency = dr.asset.clone();
+
+    ttr
+}
+
+pub fn ttr_from_withdraw(exchange: &str, wr: &WithdrawRec) -> TokenTaxRec {
+    let comment = create_tt_cmt_ver6_string("withdraw", &wr.id, &wr.asset);
+    let mut ttr = empty_ttr(
+        TokenTaxRecType::Withdrawal,
+        exchange,
+        wr.apply_time,
+        comment,
+    );
+    ttr.sell_amount = Some(wr.amount);
+    ttr.sell_currency = wr.asset.clone();
+    if wr.transaction_fee > dec!(0) {
+        ttr.fee_amount = Some(wr.transaction_fee);
+        ttr.fee_currency = wr.asset.clone();
+    }
+
+    ttr
+}
+
+pub fn ttr_from_trade(exchange: &str, symbol: &Symbol, tr: &TradeRec) -> TokenTaxRec {
+    let comment = create_tt_cmt_ver6_string("trade", &tr.id.to_string(), &tr.symbol);
+    let mut ttr = empty_ttr(TokenTaxRecType::Trade, exchange, tr.time, comment);
+    if tr.is_buyer {
+        ttr.buy_amount = Some(tr.qty);
+        ttr.buy_currency = symbol.base_asset.clone();
+        ttr.sell_amount = Some(tr.quote_qty);
+        ttr.sell_currency = symbol.quote_asset.clone();
+    } else {
+        ttr.buy_amount = Some(tr.quote_qty);
+        ttr.buy_currency = symbol.quote_asset.clone();
+        ttr.sell_amount = Some(tr.qty);
+        ttr.sell_currency = symbol.base_asset.clone();
+    }
+    if tr.commission > dec!(0) {
+        ttr.fee_amount = Some(tr.commission);
+        ttr.fee_currency = tr.commission_asset.clone();
+    }
+
+    ttr
+}
+
+/// Fiat deposits are a Deposit and withdrawals a Withdrawal of
+/// FIAT_CURRENCY, the transaction and platform fees are the fee.
+/// None if it didn't complete or has no time.
+pub fn ttr_from_asset_log(exchange: &str, alr: &AssetLogRec, deposit: bool) -> Option<TokenTaxRec> {
+    if alr.order_status != FIAT_STATUS_SUCCESSFUL {
+        return None;
+    }
+    let time = alr.create_time?;
+
+    let (type_txs, operation) = if deposit {
+        (TokenTaxRecType::Deposit, "fiat-deposit")
+    } else {
+        (TokenTaxRecType::Withdrawal, "fiat-withdraw")
+    };
+    let comment = create_tt_cmt_ver6_string(operation, &alr.order_id, FIAT_CURRENCY);
+    let mut ttr = empty_ttr(type_txs, exchange, time, comment);
+    if deposit {
+        ttr.buy_amount = Some(alr.amount);
+        ttr.buy_currency = FIAT_CURRENCY.to_owned();
+    } else {
+        ttr.sell_amount = Some(alr.amount);
+        ttr.sell_currency = FIAT_CURRENCY.to_owned();
+    }
+    let fee = alr.transaction_fee + alr.platform_fee;
+    if fee > dec!(0) {
+        ttr.fee_amount = Some(fee);
+        ttr.fee_currency = FIAT_CURRENCY.to_owned();
+    }
+
+    Some(ttr)
+}
+
+/// The completed deposits and withdrawals, the fiat history and the
+/// trades of the account between two times.
+#[derive(Debug, Default)]
+pub struct ApiHistory {
+    pub deposits: Vec<DepositRec>,
+    pub withdrawals: Vec<WithdrawRec>,
+    pub fiat_deposits: Vec<AssetLogRec>,
+    pub fiat_withdrawals: Vec<AssetLogRec>,
+    pub trades: Vec<TradeRec>,
+}
+
+/// Get the history of the account between start and end. The trades
+/// are from the sync store if synced is true. Otherwise if symbols is
+/// None the trades of the symbols between the currently held,
+/// deposited, withdrawn and value assets are used.
+pub async fn get_api_history(
+    config: &Configuration,
+    ei: &ExchangeInfo,
+    symbols: Option<Vec<String>>,
+    synced: bool,
+    start_date_time: Option<DateTime<Utc>>,
+    end_date_time: Option<DateTime<Utc>>,
+) -> Result<ApiHistory, Box<dyn std::error::Error>> {
+    let mut history = ApiHistory::default();
+    let mut assets: BTreeSet<String> = VALUE_ASSETS.iter().cloned().collect();
+
+    for dr in get_deposit_history(config, None, None, start_date_time, end_date_time).await? {
+        if dr.status == DEPOSIT_STATUS_SUCCESS {
+            assets.insert(dr.asset.clone());
+            history.deposits.push(dr);
+        }
+    }
+    for wr in get_withdraw_history(config, None, None, start_date_time, end_date_time).await? {
+        if wr.status == WITHDRAW_STATUS_COMPLETED {
+            assets.insert(wr.asset.clone());
+            history.withdrawals.push(wr);
+        }
+    }
+
+    history.fiat_deposits = get_fiat_currency_deposit_history(
+        config,
+        Some(FIAT_CURRENCY),
+        None,
+        None,
+        None,
+        None,
+        start_date_time,
+        end_date_time,
+    )
+    .await?;
+    history.fiat_withdrawals = get_fiat_currency_withdraw_history(
+        config,
+        Some(FIAT_CURRENCY),
+        None,
+        None,
+        None,
+        None,
+        start_date_time,
+        end_date_time,
+    )
+    .await?;
+
+    let mut trades: Vec<TradeRec> = if synced {
+        let mut trades = read_synced_trades(&config.sync_dir)?;
+        if let Some(symbols) = &symbols {
+            trades.retain(|tr| symbols.contains(&tr.symbol));
+        }
+        trades
+    } else {
+        let symbols = match symbols {
+            Some(s) => s,
+            None => {
+                let ai = get_account_info(config, utc_now_to_time_ms()).await?;
+                for b in ai.balances_map.values() {
+                    if b.free + b.locked != dec!(0) {
+                        assets.insert(b.asset.clone());
+                    }
+                }
+                symbols_of_assets(ei, &assets)
+            }
+        };
+        let mut trades: Vec<TradeRec> = Vec::new();
+        for sym_name in &symbols {
+            if config.progress_info {
+                print!("Getting trades of {sym_name}          \r");
+            }
+            trades.append(&mut get_all_my_trades(config, sym_name).await?);
+        }
+        trades
+    };
+
+    let start_time_ms = start_date_time.map_or(i64::MIN, |sdt| utc_to_time_ms(&sdt));
+    let end_time_ms = end_date_time.map_or(i64::MAX, |edt| utc_to_time_ms(&edt));
+    trades.retain(|tr| tr.time >= start_time_ms && tr.time <= end_time_ms);
+    history.trades = trades;
+    trace!(
+        "get_api_history: {} deposits {} withdrawals {} fiat deposits {} fiat withdrawals {} trades",
+        history.deposits.len(),
+        history.withdrawals.len(),
+        history.fiat_deposits.len(),
+        history.fiat_withdrawals.len(),
+        history.trades.len()
+    );
+
+    Ok(history)
+}
+
+/// Get the history of the account between start and end as
+/// TokenTaxRecs sorted by time, see get_api_history.
+pub async fn token_tax_recs_from_api(
+    config: &Configuration,
+    ei: &ExchangeInfo,
+    symbols: Option<Vec<String>>,
+    synced: bool,
+    start_date_time: Option<DateTime<Utc>>,
+    end_date_time: Option<DateTime<Utc>>,
+) -> Result<Vec<TokenTaxRec>, Box<dyn std::error::Error>> {
+    let exchange = config.domain.as_str();
+    let history =
+        get_api_history(config, ei, symbols, synced, start_date_time, end_date_time).await?;
+
+    let mut recs: Vec<TokenTaxRec> = Vec::new();
+    for dr in &history.deposits {
+        recs.push(ttr_from_deposit(exchange, dr));
+    }
+    for wr in &history.withdrawals {
+        recs.push(ttr_from_withdraw(exchange, wr));
+    }
+
+    let mut no_time_count = 0;
+    let fiat_recs = history
+        .fiat_deposits
+        .iter()
+        .map(|alr| (alr, true))
+        .chain(history.fiat_withdrawals.iter().map(|alr| (alr, false)));
+    for (alr, deposit) in fiat_recs {
+        match ttr_from_asset_log(exchange, alr, deposit) {
+            Some(ttr) => recs.push(ttr),
+            None if alr.order_status == FIAT_STATUS_SUCCESSFUL => no_time_count += 1,
+            None => (),
+        }
+    }
+    if no_time_count > 0 {
+        println!(
+            "WARNING {no_time_count} fiat deposits and withdrawals have no time and were skipped"
+        );
+    }
+
+    for tr in &history.trades {
+        let symbol = match ei.get_symbol(&tr.symbol) {
+            Some(s) => s,
+            None => return Err(format!("No symbol named {}", tr.symbol).into()),
+        };
+        recs.push(ttr_from_trade(exchange, symbol, tr));
+    }
+
+    recs.sort_by_key(|ttr| ttr.time);
+
+    Ok(recs)
+}
+
+pub async fn tt_file_from_api(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let out_token_tax_path_str = sc_matches
+        .value_of("OUT_FILE")
+        .unwrap_or_else(|| panic!("out-file option is missing"));
+    let out_token_tax_path = Path::new(out_token_tax_path_str);
+
+    let time_ms_offset = time_offset_days_to_time_ms_offset(sc_matches)?;
+    let (start, end) = start_end_date_times(sc_matches)?;
+    let symbols: Option<Vec<String>> = sc_matches
+        .values_of("SYMBOLS")
+        .map(|v| v.map(|s| s.to_string()).collect());
+    let synced = sc_matches.is_present("SYNCED");
+
+    println!("Get history");
+    let ei = get_exchange_info(config).await?;
+    let mut recs = token_tax_recs_from_api(config, &ei, symbols, synced, start, end).await?;
+    if let Some(offset) = time_ms_offset {
+        for ttr in recs.iter_mut() {
+            ttr.time += offset;
+        }
+    }
+
+    println!("Writing token tax records");
+    let writer = create_buf_writer_from_path(out_token_tax_path)?;
+    let mut token_tax_writer = csv::Writer::from_writer(writer);
+    println!("Output token tax recs: len={}", recs.len());
+    for ttr in recs {
+        token_tax_writer.serialize(ttr)?;
+    }
+    token_tax_writer.flush()?;
+    println!("Output token tax recs: Done");
+
+    println!();
+    println!("Done");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{eth_usd, trade_rec};
+
+    #[test]
+    fn test_ttr_from_trade() {
+        let symbol = eth_usd();
+
+        let ttr = ttr_from_trade(
+            "binance.us",
+            &symbol,
+            &trade_rec(true, dec!(2), dec!(3000), 1),
+        );
+        assert!(matches!(ttr.type_txs, TokenTaxRecType::Trade));
+        assert_eq!(ttr.buy_amount, Some(dec!(2)));
+        assert_eq!(ttr.buy_currency, "ETH");
+        assert_eq!(ttr.sell_amount, Some(dec!(3000)));
+        assert_eq!(ttr.sell_currency, "USD");
+        assert_eq!(ttr.fee_amount, Some(dec!(0.01)));
+        assert_eq!(ttr.fee_currency, "BNB");
+        assert_eq!(ttr.exchange, "binance.us");
+        assert_eq!(ttr.comment, "v6,trade,1,ETHUSD");
+        assert_eq!(ttr.time, 1);
+
+        let ttr = ttr_from_trade(
+            "binance.us",
+            &symbol,
+            &trade_rec(false, dec!(2), dec!(3000), 1),
+        );
+        assert_eq!(ttr.buy_amount, Some(dec!(3000)));
+        assert_eq!(ttr.buy_currency, "USD");
+        assert_eq!(ttr.sell_amount, Some(dec!(2)));
+        assert_eq!(ttr.sell_currency, "ETH");
+    }
+
+    #[test]
+    fn test_ttr_from_deposit_and_withdraw() {
+        let dr: DepositRec = serde_json::from_str(
+            r#"{"insertTime":1000,"amount":"0.5","address":"","asset":"BTC","status":1,"txId":"abc"}"#,
+        )
+        .unwrap();
+        let ttr = ttr_from_deposit("binance.us", &dr);
+        assert!(matches!(ttr.type_txs, TokenTaxRecType::Deposit));
+        assert_eq!(ttr.buy_amount, Some(dec!(0.5)));
+        assert_eq!(ttr.buy_currency, "BTC");
+        assert!(ttr.sell_amount.is_none());
+        assert!(ttr.fee_amount.is_none());
+        assert_eq!(ttr.comment, "v6,deposit,abc,BTC");
+        assert_eq!(ttr.time, 1000);
+
+        let wr: WithdrawRec = serde_json::from_str(
+            r#"{"amount":"0.5","transactionFee":"0.01","address":"0x1","id":"w1","asset":"ETH","applyTime":4000,"status":6,"network":"ETH"}"#,
+        )
+        .unwrap();
+        let ttr = ttr_from_withdraw("binance.us", &wr);
+        assert!(matches!(ttr.type_txs, TokenTaxRecType::Withdrawal));
+        assert!(ttr.buy_amount.is_none());
+        assert_eq!(ttr.sell_amount, Some(dec!(0.5)));
+        assert_eq!(ttr.sell_currency, "ETH");
+        assert_eq!(ttr.fee_amount, Some(dec!(0.01)));
+        assert_eq!(ttr.fee_currency, "ETH");
+        assert_eq!(ttr.comment, "v6,withdraw,w1,ETH");
+        assert_eq!(ttr.time, 4000);
+    }
+
+    #[test]
+    fn test_ttr_from_asset_log() {
+        let alr: AssetLogRec = serde_json::from_str(
+            r#"{"orderId":"o1","paymentChannel":"PRIMETRUST","paymentMethod":"CREDIT_CARD","orderStatus":"Successful","amount":"95.5","transactionFee":"4.5","platformFee":"0","createTime":5000}"#,
+        )
+        .unwrap();
+        let ttr = ttr_from_asset_log("binance.us", &alr, true).unwrap();
+        assert!(matches!(ttr.type_txs, TokenTaxRecType::Deposit));
+        assert_eq!(ttr.buy_amount, Some(dec!(95.5)));
+        assert_eq!(ttr.buy_currency, "USD");
+        assert_eq!(ttr.fee_amount, Some(dec!(4.5)));
+        assert_eq!(ttr.fee_currency, "USD");
+        assert_eq!(ttr.comment, "v6,fiat-deposit,o1,USD");
+        assert_eq!(ttr.time, 5000);
+
+        let ttr = ttr_from_asset_log("binance.us", &alr, false).unwrap();
+        assert!(matches!(ttr.type_txs, TokenTaxRecType::Withdrawal));
+        assert_eq!(ttr.sell_amount, Some(dec!(95.5)));
+        assert_eq!(ttr.sell_currency, "USD");
+
+        // Not completed or no time
+        let alr: AssetLogRec = serde_json::from_str(
+            r#"{"orderId":"o2","paymentMethod":"WIRE","orderStatus":"Processing","amount":"65","transactionFee":"20","platformFee":"0","createTime":5000}"#,
+        )
+        .unwrap();
+        assert!(ttr_from_asset_log("binance.us", &alr, true).is_none());
+        let alr: AssetLogRec = serde_json::from_str(
+            r#"{"orderId":"o3","paymentMethod":"WIRE","orderStatus":"Successful","amount":"450","transactionFee":"0","platformFee":"0"}"#,
+        )
+        .unwrap();
+        assert!(ttr_from_asset_log("binance.us", &alr, true).is_none());
+    }
+
+    #[test]
+    fn test_tt_csv_round_trip() {
+        // The records are read back as cttf and tbffttf read them
+        let ttr = ttr_from_trade(
+            "binance.us",
+            &eth_usd(),
+            &trade_rec(true, dec!(2), dec!(3000), 1640995200000),
+        );
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(&ttr).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let recs: Vec<TokenTaxRec> = rdr.deserialize().map(|r| r.unwrap()).collect();
+        assert_eq!(recs.len(), 1);
+        assert_eq!(recs[0].buy_amount, Some(dec!(2)));
+        assert_eq!(recs[0].sell_currency, "USD");
+        assert_eq!(recs[0].fee_amount, Some(dec!(0.01)));
+        assert_eq!(recs[0].comment, "v6,trade,1,ETHUSD");
+        assert_eq!(recs[0].time, 1640995200000);
+    }
+}
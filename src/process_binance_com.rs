@@ -120,24 +120,30 @@ impl PartialEq for TradeRec {
 
 impl PartialOrd for TradeRec {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match self.user_id.partial_cmp(&other.user_id) {
-            Some(core::cmp::Ordering::Equal) => {}
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TradeRec {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match self.user_id.cmp(&other.user_id) {
+            core::cmp::Ordering::Equal => {}
             ord => return ord,
         }
-        match self.time.partial_cmp(&other.time) {
-            Some(core::cmp::Ordering::Equal) => {}
+        match self.time.cmp(&other.time) {
+            core::cmp::Ordering::Equal => {}
             ord => return ord,
         }
-        match self.account.partial_cmp(&other.account) {
-            Some(core::cmp::Ordering::Equal) => {}
+        match self.account.cmp(&other.account) {
+            core::cmp::Ordering::Equal => {}
             ord => return ord,
         }
-        match self.operation.partial_cmp(&other.operation) {
-            Some(core::cmp::Ordering::Equal) => {}
+        match self.operation.cmp(&other.operation) {
+            core::cmp::Ordering::Equal => {}
             ord => return ord,
         }
-        match self.coin.partial_cmp(&other.coin) {
-            Some(core::cmp::Ordering::Equal) => {}
+        match self.coin.cmp(&other.coin) {
+            core::cmp::Ordering::Equal => {}
             ord => return ord,
         }
         // I may not want to do this here and instead do it when converting?
@@ -150,29 +156,20 @@ impl PartialOrd for TradeRec {
                 // positive numbers and closer to 0 is considered smaller.
                 // This allows Buy, Fee and Transaction Related operation
                 // to be sorted in the same order.
-                match self.change.abs().partial_cmp(&other.change.abs()) {
-                    Some(core::cmp::Ordering::Equal) => {}
+                match self.change.abs().cmp(&other.change.abs()) {
+                    core::cmp::Ordering::Equal => {}
                     ord => return ord,
                 }
             }
             _ => {
                 // Sort normally
-                match self.change.partial_cmp(&other.change) {
-                    Some(core::cmp::Ordering::Equal) => {}
+                match self.change.cmp(&other.change) {
+                    core::cmp::Ordering::Equal => {}
                     ord => return ord,
                 }
             }
         }
-        self.remark.partial_cmp(&other.remark)
-    }
-}
-
-impl Ord for TradeRec {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match self.partial_cmp(other) {
-            Some(ord) => ord,
-            None => panic!("WTF"),
-        }
+        self.remark.cmp(&other.remark)
     }
 }
 
@@ -1701,7 +1698,7 @@ mod test {
         //123456789,2020-12-26 18:36:01,Spot,Fee,BNB,-0.00255590,"" //first Trade
         //println!("csv: {csv:?}");
 
-        let expected = vec![
+        let expected = [
             TokenTaxRec::from(
                 TokenTaxRecType::Income,
                 Some(dec!(1.71544)),
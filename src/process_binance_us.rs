@@ -23,7 +23,7 @@
 //!  * Some Category == Distribution records have an empty
 //!    Realized_Amount_For_Primary_Asset_In_USD_Value field that is empty.
 //!    Such as:
-//!      12345678,2021-12-31 00:07:03.819,Distribution,Referral Commission,88367941,880499527,SUSHI,0.00224,"","","","","","","","","","",Wallet,"",""
+//!    12345678,2021-12-31 00:07:03.819,Distribution,Referral Commission,88367941,880499527,SUSHI,0.00224,"","","","","","","","","","",Wallet,"",""
 //!    So for these I need to "lookup and calcuate" the Realized_Amount_For_Primary_Asset_In_USD_Value.
 //!
 
@@ -310,7 +310,7 @@ impl AssetRec {
                             "Referral Rewards" => state = State::UpdatingDistributionReferral,
                             "Staking Rewards" => state = State::UpdatingDistributionStaking,
                             "Others" => state = State::UpdatingDistributionOthers,
-                            _ => panic!("Unknown operation: {}", &dr.operation),
+                            _ => panic!("Unknown operation: {}", dr.operation),
                         }
 
                         time_of_next_consolidation_window = next_window(dr);
@@ -1813,10 +1813,10 @@ Time
 
     #[test]
     fn test_serialize_to_csv() {
-        let trs = vec![TimeRec { time: 0 }, TimeRec { time: 123 }];
+        let trs = [TimeRec { time: 0 }, TimeRec { time: 123 }];
 
         let mut wtr = csv::Writer::from_writer(vec![]);
-        wtr.serialize(trs.get(0)).expect("Error serializing");
+        wtr.serialize(trs.first()).expect("Error serializing");
         wtr.serialize(trs.get(1)).expect("Error serializing");
 
         let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
@@ -1914,7 +1914,7 @@ Time
         let mut reader = csv::Reader::from_reader(rdr);
 
         let mut wtr = csv::Writer::from_writer(vec![]);
-        for (_idx, entry) in reader.deserialize().enumerate() {
+        for entry in reader.deserialize() {
             //println!("{_idx}: entry: {:?}", entry);
             let record: DistRec = entry.unwrap();
             wtr.serialize(record).expect("Error serializing");
@@ -81,7 +81,7 @@ impl AssetRec {
 
         self.ttr_vec.sort();
 
-        if let Some(first_ttr) = self.ttr_vec.get(0) {
+        if let Some(first_ttr) = self.ttr_vec.first() {
             let mut first_dt = DateTimeUtc::from_utc_time_ms(first_ttr.time);
             let mut start_period = first_dt.beginning_of_this_month();
             let mut end_period = start_period.beginning_of_next_month();
@@ -742,7 +742,7 @@ pub async fn uniq_currency_token_tax_files(
     );
     for ar in &mut asset_rec_map.bt.values() {
         let mut was_pushed = false;
-        if let Some(rec) = ar.ttr_vec.get(0) {
+        if let Some(rec) = ar.ttr_vec.first() {
             // We have rec, but lets prefer an Income rec as they are simple
             for ttr in ar.ttr_vec.iter() {
                 if ttr.type_txs == TokenTaxRecType::Income {
@@ -0,0 +1,596 @@
+//! Reconcile exported history files with the api history.
+//!
+//! Both sides are reduced to movements, a signed quantity of one asset,
+//! which are matched by order id, then transaction id and finally by
+//! time and amount within a tolerance. Rows of the files that are not
+//! part of the api history, such as distributions, are skipped.
+//!
+use clap::ArgMatches;
+use log::trace;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    arg_matches::{start_end_date_times, time_offset_days_to_time_ms_offset},
+    binance_exchange_info::{get_exchange_info, ExchangeInfo},
+    common::{create_buf_reader, verify_input_files_exist},
+    configuration::Configuration,
+    process_binance_api::{get_api_history, ApiHistory, FIAT_STATUS_SUCCESSFUL},
+    process_binance_com::TradeRec as ComTradeRec,
+    process_binance_us::DistRec,
+};
+
+use dec_utils::dec_to_separated_string;
+use time_ms_conversions::{time_ms_to_utc, utc_to_time_ms};
+
+/// A signed quantity of an asset added to or removed from the account
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movement {
+    pub source: String,
+    pub time: i64,
+    pub asset: String,
+    pub quantity: Decimal,
+    pub order_id: Option<String>,
+    pub transaction_id: Option<String>,
+}
+
+impl fmt::Display for Movement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:<6} {:>20} {}",
+            time_ms_to_utc(self.time),
+            self.asset,
+            dec_to_separated_string(self.quantity, 8),
+            self.source
+        )
+    }
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s.to_owned())
+    }
+}
+
+/// Append a movement of quantity, zero quantities are ignored
+fn push_movement(
+    movements: &mut Vec<Movement>,
+    template: &Movement,
+    asset: &str,
+    quantity: Decimal,
+) {
+    if quantity != dec!(0) {
+        movements.push(Movement {
+            asset: asset.to_owned(),
+            quantity,
+            ..template.clone()
+        });
+    }
+}
+
+/// The movements of a binance.us distribution record, None if the
+/// category isn't part of the api history.
+pub fn movements_from_dist_rec(source: &str, dr: &DistRec) -> Option<Vec<Movement>> {
+    let template = Movement {
+        source: source.to_owned(),
+        time: dr.time,
+        asset: "".to_owned(),
+        quantity: dec!(0),
+        order_id: non_empty(&dr.order_id),
+        transaction_id: Some(dr.transaction_id.to_string()),
+    };
+    let amount = |a: Option<Decimal>| a.unwrap_or_default().abs();
+
+    let mut movements: Vec<Movement> = Vec::new();
+    match (dr.category.as_str(), dr.operation.as_str()) {
+        ("Spot Trading", "Buy") => {
+            let base = amount(dr.realized_amount_for_base_asset);
+            let quote = amount(dr.realized_amount_for_quote_asset);
+            push_movement(&mut movements, &template, &dr.base_asset, base);
+            push_movement(&mut movements, &template, &dr.quote_asset, -quote);
+        }
+        ("Spot Trading", "Sell") => {
+            let base = amount(dr.realized_amount_for_base_asset);
+            let quote = amount(dr.realized_amount_for_quote_asset);
+            push_movement(&mut movements, &template, &dr.base_asset, -base);
+            push_movement(&mut movements, &template, &dr.quote_asset, quote);
+        }
+        ("Deposit", _) => {
+            let primary = amount(dr.realized_amount_for_primary_asset);
+            push_movement(&mut movements, &template, &dr.primary_asset, primary);
+        }
+        ("Withdrawal", _) => {
+            let primary = amount(dr.realized_amount_for_primary_asset);
+            push_movement(&mut movements, &template, &dr.primary_asset, -primary);
+        }
+        _ => return None,
+    }
+    let fee = amount(dr.realized_amount_for_fee_asset);
+    push_movement(&mut movements, &template, &dr.fee_asset, -fee);
+
+    Some(movements)
+}
+
+/// The movement of a binance.com trade history record, None if the
+/// operation isn't part of the api history. These have no ids.
+pub fn movement_from_com_trade_rec(source: &str, tr: &ComTradeRec) -> Option<Movement> {
+    match (tr.account.as_str(), tr.operation.as_str()) {
+        ("Spot", "Buy")
+        | ("Spot", "Sell")
+        | ("Spot", "Transaction Related")
+        | ("Spot", "Fee")
+        | ("Spot", "Deposit")
+        | ("Spot", "Withdraw") => Some(Movement {
+            source: source.to_owned(),
+            time: tr.time,
+            asset: tr.coin.clone(),
+            quantity: tr.change,
+            order_id: None,
+            transaction_id: None,
+        }),
+        _ => None,
+    }
+}
+
+/// The movements of the api history
+pub fn movements_from_api(
+    ei: &ExchangeInfo,
+    history: &ApiHistory,
+) -> Result<Vec<Movement>, Box<dyn std::error::Error>> {
+    let mut movements: Vec<Movement> = Vec::new();
+
+    for tr in &history.trades {
+        let symbol = match ei.get_symbol(&tr.symbol) {
+            Some(s) => s,
+            None => return Err(format!("No symbol named {}", tr.symbol).into()),
+        };
+        let template = Movement {
+            source: format!("trade {} {}", tr.symbol, tr.id),
+            time: tr.time,
+            asset: "".to_owned(),
+            quantity: dec!(0),
+            order_id: Some(tr.order_id.to_string()),
+            transaction_id: Some(tr.id.to_string()),
+        };
+        let sign = if tr.is_buyer { dec!(1) } else { dec!(-1) };
+        push_movement(&mut movements, &template, &symbol.base_asset, sign * tr.qty);
+        push_movement(
+            &mut movements,
+            &template,
+            &symbol.quote_asset,
+            -sign * tr.quote_qty,
+        );
+        push_movement(
+            &mut movements,
+            &template,
+            &tr.commission_asset,
+            -tr.commission,
+        );
+    }
+
+    for dr in &history.deposits {
+        let template = Movement {
+            source: format!("deposit {}", dr.tx_id.as_deref().unwrap_or("")),
+            time: dr.insert_time,
+            asset: "".to_owned(),
+            quantity: dec!(0),
+            order_id: None,
+            transaction_id: dr.tx_id.clone(),
+        };
+        push_movement(&mut movements, &template, &dr.asset, dr.amount);
+    }
+
+    for wr in &history.withdrawals {
+        let template = Movement {
+            source: format!("withdraw {}", wr.id),
+            time: wr.apply_time,
+            asset: "".to_owned(),
+            quantity: dec!(0),
+            order_id: Some(wr.id.clone()),
+            transaction_id: wr.tx_id.clone(),
+        };
+        push_movement(&mut movements, &template, &wr.asset, -wr.amount);
+        push_movement(&mut movements, &template, &wr.asset, -wr.transaction_fee);
+    }
+
+    let fiat_recs = history
+        .fiat_deposits
+        .iter()
+        .map(|alr| (alr, dec!(1)))
+        .chain(history.fiat_withdrawals.iter().map(|alr| (alr, dec!(-1))));
+    for (alr, sign) in fiat_recs {
+        if alr.order_status != FIAT_STATUS_SUCCESSFUL {
+            continue;
+        }
+        let template = Movement {
+            source: format!("fiat {}", alr.order_id),
+            time: alr.create_time.unwrap_or_default(),
+            asset: "".to_owned(),
+            quantity: dec!(0),
+            order_id: Some(alr.order_id.clone()),
+            transaction_id: None,
+        };
+        // The fees are charged in addition to the amount
+        let fee = alr.transaction_fee + alr.platform_fee;
+        push_movement(&mut movements, &template, "USD", sign * alr.amount);
+        push_movement(&mut movements, &template, "USD", -fee);
+    }
+
+    movements.sort_by_key(|m| m.time);
+
+    Ok(movements)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchedBy {
+    OrderId,
+    TransactionId,
+    TimeAmount,
+}
+
+#[derive(Debug)]
+pub struct Mismatch {
+    pub by: MatchedBy,
+    pub file: Movement,
+    pub api: Movement,
+}
+
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    pub matched: usize,
+    pub mismatches: Vec<Mismatch>,
+    pub missing_from_api: Vec<Movement>,
+    pub missing_from_files: Vec<Movement>,
+    /// asset: (files quantity, api quantity) of assets that differ
+    pub net_differences: BTreeMap<String, (Decimal, Decimal)>,
+}
+
+/// Index of the unused api movement of the same asset and direction as
+/// fm that matches by, the closest in amount then time.
+fn best_match(
+    fm: &Movement,
+    api: &[Movement],
+    used: &[bool],
+    by: MatchedBy,
+    time_tolerance_ms: i64,
+    amount_tolerance: Decimal,
+) -> Option<usize> {
+    api.iter()
+        .enumerate()
+        .filter(|(i, am)| {
+            !used[*i]
+                && am.asset == fm.asset
+                && am.quantity.is_sign_negative() == fm.quantity.is_sign_negative()
+        })
+        .filter(|(_, am)| match by {
+            MatchedBy::OrderId => fm.order_id.is_some() && fm.order_id == am.order_id,
+            MatchedBy::TransactionId => {
+                fm.transaction_id.is_some() && fm.transaction_id == am.transaction_id
+            }
+            MatchedBy::TimeAmount => {
+                (am.time - fm.time).abs() <= time_tolerance_ms
+                    && (am.quantity - fm.quantity).abs() <= amount_tolerance
+            }
+        })
+        .min_by_key(|(_, am)| ((am.quantity - fm.quantity).abs(), (am.time - fm.time).abs()))
+        .map(|(i, _)| i)
+}
+
+/// Match the movements of the files with those of the api. A duplicated
+/// file row has nothing left to match so it's missing from the api.
+pub fn reconcile(
+    files: &[Movement],
+    api: &[Movement],
+    time_tolerance_ms: i64,
+    amount_tolerance: Decimal,
+) -> ReconcileReport {
+    let mut report = ReconcileReport::default();
+    let mut file_used = vec![false; files.len()];
+    let mut api_used = vec![false; api.len()];
+
+    // Match by ids first so a time match can't take an api movement
+    // that a later file movement matches by id
+    for by in [
+        MatchedBy::OrderId,
+        MatchedBy::TransactionId,
+        MatchedBy::TimeAmount,
+    ] {
+        for (fi, fm) in files.iter().enumerate() {
+            if file_used[fi] {
+                continue;
+            }
+            if let Some(ai) =
+                best_match(fm, api, &api_used, by, time_tolerance_ms, amount_tolerance)
+            {
+                file_used[fi] = true;
+                api_used[ai] = true;
+                if (api[ai].quantity - fm.quantity).abs() <= amount_tolerance {
+                    report.matched += 1;
+                } else {
+                    report.mismatches.push(Mismatch {
+                        by,
+                        file: fm.clone(),
+                        api: api[ai].clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    for (fi, fm) in files.iter().enumerate() {
+        if !file_used[fi] {
+            report.missing_from_api.push(fm.clone());
+        }
+    }
+    for (ai, am) in api.iter().enumerate() {
+        if !api_used[ai] {
+            report.missing_from_files.push(am.clone());
+        }
+    }
+
+    let mut nets: BTreeMap<String, (Decimal, Decimal)> = BTreeMap::new();
+    for fm in files {
+        nets.entry(fm.asset.clone()).or_default().0 += fm.quantity;
+    }
+    for am in api {
+        nets.entry(am.asset.clone()).or_default().1 += am.quantity;
+    }
+    nets.retain(|_, (f, a)| (*f - *a).abs() > amount_tolerance);
+    report.net_differences = nets;
+
+    report
+}
+
+/// Read the movements of binance.us distribution and binance.com trade
+/// history files, the kind of each file is determined by its header.
+/// Returns the movements and the count of rows not in the api history.
+pub fn read_movement_files(
+    in_file_paths: &[&str],
+    time_ms_offset: Option<i64>,
+) -> Result<(Vec<Movement>, usize), Box<dyn std::error::Error>> {
+    verify_input_files_exist(in_file_paths)?;
+
+    let mut movements: Vec<Movement> = Vec::new();
+    let mut skipped = 0usize;
+    for f in in_file_paths {
+        let reader = create_buf_reader(f)?;
+        let mut rdr = csv::Reader::from_reader(reader);
+        let headers = rdr.headers()?.clone();
+        let is_dist = headers.iter().any(|h| h == "Transaction_Id");
+        let is_com = headers.iter().any(|h| h == "UTC_Time");
+        if !is_dist && !is_com {
+            return Err(format!(
+                "{f} is not a binance.us distribution or binance.com trade history file"
+            )
+            .into());
+        }
+
+        for (rec_idx, result) in rdr.records().enumerate() {
+            let source = format!("{f}:{}", rec_idx + 2);
+            let record = result?;
+            let mut file_movements = if is_dist {
+                let dr: DistRec = record.deserialize(Some(&headers))?;
+                movements_from_dist_rec(&source, &dr)
+            } else {
+                let tr: ComTradeRec = record.deserialize(Some(&headers))?;
+                movement_from_com_trade_rec(&source, &tr).map(|m| vec![m])
+            };
+            match file_movements.as_mut() {
+                Some(fms) => {
+                    if let Some(offset) = time_ms_offset {
+                        for fm in fms.iter_mut() {
+                            fm.time += offset;
+                        }
+                    }
+                    movements.append(fms);
+                }
+                None => skipped += 1,
+            }
+        }
+    }
+    movements.sort_by_key(|m| m.time);
+
+    Ok((movements, skipped))
+}
+
+pub async fn reconcile_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let in_file_paths: Vec<&str> = sc_matches
+        .values_of("IN_FILES")
+        .expect("files option is missing")
+        .collect();
+    let time_ms_offset = time_offset_days_to_time_ms_offset(sc_matches)?;
+    let symbols: Option<Vec<String>> = sc_matches
+        .values_of("SYMBOLS")
+        .map(|v| v.map(|s| s.to_string()).collect());
+    let synced = sc_matches.is_present("SYNCED");
+    let time_tolerance_ms = match sc_matches.value_of("TIME_TOLERANCE") {
+        Some(s) => match s.parse::<i64>() {
+            Ok(secs) => secs * 1000,
+            Err(e) => return Err(format!("time-tolerance {s} is invalid: {e}").into()),
+        },
+        None => 60 * 1000,
+    };
+    let amount_tolerance = match sc_matches.value_of("AMOUNT_TOLERANCE") {
+        Some(s) => match Decimal::from_str(s) {
+            Ok(d) => d,
+            Err(e) => return Err(format!("amount-tolerance {s} is invalid: {e}").into()),
+        },
+        None => dec!(0.00000001),
+    };
+
+    println!("Read files");
+    let (mut file_movements, skipped) = read_movement_files(&in_file_paths, time_ms_offset)?;
+
+    // The period is that of the files unless given
+    let (mut start, mut end) = start_end_date_times(sc_matches)?;
+    if let Some(sdt) = start {
+        let start_time_ms = utc_to_time_ms(&sdt);
+        let end_time_ms = end.map_or(i64::MAX, |edt| utc_to_time_ms(&edt));
+        file_movements.retain(|m| m.time >= start_time_ms && m.time <= end_time_ms);
+    } else if let (Some(first), Some(last)) = (file_movements.first(), file_movements.last()) {
+        start = Some(time_ms_to_utc(first.time - time_tolerance_ms));
+        end = Some(time_ms_to_utc(last.time + time_tolerance_ms));
+    }
+    trace!("reconcile_cmd: start: {start:?} end: {end:?}");
+
+    println!("Get history");
+    let ei = get_exchange_info(config).await?;
+    let history = get_api_history(config, &ei, symbols, synced, start, end).await?;
+    let api_movements = movements_from_api(&ei, &history)?;
+
+    let report = reconcile(
+        &file_movements,
+        &api_movements,
+        time_tolerance_ms,
+        amount_tolerance,
+    );
+
+    println!();
+    if let (Some(s), Some(e)) = (start, end) {
+        println!("Period: {s} to {e}");
+    }
+    println!(
+        "Movements: files {} api {}, skipped {skipped} file rows not in the api history",
+        file_movements.len(),
+        api_movements.len()
+    );
+    println!("Matched: {}", report.matched);
+
+    println!();
+    println!("Amount mismatches: {}", report.mismatches.len());
+    for mm in &report.mismatches {
+        println!("  {:?}", mm.by);
+        println!("    files: {}", mm.file);
+        println!("    api:   {}", mm.api);
+    }
+
+    println!();
+    println!("Missing from the api: {}", report.missing_from_api.len());
+    for m in &report.missing_from_api {
+        println!("  {m}");
+    }
+
+    println!();
+    println!(
+        "Missing from the files: {}",
+        report.missing_from_files.len()
+    );
+    for m in &report.missing_from_files {
+        println!("  {m}");
+    }
+
+    println!();
+    println!("Net quantity differences: {}", report.net_differences.len());
+    if !report.net_differences.is_empty() {
+        let col_1 = 6;
+        let col_2 = 20;
+        println!(
+            "  {:<col_1$} {:>col_2$} {:>col_2$} {:>col_2$}",
+            "Asset", "Files", "Api", "Difference"
+        );
+        for (asset, (f, a)) in &report.net_differences {
+            println!(
+                "  {:<col_1$} {:>col_2$} {:>col_2$} {:>col_2$}",
+                asset,
+                dec_to_separated_string(*f, 8),
+                dec_to_separated_string(*a, 8),
+                dec_to_separated_string(*f - *a, 8),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn movement(
+        source: &str,
+        time: i64,
+        asset: &str,
+        quantity: Decimal,
+        order_id: Option<&str>,
+    ) -> Movement {
+        Movement {
+            source: source.to_owned(),
+            time,
+            asset: asset.to_owned(),
+            quantity,
+            order_id: order_id.map(|s| s.to_owned()),
+            transaction_id: None,
+        }
+    }
+
+    #[test]
+    fn test_movements_from_dist_rec() {
+        let csv = "User_Id,Time,Category,Operation,Order_Id,Transaction_Id,Primary_Asset,Realized_Amount_For_Primary_Asset,Realized_Amount_For_Primary_Asset_In_USD_Value,Base_Asset,Realized_Amount_For_Base_Asset,Realized_Amount_For_Base_Asset_In_USD_Value,Quote_Asset,Realized_Amount_For_Quote_Asset,Realized_Amount_For_Quote_Asset_In_USD_Value,Fee_Asset,Realized_Amount_For_Fee_Asset,Realized_Amount_For_Fee_Asset_In_USD_Value,Payment_Method,Withdrawal_Method,Additional_Note
+12345678,2021-01-29T02:46:52.000+00:00,Spot Trading,Buy,268907674,135260184,,,,ETH,0.5,,USD,650,,BNB,0.001,,Wallet,,
+12345678,2021-02-01T00:00:00.000+00:00,Distribution,Staking Rewards,,135260185,BNB,0.01,,,,,,,,,,,Wallet,,
+";
+        let mut rdr = csv::Reader::from_reader(csv.as_bytes());
+        let drs: Vec<DistRec> = rdr.deserialize().map(|r| r.unwrap()).collect();
+
+        let ms = movements_from_dist_rec("f:2", &drs[0]).unwrap();
+        assert_eq!(ms.len(), 3);
+        assert_eq!(ms[0].asset, "ETH");
+        assert_eq!(ms[0].quantity, dec!(0.5));
+        assert_eq!(ms[0].order_id.as_deref(), Some("268907674"));
+        assert_eq!(ms[0].transaction_id.as_deref(), Some("135260184"));
+        assert_eq!(ms[1].asset, "USD");
+        assert_eq!(ms[1].quantity, dec!(-650));
+        assert_eq!(ms[2].asset, "BNB");
+        assert_eq!(ms[2].quantity, dec!(-0.001));
+
+        // Distributions aren't in the api history
+        assert!(movements_from_dist_rec("f:3", &drs[1]).is_none());
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let files = vec![
+            movement("f:2", 1000, "ETH", dec!(0.5), Some("1")),
+            movement("f:3", 2000, "ETH", dec!(1), Some("2")),
+            // Duplicated row
+            movement("f:4", 2000, "ETH", dec!(1), Some("2")),
+            // No ids, matched by time and amount
+            movement("f:5", 3000, "BTC", dec!(-0.1), None),
+            movement("f:6", 9000, "ADA", dec!(10), None),
+        ];
+        let api = vec![
+            movement("trade 1", 1000, "ETH", dec!(0.5), Some("1")),
+            movement("trade 2", 2000, "ETH", dec!(1.1), Some("2")),
+            movement("withdraw a", 3050, "BTC", dec!(-0.1), Some("a")),
+            movement("trade 3", 5000, "SOL", dec!(3), Some("3")),
+        ];
+
+        let report = reconcile(&files, &api, 60 * 1000, dec!(0.00000001));
+        assert_eq!(report.matched, 2);
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].by, MatchedBy::OrderId);
+        assert_eq!(report.mismatches[0].api.quantity, dec!(1.1));
+        assert_eq!(report.missing_from_api.len(), 2);
+        assert_eq!(report.missing_from_api[0].source, "f:4");
+        assert_eq!(report.missing_from_api[1].source, "f:6");
+        assert_eq!(report.missing_from_files.len(), 1);
+        assert_eq!(report.missing_from_files[0].source, "trade 3");
+        assert_eq!(report.net_differences.len(), 3);
+        assert_eq!(report.net_differences["ETH"], (dec!(2.5), dec!(1.6)));
+        assert_eq!(report.net_differences["ADA"], (dec!(10), dec!(0)));
+        assert_eq!(report.net_differences["SOL"], (dec!(0), dec!(3)));
+
+        // A tight time tolerance leaves the withdrawal unmatched
+        let report = reconcile(&files, &api, 10, dec!(0.00000001));
+        assert_eq!(report.matched, 1);
+        assert_eq!(report.missing_from_api.len(), 3);
+        assert_eq!(report.missing_from_files.len(), 2);
+    }
+}
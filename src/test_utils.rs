@@ -0,0 +1,95 @@
+//! Fixtures shared by the tests of several modules
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+use crate::{binance_exchange_info::Symbol, binance_my_trades::TradeRec};
+
+/// An order book of three bids and three asks
+pub const ORDER_BOOK: &str = r#"{
+    "lastUpdateId": 1027024,
+    "bids": [
+        ["10.00", "1.0"],
+        ["9.50", "2.0"],
+        ["8.00", "5.0"]
+    ],
+    "asks": [
+        ["10.50", "1.0"],
+        ["11.00", "1.0"],
+        ["15.00", "10.0"]
+    ]
+}"#;
+
+/// An ETHUSD trade with a BNB commission of 0.01
+pub fn trade_rec(is_buyer: bool, qty: Decimal, quote_qty: Decimal, time: i64) -> TradeRec {
+    TradeRec {
+        symbol: "ETHUSD".to_string(),
+        id: 1,
+        order_id: 1,
+        order_list_id: -1,
+        price: quote_qty / qty,
+        qty,
+        quote_qty,
+        commission: dec!(0.01),
+        commission_asset: "BNB".to_string(),
+        time,
+        is_buyer,
+        is_maker: false,
+        is_best_match: true,
+    }
+}
+
+/// The ETHUSD symbol without filters
+pub fn eth_usd() -> Symbol {
+    serde_json::from_str(
+        r#"{
+            "symbol": "ETHUSD",
+            "status": "TRADING",
+            "baseAsset": "ETH",
+            "baseAssetPrecision": 8,
+            "quoteAsset": "USD",
+            "quotePrecision": 4,
+            "quoteAssetPrecision": 4,
+            "baseCommissionPrecision": 8,
+            "quoteCommissionPrecision": 2,
+            "orderTypes": ["LIMIT", "MARKET"],
+            "icebergAllowed": true,
+            "ocoAllowed": true,
+            "quoteOrderQtyMarketAllowed": true,
+            "isSpotTradingAllowed": true,
+            "isMarginTradingAllowed": false,
+            "filters": [],
+            "permissions": ["SPOT"]
+        }"#,
+    )
+    .unwrap()
+}
+
+/// A fresh directory under the system temp dir, removed when dropped
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create `<temp_dir>/<name>-test-<pid>`, replacing any left by an earlier run
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("{name}-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
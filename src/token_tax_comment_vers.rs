@@ -5,6 +5,7 @@
 use lazy_static::lazy_static;
 
 use crate::{
+    binance_order_response::DustTransferResultRec,
     process_binance_com::{CommissionRec, TradeRec},
     process_binance_us::DistRec,
 };
@@ -28,6 +29,12 @@ lazy_static! {
 
     pub static ref TT_CMT_VER4_CSV_HEADER: String = "version,FileIdx,LineNumber,OrderId,TransactionId,Category,Operation".to_owned();
     pub static ref TT_CMT_VER4: String = "v4".to_owned();
+
+    pub static ref TT_CMT_VER5_CSV_HEADER: String = "version,TranId,OperateTime,FromAsset,Operation".to_owned();
+    pub static ref TT_CMT_VER5: String = "v5".to_owned();
+
+    pub static ref TT_CMT_VER6_CSV_HEADER: String = "version,Operation,Id,Asset".to_owned();
+    pub static ref TT_CMT_VER6: String = "v6".to_owned();
 }
 
 pub fn create_tt_cmt_ver1_string(bccr: &CommissionRec) -> String {
@@ -60,3 +67,16 @@ pub fn create_tt_cmt_ver4_string(dr: &DistRec) -> String {
         ver, dr.file_idx, dr.line_number, dr.order_id, dr.transaction_id, dr.category, dr.operation
     )
 }
+
+pub fn create_tt_cmt_ver5_string(dtr: &DustTransferResultRec, operation: &str) -> String {
+    let ver = TT_CMT_VER5.as_str();
+    format!(
+        "{ver},{},{},{},{operation}",
+        dtr.tran_id, dtr.operate_time, dtr.from_asset,
+    )
+}
+
+pub fn create_tt_cmt_ver6_string(operation: &str, id: &str, asset: &str) -> String {
+    let ver = TT_CMT_VER6.as_str();
+    format!("{ver},{operation},{id},{asset}")
+}
@@ -0,0 +1,428 @@
+//! Per symbol summary of my trades with realized and unrealized P&L.
+//!
+//! The P&L is in the quote asset of the symbol and doesn't include the
+//! commissions, which are reported separately in USD. Sells of more
+//! than was bought, such as of a deposit, have no cost basis and are
+//! reported as unmatched.
+//!
+use clap::ArgMatches;
+use log::trace;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    binance_exchange_info::{get_exchange_info, Symbol},
+    binance_my_trades::TradeRec,
+    binance_sell_rules::{get_all_my_trades, CostBasis},
+    binance_ticker::{get_ticker_prices, prices_map},
+    binance_trade::convert,
+    configuration::Configuration,
+    trade_sync::read_synced_trades,
+};
+
+use dec_utils::{dec_to_separated_string, dec_to_usd_string};
+
+/// Commissions are converted at the price of this interval
+const COMMISSION_PRICE_INTERVAL_MS: i64 = 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LotMethod {
+    /// Sells close the oldest buys first
+    Fifo,
+
+    /// Sells close the newest buys first
+    Lifo,
+
+    /// Sells close at the average price of the buys
+    Average,
+}
+
+impl LotMethod {
+    pub fn from_string(s: &str) -> Result<LotMethod, Box<dyn std::error::Error>> {
+        match s {
+            "fifo" => Ok(LotMethod::Fifo),
+            "lifo" => Ok(LotMethod::Lifo),
+            "average" => Ok(LotMethod::Average),
+            _ => Err(format!("Unknown lot method, {s}, expecting: fifo, lifo or average").into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+impl SummaryFormat {
+    pub fn from_string(s: &str) -> Result<SummaryFormat, Box<dyn std::error::Error>> {
+        match s {
+            "table" => Ok(SummaryFormat::Table),
+            "csv" => Ok(SummaryFormat::Csv),
+            "json" => Ok(SummaryFormat::Json),
+            _ => Err(format!("Unknown format, {s}, expecting: table, csv or json").into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Lot {
+    qty: Decimal,
+    price: Decimal,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SymbolSummary {
+    pub symbol: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub buy_count: usize,
+    pub buy_qty: Decimal,
+    pub buy_quote_qty: Decimal,
+    pub sell_count: usize,
+    pub sell_qty: Decimal,
+    pub sell_quote_qty: Decimal,
+
+    // Average price of all the buys
+    pub avg_entry_price: Decimal,
+
+    // Quantity and cost of the lots still open
+    pub position_qty: Decimal,
+    pub position_cost: Decimal,
+
+    // Quantity sold with no buy to close
+    pub unmatched_sell_qty: Decimal,
+
+    // P&L before commissions
+    pub realized_pnl: Decimal,
+    pub current_price: Option<Decimal>,
+    pub unrealized_pnl: Option<Decimal>,
+    pub commission_usd: Decimal,
+}
+
+impl SymbolSummary {
+    /// Set the current price and the unrealized P&L of the open lots
+    pub fn set_current_price(&mut self, price: Decimal) {
+        self.current_price = Some(price);
+        self.unrealized_pnl = Some(self.position_qty * price - self.position_cost);
+    }
+}
+
+/// Summarize the trades of symbol, the commission and current price
+/// aren't set. The realized and unrealized P&L of every lot method are
+/// before commissions, which are only in commission_usd.
+pub fn summarize_trades(
+    symbol: &Symbol,
+    trades: &[TradeRec],
+    lot_method: LotMethod,
+) -> SymbolSummary {
+    let mut summary = SymbolSummary {
+        symbol: symbol.symbol.clone(),
+        base_asset: symbol.base_asset.clone(),
+        quote_asset: symbol.quote_asset.clone(),
+        ..Default::default()
+    };
+
+    let mut trades: Vec<&TradeRec> = trades.iter().collect();
+    trades.sort_by_key(|tr| (tr.time, tr.id));
+
+    // The average method uses the cost basis of the sell rules
+    let mut cost_basis = CostBasis::default();
+    let mut lots: VecDeque<Lot> = VecDeque::new();
+    for tr in trades {
+        if tr.is_buyer {
+            summary.buy_count += 1;
+            summary.buy_qty += tr.qty;
+            summary.buy_quote_qty += tr.quote_qty;
+
+            match lot_method {
+                LotMethod::Average => cost_basis.buy(tr.qty, tr.quote_qty, tr.time),
+                _ => lots.push_back(Lot {
+                    qty: tr.qty,
+                    price: tr.price,
+                }),
+            }
+        } else {
+            summary.sell_count += 1;
+            summary.sell_qty += tr.qty;
+            summary.sell_quote_qty += tr.quote_qty;
+
+            if lot_method == LotMethod::Average {
+                let (qty, cost) = cost_basis.sell(tr.qty);
+                summary.realized_pnl += qty * tr.price - cost;
+                summary.unmatched_sell_qty += tr.qty - qty;
+                continue;
+            }
+
+            let mut remaining = tr.qty;
+            while remaining > dec!(0) {
+                let lot = match lot_method {
+                    LotMethod::Fifo => lots.front_mut(),
+                    _ => lots.back_mut(),
+                };
+                let lot = match lot {
+                    Some(lot) => lot,
+                    None => {
+                        summary.unmatched_sell_qty += remaining;
+                        break;
+                    }
+                };
+                let qty = remaining.min(lot.qty);
+                summary.realized_pnl += qty * (tr.price - lot.price);
+                lot.qty -= qty;
+                remaining -= qty;
+                if lot.qty == dec!(0) {
+                    match lot_method {
+                        LotMethod::Fifo => lots.pop_front(),
+                        _ => lots.pop_back(),
+                    };
+                }
+            }
+        }
+    }
+
+    if summary.buy_qty > dec!(0) {
+        summary.avg_entry_price = summary.buy_quote_qty / summary.buy_qty;
+    }
+    for lot in &lots {
+        summary.position_qty += lot.qty;
+        summary.position_cost += lot.qty * lot.price;
+    }
+    summary.position_qty += cost_basis.qty;
+    summary.position_cost += cost_basis.cost;
+
+    summary
+}
+
+/// The USD value of the commissions of the trades. The USD price of a
+/// commission asset is requested once per interval, the cache is shared
+/// between symbols.
+async fn commission_in_usd(
+    config: &Configuration,
+    trades: &[TradeRec],
+    usd_prices: &mut HashMap<(String, i64), Decimal>,
+) -> Result<Decimal, Box<dyn std::error::Error>> {
+    let mut total = dec!(0);
+    for tr in trades.iter().filter(|tr| tr.commission != dec!(0)) {
+        let key = (
+            tr.commission_asset.clone(),
+            tr.time / COMMISSION_PRICE_INTERVAL_MS,
+        );
+        let usd_price = match usd_prices.get(&key) {
+            Some(price) => *price,
+            None => {
+                let price = convert(config, tr.time, &tr.commission_asset, dec!(1), "USD").await?;
+                usd_prices.insert(key, price);
+                price
+            }
+        };
+        total += usd_price * tr.commission;
+    }
+
+    Ok(total)
+}
+
+fn display_table(summaries: &[SymbolSummary]) {
+    let col_1 = 10;
+    let col_2 = 6;
+    let col_3 = 16;
+    let col_4 = 14;
+    println!(
+        "{:<col_1$} {:>col_2$} {:>col_3$} {:>col_2$} {:>col_3$} {:>col_4$} {:>col_3$} {:>col_4$} {:>col_4$} {:>col_4$}",
+        "Symbol",
+        "Buys",
+        "Buy qty",
+        "Sells",
+        "Sell qty",
+        "Avg entry",
+        "Position",
+        "Realized",
+        "Unrealized",
+        "Commission",
+    );
+    let mut total_commission_usd = dec!(0);
+    let mut unmatched: Vec<&str> = Vec::new();
+    for s in summaries {
+        total_commission_usd += s.commission_usd;
+        if s.unmatched_sell_qty > dec!(0) {
+            unmatched.push(&s.symbol);
+        }
+        println!(
+            "{:<col_1$} {:>col_2$} {:>col_3$} {:>col_2$} {:>col_3$} {:>col_4$} {:>col_3$} {:>col_4$} {:>col_4$} {:>col_4$}",
+            s.symbol,
+            s.buy_count,
+            dec_to_separated_string(s.buy_qty, 8),
+            s.sell_count,
+            dec_to_separated_string(s.sell_qty, 8),
+            dec_to_separated_string(s.avg_entry_price, 4),
+            dec_to_separated_string(s.position_qty, 8),
+            dec_to_separated_string(s.realized_pnl, 2),
+            s.unrealized_pnl
+                .map_or("".to_owned(), |u| dec_to_separated_string(u, 2)),
+            dec_to_usd_string(s.commission_usd),
+        );
+    }
+    println!(
+        "P&L is in the quote asset, total commission: {}",
+        dec_to_usd_string(total_commission_usd)
+    );
+    if !unmatched.is_empty() {
+        println!(
+            "WARNING sells without a buy to close, such as of deposits, aren't in the realized P&L of {}",
+            unmatched.join(", ")
+        );
+    }
+}
+
+pub async fn trade_summary_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sym_names: Vec<&str> = sc_matches
+        .values_of("SYMBOLS")
+        .expect("SYMBOLS is missing")
+        .collect();
+    let lot_method = LotMethod::from_string(sc_matches.value_of("LOT_METHOD").unwrap_or("fifo"))?;
+    let format = SummaryFormat::from_string(sc_matches.value_of("FORMAT").unwrap_or("table"))?;
+    let synced = sc_matches.is_present("SYNCED");
+
+    let ei = get_exchange_info(config).await?;
+    let prices = prices_map(&get_ticker_prices(config, None).await?);
+    let synced_trades = if synced {
+        read_synced_trades(&config.sync_dir)?
+    } else {
+        Vec::new()
+    };
+
+    let mut usd_prices: HashMap<(String, i64), Decimal> = HashMap::new();
+    let mut summaries: Vec<SymbolSummary> = Vec::new();
+    for sym_name in sym_names {
+        let symbol = match ei.get_symbol(sym_name) {
+            Some(s) => s,
+            None => return Err(format!("Symbol {sym_name} isn't valid").into()),
+        };
+        let trades: Vec<TradeRec> = if synced {
+            synced_trades
+                .iter()
+                .filter(|tr| tr.symbol == symbol.symbol)
+                .cloned()
+                .collect()
+        } else {
+            get_all_my_trades(config, &symbol.symbol).await?
+        };
+        trace!("trade_summary_cmd: {sym_name} {} trades", trades.len());
+
+        let mut summary = summarize_trades(symbol, &trades, lot_method);
+        summary.commission_usd = commission_in_usd(config, &trades, &mut usd_prices).await?;
+        if let Some(price) = prices.get(&symbol.symbol) {
+            summary.set_current_price(*price);
+        }
+        summaries.push(summary);
+    }
+
+    match format {
+        SummaryFormat::Table => display_table(&summaries),
+        SummaryFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(std::io::stdout());
+            for s in &summaries {
+                wtr.serialize(s)?;
+            }
+            wtr.flush()?;
+        }
+        SummaryFormat::Json => println!("{}", serde_json::to_string_pretty(&summaries)?),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{eth_usd, trade_rec};
+
+    #[test]
+    fn test_summarize_trades() {
+        let trades = vec![
+            trade_rec(true, dec!(1), dec!(1000), 1000),
+            trade_rec(true, dec!(1), dec!(2000), 2000),
+            trade_rec(false, dec!(1.5), dec!(4500), 3000),
+        ];
+
+        let s = summarize_trades(&eth_usd(), &trades, LotMethod::Fifo);
+        assert_eq!(s.buy_count, 2);
+        assert_eq!(s.buy_qty, dec!(2));
+        assert_eq!(s.buy_quote_qty, dec!(3000));
+        assert_eq!(s.sell_count, 1);
+        assert_eq!(s.sell_quote_qty, dec!(4500));
+        assert_eq!(s.avg_entry_price, dec!(1500));
+        // 1 * (3000 - 1000) + 0.5 * (3000 - 2000)
+        assert_eq!(s.realized_pnl, dec!(2500));
+        assert_eq!(s.position_qty, dec!(0.5));
+        assert_eq!(s.position_cost, dec!(1000));
+
+        let s = summarize_trades(&eth_usd(), &trades, LotMethod::Lifo);
+        // 1 * (3000 - 2000) + 0.5 * (3000 - 1000)
+        assert_eq!(s.realized_pnl, dec!(2000));
+        assert_eq!(s.position_cost, dec!(500));
+
+        let mut s = summarize_trades(&eth_usd(), &trades, LotMethod::Average);
+        assert_eq!(s.realized_pnl, dec!(2250));
+        assert_eq!(s.position_cost, dec!(750));
+
+        s.set_current_price(dec!(4000));
+        assert_eq!(s.unrealized_pnl, Some(dec!(1250)));
+    }
+
+    #[test]
+    fn test_summarize_trades_unmatched_sell() {
+        let trades = vec![
+            trade_rec(true, dec!(1), dec!(1000), 1000),
+            trade_rec(false, dec!(3), dec!(4500), 2000),
+        ];
+
+        let s = summarize_trades(&eth_usd(), &trades, LotMethod::Fifo);
+        assert_eq!(s.realized_pnl, dec!(500));
+        assert_eq!(s.unmatched_sell_qty, dec!(2));
+        assert_eq!(s.position_qty, dec!(0));
+
+        let s = summarize_trades(&eth_usd(), &trades, LotMethod::Average);
+        assert_eq!(s.realized_pnl, dec!(500));
+        assert_eq!(s.unmatched_sell_qty, dec!(2));
+        assert_eq!(s.position_qty, dec!(0));
+        assert_eq!(s.position_cost, dec!(0));
+    }
+
+    #[test]
+    fn test_summarize_trades_before_commissions() {
+        let trades = vec![
+            trade_rec(true, dec!(1), dec!(1000), 1000),
+            trade_rec(true, dec!(1), dec!(2000), 2000),
+            trade_rec(false, dec!(1.5), dec!(4500), 3000),
+        ];
+        let with_usd_commissions: Vec<TradeRec> = trades
+            .iter()
+            .map(|tr| TradeRec {
+                commission: dec!(10),
+                commission_asset: "USD".to_string(),
+                ..tr.clone()
+            })
+            .collect();
+
+        for (lot_method, realized_pnl) in [
+            (LotMethod::Fifo, dec!(2500)),
+            (LotMethod::Lifo, dec!(2000)),
+            (LotMethod::Average, dec!(2250)),
+        ] {
+            let s = summarize_trades(&eth_usd(), &with_usd_commissions, lot_method);
+            assert_eq!(s.realized_pnl, realized_pnl);
+            assert_eq!(
+                s.position_cost,
+                summarize_trades(&eth_usd(), &trades, lot_method).position_cost
+            );
+            assert_eq!(s.commission_usd, dec!(0));
+        }
+    }
+}
@@ -0,0 +1,445 @@
+use clap::ArgMatches;
+use log::trace;
+use rust_decimal_macros::dec;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+use tokio::time::{sleep, Duration};
+
+use crate::{
+    binance_account_info::get_account_info,
+    binance_exchange_info::{get_exchange_info, ExchangeInfo},
+    binance_history::{get_deposit_history, get_withdraw_history},
+    binance_my_trades::{get_my_trades, TradeRec},
+    binance_orders::{get_all_orders, OrderRec},
+    binance_trade::order_log_file,
+    common::VALUE_ASSETS,
+    configuration::Configuration,
+    historical_valuation::symbols_of_assets,
+};
+
+use time_ms_conversions::utc_now_to_time_ms;
+
+const TRADES_FILE: &str = "trades.jsonl";
+const ORDERS_FILE: &str = "orders.jsonl";
+const CURSORS_FILE: &str = "cursors.json";
+
+/// Maximum records per myTrades and allOrders request
+const PAGE_LIMIT: i64 = 1000;
+
+/// Where the next sync of each symbol starts
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SyncCursors {
+    // The next trade id
+    #[serde(default)]
+    pub trades: BTreeMap<String, i64>,
+
+    // The next order id, orders that may still change are fetched again
+    #[serde(default)]
+    pub orders: BTreeMap<String, i64>,
+}
+
+impl SyncCursors {
+    pub fn load(dir: &Path) -> Result<SyncCursors, Box<dyn std::error::Error>> {
+        let path = dir.join(CURSORS_FILE);
+        if path.exists() {
+            let data = fs::read_to_string(&path)?;
+            match serde_json::from_str(&data) {
+                Ok(c) => Ok(c),
+                Err(e) => Err(format!("Error processing {}: {e}", path.to_string_lossy()).into()),
+            }
+        } else {
+            Ok(SyncCursors::default())
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(dir)?;
+        let mut writer = fs::File::create(dir.join(CURSORS_FILE))?;
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    pub fn symbols(&self) -> BTreeSet<String> {
+        self.trades
+            .keys()
+            .chain(self.orders.keys())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Orders with these statuses will not change
+pub fn is_final_order_status(status: &str) -> bool {
+    matches!(
+        status,
+        "FILLED" | "CANCELED" | "REJECTED" | "EXPIRED" | "EXPIRED_IN_MATCH"
+    )
+}
+
+/// The cursor after orders, the oldest order that may still change
+/// or the order after the newest.
+pub fn next_order_cursor(orders: &[OrderRec], cursor: i64) -> i64 {
+    match orders
+        .iter()
+        .filter(|o| !is_final_order_status(&o.status))
+        .map(|o| o.order_id)
+        .min()
+    {
+        Some(order_id) => order_id,
+        None => orders
+            .iter()
+            .map(|o| o.order_id + 1)
+            .max()
+            .unwrap_or(cursor),
+    }
+}
+
+fn append_jsonl<T: Serialize>(path: &Path, recs: &[T]) -> Result<(), Box<dyn std::error::Error>> {
+    if recs.is_empty() {
+        return Ok(());
+    }
+    let mut writer = order_log_file(path)?;
+    for rec in recs {
+        serde_json::to_writer(&mut writer, rec)?;
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+fn read_jsonl<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let reader = BufReader::new(fs::File::open(path)?);
+    let mut recs: Vec<T> = Vec::new();
+    for (i, result) in reader.lines().enumerate() {
+        let line = result?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(rec) => recs.push(rec),
+            Err(e) => {
+                let path_string = path.to_string_lossy();
+                return Err(format!("{path_string} line: {} Err: {}", i + 1, e).into());
+            }
+        }
+    }
+
+    Ok(recs)
+}
+
+/// The trades in the store of dir sorted by time
+pub fn read_synced_trades(dir: &Path) -> Result<Vec<TradeRec>, Box<dyn std::error::Error>> {
+    let mut trades: BTreeMap<(String, i64), TradeRec> = BTreeMap::new();
+    for tr in read_jsonl::<TradeRec>(&dir.join(TRADES_FILE))? {
+        trades.insert((tr.symbol.clone(), tr.id), tr);
+    }
+    let mut trades: Vec<TradeRec> = trades.into_values().collect();
+    trades.sort_by_key(|tr| tr.time);
+
+    Ok(trades)
+}
+
+/// The latest version of each order in the store of dir sorted by time
+pub fn read_synced_orders(dir: &Path) -> Result<Vec<OrderRec>, Box<dyn std::error::Error>> {
+    let mut orders: BTreeMap<(String, i64), OrderRec> = BTreeMap::new();
+    for o in read_jsonl::<OrderRec>(&dir.join(ORDERS_FILE))? {
+        orders.insert((o.symbol.clone(), o.order_id), o);
+    }
+    let mut orders: Vec<OrderRec> = orders.into_values().collect();
+    orders.sort_by_key(|o| o.time);
+
+    Ok(orders)
+}
+
+/// Append the trades of symbol from from_id, returns the number of
+/// trades and the next from_id.
+async fn sync_trades(
+    config: &Configuration,
+    dir: &Path,
+    symbol: &str,
+    mut from_id: i64,
+) -> Result<(usize, i64), Box<dyn std::error::Error>> {
+    let mut count = 0;
+    loop {
+        let t = get_my_trades(
+            config,
+            symbol,
+            Some(from_id as u64),
+            None,
+            None,
+            Some(PAGE_LIMIT),
+        )
+        .await?;
+        append_jsonl(&dir.join(TRADES_FILE), &t.trades)?;
+        count += t.trades.len();
+        if let Some(last) = t.trades.last() {
+            from_id = last.id + 1;
+        }
+        if t.trades.len() < PAGE_LIMIT as usize {
+            break;
+        }
+    }
+
+    Ok((count, from_id))
+}
+
+/// Append the orders of symbol from order_id, returns the number of
+/// orders and the next order_id.
+async fn sync_orders(
+    config: &Configuration,
+    dir: &Path,
+    symbol: &str,
+    order_id: i64,
+) -> Result<(usize, i64), Box<dyn std::error::Error>> {
+    let mut fetched: Vec<OrderRec> = Vec::new();
+    let mut page_order_id = order_id;
+    loop {
+        let mut o = get_all_orders(
+            config,
+            symbol,
+            Some(page_order_id as u64),
+            None,
+            None,
+            Some(PAGE_LIMIT),
+        )
+        .await?;
+        append_jsonl(&dir.join(ORDERS_FILE), &o.orders)?;
+        let len = o.orders.len();
+        if let Some(last) = o.orders.last() {
+            page_order_id = last.order_id + 1;
+        }
+        fetched.append(&mut o.orders);
+        if len < PAGE_LIMIT as usize {
+            break;
+        }
+    }
+
+    Ok((fetched.len(), next_order_cursor(&fetched, order_id)))
+}
+
+/// Symbols with cursors and those between held, deposited, withdrawn and value assets
+async fn default_symbols(
+    config: &Configuration,
+    cursors: &SyncCursors,
+    ei: &ExchangeInfo,
+) -> Result<BTreeSet<String>, Box<dyn std::error::Error>> {
+    let mut assets: BTreeSet<String> = VALUE_ASSETS.iter().cloned().collect();
+    let ai = get_account_info(config, utc_now_to_time_ms()).await?;
+    for b in ai.balances_map.values() {
+        if b.free + b.locked != dec!(0) {
+            assets.insert(b.asset.clone());
+        }
+    }
+    for dr in get_deposit_history(config, None, None, None, None).await? {
+        assets.insert(dr.asset);
+    }
+    for wr in get_withdraw_history(config, None, None, None, None).await? {
+        assets.insert(wr.asset);
+    }
+
+    let mut symbols = cursors.symbols();
+    symbols.extend(symbols_of_assets(ei, &assets));
+
+    Ok(symbols)
+}
+
+pub async fn sync_cmd(
+    config: &Configuration,
+    sc_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    trace!("sync_cmd:+");
+
+    let dir = config.sync_dir.as_path();
+    let mut cursors = SyncCursors::load(dir)?;
+    let ei = get_exchange_info(config).await?;
+
+    let symbols: BTreeSet<String> = if let Some(values) = sc_matches.values_of("SYMBOLS") {
+        values.map(|s| s.to_string()).collect()
+    } else if sc_matches.is_present("ALL") {
+        ei.symbols_map.keys().cloned().collect()
+    } else {
+        default_symbols(config, &cursors, &ei).await?
+    };
+
+    let mut total_trades = 0;
+    let mut total_orders = 0;
+    for (i, symbol) in symbols.iter().enumerate() {
+        if ei.get_symbol(symbol).is_none() {
+            return Err(format!("No symbol named {symbol}").into());
+        }
+        if config.progress_info {
+            print!("Syncing {symbol} {}/{}          \r", i + 1, symbols.len());
+            let _ = std::io::stdout().flush();
+        }
+
+        let from_id = cursors.trades.get(symbol).copied().unwrap_or(0);
+        let (trades, next_from_id) = sync_trades(config, dir, symbol, from_id).await?;
+        let order_id = cursors.orders.get(symbol).copied().unwrap_or(0);
+        let (orders, next_order_id) = sync_orders(config, dir, symbol, order_id).await?;
+
+        // Only symbols that have been traded are remembered
+        if next_from_id > 0 || next_order_id > 0 {
+            cursors.trades.insert(symbol.clone(), next_from_id);
+            cursors.orders.insert(symbol.clone(), next_order_id);
+            cursors.save(dir)?;
+        }
+        if trades > 0 || orders > 0 {
+            println!("{symbol:10} {trades:6} trades {orders:6} orders");
+        }
+        total_trades += trades;
+        total_orders += orders;
+
+        if config.throttle_rate_ms > 0 {
+            sleep(Duration::from_millis(config.throttle_rate_ms)).await;
+        }
+    }
+
+    let synced_trades = read_synced_trades(dir)?;
+    let synced_orders = read_synced_orders(dir)?;
+    println!(
+        "Synced {} symbols, {} new trades and {} orders, {:?} has {} trades and {} orders",
+        symbols.len(),
+        total_trades,
+        total_orders,
+        dir,
+        synced_trades.len(),
+        synced_orders.len()
+    );
+
+    trace!("sync_cmd:-");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    fn order_rec(symbol: &str, order_id: i64, status: &str) -> OrderRec {
+        serde_json::from_str(&format!(
+            r#"{{
+                "symbol": "{symbol}",
+                "orderId": {order_id},
+                "orderListId": -1,
+                "clientOrderId": "c{order_id}",
+                "price": "0.0",
+                "origQty": "1.0",
+                "executedQty": "1.0",
+                "cummulativeQuoteQty": "100.0",
+                "status": "{status}",
+                "timeInForce": "GTC",
+                "type": "MARKET",
+                "side": "BUY",
+                "stopPrice": "0.0",
+                "icebergQty": "0.0",
+                "time": {order_id},
+                "updateTime": {order_id},
+                "isWorking": true,
+                "origQuoteOrderQty": "0.0"
+            }}"#
+        ))
+        .unwrap()
+    }
+
+    fn trade_rec(symbol: &str, id: i64, time: i64) -> TradeRec {
+        serde_json::from_str(&format!(
+            r#"{{
+                "symbol": "{symbol}",
+                "id": {id},
+                "orderId": {id},
+                "orderListId": -1,
+                "price": "100",
+                "qty": "1",
+                "quoteQty": "100",
+                "commission": "0.001",
+                "commissionAsset": "BNB",
+                "time": {time},
+                "isBuyer": true,
+                "isMaker": false,
+                "isBestMatch": true
+            }}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_next_order_cursor() {
+        assert_eq!(next_order_cursor(&[], 7), 7);
+
+        let orders = vec![
+            order_rec("ETHUSD", 10, "FILLED"),
+            order_rec("ETHUSD", 11, "CANCELED"),
+        ];
+        assert_eq!(next_order_cursor(&orders, 0), 12);
+
+        let orders = vec![
+            order_rec("ETHUSD", 10, "FILLED"),
+            order_rec("ETHUSD", 11, "NEW"),
+            order_rec("ETHUSD", 12, "PARTIALLY_FILLED"),
+            order_rec("ETHUSD", 13, "FILLED"),
+        ];
+        assert_eq!(next_order_cursor(&orders, 0), 11);
+    }
+
+    #[test]
+    fn test_store_and_cursors() {
+        // A store directory that doesn't exist yet reads as empty
+        let tmp = TempDir::new("sync");
+        let dir = tmp.path().join("sync");
+
+        assert_eq!(SyncCursors::load(&dir).unwrap(), SyncCursors::default());
+        assert!(read_synced_trades(&dir).unwrap().is_empty());
+
+        let mut cursors = SyncCursors::default();
+        cursors.trades.insert("ETHUSD".to_string(), 3);
+        cursors.orders.insert("BTCUSD".to_string(), 11);
+        cursors.save(&dir).unwrap();
+        let loaded = SyncCursors::load(&dir).unwrap();
+        assert_eq!(loaded, cursors);
+        assert_eq!(
+            loaded.symbols().into_iter().collect::<Vec<String>>(),
+            vec!["BTCUSD", "ETHUSD"]
+        );
+
+        // Trades fetched twice are only read once
+        append_jsonl(
+            &dir.join(TRADES_FILE),
+            &[trade_rec("ETHUSD", 2, 200), trade_rec("ETHUSD", 1, 100)],
+        )
+        .unwrap();
+        append_jsonl(
+            &dir.join(TRADES_FILE),
+            &[trade_rec("ETHUSD", 2, 200), trade_rec("BTCUSD", 1, 150)],
+        )
+        .unwrap();
+        let trades = read_synced_trades(&dir).unwrap();
+        let ids: Vec<(&str, i64)> = trades.iter().map(|t| (t.symbol.as_str(), t.id)).collect();
+        assert_eq!(ids, vec![("ETHUSD", 1), ("BTCUSD", 1), ("ETHUSD", 2)]);
+
+        // The latest version of an order is read
+        append_jsonl(
+            &dir.join(ORDERS_FILE),
+            &[
+                order_rec("BTCUSD", 11, "NEW"),
+                order_rec("BTCUSD", 10, "FILLED"),
+            ],
+        )
+        .unwrap();
+        append_jsonl(&dir.join(ORDERS_FILE), &[order_rec("BTCUSD", 11, "FILLED")]).unwrap();
+        let orders = read_synced_orders(&dir).unwrap();
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[0].order_id, 10);
+        assert_eq!(orders[1].order_id, 11);
+        assert_eq!(orders[1].status, "FILLED");
+    }
+}
@@ -0,0 +1,398 @@
+//! The withdrawal address book, withdraw_addresses in the configuration.
+//!
+//! Withdrawals are only made to entries of the book unless explicitly
+//! overridden. Addresses are validated for their network, the checksum
+//! is verified for EVM networks (EIP-55 when mixed case), BTC, LTC,
+//! DOGE and TRX. Other networks are only checked for being non-empty
+//! and free of whitespace.
+//!
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+
+use crate::configuration::WithdrawAddressRec;
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc830a3;
+
+/// Where a withdrawal is sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WithdrawDestination {
+    pub address: String,
+    pub memo: Option<String>,
+    pub network: Option<String>,
+    pub label: Option<String>,
+}
+
+impl WithdrawDestination {
+    /// The destination with the memo and label of the withdrawal. An
+    /// entry's memo and label are part of what's whitelisted, a
+    /// different one is an error unless allow_unlisted is true.
+    pub fn with_memo_and_label(
+        mut self,
+        memo: Option<&str>,
+        label: Option<&str>,
+        allow_unlisted: bool,
+    ) -> Result<WithdrawDestination, Box<dyn std::error::Error>> {
+        // Only entries of the book have a label
+        let whitelisted = self.label.is_some() && !allow_unlisted;
+        if let Some(m) = memo {
+            if self.memo.as_deref() != Some(m) {
+                if whitelisted {
+                    return Err(format!("--dest-sec-addr {m} isn't the memo of withdraw_addresses entry {}, use --allow-unlisted-addr to override", self.label.unwrap_or_default()).into());
+                }
+                self.memo = Some(m.to_owned());
+            }
+        }
+        if let Some(l) = label {
+            if self.label.as_deref() != Some(l) {
+                if whitelisted {
+                    return Err(format!("--dest-label {l} isn't the label of withdraw_addresses entry {}, use --allow-unlisted-addr to override", self.label.unwrap_or_default()).into());
+                }
+                self.label = Some(l.to_owned());
+            }
+        }
+
+        Ok(self)
+    }
+}
+
+fn base58_decode(s: &str) -> Option<Vec<u8>> {
+    // Big endian
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in s.bytes() {
+        let mut carry = BASE58_ALPHABET.iter().position(|&a| a == c)? as u32;
+        for b in bytes.iter_mut().rev() {
+            carry += (*b as u32) * 58;
+            *b = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.insert(0, (carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+
+    // Each leading '1' is a leading zero byte
+    let mut decoded = vec![0u8; s.bytes().take_while(|&c| c == b'1').count()];
+    decoded.append(&mut bytes);
+
+    Some(decoded)
+}
+
+/// The payload of a base58check string if the checksum is valid
+fn base58check_decode(s: &str) -> Option<Vec<u8>> {
+    let decoded = base58_decode(s)?;
+    if decoded.len() < 5 {
+        return None;
+    }
+    let (payload, checksum) = decoded.split_at(decoded.len() - 4);
+    let hash = Sha256::digest(Sha256::digest(payload));
+    if &hash[..4] == checksum {
+        Some(payload.to_vec())
+    } else {
+        None
+    }
+}
+
+/// True if s is a base58check string of len bytes starting with one of versions
+fn is_base58check_address(s: &str, versions: &[u8], len: usize) -> bool {
+    match base58check_decode(s) {
+        Some(payload) => payload.len() == len && versions.contains(&payload[0]),
+        None => false,
+    }
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut chk: u32 = 1;
+    for v in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ffffff) << 5) ^ (*v as u32);
+        for (i, g) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+
+    chk
+}
+
+/// True if s is a bech32 or bech32m string with the human readable part hrp
+fn is_bech32_address(s: &str, hrp: &str) -> bool {
+    let lower = s.to_lowercase();
+    if (s != lower && s != s.to_uppercase()) || s.len() > 90 {
+        return false;
+    }
+    let (s_hrp, data) = match lower.rsplit_once('1') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    if s_hrp != hrp || data.len() < 6 {
+        return false;
+    }
+
+    let mut values: Vec<u8> = s_hrp.bytes().map(|b| b >> 5).collect();
+    values.push(0);
+    values.extend(s_hrp.bytes().map(|b| b & 31));
+    for c in data.bytes() {
+        match BECH32_CHARSET.iter().position(|&a| a == c) {
+            Some(v) => values.push(v as u8),
+            None => return false,
+        }
+    }
+    let polymod = bech32_polymod(&values);
+
+    polymod == BECH32_CONST || polymod == BECH32M_CONST
+}
+
+/// True if s is 0x and 40 hex digits, mixed case must be a valid
+/// EIP-55 checksum.
+fn is_evm_address(s: &str) -> bool {
+    let hex = match s.strip_prefix("0x") {
+        Some(h) => h,
+        None => return false,
+    };
+    if hex.len() != 40 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return false;
+    }
+    let has_upper = hex.chars().any(|c| c.is_ascii_uppercase());
+    let has_lower = hex.chars().any(|c| c.is_ascii_lowercase());
+    if !(has_upper && has_lower) {
+        // No checksum
+        return true;
+    }
+
+    let hash = Keccak256::digest(hex.to_lowercase().as_bytes());
+    hex.chars().enumerate().all(|(i, c)| {
+        let nibble = if i % 2 == 0 {
+            hash[i / 2] >> 4
+        } else {
+            hash[i / 2] & 0xf
+        };
+        !c.is_ascii_alphabetic() || c.is_ascii_uppercase() == (nibble >= 8)
+    })
+}
+
+/// Verify address is valid for network
+pub fn validate_address(network: &str, address: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let valid = match network.to_uppercase().as_str() {
+        "ETH" | "BSC" | "MATIC" | "AVAXC" | "ARBITRUM" | "OPTIMISM" => is_evm_address(address),
+        "BTC" => {
+            is_bech32_address(address, "bc") || is_base58check_address(address, &[0x00, 0x05], 21)
+        }
+        "LTC" => {
+            is_bech32_address(address, "ltc")
+                || is_base58check_address(address, &[0x30, 0x32, 0x05], 21)
+        }
+        "DOGE" => is_base58check_address(address, &[0x1e, 0x16], 21),
+        "TRX" => is_base58check_address(address, &[0x41], 21),
+        "SOL" => base58_decode(address).is_some_and(|d| d.len() == 32),
+        _ => !address.is_empty() && !address.chars().any(char::is_whitespace),
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("{address} is not a valid {network} address").into())
+    }
+}
+
+/// The entry of book for asset whose label or address is addr
+pub fn find_withdraw_address<'a>(
+    book: &'a [WithdrawAddressRec],
+    asset: &str,
+    addr: &str,
+) -> Option<&'a WithdrawAddressRec> {
+    book.iter()
+        .find(|e| e.asset == asset && (e.label == addr || e.address == addr))
+}
+
+/// The destination of a withdrawal of asset to addr, a label or address
+/// in book. If addr is None the only entry of book for asset is used.
+/// Addresses not in book are an error unless allow_unlisted is true.
+pub fn withdraw_destination(
+    book: &[WithdrawAddressRec],
+    asset: &str,
+    addr: Option<&str>,
+    allow_unlisted: bool,
+) -> Result<WithdrawDestination, Box<dyn std::error::Error>> {
+    let entry = match addr {
+        Some(addr) => match find_withdraw_address(book, asset, addr) {
+            Some(e) => e,
+            None if allow_unlisted => {
+                validate_address(asset, addr)?;
+                return Ok(WithdrawDestination {
+                    address: addr.to_owned(),
+                    memo: None,
+                    network: None,
+                    label: None,
+                });
+            }
+            None => {
+                return Err(format!("{addr} is not a {asset} entry of withdraw_addresses, add it to the configuration or use --allow-unlisted-addr").into());
+            }
+        },
+        None => {
+            let entries: Vec<&WithdrawAddressRec> =
+                book.iter().filter(|e| e.asset == asset).collect();
+            match entries.len() {
+                0 => return Err(format!("No withdraw address given, expecting --withdraw-addr or a {asset} entry of withdraw_addresses in the configuration").into()),
+                1 => entries[0],
+                _ => {
+                    let labels: Vec<&str> = entries.iter().map(|e| e.label.as_str()).collect();
+                    return Err(format!(
+                        "{asset} has several withdraw_addresses, choose one with --withdraw-addr: {}",
+                        labels.join(", ")
+                    )
+                    .into());
+                }
+            }
+        }
+    };
+
+    let network = entry.network.as_deref().unwrap_or(&entry.asset);
+    if let Err(e) = validate_address(network, &entry.address) {
+        return Err(format!("withdraw_addresses entry {}: {e}", entry.label).into());
+    }
+
+    Ok(WithdrawDestination {
+        address: entry.address.clone(),
+        memo: entry.memo.clone(),
+        network: entry.network.clone(),
+        label: Some(entry.label.clone()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_address() {
+        // EIP-55 examples
+        assert!(validate_address("ETH", "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_ok());
+        assert!(validate_address("BSC", "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359").is_ok());
+        assert!(validate_address("ETH", "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").is_ok());
+        assert!(validate_address("ETH", "0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_err());
+        assert!(validate_address("ETH", "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA").is_err());
+
+        assert!(validate_address("BTC", "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").is_ok());
+        assert!(validate_address("BTC", "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy").is_ok());
+        assert!(validate_address("BTC", "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").is_ok());
+        assert!(validate_address("BTC", "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3").is_err());
+        assert!(validate_address("BTC", "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdp").is_err());
+        assert!(validate_address("BTC", "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_err());
+
+        assert!(validate_address("XLM", "GABC").is_ok());
+        assert!(validate_address("XLM", "GA BC").is_err());
+        assert!(validate_address("XLM", "").is_err());
+    }
+
+    #[test]
+    fn test_withdraw_destination() {
+        let book = vec![
+            WithdrawAddressRec {
+                label: "ledger".to_owned(),
+                asset: "ETH".to_owned(),
+                network: Some("ETH".to_owned()),
+                address: "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_owned(),
+                memo: None,
+            },
+            WithdrawAddressRec {
+                label: "cold".to_owned(),
+                asset: "BTC".to_owned(),
+                network: None,
+                address: "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2".to_owned(),
+                memo: None,
+            },
+            WithdrawAddressRec {
+                label: "cold-2".to_owned(),
+                asset: "BTC".to_owned(),
+                network: None,
+                address: "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq".to_owned(),
+                memo: None,
+            },
+        ];
+
+        let d = withdraw_destination(&book, "ETH", Some("ledger"), false).unwrap();
+        assert_eq!(d.address, "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed");
+        assert_eq!(d.network.as_deref(), Some("ETH"));
+        assert_eq!(d.label.as_deref(), Some("ledger"));
+
+        // By address or the only entry of the asset
+        let d = withdraw_destination(
+            &book,
+            "ETH",
+            Some("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            false,
+        )
+        .unwrap();
+        assert_eq!(d.label.as_deref(), Some("ledger"));
+        let d = withdraw_destination(&book, "ETH", None, false).unwrap();
+        assert_eq!(d.label.as_deref(), Some("ledger"));
+
+        // Several entries need a choice
+        assert!(withdraw_destination(&book, "BTC", None, false).is_err());
+        let d = withdraw_destination(&book, "BTC", Some("cold-2"), false).unwrap();
+        assert_eq!(d.address, "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq");
+
+        // Not in the book, or for a different asset
+        let unlisted = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+        assert!(withdraw_destination(&book, "ETH", Some(unlisted), false).is_err());
+        assert!(withdraw_destination(&book, "BTC", Some("ledger"), false).is_err());
+        let d = withdraw_destination(&book, "ETH", Some(unlisted), true).unwrap();
+        assert_eq!(d.address, unlisted);
+        assert!(d.label.is_none());
+
+        // Unlisted addresses are still validated
+        assert!(withdraw_destination(&book, "ETH", Some("0x1234"), true).is_err());
+    }
+
+    #[test]
+    fn test_with_memo_and_label() {
+        let book = vec![WithdrawAddressRec {
+            label: "exchange".to_owned(),
+            asset: "XLM".to_owned(),
+            network: None,
+            address: "GABC".to_owned(),
+            memo: Some("123".to_owned()),
+        }];
+        let dest = withdraw_destination(&book, "XLM", None, false).unwrap();
+
+        // The entry's own memo and label are fine
+        let d = dest
+            .clone()
+            .with_memo_and_label(Some("123"), Some("exchange"), false)
+            .unwrap();
+        assert_eq!(d, dest);
+        let d = dest.clone().with_memo_and_label(None, None, false).unwrap();
+        assert_eq!(d.memo.as_deref(), Some("123"));
+
+        // A different memo or label needs --allow-unlisted-addr
+        assert!(dest
+            .clone()
+            .with_memo_and_label(Some("999"), None, false)
+            .is_err());
+        assert!(dest
+            .clone()
+            .with_memo_and_label(None, Some("other"), false)
+            .is_err());
+        let d = dest
+            .clone()
+            .with_memo_and_label(Some("999"), Some("other"), true)
+            .unwrap();
+        assert_eq!(d.memo.as_deref(), Some("999"));
+        assert_eq!(d.label.as_deref(), Some("other"));
+
+        // Unlisted addresses take the given memo and label
+        let d = withdraw_destination(&book, "XLM", Some("GXYZ"), true)
+            .unwrap()
+            .with_memo_and_label(Some("42"), Some("friend"), true)
+            .unwrap();
+        assert_eq!(d.memo.as_deref(), Some("42"));
+        assert_eq!(d.label.as_deref(), Some("friend"));
+    }
+}
@@ -0,0 +1,282 @@
+//! Withdrawal safety limits, the kill switch file, the daily USD caps
+//! and the cooling-off period of newly used addresses.
+//!
+//! The caps and cooling-off are computed from the withdrawals of the
+//! order log, entries logged before withdrawals had a time are ignored.
+//!
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use log::trace;
+use rust_decimal::prelude::*;
+
+use crate::{
+    binance_order_response::{TradeResponse, WithdrawResponseRec},
+    configuration::Configuration,
+};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+const HOUR_MS: i64 = 60 * 60 * 1000;
+
+/// Refuse all withdrawals while the kill switch file exists
+pub fn verify_kill_switch_off(config: &Configuration) -> Result<(), Box<dyn std::error::Error>> {
+    if config.withdraw_kill_switch_path.exists() {
+        Err(format!(
+            "Withdrawals are disabled while {} exists",
+            config.withdraw_kill_switch_path.to_string_lossy()
+        )
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+/// The withdrawals of the order log at order_log_path, empty if it doesn't
+/// exist. Blank lines are skipped, an unparseable line is an error so the
+/// limits aren't computed from a partial log.
+pub fn logged_withdrawals(
+    order_log_path: &Path,
+) -> Result<Vec<WithdrawResponseRec>, Box<dyn std::error::Error>> {
+    let mut withdrawals = Vec::new();
+    if !order_log_path.exists() {
+        return Ok(withdrawals);
+    }
+
+    let reader = BufReader::new(File::open(order_log_path)?);
+    for (i, result) in reader.lines().enumerate() {
+        let line = result?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let tr: TradeResponse = match serde_json::from_str(&line) {
+            Ok(tr) => tr,
+            Err(e) => {
+                return Err(format!(
+                    "Error processing {} line {}: {e}",
+                    order_log_path.to_string_lossy(),
+                    i + 1
+                )
+                .into())
+            }
+        };
+        match tr {
+            TradeResponse::SuccessWithdraw(wrr)
+            | TradeResponse::FailureWithdraw(wrr)
+            | TradeResponse::SuccessTestWithdraw(wrr) => withdrawals.push(wrr),
+            _ => {}
+        }
+    }
+    trace!("logged_withdrawals: {}", withdrawals.len());
+
+    Ok(withdrawals)
+}
+
+/// USD value of the successful live withdrawals of asset, or all
+/// assets if None, at or after start_ms
+fn withdrawn_usd_since(
+    withdrawals: &[WithdrawResponseRec],
+    asset: Option<&str>,
+    start_ms: i64,
+) -> Decimal {
+    withdrawals
+        .iter()
+        .filter(|wrr| wrr.success && !wrr.test && wrr.time > 0 && wrr.time >= start_ms)
+        .filter(|wrr| asset.is_none_or(|a| wrr.params.sym_name == a))
+        .map(|wrr| wrr.params.quantity_usd)
+        .sum()
+}
+
+/// Verify withdrawing value_usd of asset keeps the last 24 hours of
+/// withdrawals within the asset's and the total daily limits.
+pub fn verify_daily_limits(
+    config: &Configuration,
+    withdrawals: &[WithdrawResponseRec],
+    asset: &str,
+    value_usd: Decimal,
+    now_ms: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let start_ms = now_ms - DAY_MS;
+
+    if let Some(limit) = config.withdraw_asset_daily_limits_usd.get(asset) {
+        let withdrawn = withdrawn_usd_since(withdrawals, Some(asset), start_ms);
+        if withdrawn + value_usd > *limit {
+            return Err(format!(
+                "Withdrawing ${:.2} of {asset} exceeds its daily limit of ${:.2}, ${:.2} was withdrawn in the last 24 hours",
+                value_usd, limit, withdrawn
+            )
+            .into());
+        }
+    }
+
+    if let Some(limit) = config.withdraw_daily_limit_usd {
+        let withdrawn = withdrawn_usd_since(withdrawals, None, start_ms);
+        if withdrawn + value_usd > limit {
+            return Err(format!(
+                "Withdrawing ${:.2} exceeds the daily limit of ${:.2}, ${:.2} was withdrawn in the last 24 hours",
+                value_usd, limit, withdrawn
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// The milliseconds remaining in the cooling-off period of address,
+/// None if there is no period, it has passed or address hasn't been used.
+/// The period starts when address is first used by a successful
+/// withdrawal of the same test mode.
+pub fn cooling_off_remaining_ms(
+    config: &Configuration,
+    withdrawals: &[WithdrawResponseRec],
+    address: &str,
+    test: bool,
+    now_ms: i64,
+) -> Option<i64> {
+    let period_ms = config.withdraw_cooling_off_hours? as i64 * HOUR_MS;
+    let first_used_ms = withdrawals
+        .iter()
+        .filter(|wrr| wrr.success && wrr.test == test && wrr.time > 0)
+        .filter(|wrr| wrr.params.address.as_deref() == Some(address))
+        .map(|wrr| wrr.time)
+        .min()?;
+
+    let remaining_ms = first_used_ms + period_ms - now_ms;
+    if remaining_ms > 0 {
+        Some(remaining_ms)
+    } else {
+        None
+    }
+}
+
+/// Hours of ms to one decimal place
+pub fn ms_to_hours(ms: i64) -> Decimal {
+    (Decimal::from(ms) / Decimal::from(HOUR_MS)).round_dp(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{binance_withdraw_cmd::WithdrawParams, test_utils::TempDir};
+    use rust_decimal_macros::dec;
+
+    fn wrr(
+        asset: &str,
+        address: &str,
+        usd: Decimal,
+        time: i64,
+        success: bool,
+    ) -> WithdrawResponseRec {
+        WithdrawResponseRec {
+            success,
+            time,
+            params: WithdrawParams {
+                sym_name: asset.to_owned(),
+                address: Some(address.to_owned()),
+                quantity_usd: usd,
+                ..WithdrawParams::default()
+            },
+            ..WithdrawResponseRec::default()
+        }
+    }
+
+    #[test]
+    fn test_verify_daily_limits() {
+        let now = 10 * DAY_MS;
+        let withdrawals = vec![
+            wrr("ETH", "a", dec!(3000), now - HOUR_MS, true),
+            wrr("BTC", "b", dec!(2000), now - 2 * HOUR_MS, true),
+            // Failed, too old, test or without a time aren't counted
+            wrr("ETH", "a", dec!(9000), now - HOUR_MS, false),
+            wrr("ETH", "a", dec!(9000), now - DAY_MS - 1, true),
+            WithdrawResponseRec {
+                test: true,
+                ..wrr("ETH", "a", dec!(9000), now, true)
+            },
+            wrr("ETH", "a", dec!(9000), 0, true),
+        ];
+
+        let mut config = Configuration::default();
+        assert!(verify_daily_limits(&config, &withdrawals, "ETH", dec!(1000000), now).is_ok());
+
+        config
+            .withdraw_asset_daily_limits_usd
+            .insert("ETH".to_owned(), dec!(5000));
+        assert!(verify_daily_limits(&config, &withdrawals, "ETH", dec!(2000), now).is_ok());
+        assert!(verify_daily_limits(&config, &withdrawals, "ETH", dec!(2000.01), now).is_err());
+        assert!(verify_daily_limits(&config, &withdrawals, "BTC", dec!(2000.01), now).is_ok());
+
+        config.withdraw_daily_limit_usd = Some(dec!(6000));
+        assert!(verify_daily_limits(&config, &withdrawals, "BTC", dec!(1000), now).is_ok());
+        assert!(verify_daily_limits(&config, &withdrawals, "BTC", dec!(1000.01), now).is_err());
+    }
+
+    #[test]
+    fn test_cooling_off_remaining_ms() {
+        let now = 10 * DAY_MS;
+        let withdrawals = vec![
+            wrr("ETH", "old", dec!(100), now - 50 * HOUR_MS, true),
+            wrr("ETH", "new", dec!(0), now - 2 * HOUR_MS, false),
+            wrr("ETH", "new", dec!(100), now - HOUR_MS, true),
+        ];
+
+        let mut config = Configuration::default();
+        assert_eq!(
+            cooling_off_remaining_ms(&config, &withdrawals, "never", false, now),
+            None
+        );
+
+        config.withdraw_cooling_off_hours = Some(48);
+        assert_eq!(
+            cooling_off_remaining_ms(&config, &withdrawals, "old", false, now),
+            None
+        );
+        // The failed withdrawal isn't the first use
+        assert_eq!(
+            cooling_off_remaining_ms(&config, &withdrawals, "new", false, now),
+            Some(47 * HOUR_MS)
+        );
+        assert_eq!(
+            cooling_off_remaining_ms(&config, &withdrawals, "never", false, now),
+            None
+        );
+
+        // Test withdrawals have their own first use
+        let test_withdrawals = vec![WithdrawResponseRec {
+            test: true,
+            ..wrr("ETH", "old", dec!(100), now - HOUR_MS, true)
+        }];
+        assert_eq!(
+            cooling_off_remaining_ms(&config, &test_withdrawals, "old", true, now),
+            Some(47 * HOUR_MS)
+        );
+        assert_eq!(
+            cooling_off_remaining_ms(&config, &test_withdrawals, "old", false, now),
+            None
+        );
+        assert_eq!(ms_to_hours(47 * HOUR_MS), dec!(47));
+    }
+
+    #[test]
+    fn test_logged_withdrawals() {
+        let dir = TempDir::new("withdraw-limits");
+        let path = dir.path().join("order_log.txt");
+        assert!(logged_withdrawals(&path).unwrap().is_empty());
+
+        let tr = TradeResponse::SuccessWithdraw(wrr("ETH", "a", dec!(100), 1000, true));
+        let line = serde_json::to_string(&tr).unwrap();
+        std::fs::write(&path, format!("{line}\n\n{{\"truncated\n  \n{line}\n")).unwrap();
+
+        // Blank lines are skipped, an unparseable line is an error
+        let e = logged_withdrawals(&path).unwrap_err();
+        assert!(e.to_string().contains("line 3"), "{e}");
+
+        std::fs::write(&path, format!("{line}\n\n  \n{line}\n")).unwrap();
+        let withdrawals = logged_withdrawals(&path).unwrap();
+        assert_eq!(withdrawals.len(), 2);
+        assert_eq!(withdrawals[1].params.quantity_usd, dec!(100));
+    }
+}